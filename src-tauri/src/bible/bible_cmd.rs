@@ -1,11 +1,11 @@
-use std::{collections::HashSet, num::NonZeroU32, sync::Mutex};
+use std::{collections::HashSet, fs, num::NonZeroU32, path::Path, sync::Mutex};
 
-use biblio_json::{core::{OsisBook, StrongsLang, StrongsNumber, VerseId, lang::Language}, modules::{EntryId, Module, ModuleId}};
+use biblio_json::{Package, core::{OsisBook, RefId, StrongsLang, StrongsNumber, VerseId, lang::Language}, modules::{EntryId, Module, ModuleId, bible::BookInfo}};
 use itertools::Itertools;
 use serde::{Deserialize, Serialize};
 use tauri::{Emitter, State};
 
-use crate::{bible::{BIBLE_DISPLAY_SETTINGS_CHANGED_EVENT_NAME, BibleDisplaySettings, BibleInfo, BibleDisplaySettingsChangedEvent, BiblioJsonPackageHandle, fetching::PackageEx, render::{RenderSearchArgs, fetch_verse_render_data, render_verses, render_word_search_verses}}, core::app::AppState, repr::{module_config::ModuleConfigJson, readings_date::ReadingsDateJson, searching::{ModuleSearchHitJson, WordSearchQueryJson}, *}, searching::{module_searching::WordSearchMode, word_search_engine::WordSearchQuery}};
+use crate::{bible::{BIBLE_DISPLAY_SETTINGS_CHANGED_EVENT_NAME, BibleDisplaySettings, BibleInfo, BibleDisplaySettingsChangedEvent, BiblioJsonPackageHandle, PACKAGE_NOT_LOADED_ERROR, concordance::{ConcordanceArgs, ConcordanceCache, build_strongs_concordance}, fetching::PackageEx, get_language_default_bible, importing::{ImportedBible, get_reference_books, osis::parse_osis, sqlite::read_sqlite_module, sword::{find_sword_confs, read_sword_module}, usfm::{parse_usfm_books, read_usfm_files}}, notebook_export::{NotebookExportFormat, NotebookExportResult, export_notebook}, notebook_import::{NotebookImportResult, parse_notebook_rows, read_notebook_rows}, notebooks::{NOTEBOOK_CHANGED_EVENT_NAME, NotebookChangedEvent, NotebookEditResult, NotebookEntryArgs, NotebookStore}, render::{RenderComparisonArgs, RenderParallelArgs, RenderSearchArgs, WordSearchCache, fetch_verse_render_data, render_interlinear_verses, render_parallel_chapter, render_verse_comparison, render_verses, render_word_search_verses}, user_modules::{MODULES_CHANGED_EVENT_NAME, ModuleChangeResult, ModulesChangedEvent, UserModuleStore}}, core::{app::AppState, journal::AppStateChange}, repr::{module_config::ModuleConfigJson, readings_date::ReadingsDateJson, searching::{ModuleSearchHitJson, WordSearchQueryJson}, *}, searching::{module_searching::WordSearchMode, ranking::{SearchSortMode, TermFrequencies, rank_hits}, word_search_engine::WordSearchQuery}};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
@@ -47,6 +47,13 @@ pub enum BibleCommand
         strongs: StrongsNumberJson,
         shown_modules: HashSet<ModuleId>,
     },
+    FetchStrongsConcordance
+    {
+        strongs: StrongsNumberJson,
+        bible: ModuleId,
+        page_index: u32,
+        page_size: u32,
+    },
     FetchWordEntries
     {
         verse: VerseIdJson,
@@ -78,12 +85,31 @@ pub enum BibleCommand
         show_strongs: bool,
         bible: ModuleId,
         shown_modules: HashSet<ModuleId>,
+        #[serde(default)]
+        interlinear: bool,
+    },
+    RenderParallelChapter
+    {
+        chapter: ChapterIdJson,
+        bibles: Vec<ModuleId>,
+        show_strongs: bool,
+        shown_modules: HashSet<ModuleId>,
+    },
+    RenderVerseComparison
+    {
+        verses: Vec<VerseIdJson>,
+        bibles: Vec<ModuleId>,
+        base: ModuleId,
+        show_strongs: bool,
+        shown_modules: HashSet<ModuleId>,
     },
     RunModuleWordSearch
     {
         query: WordSearchQueryJson,
         modules: Vec<ModuleId>,
         mode: WordSearchMode,
+        #[serde(default)]
+        sort: SearchSortMode,
 
         page_index: u32,
         page_size: u32,
@@ -95,6 +121,8 @@ pub enum BibleCommand
         page_index: u32,
         page_size: u32,
         shown_modules: HashSet<ModuleId>,
+        #[serde(default)]
+        sort: SearchSortMode,
     },
     FetchModuleConfigs,
     GetEntryIndex
@@ -122,7 +150,111 @@ pub enum BibleCommand
     GetLanguageDefaultBible
     {
         language: String
-    }
+    },
+    FetchPackageLoadReport,
+    CreateNotebook
+    {
+        name: String,
+        description: Option<String>,
+        bible: Option<ModuleId>,
+    },
+    DeleteNotebook
+    {
+        module: ModuleId,
+    },
+    AddNote
+    {
+        module: ModuleId,
+        name: Option<String>,
+        content: String,
+        references: Vec<RefIdJson>,
+    },
+    UpdateNote
+    {
+        module: ModuleId,
+        entry: u32,
+        name: Option<String>,
+        content: String,
+        references: Vec<RefIdJson>,
+    },
+    AddHighlight
+    {
+        module: ModuleId,
+        name: String,
+        description: Option<String>,
+        color: String,
+        priority: u32,
+        references: Vec<RefIdJson>,
+    },
+    UpdateHighlight
+    {
+        module: ModuleId,
+        entry: u32,
+        name: String,
+        description: Option<String>,
+        color: String,
+        priority: u32,
+        references: Vec<RefIdJson>,
+    },
+    DeleteNotebookEntry
+    {
+        module: ModuleId,
+        entry: u32,
+    },
+    ExportNotebook
+    {
+        module: ModuleId,
+        format: NotebookExportFormat,
+        path: String,
+    },
+    ImportNotebook
+    {
+        path: String,
+        name: String,
+        bible: ModuleId,
+    },
+    ImportModuleFolder
+    {
+        path: String,
+        module_type: ModuleTypeJson,
+    },
+    ImportModuleArchive
+    {
+        path: String,
+        module_type: ModuleTypeJson,
+    },
+    ImportOsisBible
+    {
+        path: String,
+    },
+    ImportUsfmBible
+    {
+        path: String,
+        name: String,
+        short_name: String,
+        language: Option<String>,
+    },
+    ImportSwordModules
+    {
+        path: String,
+    },
+    ImportSqliteModule
+    {
+        path: String,
+    },
+    FetchDisabledModuleInfos,
+    DisableModule
+    {
+        module: ModuleId,
+    },
+    EnableModule
+    {
+        module: ModuleId,
+    },
+    UninstallModule
+    {
+        module: ModuleId,
+    },
 }
 
 #[tauri::command(rename_all = "snake_case")]
@@ -130,6 +262,10 @@ pub fn run_bible_command(
     app_handle: tauri::AppHandle, 
     app_state: State<'_, Mutex<AppState>>, 
     package: State<'_, BiblioJsonPackageHandle>, 
+    notebooks: State<'_, NotebookStore>,
+    search_cache: State<'_, WordSearchCache>,
+    concordance_cache: State<'_, ConcordanceCache>,
+    user_modules: State<'_, UserModuleStore>,
     command: BibleCommand
 ) -> Option<String>
 {
@@ -142,14 +278,14 @@ pub fn run_bible_command(
                     display_name: bible.config.short_name.clone().unwrap_or(bible.config.name.clone()),
                     books: bible.source.book_infos.clone()
                 }).collect_vec()
-            });
+            })?;
 
             Some(serde_json::to_string(&bibles).unwrap())
         },
         BibleCommand::FetchModuleInfos => {
             let modules = package.visit(|p| {
                 p.modules.values().map(|m| ModuleInfoJson::from(m.get_info())).collect_vec()
-            });
+            })?;
 
             Some(serde_json::to_string(&modules).unwrap())
         }
@@ -162,21 +298,15 @@ pub fn run_bible_command(
         },
         BibleCommand::SetBibleDisplaySettings { version_state } => {
             let mut state = app_state.lock().unwrap();
-            let old = state.bible_display_settings.clone();
-            state.bible_display_settings = version_state;
-
-            app_handle.emit(BIBLE_DISPLAY_SETTINGS_CHANGED_EVENT_NAME, BibleDisplaySettingsChangedEvent {
-                old: old,
-                new: state.bible_display_settings.clone(),
-            }).unwrap();
+            set_display_settings(&app_handle, &mut state, version_state);
             None
         },
         BibleCommand::FetchVerseRenderData { verses, bible, shown_modules } => {
             let verses = verses.iter().map(|v| v.into()).collect_vec();
 
-            let response = package.visit(|p| {
-                fetch_verse_render_data(p, &verses, &bible, &shown_modules)
-            });
+            let response = package.visit_indexed(|p, index| {
+                fetch_verse_render_data(p, index, &verses, &bible, &shown_modules)
+            })?;
 
             Some(serde_json::to_string(&response).unwrap())
         },
@@ -198,55 +328,114 @@ pub fn run_bible_command(
                             StrongsDefEntryJson::new(d, &info)
                         }))
                     .collect_vec()
-            });
+            })?;
+
+            Some(serde_json::to_string(&response).unwrap())
+        },
+        BibleCommand::FetchStrongsConcordance { strongs, bible, page_index, page_size } => {
+            let strongs = StrongsNumber::from(strongs);
+            let response = package.visit_indexed(|p, index| {
+                build_strongs_concordance(ConcordanceArgs {
+                    package: p,
+                    index,
+                    strongs: &strongs,
+                    bible: &bible,
+                    page_index,
+                    page_size,
+                    cache: &concordance_cache,
+                })
+            })?;
 
             Some(serde_json::to_string(&response).unwrap())
         },
         BibleCommand::FetchWordEntries { verse, word, bible, shown_modules } => {
-            let response = package.visit(|p | {
-                p.fetch_word_entries(verse.into(), word, &bible, &shown_modules)
-            });
+            let response = package.visit_indexed(|p, index| {
+                p.fetch_word_entries(index, verse.into(), word, &bible, &shown_modules)
+            })?;
 
             Some(serde_json::to_string(&response).unwrap())
         },
         BibleCommand::FetchVerseEntries { verse, bible, shown_modules } => {
-            let response = package.visit(|p | {
-                p.fetch_verse_entries(verse.into(), &bible, &shown_modules)
-            });
+            let response = package.visit_indexed(|p, index| {
+                p.fetch_verse_entries(index, verse.into(), &bible, &shown_modules)
+            })?;
 
             Some(serde_json::to_string(&response).unwrap())
         }
         BibleCommand::FetchChapterEntries { chapter, bible, shown_modules } => {
-            let response = package.visit(|p | {
-                p.fetch_chapter_entries(chapter.into(), &bible, &shown_modules)
-            });
+            let response = package.visit_indexed(|p, index| {
+                p.fetch_chapter_entries(index, chapter.into(), &bible, &shown_modules)
+            })?;
 
             Some(serde_json::to_string(&response).unwrap())
         },
         BibleCommand::FetchBookEntries { book, bible, shown_modules } => {
-            let response = package.visit(|p | {
-                p.fetch_book_entries(book, &bible, &shown_modules)
-            });
+            let response = package.visit_indexed(|p, index| {
+                p.fetch_book_entries(index, book, &bible, &shown_modules)
+            })?;
 
             Some(serde_json::to_string(&response).unwrap())
         }
-        BibleCommand::RenderVerses { verses, show_strongs, bible, shown_modules } => {
+        BibleCommand::RenderVerses { verses, show_strongs, bible, shown_modules, interlinear } => {
             let verses = verses.iter().map(|v| VerseId::from(v)).collect_vec();
             
-            let response = package.visit(|p| {
-                render_verses(p, &verses, &bible, show_strongs, &shown_modules)
-            });
+            let response = package.visit_indexed(|p, index| {
+                if interlinear
+                {
+                    render_interlinear_verses(p, index, &verses, &bible, &shown_modules)
+                }
+                else 
+                {
+                    render_verses(p, index, &verses, &bible, show_strongs, &shown_modules)
+                }
+            })?;
+
+            Some(serde_json::to_string(&response).unwrap())
+        },
+        BibleCommand::RenderParallelChapter { chapter, bibles, show_strongs, shown_modules } => {
+            let response = package.visit_indexed(|p, index| {
+                render_parallel_chapter(RenderParallelArgs {
+                    package: p,
+                    index,
+                    chapter: chapter.into(),
+                    bibles: &bibles,
+                    show_strongs,
+                    shown_modules: &shown_modules,
+                })
+            })?;
 
             Some(serde_json::to_string(&response).unwrap())
         },
-        BibleCommand::RunModuleWordSearch { query, modules, mode, page_size, page_index } => {
+        BibleCommand::RenderVerseComparison { verses, bibles, base, show_strongs, shown_modules } => {
+            let verses = verses.iter().map(VerseId::from).collect_vec();
+
+            let response = package.visit_indexed(|p, index| {
+                render_verse_comparison(RenderComparisonArgs {
+                    package: p,
+                    index,
+                    verses: &verses,
+                    bibles: &bibles,
+                    base: &base,
+                    show_strongs,
+                    shown_modules: &shown_modules,
+                })
+            })?;
+
+            Some(serde_json::to_string(&response).unwrap())
+        },
+        BibleCommand::RunModuleWordSearch { query, modules, mode, sort, page_size, page_index } => {
             let bible = app_state.lock().unwrap().bible_display_settings.bible_version.clone();
             let query: WordSearchQuery = query.into();
 
             let start = page_size as usize * page_index as usize;
 
-            let response = package.visit(|p| {
-                let hits = query.run_query(p, &modules, mode);
+            let response = package.visit_indexed(|p, index| {
+                let mut hits = query.run_query(p, index, &modules, mode, sort);
+                if sort == SearchSortMode::Relevance
+                {
+                    rank_hits(&mut hits, &TermFrequencies::new(index, &modules, &query));
+                }
+
                 let total_count = hits.len() as u32;
                 
                 let hits = hits.into_iter()
@@ -259,22 +448,25 @@ pub fn run_bible_command(
                     hits,
                     total_count,
                 }
-            });
+            })?;
 
             Some(serde_json::to_string(&response).unwrap())
         },
-        BibleCommand::RenderWordSearchQuery { query, show_strongs, page_index, page_size, shown_modules } => {
+        BibleCommand::RenderWordSearchQuery { query, show_strongs, page_index, page_size, shown_modules, sort } => {
             let query: WordSearchQuery = query.into();
-            let response = package.visit(|package| {
+            let response = package.visit_indexed(|package, index| {
                 render_word_search_verses(RenderSearchArgs {
                     query: &query, 
                     package, 
+                    index,
                     show_strongs, 
                     page_index, 
                     page_size,
                     shown_modules: &shown_modules,
+                    cache: &search_cache,
+                    sort,
                 })
-            });
+            })?;
 
             Some(serde_json::to_string(&response).unwrap())
         },
@@ -283,7 +475,7 @@ pub fn run_bible_command(
                 package.modules.values()
                     .map(|m| ModuleConfigJson::new(m))
                     .collect_vec()
-            });
+            })?;
 
             Some(serde_json::to_string(&response).unwrap())
         },
@@ -292,7 +484,7 @@ pub fn run_bible_command(
                 package.get_mod(&module).unwrap().entries()
                     .find_position(|e| e.id() == entry)
                     .map(|(i, _)| i as u32)
-            });
+            })?;
 
             Some(serde_json::to_string(&response).unwrap())
         }
@@ -313,7 +505,7 @@ pub fn run_bible_command(
                     .collect_vec();
 
                 package.convert_to_json_entries(entries, &bible)
-            });
+            })?;
 
             Some(serde_json::to_string(&response).unwrap())
         }
@@ -342,7 +534,7 @@ pub fn run_bible_command(
                         }
                     })
                     .collect_vec()
-            });
+            })?;
 
             Some(serde_json::to_string(&response).unwrap())
         }
@@ -352,7 +544,7 @@ pub fn run_bible_command(
                 readings_module.get_reading(*start_date, *selected_date).map(|r| {
                     r.readings.iter().map(|r| RefIdJson::from(r)).collect_vec()
                 })
-            });
+            })?;
             
             Some(serde_json::to_string(&response).unwrap())
         },
@@ -360,13 +552,427 @@ pub fn run_bible_command(
             let lang = Language::new(&language).ok()?;
 
             let response = package.visit(|package| {
-                package.modules.values()
-                    .filter_map(Module::as_bible)
-                    .find(|b| b.config.language == Some(lang))
-                    .map(|b| b.config.id.get().to_string())
-            });
+                get_language_default_bible(package, Some(lang)).map(|id| id.get().to_string())
+            })?;
 
             response
+        },
+        BibleCommand::FetchPackageLoadReport => {
+            let report = package.get_load_report()?;
+            Some(serde_json::to_string(&report).unwrap())
+        },
+        BibleCommand::CreateNotebook { name, description, bible } => {
+            let result = package.edit_module(|p| {
+                notebooks.create_notebook(p, &name, description.as_deref(), bible.as_ref()).map(|id| (id, None))
+            })?;
+
+            if let Ok((id, _)) = &result
+            {
+                show_module(&app_handle, &app_state, id);
+            }
+
+            Some(serde_json::to_string(&finish_notebook_edit(&app_handle, result, false)).unwrap())
+        },
+        BibleCommand::DeleteNotebook { module } => {
+            let result = package.edit_module(|p| {
+                notebooks.delete_notebook(p, &module).map(|_| (module.clone(), None))
+            })?;
+
+            if result.is_ok()
+            {
+                let mut state = app_state.lock().unwrap();
+                let mut settings = state.bible_display_settings.clone();
+                settings.shown_modules.remove(&module);
+                set_display_settings(&app_handle, &mut state, settings);
+            }
+
+            Some(serde_json::to_string(&finish_notebook_edit(&app_handle, result, true)).unwrap())
+        },
+        BibleCommand::AddNote { module, name, content, references } => {
+            let args = NotebookEntryArgs::Note { 
+                name, 
+                content, 
+                references: references.iter().map(RefId::from).collect_vec(),
+            };
+
+            let result = package.edit_module(|p| {
+                notebooks.add_entry(p, &module, args).map(|entry| (module.clone(), Some(entry)))
+            })?;
+
+            Some(serde_json::to_string(&finish_notebook_edit(&app_handle, result, false)).unwrap())
+        },
+        BibleCommand::UpdateNote { module, entry, name, content, references } => {
+            let args = NotebookEntryArgs::Note { 
+                name, 
+                content, 
+                references: references.iter().map(RefId::from).collect_vec(),
+            };
+
+            let result = package.edit_module(|p| {
+                notebooks.update_entry(p, &module, entry, args).map(|_| (module.clone(), Some(entry)))
+            })?;
+
+            Some(serde_json::to_string(&finish_notebook_edit(&app_handle, result, false)).unwrap())
+        },
+        BibleCommand::AddHighlight { module, name, description, color, priority, references } => {
+            let args = NotebookEntryArgs::Highlight { 
+                name, 
+                description, 
+                color, 
+                priority, 
+                references: references.iter().map(RefId::from).collect_vec(),
+            };
+
+            let result = package.edit_module(|p| {
+                notebooks.add_entry(p, &module, args).map(|entry| (module.clone(), Some(entry)))
+            })?;
+
+            Some(serde_json::to_string(&finish_notebook_edit(&app_handle, result, false)).unwrap())
+        },
+        BibleCommand::UpdateHighlight { module, entry, name, description, color, priority, references } => {
+            let args = NotebookEntryArgs::Highlight { 
+                name, 
+                description, 
+                color, 
+                priority, 
+                references: references.iter().map(RefId::from).collect_vec(),
+            };
+
+            let result = package.edit_module(|p| {
+                notebooks.update_entry(p, &module, entry, args).map(|_| (module.clone(), Some(entry)))
+            })?;
+
+            Some(serde_json::to_string(&finish_notebook_edit(&app_handle, result, false)).unwrap())
+        },
+        BibleCommand::DeleteNotebookEntry { module, entry } => {
+            let result = package.edit_module(|p| {
+                notebooks.delete_entry(p, &module, entry).map(|_| (module.clone(), Some(entry)))
+            })?;
+
+            Some(serde_json::to_string(&finish_notebook_edit(&app_handle, result, false)).unwrap())
+        },
+        BibleCommand::ExportNotebook { module, format, path } => {
+            let bible = app_state.lock().unwrap().bible_display_settings.bible_version.clone();
+            let result = package.visit(|p| {
+                let Some(Module::Notebook(notebook)) = p.get_mod(&module) else {
+                    return Err(format!("Notebook '{}' is not loaded", module));
+                };
+
+                let bible = p.get_mod(&bible).and_then(Module::as_bible).ok_or(format!("Bible '{}' is not loaded", bible))?;
+                export_notebook(notebook, bible, format)
+            })?;
+
+            let result = result.and_then(|data| fs::write(&path, data).map(|_| path).map_err(|e| e.to_string()));
+            Some(serde_json::to_string(&NotebookExportResult::from(result)).unwrap())
+        },
+        BibleCommand::ImportNotebook { path, name, bible } => {
+            let (rows, read_errors) = match read_notebook_rows(Path::new(&path))
+            {
+                Ok(rows) => rows,
+                Err(message) => return Some(serde_json::to_string(&NotebookImportResult::Error { message, errors: vec![] }).unwrap()),
+            };
+
+            let (entries, mut errors) = package.visit(|p| parse_notebook_rows(&rows, &bible, p))?;
+            errors.extend(read_errors);
+            errors.sort_by_key(|e| e.line);
+            if entries.is_empty()
+            {
+                let message = "The file does not contain any notes that could be imported".to_string();
+                return Some(serde_json::to_string(&NotebookImportResult::Error { message, errors }).unwrap());
+            }
+
+            let imported = entries.len();
+            let result = package.edit_module(|p| {
+                let id = notebooks.create_notebook(p, &name, None, Some(&bible))?;
+                match notebooks.add_entries(p, &id, entries)
+                {
+                    Ok(_) => Ok((id, ())),
+                    Err(e) => {
+                        let _ = notebooks.delete_notebook(p, &id);
+                        Err(e)
+                    }
+                }
+            })?;
+
+            let result = match result
+            {
+                Ok((module, _)) => {
+                    show_module(&app_handle, &app_state, &module);
+                    finish_notebook_edit(&app_handle, Ok((module.clone(), None)), false);
+                    NotebookImportResult::Success { module, imported, errors }
+                },
+                Err(message) => NotebookImportResult::Error { message, errors },
+            };
+
+            Some(serde_json::to_string(&result).unwrap())
+        },
+        BibleCommand::ImportModuleFolder { path, module_type } => {
+            let exists = |id: &ModuleId| package.contains_module(id).unwrap_or(true);
+            let result = user_modules.import_folder(Path::new(&path), module_type, exists).map(|m| vec![m]);
+            Some(serde_json::to_string(&finish_module_import(&app_handle, &app_state, &package, result, vec![])).unwrap())
+        },
+        BibleCommand::ImportModuleArchive { path, module_type } => {
+            let exists = |id: &ModuleId| package.contains_module(id).unwrap_or(true);
+            let result = user_modules.import_archive(Path::new(&path), module_type, exists).map(|m| vec![m]);
+            Some(serde_json::to_string(&finish_module_import(&app_handle, &app_state, &package, result, vec![])).unwrap())
+        },
+        BibleCommand::ImportOsisBible { path } => {
+            let exists = |id: &ModuleId| package.contains_module(id).unwrap_or(true);
+            let result = fs::read_to_string(&path)
+                .map_err(|e| e.to_string())
+                .and_then(|text| parse_osis(&text))
+                .and_then(|bible| user_modules.import_bible(&bible, exists));
+
+            Some(serde_json::to_string(&finish_module_import(&app_handle, &app_state, &package, result, vec![])).unwrap())
+        },
+        BibleCommand::ImportUsfmBible { path, name, short_name, language } => {
+            let exists = |id: &ModuleId| package.contains_module(id).unwrap_or(true);
+            let path = Path::new(&path);
+            let books = match path.extension().is_some_and(|e| e == "zip")
+            {
+                true => user_modules.with_extracted_archive(path, read_usfm_files),
+                false => read_usfm_files(path),
+            };
+
+            let result = books.and_then(|books| {
+                let mut bible = ImportedBible::new(name, short_name, language);
+                parse_usfm_books(books.iter().map(String::as_str), &mut bible)?;
+                user_modules.import_bible(&bible, exists)
+            });
+
+            Some(serde_json::to_string(&finish_module_import(&app_handle, &app_state, &package, result, vec![])).unwrap())
+        },
+        BibleCommand::ImportSwordModules { path } => {
+            let exists = |id: &ModuleId| package.contains_module(id).unwrap_or(true);
+            let books = package.visit(get_reference_books)
+                .ok_or(PACKAGE_NOT_LOADED_ERROR.to_string())
+                .and_then(|b| b.ok_or("A KJV bible is needed to read the versification of SWORD modules".to_string()));
+
+            let mut warnings = vec![];
+            let path = Path::new(&path);
+            let result = books.and_then(|books| {
+                let mut import = |dir: &Path| import_sword_modules(&user_modules, dir, &books, &exists, &mut warnings);
+                match path.extension().is_some_and(|e| e == "zip")
+                {
+                    true => user_modules.with_extracted_archive(path, import),
+                    false => import(path),
+                }
+            });
+
+            Some(serde_json::to_string(&finish_module_import(&app_handle, &app_state, &package, result, warnings)).unwrap())
+        },
+        BibleCommand::ImportSqliteModule { path } => {
+            let exists = |id: &ModuleId| package.contains_module(id).unwrap_or(true);
+            let books = package.visit(get_reference_books).flatten().unwrap_or_default();
+
+            let mut warnings = vec![];
+            let result = read_sqlite_module(Path::new(&path), &books).and_then(|(module, module_warnings)| {
+                warnings = module_warnings;
+                user_modules.import_converted_module(&module, exists)
+            });
+
+            Some(serde_json::to_string(&finish_module_import(&app_handle, &app_state, &package, result, warnings)).unwrap())
+        },
+        BibleCommand::FetchDisabledModuleInfos => {
+            let modules = package.visit_disabled(|disabled| {
+                disabled.values().map(|m| ModuleInfoJson::from(m.get_info())).collect_vec()
+            })?;
+
+            Some(serde_json::to_string(&modules).unwrap())
+        },
+        BibleCommand::DisableModule { module } => {
+            let language = package.visit(|p| get_bible_language(p, &module))?;
+            let result = package.disable_module(&module).map(|_| {
+                app_state.lock().unwrap().disabled_modules.insert(module.clone());
+                module
+            });
+
+            Some(serde_json::to_string(&finish_module_removal(&app_handle, &app_state, &package, result, language)).unwrap())
+        },
+        BibleCommand::EnableModule { module } => {
+            let result = package.enable_module(&module).map(|_| {
+                app_state.lock().unwrap().disabled_modules.remove(&module);
+                module.clone()
+            });
+
+            if result.is_ok()
+            {
+                app_handle.emit(MODULES_CHANGED_EVENT_NAME, ModulesChangedEvent {
+                    added: vec![module],
+                    removed: vec![],
+                }).unwrap();
+            }
+
+            Some(serde_json::to_string(&ModuleChangeResult::from(result)).unwrap())
+        },
+        BibleCommand::UninstallModule { module } => {
+            let language = package.visit(|p| get_bible_language(p, &module))?;
+            let result = if notebooks.is_user_notebook(&module)
+            {
+                let _ = package.enable_module(&module);
+                package.edit_module(|p| notebooks.delete_notebook(p, &module).map(|_| (module.clone(), ())))?
+                    .map(|_| ())
+                    .inspect(|_| {
+                        app_handle.emit(NOTEBOOK_CHANGED_EVENT_NAME, NotebookChangedEvent {
+                            module: module.clone(),
+                            deleted: true,
+                        }).unwrap();
+                    })
+            }
+            else 
+            {
+                user_modules.uninstall_module(&module)
+                    .and_then(|_| package.remove_module(&module))
+                    .map(|_| ())
+            };
+
+            let result = result.map(|_| {
+                app_state.lock().unwrap().disabled_modules.remove(&module);
+                module
+            });
+
+            Some(serde_json::to_string(&finish_module_removal(&app_handle, &app_state, &package, result, language)).unwrap())
+        },
+    }
+}
+
+fn get_bible_language(package: &Package, module: &ModuleId) -> Option<Language>
+{
+    package.get_mod(module)
+        .and_then(Module::as_bible)
+        .and_then(|b| b.config.language)
+}
+
+/// Stops showing a module that was disabled or uninstalled, falling back to another bible if it was being read
+fn finish_module_removal(app_handle: &tauri::AppHandle, app_state: &Mutex<AppState>, package: &BiblioJsonPackageHandle, result: Result<ModuleId, String>, language: Option<Language>) -> ModuleChangeResult
+{
+    if let Ok(module) = &result
+    {
+        let mut state = app_state.lock().unwrap();
+        let settings = package.visit(|p| {
+            let mut settings = state.bible_display_settings.clone();
+            settings.remove_missing_modules(p, language);
+            settings
+        }).unwrap_or_else(|| state.bible_display_settings.clone());
+
+        set_display_settings(app_handle, &mut state, settings);
+
+        app_handle.emit(MODULES_CHANGED_EVENT_NAME, ModulesChangedEvent {
+            added: vec![],
+            removed: vec![module.clone()],
+        }).unwrap();
+    }
+
+    result.into()
+}
+
+/// Imports every module in a SWORD library, failing only if none of them could be imported.
+/// Modules that fail are reported in `warnings`, along with anything that was imported incorrectly
+fn import_sword_modules(user_modules: &UserModuleStore, dir: &Path, books: &[BookInfo], exists: &impl Fn(&ModuleId) -> bool, warnings: &mut Vec<String>) -> Result<Vec<Module>, String>
+{
+    let mut imported = vec![];
+    let mut errors = vec![];
+    for conf in find_sword_confs(dir)?
+    {
+        let result = read_sword_module(&conf, books).and_then(|(module, module_warnings)| {
+            let modules = user_modules.import_converted_module(&module, exists)?;
+            warnings.extend(module_warnings);
+            Ok(modules)
+        });
+
+        match result
+        {
+            Ok(modules) => imported.extend(modules),
+            Err(e) => errors.push(format!("{}: {}", conf.file_stem().unwrap_or_default().to_string_lossy(), e)),
         }
     }
+
+    if imported.is_empty()
+    {
+        return Err(errors.join("\n"));
+    }
+
+    warnings.extend(errors);
+    Ok(imported)
+}
+
+/// Adds imported modules to the live package, showing them by default if they are entry modules.
+/// The result holds the first module, which the others were imported alongside
+fn finish_module_import(app_handle: &tauri::AppHandle, app_state: &Mutex<AppState>, package: &BiblioJsonPackageHandle, result: Result<Vec<Module>, String>, warnings: Vec<String>) -> ModuleChangeResult
+{
+    let result = result.and_then(|modules| {
+        let added = modules.iter()
+            .map(|m| (m.id().clone(), ModuleTypeJson::from(m.get_info().module_type)))
+            .collect_vec();
+
+        package.add_modules(modules)?;
+        Ok(added)
+    });
+
+    let result = result.and_then(|added| {
+        let shown = added.iter()
+            .filter(|(_, t)| !matches!(t, ModuleTypeJson::Bible | ModuleTypeJson::Readings | ModuleTypeJson::StrongsLinks))
+            .map(|(id, _)| id.clone())
+            .collect_vec();
+
+        if !shown.is_empty()
+        {
+            let mut state = app_state.lock().unwrap();
+            let mut settings = state.bible_display_settings.clone();
+            settings.shown_modules.extend(shown);
+            set_display_settings(app_handle, &mut state, settings);
+        }
+
+        let added = added.into_iter().map(|(id, _)| id).collect_vec();
+        let first = added.first().cloned().ok_or("No modules were imported".to_string())?;
+
+        app_handle.emit(MODULES_CHANGED_EVENT_NAME, ModulesChangedEvent {
+            added,
+            removed: vec![],
+        }).unwrap();
+
+        Ok(first)
+    });
+
+    match result
+    {
+        Ok(module) => ModuleChangeResult::Success { module, warnings },
+        Err(message) => ModuleChangeResult::Error { message },
+    }
+}
+
+fn show_module(app_handle: &tauri::AppHandle, app_state: &Mutex<AppState>, id: &ModuleId)
+{
+    let mut state = app_state.lock().unwrap();
+    let mut settings = state.bible_display_settings.clone();
+    settings.shown_modules.insert(id.clone());
+    set_display_settings(app_handle, &mut state, settings);
+}
+
+/// Swaps in the new display settings, letting the frontend know and recording the change so that it can be undone
+fn set_display_settings(app_handle: &tauri::AppHandle, state: &mut AppState, new: BibleDisplaySettings)
+{
+    let old = std::mem::replace(&mut state.bible_display_settings, new);
+    app_handle.emit(BIBLE_DISPLAY_SETTINGS_CHANGED_EVENT_NAME, BibleDisplaySettingsChangedEvent {
+        old: old.clone(),
+        new: state.bible_display_settings.clone(),
+    }).unwrap();
+
+    AppStateChange::BibleDisplaySettings {
+        old,
+        new: state.bible_display_settings.clone(),
+    }.record(app_handle);
+}
+
+fn finish_notebook_edit(app_handle: &tauri::AppHandle, result: Result<(ModuleId, Option<u32>), String>, deleted: bool) -> NotebookEditResult
+{
+    if let Ok((module, _)) = &result
+    {
+        app_handle.emit(NOTEBOOK_CHANGED_EVENT_NAME, NotebookChangedEvent {
+            module: module.clone(),
+            deleted,
+        }).unwrap();
+    }
+
+    result.into()
 }
\ No newline at end of file
@@ -0,0 +1,282 @@
+use std::{collections::HashMap, num::NonZeroU32, sync::{Arc, Mutex}};
+
+use biblio_json::{Package, core::{OsisBook, StrongsNumber, VerseId}, modules::{Module, ModuleId}};
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+
+use crate::{bible::indexing::PackageIndex, repr::{StrongsNumberJson, VerseIdJson}};
+
+/// Every occurrence of a strongs number in a bible, with the verses paged and the counts over the whole bible
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct StrongsConcordance
+{
+    pub strongs: StrongsNumberJson,
+    pub bible: ModuleId,
+    /// Number of times the strongs number is translated, which may be more than once in a verse
+    pub occurrence_count: u32,
+    pub verse_count: u32,
+    pub books: Vec<ConcordanceBook>,
+    /// The distinct ways the strongs number is translated, most frequent first
+    pub renderings: Vec<ConcordanceRendering>,
+    pub hits: Vec<ConcordanceHit>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct ConcordanceBook
+{
+    pub book: OsisBook,
+    pub occurrence_count: u32,
+    pub verse_count: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct ConcordanceRendering
+{
+    pub text: String,
+    pub count: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct ConcordanceHit
+{
+    pub verse: VerseIdJson,
+    /// 0 based indices of the words that translate the strongs number
+    pub words: Vec<u32>,
+    pub renderings: Vec<String>,
+}
+
+pub struct ConcordanceArgs<'a>
+{
+    pub package: &'a Package,
+    pub index: &'a PackageIndex,
+    pub strongs: &'a StrongsNumber,
+    pub bible: &'a ModuleId,
+    pub page_index: u32,
+    pub page_size: u32,
+    pub cache: &'a ConcordanceCache,
+}
+
+/// Keeps the last concordance that was built with all of its hits, so that changing pages does not walk the whole bible again.
+/// It is tied to the [`PackageIndex::generation`] it was built at, so any change to the modules invalidates it
+#[derive(Debug, Default)]
+pub struct ConcordanceCache(Mutex<Option<(StrongsNumber, ModuleId, u64, Arc<StrongsConcordance>)>>);
+
+impl ConcordanceCache
+{
+    pub fn new() -> Self
+    {
+        Self::default()
+    }
+
+    fn get_or_build(&self, strongs: &StrongsNumber, bible: &ModuleId, generation: u64, build: impl FnOnce() -> Option<StrongsConcordance>) -> Option<Arc<StrongsConcordance>>
+    {
+        let mut cached = self.0.lock().unwrap();
+        if let Some((cached_strongs, cached_bible, cached_generation, concordance)) = cached.as_ref()
+        {
+            if cached_strongs == strongs && cached_bible == bible && *cached_generation == generation
+            {
+                return Some(concordance.clone());
+            }
+        }
+
+        let concordance = Arc::new(build()?);
+        *cached = Some((strongs.clone(), bible.clone(), generation, concordance.clone()));
+        Some(concordance)
+    }
+}
+
+/// Builds a concordance for a strongs number from the bible's strongs links, in canonical order, or takes it from the cache.
+/// Returns `None` if the bible does not exist
+pub fn build_strongs_concordance(args: ConcordanceArgs) -> Option<StrongsConcordance>
+{
+    let ConcordanceArgs { package, index, strongs, bible, page_index, page_size, cache } = args;
+    let concordance = cache.get_or_build(strongs, bible, index.generation, || build_full_concordance(package, index, strongs, bible))?;
+
+    let start = page_size as usize * page_index as usize;
+    Some(StrongsConcordance {
+        strongs: concordance.strongs.clone(),
+        bible: concordance.bible.clone(),
+        occurrence_count: concordance.occurrence_count,
+        verse_count: concordance.verse_count,
+        books: concordance.books.clone(),
+        renderings: concordance.renderings.clone(),
+        hits: concordance.hits.iter()
+            .skip(start)
+            .take(page_size as usize)
+            .cloned()
+            .collect(),
+    })
+}
+
+/// The concordance with every one of its hits
+fn build_full_concordance(package: &Package, index: &PackageIndex, strongs: &StrongsNumber, bible: &ModuleId) -> Option<StrongsConcordance>
+{
+    let module = package.get_mod(bible).and_then(Module::as_bible)?;
+
+    // The word index has the verses of the strongs number, until it is built every verse of the bible is checked
+    let verses = match index.words.as_ref().and_then(|w| w.get_bible(bible))
+    {
+        Some(words) => words.get_strongs_verses(strongs),
+        None => module.source.book_infos.iter()
+            .flat_map(|book| numbered_chapters(&book.chapters).flat_map(move |(chapter, verse_count)| {
+                (1..=verse_count).map(move |verse| VerseId {
+                    book: book.osis_book,
+                    chapter,
+                    verse: NonZeroU32::new(verse).unwrap(),
+                })
+            }))
+            .collect_vec(),
+    };
+
+    let hits = verses.into_iter()
+        .filter_map(|id| find_verse_hit(package, index, strongs, bible, id).map(|hit| (id, hit)))
+        .collect_vec();
+
+    let books = count_books(&hits);
+    Some(StrongsConcordance {
+        strongs: strongs.into(),
+        bible: bible.clone(),
+        occurrence_count: books.iter().map(|b| b.occurrence_count).sum(),
+        verse_count: hits.len() as u32,
+        books,
+        renderings: count_renderings(&hits),
+        hits: hits.into_iter().map(|(_, hit)| hit).collect(),
+    })
+}
+
+/// The verse and occurrence counts of each book, from hits in canonical order
+fn count_books(hits: &[(VerseId, ConcordanceHit)]) -> Vec<ConcordanceBook>
+{
+    hits.iter()
+        .chunk_by(|(id, _)| id.book)
+        .into_iter()
+        .map(|(book, verses)| {
+            let (verse_count, occurrence_count) = verses.fold((0, 0), |(v, o), (_, hit)| (v + 1, o + hit.renderings.len() as u32));
+            ConcordanceBook { book, occurrence_count, verse_count }
+        })
+        .collect_vec()
+}
+
+fn count_renderings(hits: &[(VerseId, ConcordanceHit)]) -> Vec<ConcordanceRendering>
+{
+    hits.iter()
+        .flat_map(|(_, hit)| &hit.renderings)
+        .counts()
+        .into_iter()
+        .map(|(text, count)| ConcordanceRendering { text: text.clone(), count: count as u32 })
+        .sorted_by(|a, b| b.count.cmp(&a.count).then_with(|| a.text.cmp(&b.text)))
+        .collect_vec()
+}
+
+fn numbered_chapters(chapters: &[u32]) -> impl Iterator<Item = (NonZeroU32, u32)> + '_
+{
+    chapters.iter()
+        .enumerate()
+        .map(|(i, count)| (NonZeroU32::new(i as u32 + 1).unwrap(), *count))
+}
+
+/// Finds the words of the verse linked to the strongs number, keeping the words of a linked word range together
+fn find_verse_hit(package: &Package, index: &PackageIndex, strongs: &StrongsNumber, bible: &ModuleId, verse: VerseId) -> Option<ConcordanceHit>
+{
+    let links = index.strongs.get_links(bible, verse)?;
+    let words = &package.get_mod(bible)?.as_bible()?.source.verses.get(&verse)?.words;
+
+    let mut linked: HashMap<usize, Vec<usize>> = HashMap::new();
+    for i in 0..words.len()
+    {
+        let Some(link) = links.get_word_link(i) else { continue; };
+        if links.get_word_strongs(i).is_some_and(|s| s.contains(strongs))
+        {
+            linked.entry(link).or_default().push(i);
+        }
+    }
+
+    if linked.is_empty()
+    {
+        return None;
+    }
+
+    let groups = linked.into_values().sorted_by_key(|w| w[0]).collect_vec();
+    let renderings = groups.iter()
+        .map(|group| group.iter()
+            .map(|w| words[*w].text.chars().filter(|c| c.is_alphanumeric() || *c == '\'').flat_map(char::to_lowercase).collect::<String>())
+            .join(" "))
+        .collect();
+
+    Some(ConcordanceHit {
+        verse: verse.into(),
+        words: groups.into_iter().flatten().map(|w| w as u32).collect(),
+        renderings,
+    })
+}
+
+#[cfg(test)]
+mod tests 
+{
+    use std::str::FromStr;
+
+    use crate::bible::importing::parse_osis_book;
+
+    use super::*;
+
+    fn create_hit(book: OsisBook, chapter: u32, verse: u32, renderings: &[&str]) -> (VerseId, ConcordanceHit)
+    {
+        let id = VerseId::new(book, NonZeroU32::new(chapter).unwrap(), NonZeroU32::new(verse).unwrap());
+        let hit = ConcordanceHit {
+            verse: id.into(),
+            words: (0..renderings.len() as u32).collect(),
+            renderings: renderings.iter().map(|r| r.to_string()).collect(),
+        };
+
+        (id, hit)
+    }
+
+    #[test]
+    fn test_concordance_counts()
+    {
+        let exod = parse_osis_book("Exod").unwrap();
+        let hits = vec![
+            create_hit(OsisBook::Gen, 1, 1, &["god"]),
+            create_hit(OsisBook::Gen, 1, 2, &["god", "gods"]),
+            create_hit(exod, 3, 4, &["god"]),
+        ];
+
+        let books = count_books(&hits).into_iter()
+            .map(|b| (b.book, b.verse_count, b.occurrence_count))
+            .collect_vec();
+
+        assert_eq!(books, vec![(OsisBook::Gen, 2, 3), (exod, 1, 1)]);
+
+        let renderings = count_renderings(&hits).into_iter()
+            .map(|r| (r.text, r.count))
+            .collect_vec();
+
+        assert_eq!(renderings, vec![("god".to_string(), 3), ("gods".to_string(), 1)]);
+    }
+
+    #[test]
+    fn test_cache_generation()
+    {
+        let cache = ConcordanceCache::new();
+        let strongs = StrongsNumber::from_str("H430").unwrap();
+        let bible = ModuleId::new("kjv_eng".into());
+        let strongs_ref = &strongs;
+        let build = |count| move || Some(StrongsConcordance {
+            strongs: strongs_ref.into(),
+            bible: ModuleId::new("kjv_eng".into()),
+            occurrence_count: count,
+            verse_count: count,
+            books: vec![],
+            renderings: vec![],
+            hits: vec![],
+        });
+
+        assert_eq!(cache.get_or_build(&strongs, &bible, 0, build(1)).unwrap().occurrence_count, 1);
+        assert_eq!(cache.get_or_build(&strongs, &bible, 0, build(2)).unwrap().occurrence_count, 1);
+        assert_eq!(cache.get_or_build(&strongs, &bible, 1, build(3)).unwrap().occurrence_count, 3);
+    }
+}
@@ -1,18 +1,18 @@
 use std::{collections::{HashMap, HashSet}, num::NonZeroU32};
 
-use biblio_json::{Package, core::{Atom, ChapterId, OsisBook, RefId, RefIdInner, VerseId, WordRange}, modules::{Module, ModuleEntry, ModuleId, ModuleInfo, notebook::NotebookEntry, xrefs::XRefEntry}};
+use biblio_json::{Package, core::{Atom, ChapterId, OsisBook, RefId, RefIdInner, VerseId}, modules::{Module, ModuleEntry, ModuleId, ModuleInfo, notebook::NotebookEntry, xrefs::XRefEntry}};
 use itertools::Itertools;
 
-use crate::repr::ModuleEntryJson;
+use crate::{bible::{indexing::PackageIndex, versification::Versification}, repr::ModuleEntryJson};
 
 pub trait PackageEx
 {
     fn convert_to_json_entries<'a>(&'a self, fetched: Vec<(ModuleEntry<'a>, ModuleInfo)>, bible: &ModuleId) -> Vec<ModuleEntryJson>;
-    fn fetch_word_entries(&self, verse: VerseId, word: NonZeroU32, bible: &ModuleId, shown_modules: &HashSet<ModuleId>) -> Vec<ModuleEntryJson>;
-    fn fetch_verse_entries(&self, verse: VerseId, bible: &ModuleId, shown_modules: &HashSet<ModuleId>) -> Vec<ModuleEntryJson>;
-    fn fetch_chapter_entries(&self, chapter: ChapterId, bible: &ModuleId, shown_modules: &HashSet<ModuleId>) -> Vec<ModuleEntryJson>;
-    fn fetch_book_entries(&self, book: OsisBook, bible: &ModuleId, shown_modules: &HashSet<ModuleId>) -> Vec<ModuleEntryJson>;
-    fn fetch_words_have_entries(&self, words: &[(VerseId, NonZeroU32)], bible: &ModuleId, shown_modules: &HashSet<ModuleId>) -> Vec<(VerseId, NonZeroU32)>;
+    fn fetch_word_entries(&self, index: &PackageIndex, verse: VerseId, word: NonZeroU32, bible: &ModuleId, shown_modules: &HashSet<ModuleId>) -> Vec<ModuleEntryJson>;
+    fn fetch_verse_entries(&self, index: &PackageIndex, verse: VerseId, bible: &ModuleId, shown_modules: &HashSet<ModuleId>) -> Vec<ModuleEntryJson>;
+    fn fetch_chapter_entries(&self, index: &PackageIndex, chapter: ChapterId, bible: &ModuleId, shown_modules: &HashSet<ModuleId>) -> Vec<ModuleEntryJson>;
+    fn fetch_book_entries(&self, index: &PackageIndex, book: OsisBook, bible: &ModuleId, shown_modules: &HashSet<ModuleId>) -> Vec<ModuleEntryJson>;
+    fn fetch_words_have_entries(&self, index: &PackageIndex, words: &[(VerseId, NonZeroU32)], bible: &ModuleId, shown_modules: &HashSet<ModuleId>) -> Vec<(VerseId, NonZeroU32)>;
 }
 
 impl PackageEx for Package
@@ -44,7 +44,7 @@ impl PackageEx for Package
         }).collect_vec()
     }
     
-    fn fetch_word_entries(&self, verse: VerseId, word: NonZeroU32, bible: &ModuleId, shown_modules: &HashSet<ModuleId>) -> Vec<ModuleEntryJson>
+    fn fetch_word_entries(&self, index: &PackageIndex, verse: VerseId, word: NonZeroU32, bible: &ModuleId, shown_modules: &HashSet<ModuleId>) -> Vec<ModuleEntryJson>
     {
         let binding = self.get_mod(bible).as_ref().unwrap()
             .as_bible();
@@ -55,10 +55,13 @@ impl PackageEx for Package
             .words[word.get() as usize - 1].clone();
 
         let strongs_defs = self.modules.values().filter(|m| shown_modules.contains(m.id())).filter_map(Module::as_strongs_defs).collect_vec();
+        let versification = index.get_versification(bible);
+        let bible_verse = verse;
 
         // Need to include strongs links here as a default, as otherwise it will not render
         let entries = self.modules.values().filter(|m| shown_modules.contains(m.id()) || m.is_strongs_links()).map(|module| {
             let info = module.get_info();
+            let verse = versification.map_verse(bible_verse, get_entry_module_versification(index, module));
             match module
             {
                 Module::Dictionary(dictionary) => {
@@ -66,7 +69,7 @@ impl PackageEx for Package
                         .map(|e| vec![(ModuleEntry::Dictionary(e), info)])
                         .unwrap_or_default()
                 },
-                Module::XRef(xrefs) => xrefs.entries.iter().filter(|e| {
+                Module::XRef(xrefs) => lookup_entries(&xrefs.entries, index.refs.get_word_entries(module.id(), verse)).filter(|e| {
                     match e 
                     {
                         XRefEntry::Directed { source, .. } => is_word_ref_id(source) && source.has_verse_word(verse, word),
@@ -79,24 +82,20 @@ impl PackageEx for Package
                     .collect_vec(),
                 Module::StrongsLinks(links) => {
                     if links.config.bible != *bible { return vec![] }
-                    let Some(links) = links.get_links(&verse) else { return vec![] };
-                    let Some(strongs_word) = links.words.iter().find(|w| match w.range {
-                        WordRange::Single(s) => s == word,
-                        WordRange::Range(s, e) => s <= word && e >= word,
-                    }) else { return vec![] };
+                    let Some(strongs) = index.strongs.get_word_strongs(bible, bible_verse, word) else { return vec![] };
                     
-                    strongs_word.strongs.iter().map(|s| {
+                    strongs.iter().map(|s| {
                         strongs_defs.iter().filter_map(|defs| {
                             defs.get_def(s).map(|d| (d, Module::StrongsDefs(defs.clone()).get_info()))
                         })
                     }).flatten().map(|(d, id)| (ModuleEntry::StrongsDef(d), id)).collect_vec()
                 },
-                Module::Commentary(commentary) => commentary.entries.iter().filter(|e| {
+                Module::Commentary(commentary) => lookup_entries(&commentary.entries, index.refs.get_word_entries(module.id(), verse)).filter(|e| {
                     e.references.iter().any(|v| is_word_ref_id(v) && v.has_verse_word(verse, word))
                 })
                     .map(|e| (ModuleEntry::Commentary(e), info.clone()))
                     .collect_vec(),
-                Module::Notebook(notebook) => notebook.entries.iter().filter(|e| {
+                Module::Notebook(notebook) => lookup_entries(&notebook.entries, index.refs.get_word_entries(module.id(), verse)).filter(|e| {
                     match e 
                     {
                         NotebookEntry::Highlight { references, .. } => {
@@ -118,14 +117,19 @@ impl PackageEx for Package
         self.convert_to_json_entries(entries, bible)
     }
     
-    fn fetch_verse_entries(&self, verse: VerseId, bible: &ModuleId, shown_modules: &HashSet<ModuleId>) -> Vec<ModuleEntryJson> 
+    fn fetch_verse_entries(&self, index: &PackageIndex, verse: VerseId, bible: &ModuleId, shown_modules: &HashSet<ModuleId>) -> Vec<ModuleEntryJson> 
     {
+        let versification = index.get_versification(bible);
+
         // Need to include strongs links here as a default, as otherwise it will not render
         let entries = self.modules.values().filter(|m| shown_modules.contains(m.id()) || m.is_strongs_links()).map(|module| {
             let info = module.get_info();
+
+            let verse = versification.map_verse(verse, get_entry_module_versification(index, module));
+
             match module 
             {
-                Module::XRef(xrefs) => xrefs.entries.iter().filter(|e| {
+                Module::XRef(xrefs) => lookup_entries(&xrefs.entries, index.refs.get_verse_entries(module.id(), verse)).filter(|e| {
                     match e 
                     {
                         XRefEntry::Directed { source, .. } => source.is_verse() && source.has_verse(verse),
@@ -136,12 +140,12 @@ impl PackageEx for Package
                 })
                     .map(|e| (ModuleEntry::XRef(e), info.clone()))
                     .collect_vec(),
-                Module::Commentary(commentary) => commentary.entries.iter().filter(|e| {
+                Module::Commentary(commentary) => lookup_entries(&commentary.entries, index.refs.get_verse_entries(module.id(), verse)).filter(|e| {
                     e.references.iter().any(|v| v.is_verse() && v.has_verse(verse))
                 })
                     .map(|e| (ModuleEntry::Commentary(e), info.clone()))
                     .collect_vec(),
-                Module::Notebook(notebook) => notebook.entries.iter().filter(|e| {
+                Module::Notebook(notebook) => lookup_entries(&notebook.entries, index.refs.get_verse_entries(module.id(), verse)).filter(|e| {
                     match e 
                     {
                         NotebookEntry::Highlight { references, .. } => {
@@ -163,14 +167,19 @@ impl PackageEx for Package
         self.convert_to_json_entries(entries, bible)
     }
     
-    fn fetch_chapter_entries(&self, chapter: ChapterId, bible: &ModuleId, shown_modules: &HashSet<ModuleId>) -> Vec<ModuleEntryJson> 
+    fn fetch_chapter_entries(&self, index: &PackageIndex, chapter: ChapterId, bible: &ModuleId, shown_modules: &HashSet<ModuleId>) -> Vec<ModuleEntryJson> 
     {
+        let versification = index.get_versification(bible);
+        let verse_count = get_chapter_verse_count(self, bible, chapter);
+        let bible_chapter = chapter;
+
         // Need to include strongs links here as a default, as otherwise it will not render
         let entries = self.modules.values().filter(|m| shown_modules.contains(m.id()) || m.is_strongs_links()).map(|module| {
             let info = module.get_info();
+            let chapter = versification.map_chapter(bible_chapter, verse_count, get_entry_module_versification(index, module));
             match module 
             {
-                Module::XRef(xrefs) => xrefs.entries.iter().filter(|e| {
+                Module::XRef(xrefs) => lookup_entries(&xrefs.entries, index.refs.get_chapter_entries(module.id(), chapter)).filter(|e| {
                     match e 
                     {
                         XRefEntry::Directed { source, .. } => source.is_chapter() && source.has_chapter(chapter),
@@ -181,12 +190,12 @@ impl PackageEx for Package
                 })
                 .map(|e| (ModuleEntry::XRef(e), info.clone()))
                 .collect_vec(),
-                Module::Commentary(commentary) => commentary.entries.iter().filter(|e| {
+                Module::Commentary(commentary) => lookup_entries(&commentary.entries, index.refs.get_chapter_entries(module.id(), chapter)).filter(|e| {
                     e.references.iter().any(|r| r.is_chapter() && r.has_chapter(chapter))
                 })
                 .map(|e| (ModuleEntry::Commentary(e), info.clone()))
                 .collect_vec(),
-                Module::Notebook(notebook) => notebook.entries.iter().filter(|e| {
+                Module::Notebook(notebook) => lookup_entries(&notebook.entries, index.refs.get_chapter_entries(module.id(), chapter)).filter(|e| {
                     match e 
                     {
                         NotebookEntry::Highlight { .. } => false,
@@ -205,14 +214,14 @@ impl PackageEx for Package
         self.convert_to_json_entries(entries, bible)
     }
     
-    fn fetch_book_entries(&self, book: OsisBook, bible: &ModuleId, shown_modules: &HashSet<ModuleId>) -> Vec<ModuleEntryJson> 
+    fn fetch_book_entries(&self, index: &PackageIndex, book: OsisBook, bible: &ModuleId, shown_modules: &HashSet<ModuleId>) -> Vec<ModuleEntryJson> 
     {
         // Need to include strongs links here as a default, as otherwise it will not render
         let entries = self.modules.values().filter(|m| shown_modules.contains(m.id()) || m.is_strongs_links()).map(|module| {
             let info = module.get_info();
             match module 
             {
-                Module::XRef(xrefs) => xrefs.entries.iter().filter(|e| {
+                Module::XRef(xrefs) => lookup_entries(&xrefs.entries, index.refs.get_book_entries(module.id(), book)).filter(|e| {
                     match e 
                     {
                         XRefEntry::Directed { source, .. } => source.is_book() && source.has_book(book),
@@ -223,12 +232,12 @@ impl PackageEx for Package
                 })
                 .map(|e| (ModuleEntry::XRef(e), info.clone()))
                 .collect_vec(),
-                Module::Commentary(commentary) => commentary.entries.iter().filter(|e| {
+                Module::Commentary(commentary) => lookup_entries(&commentary.entries, index.refs.get_book_entries(module.id(), book)).filter(|e| {
                     e.references.iter().any(|r| r.is_book() && r.has_book(book))
                 })
                 .map(|e| (ModuleEntry::Commentary(e), info.clone()))
                 .collect_vec(),
-                Module::Notebook(notebook) => notebook.entries.iter().filter(|e| {
+                Module::Notebook(notebook) => lookup_entries(&notebook.entries, index.refs.get_book_entries(module.id(), book)).filter(|e| {
                     match e 
                     {
                         NotebookEntry::Highlight { .. } => false,
@@ -247,7 +256,7 @@ impl PackageEx for Package
         self.convert_to_json_entries(entries, bible)
     }
     
-    fn fetch_words_have_entries(&self, words: &[(VerseId, NonZeroU32)], bible: &ModuleId, shown_modules: &HashSet<ModuleId>) -> Vec<(VerseId, NonZeroU32)> 
+    fn fetch_words_have_entries(&self, index: &PackageIndex, words: &[(VerseId, NonZeroU32)], bible: &ModuleId, shown_modules: &HashSet<ModuleId>) -> Vec<(VerseId, NonZeroU32)> 
     {
         let binding = self.get_mod(bible).as_ref().unwrap()
             .as_bible();
@@ -264,6 +273,7 @@ impl PackageEx for Package
 
         
         let has_defs = self.modules.values().any(|m| m.is_strongs_defs() && shown_modules.contains(m.id()));
+        let versification = index.get_versification(bible);
 
         // Need to include strongs links here as a default, as otherwise it will not render
         for module in self.modules.values().filter(|m| shown_modules.contains(m.id()) || m.is_strongs_links())
@@ -290,21 +300,25 @@ impl PackageEx for Package
                         no_entry_words.remove(&f);
                     }
                 },
-                Module::XRef(xrefs) => xrefs.entries.iter().for_each(|e| {
+                Module::XRef(xrefs) => {
+                    let module_versification = get_entry_module_versification(index, module);
                     let mut found_words = vec![];
-                    for &(verse, word) in &no_entry_words
+                    for &(bible_verse, word) in &no_entry_words
                     {
-                        let found = match e 
-                        {
-                            XRefEntry::Directed { source, .. } => is_word_ref_id(source) && source.has_verse_word(verse, word),
-                            XRefEntry::Mutual { refs, .. } => {
-                                refs.iter().any(|r| is_word_ref_id(r) && r.has_verse_word(verse, word))
-                            },
-                        };
+                        let verse = versification.map_verse(bible_verse, module_versification);
+                        let found = lookup_entries(&xrefs.entries, index.refs.get_word_entries(module.id(), verse)).any(|e| {
+                            match e 
+                            {
+                                XRefEntry::Directed { source, .. } => is_word_ref_id(source) && source.has_verse_word(verse, word),
+                                XRefEntry::Mutual { refs, .. } => {
+                                    refs.iter().any(|r| is_word_ref_id(r) && r.has_verse_word(verse, word))
+                                },
+                            }
+                        });
 
                         if found 
                         {
-                            found_words.push((verse, word));
+                            found_words.push((bible_verse, word));
                         }
                     }
 
@@ -313,18 +327,14 @@ impl PackageEx for Package
                         word_datas.remove(&f);
                         no_entry_words.remove(&f);
                     }
-                }),
+                },
                 Module::StrongsLinks(links) => {
                     if links.config.bible != *bible || !has_defs { continue; }
 
                     let mut found_words = vec![];
                     for &(verse, word) in &no_entry_words
                     {
-                        let Some(links) = links.get_links(&verse) else { continue; };
-                        let found = links.words.iter().find(|w| match w.range {
-                            WordRange::Single(s) => s == word,
-                            WordRange::Range(s, e) => s <= word && e >= word,
-                        }).is_some();
+                        let found = index.strongs.get_word_strongs(bible, verse, word).is_some();
 
                         if found
                         {
@@ -338,14 +348,19 @@ impl PackageEx for Package
                         no_entry_words.remove(&f);
                     }
                 },
-                Module::Commentary(commentary) => commentary.entries.iter().for_each(|e| {
+                Module::Commentary(commentary) => {
+                    let module_versification = get_entry_module_versification(index, module);
                     let mut found_words = vec![];
-                    for &(verse, word) in &no_entry_words
+                    for &(bible_verse, word) in &no_entry_words
                     {
-                        let found = e.references.iter().any(|v| is_word_ref_id(v) && v.has_verse_word(verse, word));
+                        let verse = versification.map_verse(bible_verse, module_versification);
+                        let found = lookup_entries(&commentary.entries, index.refs.get_word_entries(module.id(), verse)).any(|e| {
+                            e.references.iter().any(|v| is_word_ref_id(v) && v.has_verse_word(verse, word))
+                        });
+
                         if found
                         {
-                            found_words.push((verse, word));
+                            found_words.push((bible_verse, word));
                         }
                     }
 
@@ -354,25 +369,29 @@ impl PackageEx for Package
                         word_datas.remove(&f);
                         no_entry_words.remove(&f);
                     }
-                }),
-                Module::Notebook(notebook) => notebook.entries.iter().for_each(|e| {
+                },
+                Module::Notebook(notebook) => {
+                    let module_versification = get_entry_module_versification(index, module);
                     let mut found_words = vec![];
-                    for &(verse, word) in &no_entry_words
+                    for &(bible_verse, word) in &no_entry_words
                     {
-                        let found = match e {
-                            NotebookEntry::Highlight { references, .. } => {
-                                references.iter()
-                                    .any(|v| is_word_ref_id(v) && v.has_verse_word(verse, word))
-                            },
-                            NotebookEntry::Note { references, .. } => {
-                                references.iter()
-                                    .any(|v| is_word_ref_id(v) && v.has_verse_word(verse, word))
-                            },
-                        };
+                        let verse = versification.map_verse(bible_verse, module_versification);
+                        let found = lookup_entries(&notebook.entries, index.refs.get_word_entries(module.id(), verse)).any(|e| {
+                            match e {
+                                NotebookEntry::Highlight { references, .. } => {
+                                    references.iter()
+                                        .any(|v| is_word_ref_id(v) && v.has_verse_word(verse, word))
+                                },
+                                NotebookEntry::Note { references, .. } => {
+                                    references.iter()
+                                        .any(|v| is_word_ref_id(v) && v.has_verse_word(verse, word))
+                                },
+                            }
+                        });
 
                         if found
                         {
-                            found_words.push((verse, word));
+                            found_words.push((bible_verse, word));
                         }
                     }
 
@@ -381,7 +400,7 @@ impl PackageEx for Package
                         word_datas.remove(&f);
                         no_entry_words.remove(&f);
                     }
-                }),
+                },
                 _ => {}
             }
         };
@@ -390,6 +409,42 @@ impl PackageEx for Package
     }
 }
 
+/// Maps indices from the [`PackageIndex`] back to the entries of a module, skipping any that the module no longer has
+fn lookup_entries<'a, 'b, T>(entries: &'a [T], indices: &'b [usize]) -> impl Iterator<Item = &'a T> + 'b
+    where 'a : 'b
+{
+    indices.iter().filter_map(move |i| entries.get(*i))
+}
+
+/// The numbering that an entry module references verses by, its own bible's, or the KJV's if it does not have one
+fn get_entry_module_versification(index: &PackageIndex, module: &Module) -> Versification
+{
+    get_entry_module_bible(module).map_or(Versification::Kjv, |b| index.get_versification(b))
+}
+
+/// The number of verses in a chapter of the bible, or 0 if it does not have the chapter
+fn get_chapter_verse_count(package: &Package, bible: &ModuleId, chapter: ChapterId) -> u32
+{
+    package.get_mod(bible)
+        .and_then(Module::as_bible)
+        .and_then(|b| b.source.book_infos.iter().find(|i| i.osis_book == chapter.book))
+        .and_then(|i| i.chapters.get(chapter.chapter.get() as usize - 1))
+        .copied()
+        .unwrap_or_default()
+}
+
+/// The bible whose numbering an entry module's references use
+pub fn get_entry_module_bible(module: &Module) -> Option<&ModuleId>
+{
+    match module
+    {
+        Module::XRef(xrefs) => xrefs.config.bible.as_ref(),
+        Module::Commentary(commentary) => commentary.config.bible.as_ref(),
+        Module::Notebook(notebook) => notebook.config.bible.as_ref(),
+        _ => None,
+    }
+}
+
 pub fn get_first_verse(id: &RefId) -> (VerseId, Option<&ModuleId>)
 {
     let bible = id.bible.as_ref().clone();
@@ -439,7 +494,7 @@ pub fn get_first_verse(id: &RefId) -> (VerseId, Option<&ModuleId>)
     }
 }
 
-fn is_word_ref_id(r: &RefId) -> bool
+pub fn is_word_ref_id(r: &RefId) -> bool
 {
     match r.id
     {
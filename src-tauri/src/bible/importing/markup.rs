@@ -0,0 +1,930 @@
+use std::collections::HashMap;
+
+use biblio_json::core::{StrongsNumber, VerseId};
+
+use crate::{bible::importing::{ImportedBible, ImportedWord, WordStyle, parse_strongs, split_words}, core::utils::escape_html};
+
+/// Tags that `parse_html_text` understands, which are kept as they are in ThML
+const THML_BLOCK_TAGS: &[&str] = &["p", "h1", "h2", "h3", "h4", "h5", "h6", "ul", "ol", "li"];
+const THML_INLINE_TAGS: &[&str] = &["u", "i", "em", "b", "strong", "s"];
+
+/// Elements whose content is left out of the converted text
+const OSIS_SKIPPED_TAGS: &[&str] = &["note", "rdg", "figure", "header"];
+const THML_SKIPPED_TAGS: &[&str] = &["note", "script", "style"];
+const GBF_SKIPPED_TAGS: &[&str] = &["RF", "RB"];
+
+/// RTF groups whose content is not text, such as the font table of a document
+const RTF_SKIPPED_DESTINATIONS: &[&str] = &["fonttbl", "colortbl", "stylesheet", "info", "pict", "header", "footer"];
+
+/// The markup the entries of an imported module are written in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MarkupKind
+{
+    Osis,
+    Thml,
+    Gbf,
+    Plain,
+}
+
+#[derive(Debug, Clone)]
+enum Token<'a>
+{
+    Tag(Tag<'a>),
+    Text(String),
+}
+
+#[derive(Debug, Clone)]
+struct Tag<'a>
+{
+    name: &'a str,
+    closing: bool,
+    self_closing: bool,
+    attributes: Vec<(&'a str, String)>,
+}
+
+impl<'a> Tag<'a>
+{
+    fn attribute(&self, name: &str) -> Option<&str>
+    {
+        self.attributes.iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// GBF tags open in upper case and close with their second letter in lower case, such as `<FI>` and `<Fi>`
+    fn normalize_gbf(mut self) -> (Self, String)
+    {
+        let mut chars = self.name.chars();
+        let name = match (chars.next(), chars.next(), chars.next())
+        {
+            (Some(a), Some(b), None) if a.is_ascii_uppercase() && b.is_ascii_lowercase() => {
+                self.closing = true;
+                format!("{}{}", a, b.to_ascii_uppercase())
+            },
+            _ => self.name.to_string(),
+        };
+
+        (self, name)
+    }
+}
+
+/// What an open tag turned into, so that its closing tag can undo it
+#[derive(Debug, Clone)]
+enum OpenTag
+{
+    Html
+    {
+        name: String,
+        html: &'static str,
+        block: bool,
+    },
+    Unwrapped
+    {
+        name: String,
+    },
+    Skipped
+    {
+        name: String,
+    },
+}
+
+impl OpenTag
+{
+    fn name(&self) -> &str
+    {
+        match self
+        {
+            Self::Html { name, .. } | Self::Unwrapped { name } | Self::Skipped { name } => name,
+        }
+    }
+}
+
+/// Writes html that `parse_html_text` can read, wrapping loose text and inline tags in paragraphs
+#[derive(Debug, Default)]
+struct HtmlWriter
+{
+    html: String,
+    open: Vec<OpenTag>,
+    block_depth: usize,
+    in_loose_paragraph: bool,
+}
+
+impl HtmlWriter
+{
+    fn is_skipping(&self) -> bool
+    {
+        self.open.iter().any(|t| matches!(t, OpenTag::Skipped { .. }))
+    }
+
+    fn text(&mut self, text: &str)
+    {
+        if self.is_skipping() || (text.trim().is_empty() && !self.in_paragraph())
+        {
+            return;
+        }
+
+        self.ensure_paragraph();
+        self.html.push_str(&escape_html(text));
+    }
+
+    fn in_paragraph(&self) -> bool
+    {
+        self.block_depth > 0 || self.in_loose_paragraph
+    }
+
+    fn ensure_paragraph(&mut self)
+    {
+        if !self.in_paragraph()
+        {
+            self.html.push_str("<p>");
+            self.in_loose_paragraph = true;
+        }
+    }
+
+    fn end_loose_paragraph(&mut self)
+    {
+        if self.in_loose_paragraph
+        {
+            self.html.push_str("</p>");
+            self.in_loose_paragraph = false;
+        }
+    }
+
+    fn open(&mut self, name: &str, html: &'static str, attributes: &str, block: bool)
+    {
+        if !self.is_skipping()
+        {
+            match block
+            {
+                true => self.end_loose_paragraph(),
+                false => self.ensure_paragraph(),
+            }
+
+            self.html.push_str(&format!("<{}{}>", html, attributes));
+            if block
+            {
+                self.block_depth += 1;
+            }
+        }
+
+        let name = name.to_string();
+        self.open.push(match self.is_skipping()
+        {
+            true => OpenTag::Unwrapped { name },
+            false => OpenTag::Html { name, html, block },
+        });
+    }
+
+    fn void(&mut self, html: &'static str, attributes: &str, block: bool)
+    {
+        if self.is_skipping()
+        {
+            return;
+        }
+
+        match block
+        {
+            true => self.end_loose_paragraph(),
+            false => self.ensure_paragraph(),
+        }
+
+        self.html.push_str(&format!("<{}{}>", html, attributes));
+    }
+
+    fn unwrap(&mut self, name: &str)
+    {
+        self.open.push(OpenTag::Unwrapped { name: name.to_string() });
+    }
+
+    fn skip(&mut self, name: &str)
+    {
+        self.open.push(OpenTag::Skipped { name: name.to_string() });
+    }
+
+    /// Closes the most recent tag with the name, along with any left open inside it
+    fn close(&mut self, name: &str)
+    {
+        let Some(index) = self.open.iter().rposition(|t| t.name() == name) else { return; };
+        while self.open.len() > index
+        {
+            if let Some(OpenTag::Html { html, block, .. }) = self.open.pop()
+            {
+                self.html.push_str(&format!("</{}>", html));
+                if block
+                {
+                    self.block_depth -= 1;
+                }
+            }
+        }
+    }
+
+    fn finish(mut self) -> String
+    {
+        if let Some(first) = self.open.first().map(|t| t.name().to_string())
+        {
+            self.close(&first);
+        }
+
+        self.end_loose_paragraph();
+        self.html
+    }
+}
+
+/// Converts the body of a commentary or dictionary entry into html that `HtmlTextJson` can render.
+/// Formatting that has no html equivalent is dropped, keeping its text
+pub fn markup_to_html(markup: &str, kind: MarkupKind) -> String
+{
+    let mut writer = HtmlWriter::default();
+    if kind == MarkupKind::Plain
+    {
+        for line in markup.lines().filter(|l| !l.trim().is_empty())
+        {
+            writer.open("p", "p", "", true);
+            writer.text(line.trim());
+            writer.close("p");
+        }
+
+        return writer.finish();
+    }
+
+    for token in tokenize(markup)
+    {
+        match token
+        {
+            Token::Text(text) => writer.text(&text),
+            Token::Tag(tag) => match kind
+            {
+                MarkupKind::Osis => write_osis_tag(&mut writer, tag),
+                MarkupKind::Thml => write_thml_tag(&mut writer, tag),
+                MarkupKind::Gbf => write_gbf_tag(&mut writer, tag),
+                MarkupKind::Plain => {},
+            },
+        }
+    }
+
+    writer.finish()
+}
+
+fn write_osis_tag(writer: &mut HtmlWriter, tag: Tag)
+{
+    if tag.closing
+    {
+        if tag.name == "l"
+        {
+            writer.void("br", "", false);
+        }
+
+        return writer.close(tag.name);
+    }
+
+    match tag.name
+    {
+        "lb" | "l" if tag.self_closing => writer.void("br", "", false),
+        _ if tag.self_closing => {},
+        "reference" => match tag.attribute("osisRef")
+        {
+            Some(href) => writer.open(tag.name, "a", &format!(" href=\"{}\"", escape_html(href)), false),
+            None => writer.unwrap(tag.name),
+        },
+        "hi" => match tag.attribute("type")
+        {
+            Some("bold") => writer.open(tag.name, "b", "", false),
+            Some("italic") => writer.open(tag.name, "i", "", false),
+            Some("underline") => writer.open(tag.name, "u", "", false),
+            _ => writer.unwrap(tag.name),
+        },
+        "transChange" => writer.open(tag.name, "i", "", false),
+        "p" => writer.open(tag.name, "p", "", true),
+        "title" => writer.open(tag.name, "h3", "", true),
+        "list" => writer.open(tag.name, "ul", "", true),
+        "item" => writer.open(tag.name, "li", "", true),
+        _ if OSIS_SKIPPED_TAGS.contains(&tag.name) => writer.skip(tag.name),
+        _ => writer.unwrap(tag.name),
+    }
+}
+
+fn write_thml_tag(writer: &mut HtmlWriter, tag: Tag)
+{
+    let name = tag.name.to_ascii_lowercase();
+    let name = name.as_str();
+    if tag.closing
+    {
+        return writer.close(name);
+    }
+
+    let html = THML_BLOCK_TAGS.iter().chain(THML_INLINE_TAGS).find(|t| **t == name).copied();
+    match name
+    {
+        "br" => writer.void("br", "", false),
+        "hr" => writer.void("hr", "", true),
+        "img" => {
+            if let Some(src) = tag.attribute("src")
+            {
+                writer.void("img", &format!(" src=\"{}\"", escape_html(src)), false);
+            }
+        },
+        _ if tag.self_closing => {},
+        "a" => match tag.attribute("href")
+        {
+            Some(href) => writer.open(name, "a", &format!(" href=\"{}\"", escape_html(href)), false),
+            None => writer.unwrap(name),
+        },
+        "scripref" => match tag.attribute("osisRef")
+        {
+            Some(href) => writer.open(name, "a", &format!(" href=\"{}\"", escape_html(href)), false),
+            None => writer.unwrap(name),
+        },
+        _ if THML_SKIPPED_TAGS.contains(&name) => writer.skip(name),
+        _ => match html
+        {
+            Some(html) => writer.open(name, html, "", THML_BLOCK_TAGS.contains(&html)),
+            None => writer.unwrap(name),
+        },
+    }
+}
+
+fn write_gbf_tag(writer: &mut HtmlWriter, tag: Tag)
+{
+    let (tag, name) = tag.normalize_gbf();
+    if tag.closing
+    {
+        return writer.close(&name);
+    }
+
+    match name.as_str()
+    {
+        "CM" | "CL" => writer.void("br", "", false),
+        "FB" => writer.open(&name, "b", "", false),
+        "FI" => writer.open(&name, "i", "", false),
+        "FU" => writer.open(&name, "u", "", false),
+        "TS" => writer.open(&name, "h3", "", true),
+        _ if GBF_SKIPPED_TAGS.contains(&name.as_str()) => writer.skip(&name),
+        // Strongs numbers, morphology and other tags that stand on their own
+        _ => {},
+    }
+}
+
+/// Reads the words of a verse out of its markup, keeping red letter text, italics and strongs numbers
+pub fn read_bible_markup(verse: VerseId, markup: &str, kind: MarkupKind, bible: &mut ImportedBible, verse_indices: &mut HashMap<VerseId, usize>)
+{
+    let words = bible.get_verse_words(verse, verse_indices);
+    if kind == MarkupKind::Plain
+    {
+        split_words(markup, WordStyle::default(), &[], None, words);
+        return;
+    }
+
+    let mut reader = BibleMarkupReader::default();
+    for token in tokenize(markup)
+    {
+        match token
+        {
+            Token::Text(text) => reader.read_text(&text, words),
+            Token::Tag(tag) => match kind
+            {
+                MarkupKind::Osis => reader.read_osis_tag(tag),
+                MarkupKind::Thml => reader.read_thml_tag(tag, words),
+                MarkupKind::Gbf => reader.read_gbf_tag(tag, words),
+                MarkupKind::Plain => {},
+            },
+        }
+    }
+}
+
+/// A tag that is open while reading a verse, with the style and strongs numbers from before it was opened
+#[derive(Debug)]
+struct OpenVerseTag
+{
+    name: String,
+    style: WordStyle,
+    strongs: Vec<StrongsNumber>,
+    strongs_group: Option<u32>,
+    skipped: bool,
+}
+
+#[derive(Debug, Default)]
+struct BibleMarkupReader
+{
+    open: Vec<OpenVerseTag>,
+    style: WordStyle,
+    strongs: Vec<StrongsNumber>,
+    strongs_group: Option<u32>,
+    next_strongs_group: u32,
+    red_quote_ids: Vec<String>,
+}
+
+impl BibleMarkupReader
+{
+    fn read_text(&mut self, text: &str, words: &mut Vec<ImportedWord>)
+    {
+        if !self.open.iter().any(|t| t.skipped)
+        {
+            split_words(text, self.style, &self.strongs, self.strongs_group, words);
+        }
+    }
+
+    fn push(&mut self, name: &str, skipped: bool)
+    {
+        self.open.push(OpenVerseTag {
+            name: name.to_string(),
+            style: self.style,
+            strongs: self.strongs.clone(),
+            strongs_group: self.strongs_group,
+            skipped,
+        });
+    }
+
+    /// Closes the most recent tag with the name, along with any left open inside it
+    fn pop(&mut self, name: &str)
+    {
+        let Some(index) = self.open.iter().rposition(|t| t.name == name) else { return; };
+        let tag = self.open.drain(index..).next().unwrap();
+        self.style = tag.style;
+        self.strongs = tag.strongs;
+        self.strongs_group = tag.strongs_group;
+    }
+
+    fn read_osis_tag(&mut self, tag: Tag)
+    {
+        if tag.closing
+        {
+            return self.pop(tag.name);
+        }
+
+        // Red letter quotes that span verses are milestones marked with `sID` and `eID`
+        if tag.name == "q" && tag.self_closing
+        {
+            if let Some(id) = tag.attribute("eID")
+            {
+                self.red_quote_ids.retain(|q| q != id);
+                self.style.red = !self.red_quote_ids.is_empty();
+            }
+            else if let (Some(id), Some("Jesus")) = (tag.attribute("sID"), tag.attribute("who"))
+            {
+                self.red_quote_ids.push(id.to_string());
+                self.style.red = true;
+            }
+
+            return;
+        }
+
+        if tag.self_closing
+        {
+            return;
+        }
+
+        self.push(tag.name, tag.name == "title" || OSIS_SKIPPED_TAGS.contains(&tag.name));
+        match tag.name
+        {
+            "q" if tag.attribute("who") == Some("Jesus") => self.style.red = true,
+            "transChange" => self.style.italics = true,
+            "hi" if tag.attribute("type") == Some("italic") => self.style.italics = true,
+            "w" => {
+                self.strongs = tag.attribute("lemma")
+                    .map(|lemma| lemma.split_whitespace()
+                        .filter_map(|l| l.strip_prefix("strong:"))
+                        .filter_map(parse_strongs)
+                        .collect())
+                    .unwrap_or_default();
+
+                self.strongs_group = Some(self.next_strongs_group);
+                self.next_strongs_group += 1;
+            },
+            _ => {},
+        }
+    }
+
+    /// ThML tags strongs numbers with a `sync` tag after the word
+    fn read_thml_tag(&mut self, tag: Tag, words: &mut [ImportedWord])
+    {
+        let name = tag.name.to_ascii_lowercase();
+        if tag.closing
+        {
+            return self.pop(&name);
+        }
+
+        if name == "sync"
+        {
+            if tag.attribute("type").is_some_and(|t| t.eq_ignore_ascii_case("strongs"))
+            {
+                let strongs = tag.attribute("value").and_then(parse_strongs);
+                if let (Some(strongs), Some(word)) = (strongs, words.last_mut())
+                {
+                    word.strongs.push(strongs);
+                }
+            }
+
+            return;
+        }
+
+        if tag.self_closing
+        {
+            return;
+        }
+
+        self.push(&name, THML_SKIPPED_TAGS.contains(&name.as_str()));
+        match name.as_str()
+        {
+            "i" | "em" => self.style.italics = true,
+            "font" if tag.attribute("color").is_some_and(|c| c.eq_ignore_ascii_case("red")) => self.style.red = true,
+            "span" if tag.attribute("class").is_some_and(|c| c.contains("jesus")) => self.style.red = true,
+            _ => {},
+        }
+    }
+
+    /// GBF tags strongs numbers with a tag after the word, such as `<WH0430>`
+    fn read_gbf_tag(&mut self, tag: Tag, words: &mut [ImportedWord])
+    {
+        let (tag, name) = tag.normalize_gbf();
+        if tag.closing
+        {
+            return self.pop(&name);
+        }
+
+        if let Some(number) = name.strip_prefix('W').filter(|n| n.starts_with(['H', 'G']))
+        {
+            if let (Some(strongs), Some(word)) = (parse_strongs(number), words.last_mut())
+            {
+                word.strongs.push(strongs);
+            }
+
+            return;
+        }
+
+        match name.as_str()
+        {
+            "FR" => {
+                self.push(&name, false);
+                self.style.red = true;
+            },
+            "FI" => {
+                self.push(&name, false);
+                self.style.italics = true;
+            },
+            "TS" => self.push(&name, true),
+            n if GBF_SKIPPED_TAGS.contains(&n) => self.push(&name, true),
+            _ => {},
+        }
+    }
+}
+
+fn tokenize(markup: &str) -> Vec<Token<'_>>
+{
+    let mut tokens = vec![];
+    let mut rest = markup;
+    while !rest.is_empty()
+    {
+        let Some(start) = rest.find('<') else {
+            tokens.push(Token::Text(decode_entities(rest)));
+            break;
+        };
+
+        if start > 0
+        {
+            tokens.push(Token::Text(decode_entities(&rest[..start])));
+        }
+
+        let Some(end) = rest[start..].find('>').map(|e| start + e) else {
+            tokens.push(Token::Text(decode_entities(&rest[start..])));
+            break;
+        };
+
+        if let Some(tag) = parse_tag(&rest[start + 1..end])
+        {
+            tokens.push(Token::Tag(tag));
+        }
+
+        rest = &rest[end + 1..];
+    }
+
+    tokens
+}
+
+/// Parses the inside of a tag, returning `None` for comments, processing instructions and the like
+fn parse_tag(inner: &str) -> Option<Tag<'_>>
+{
+    if inner.starts_with(['!', '?'])
+    {
+        return None;
+    }
+
+    let (closing, inner) = match inner.strip_prefix('/')
+    {
+        Some(inner) => (true, inner),
+        None => (false, inner),
+    };
+
+    let (self_closing, inner) = match inner.trim_end().strip_suffix('/')
+    {
+        Some(inner) => (true, inner),
+        None => (false, inner),
+    };
+
+    let name_end = inner.find(char::is_whitespace).unwrap_or(inner.len());
+    let name = &inner[..name_end];
+    if name.is_empty()
+    {
+        return None;
+    }
+
+    let mut attributes = vec![];
+    let mut rest = inner[name_end..].trim_start();
+    while !rest.is_empty()
+    {
+        let key_end = rest.find(|c: char| c == '=' || c.is_whitespace()).unwrap_or(rest.len());
+        let key = &rest[..key_end];
+        rest = rest[key_end..].trim_start();
+
+        let Some(value) = rest.strip_prefix('=') else {
+            attributes.push((key, String::new()));
+            continue;
+        };
+
+        let value = value.trim_start();
+        let (value, after) = match value.chars().next()
+        {
+            Some(quote @ ('"' | '\'')) => {
+                let end = value[1..].find(quote).map(|e| e + 1).unwrap_or(value.len());
+                (&value[1..end], value.get(end + 1..).unwrap_or_default())
+            },
+            _ => {
+                let end = value.find(char::is_whitespace).unwrap_or(value.len());
+                (&value[..end], &value[end..])
+            },
+        };
+
+        attributes.push((key, decode_entities(value)));
+        rest = after.trim_start();
+    }
+
+    Some(Tag { name, closing, self_closing, attributes })
+}
+
+fn decode_entities(text: &str) -> String
+{
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&')
+    {
+        decoded.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let entity = rest.find(';')
+            .filter(|end| *end <= 10)
+            .and_then(|end| decode_entity(&rest[1..end]).map(|c| (c, end)));
+
+        match entity
+        {
+            Some((c, end)) => {
+                decoded.push(c);
+                rest = &rest[end + 1..];
+            },
+            None => {
+                decoded.push('&');
+                rest = &rest[1..];
+            },
+        }
+    }
+
+    decoded.push_str(rest);
+    decoded
+}
+
+fn decode_entity(entity: &str) -> Option<char>
+{
+    match entity
+    {
+        "amp" => Some('&'),
+        "lt" => Some('<'),
+        "gt" => Some('>'),
+        "quot" => Some('"'),
+        "apos" => Some('\''),
+        "nbsp" => Some(' '),
+        _ => {
+            let number = entity.strip_prefix('#')?;
+            let code = match number.strip_prefix(['x', 'X'])
+            {
+                Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                None => number.parse().ok()?,
+            };
+
+            char::from_u32(code)
+        },
+    }
+}
+
+/// Whether the text contains RTF control words, as the text of e-Sword modules does
+pub fn is_rtf(text: &str) -> bool
+{
+    text.as_bytes().windows(2).any(|w| w[0] == b'\\' && w[1].is_ascii_alphabetic())
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct RtfFormat
+{
+    bold: bool,
+    italics: bool,
+    underline: bool,
+    skipped: bool,
+}
+
+/// Converts RTF, or the fragments of it that e-Sword modules are written in, into html,
+/// keeping its paragraphs, line breaks, bold, italics and underlines
+pub fn rtf_to_html(rtf: &str) -> String
+{
+    let mut writer = RtfHtmlWriter::default();
+    let mut groups: Vec<RtfFormat> = vec![];
+    let mut format = RtfFormat::default();
+    let mut chars = rtf.chars().peekable();
+
+    while let Some(c) = chars.next()
+    {
+        match c
+        {
+            '{' => groups.push(format),
+            '}' => format = groups.pop().unwrap_or_default(),
+            '\r' | '\n' => {},
+            '\\' => {
+                let Some(&next) = chars.peek() else { break; };
+                if !next.is_ascii_alphabetic()
+                {
+                    chars.next();
+                    match next
+                    {
+                        '\'' => {
+                            let hex = chars.by_ref().take(2).collect::<String>();
+                            if let Ok(byte) = u8::from_str_radix(&hex, 16)
+                            {
+                                writer.text(byte as char, format);
+                            }
+                        },
+                        '*' => format.skipped = true,
+                        '~' => writer.text(' ', format),
+                        '\\' | '{' | '}' => writer.text(next, format),
+                        _ => {},
+                    }
+
+                    continue;
+                }
+
+                let mut word = String::new();
+                while let Some(c) = chars.next_if(|c| c.is_ascii_alphabetic())
+                {
+                    word.push(c);
+                }
+
+                let mut parameter = String::new();
+                while let Some(c) = chars.next_if(|c| c.is_ascii_digit() || (*c == '-' && parameter.is_empty()))
+                {
+                    parameter.push(c);
+                }
+
+                chars.next_if_eq(&' ');
+                let parameter = parameter.parse::<i32>().ok();
+                let enabled = parameter != Some(0);
+
+                match word.as_str()
+                {
+                    "par" => writer.paragraph(),
+                    "line" => writer.line_break(),
+                    "tab" => writer.text(' ', format),
+                    "b" => format.bold = enabled,
+                    "i" => format.italics = enabled,
+                    "ul" => format.underline = enabled,
+                    "ulnone" => format.underline = false,
+                    "plain" => format = RtfFormat { skipped: format.skipped, ..Default::default() },
+                    "u" => {
+                        let code = parameter.map(|p| if p < 0 { p + 65536 } else { p }).unwrap_or_default();
+                        if let Some(c) = char::from_u32(code as u32)
+                        {
+                            writer.text(c, format);
+                        }
+
+                        // The character after a unicode escape is a fallback for readers that do not support it
+                        chars.next_if(|c| *c != '\\' && *c != '{' && *c != '}');
+                    },
+                    _ if RTF_SKIPPED_DESTINATIONS.contains(&word.as_str()) => format.skipped = true,
+                    _ => {},
+                }
+            },
+            c => writer.text(c, format),
+        }
+    }
+
+    writer.finish()
+}
+
+#[derive(Debug, Default)]
+struct RtfHtmlWriter
+{
+    paragraphs: Vec<String>,
+    current: String,
+    written_format: RtfFormat,
+}
+
+impl RtfHtmlWriter
+{
+    fn text(&mut self, c: char, format: RtfFormat)
+    {
+        if format.skipped
+        {
+            return;
+        }
+
+        if format != self.written_format
+        {
+            self.close_format();
+            self.current.push_str(match (format.bold, format.italics, format.underline)
+            {
+                (true, true, true) => "<b><i><u>",
+                (true, true, false) => "<b><i>",
+                (true, false, true) => "<b><u>",
+                (true, false, false) => "<b>",
+                (false, true, true) => "<i><u>",
+                (false, true, false) => "<i>",
+                (false, false, true) => "<u>",
+                (false, false, false) => "",
+            });
+
+            self.written_format = format;
+        }
+
+        match c
+        {
+            '&' => self.current.push_str("&amp;"),
+            '<' => self.current.push_str("&lt;"),
+            '>' => self.current.push_str("&gt;"),
+            c => self.current.push(c),
+        }
+    }
+
+    fn close_format(&mut self)
+    {
+        let format = self.written_format;
+        for (open, tag) in [(format.underline, "</u>"), (format.italics, "</i>"), (format.bold, "</b>")]
+        {
+            if open
+            {
+                self.current.push_str(tag);
+            }
+        }
+
+        self.written_format = RtfFormat::default();
+    }
+
+    fn line_break(&mut self)
+    {
+        self.close_format();
+        self.current.push_str("<br>");
+    }
+
+    fn paragraph(&mut self)
+    {
+        self.close_format();
+        let paragraph = std::mem::take(&mut self.current);
+        if !paragraph.trim().is_empty()
+        {
+            self.paragraphs.push(paragraph);
+        }
+    }
+
+    fn finish(mut self) -> String
+    {
+        self.paragraph();
+        self.paragraphs.iter().map(|p| format!("<p>{}</p>", p.trim())).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests 
+{
+    use super::*;
+
+    #[test]
+    fn test_decode_html_entities() 
+    {
+        assert_eq!(decode_entities("a &amp; b &lt;c&gt; &#65;&#x42;"), "a & b <c> AB");
+        assert_eq!(decode_entities("&unknown; & alone &amp"), "&unknown; & alone &amp");
+        assert_eq!(decode_entities("&#xD800;"), "&#xD800;");
+    }
+
+    #[test]
+    fn test_osis_markup_to_html() 
+    {
+        let html = markup_to_html(r#"<hi type="bold">Grace</hi> and <note>skipped</note>peace"#, MarkupKind::Osis);
+        assert_eq!(html, "<p><b>Grace</b> and peace</p>");
+    }
+
+    #[test]
+    fn test_thml_markup_to_html() 
+    {
+        let html = markup_to_html("<p>Text<br/>more</p><scripRef osisRef=\"John.3.16\">John 3:16</scripRef>", MarkupKind::Thml);
+        assert_eq!(html, "<p>Text<br>more</p><p><a href=\"John.3.16\">John 3:16</a></p>");
+    }
+
+    #[test]
+    fn test_plain_markup_to_html() 
+    {
+        assert_eq!(markup_to_html("line one\n\n  line two ", MarkupKind::Plain), "<p>line one</p><p>line two</p>");
+    }
+}
@@ -0,0 +1,742 @@
+pub mod osis;
+pub mod usfm;
+pub mod markup;
+pub mod sword;
+pub mod sqlite;
+
+use std::{collections::HashMap, fs, num::NonZeroU32, path::{Path, PathBuf}, str::FromStr};
+
+use biblio_json::{Package, core::{OsisBook, StrongsNumber, VerseId, WordRange}, modules::{Module, ModuleId, bible::BookInfo}};
+use itertools::Itertools;
+use serde::Serialize;
+
+use crate::{bible::package_loading::{BIBLES_CATEGORY, PACKAGE_FILE}, core::utils::escape_html};
+
+pub const STRONGS_LINKS_CATEGORY: &str = "strongs_links";
+pub const COMMENTARIES_CATEGORY: &str = "commentaries";
+pub const DICTIONARIES_CATEGORY: &str = "dictionaries";
+
+/// The bible whose books and verse counts are used when a format does not provide them, such as book names
+/// for numbered books, or the KJV versification of SWORD modules
+const REFERENCE_BIBLE_ID: &str = "kjv_eng";
+
+/// Languages that biblio_json has built in book aliases for
+const BOOK_ALIAS_LANGUAGES: &[&str] = &["en", "es", "sw"];
+
+/// A bible converted from another format, before it is written out as a biblio_json module
+#[derive(Debug, Clone, Default)]
+pub struct ImportedBible
+{
+    pub id: String,
+    pub name: String,
+    pub short_name: Option<String>,
+    pub description: Option<String>,
+    pub language: Option<String>,
+    pub license: Option<String>,
+    pub data_source: Option<String>,
+    /// The display name of each book, in canonical order
+    pub books: Vec<(OsisBook, String)>,
+    pub verses: Vec<ImportedVerse>,
+    /// Written out as a commentary module on the bible
+    pub footnotes: Vec<ImportedComment>,
+    /// Section headings, written out as a commentary module on the verse each one comes before
+    pub headings: Vec<ImportedComment>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ImportedComment
+{
+    pub verse: VerseId,
+    pub text: String,
+}
+
+/// A module converted from another format that holds a single bible, commentary or dictionary
+#[derive(Debug, Clone)]
+pub enum ImportedModule
+{
+    Bible(ImportedBible),
+    Commentary(ImportedCommentary),
+    Dictionary(ImportedDictionary),
+}
+
+impl ImportedModule
+{
+    pub fn id(&self) -> &str
+    {
+        match self
+        {
+            Self::Bible(bible) => &bible.id,
+            Self::Commentary(commentary) => &commentary.id,
+            Self::Dictionary(dictionary) => &dictionary.id,
+        }
+    }
+
+    /// Writes the module as a biblio_json package in `dir`, see `write_bible_package`
+    pub fn write_package(&self, dir: &Path) -> Result<Vec<(&'static str, PathBuf)>, String>
+    {
+        match self
+        {
+            Self::Bible(bible) => write_bible_package(bible, dir),
+            Self::Commentary(commentary) => write_commentary_package(commentary, dir),
+            Self::Dictionary(dictionary) => write_dictionary_package(dictionary, dir),
+        }
+    }
+}
+
+/// A commentary converted from another format, with its entries already converted to html
+#[derive(Debug, Clone, Default)]
+pub struct ImportedCommentary
+{
+    pub id: String,
+    pub name: String,
+    pub short_name: Option<String>,
+    pub description: Option<String>,
+    pub language: Option<String>,
+    pub license: Option<String>,
+    pub data_source: Option<String>,
+    pub bible: Option<String>,
+    pub entries: Vec<ImportedCommentaryEntry>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ImportedCommentaryEntry
+{
+    pub references: Vec<VerseId>,
+    pub html: String,
+}
+
+/// A dictionary converted from another format, with its entries already converted to html
+#[derive(Debug, Clone, Default)]
+pub struct ImportedDictionary
+{
+    pub id: String,
+    pub name: String,
+    pub short_name: Option<String>,
+    pub description: Option<String>,
+    pub language: Option<String>,
+    pub license: Option<String>,
+    pub data_source: Option<String>,
+    pub entries: Vec<ImportedDictionaryEntry>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ImportedDictionaryEntry
+{
+    pub term: String,
+    pub aliases: Vec<String>,
+    pub html: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct ImportedVerse
+{
+    pub id: VerseId,
+    pub words: Vec<ImportedWord>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ImportedWord
+{
+    pub text: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub begin_punc: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub end_punc: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub red: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub italics: Option<bool>,
+    /// Words sharing a group came from the same tagged phrase, and are linked to their strongs numbers as one range
+    #[serde(skip)]
+    pub strongs_group: Option<u32>,
+    #[serde(skip)]
+    pub strongs: Vec<StrongsNumber>,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct WordStyle
+{
+    pub red: bool,
+    pub italics: bool,
+}
+
+impl ImportedBible
+{
+    /// A bible with an id made from its short name and language, such as `kjv_en`
+    pub fn new(name: String, short_name: String, language: Option<String>) -> Self
+    {
+        Self {
+            id: to_language_module_id(&short_name, language.as_deref()),
+            name,
+            short_name: Some(short_name),
+            language,
+            ..Default::default()
+        }
+    }
+
+    /// The words of the verse, creating it if this is the first time it has been seen
+    pub fn get_verse_words(&mut self, verse: VerseId, verse_indices: &mut HashMap<VerseId, usize>) -> &mut Vec<ImportedWord>
+    {
+        let index = *verse_indices.entry(verse).or_insert_with(|| {
+            self.verses.push(ImportedVerse { id: verse, words: vec![] });
+            self.verses.len() - 1
+        });
+
+        &mut self.verses[index].words
+    }
+
+    pub fn push_book(&mut self, book: OsisBook, name: String)
+    {
+        if !self.books.iter().any(|(b, _)| *b == book)
+        {
+            self.books.push((book, name));
+        }
+    }
+
+    pub fn has_strongs(&self) -> bool
+    {
+        self.verses.iter().any(|v| v.words.iter().any(|w| !w.strongs.is_empty()))
+    }
+
+    pub fn strongs_links_id(&self) -> String
+    {
+        format!("{}_strongs_links", self.id)
+    }
+
+    pub fn display_name(&self) -> &str
+    {
+        self.short_name.as_deref().unwrap_or(&self.name)
+    }
+
+    /// Turns comments on the bible, such as its footnotes, into a commentary on it
+    fn get_commentary(&self, suffix: &str, name: &str, comments: &[ImportedComment]) -> ImportedCommentary
+    {
+        ImportedCommentary {
+            id: format!("{}_{}", self.id, suffix),
+            name: format!("{} {}", self.display_name(), name),
+            short_name: None,
+            description: None,
+            language: self.language.clone(),
+            license: self.license.clone(),
+            data_source: self.data_source.clone(),
+            bible: Some(self.id.clone()),
+            entries: comments.iter().map(|c| ImportedCommentaryEntry {
+                references: vec![c.verse],
+                html: format!("<p>{}</p>", escape_html(&c.text)),
+            }).collect(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct BibleConfigToml<'a>
+{
+    name: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    short_name: Option<&'a str>,
+    id: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    description: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    language: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    license: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    data_source: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    book_aliases: Option<&'a str>,
+    books: toml::Table,
+}
+
+#[derive(Debug, Serialize)]
+struct StrongsLinksConfigToml<'a>
+{
+    name: String,
+    id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    license: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    data_source: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    language: Option<&'a str>,
+    bible: &'a str,
+}
+
+/// The config shared by commentaries and dictionaries, `bible` only being used by commentaries
+#[derive(Debug, Serialize)]
+struct EntryModuleConfigToml<'a>
+{
+    name: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    short_name: Option<&'a str>,
+    id: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    description: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    license: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    data_source: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    language: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    bible: Option<&'a str>,
+}
+
+#[derive(Debug, Serialize)]
+struct DictionaryEntryJson<'a>
+{
+    term: &'a str,
+    definition: &'a str,
+    aliases: &'a [String],
+    id: u32,
+}
+
+#[derive(Debug, Serialize)]
+struct CommentaryEntryJson
+{
+    references: Vec<String>,
+    comment: String,
+    id: u32,
+}
+
+#[derive(Debug, Serialize)]
+struct VerseJson<'a>
+{
+    id: VerseId,
+    words: &'a [ImportedWord],
+}
+
+#[derive(Debug, Serialize)]
+struct StrongsLinkEntryJson
+{
+    verse_id: VerseId,
+    words: Vec<StrongsLinkWordJson>,
+}
+
+#[derive(Debug, Serialize)]
+struct StrongsLinkWordJson
+{
+    range: WordRange,
+    strongs: Vec<StrongsNumber>,
+}
+
+/// Writes the bible as a biblio_json package in `dir`, with a strongs links module if any words were tagged.
+/// Every module is written to its own folder, the paths of which are returned with their category
+pub fn write_bible_package(bible: &ImportedBible, dir: &Path) -> Result<Vec<(&'static str, PathBuf)>, String>
+{
+    if bible.verses.is_empty()
+    {
+        return Err(format!("'{}' does not contain any verses", bible.name));
+    }
+
+    let mut modules = vec![];
+    let bible_dir = dir.join(BIBLES_CATEGORY).join(&bible.id);
+    write_bible_module(bible, &bible_dir)?;
+    modules.push((BIBLES_CATEGORY, bible_dir));
+
+    if bible.has_strongs()
+    {
+        let links_dir = dir.join(STRONGS_LINKS_CATEGORY).join(bible.strongs_links_id());
+        write_strongs_links_module(bible, &links_dir)?;
+        modules.push((STRONGS_LINKS_CATEGORY, links_dir));
+    }
+
+    let commentaries = [("footnotes", "Footnotes", &bible.footnotes), ("headings", "Headings", &bible.headings)];
+    for (suffix, name, comments) in commentaries
+    {
+        if comments.is_empty()
+        {
+            continue;
+        }
+
+        let commentary = bible.get_commentary(suffix, name, comments);
+        let commentary_dir = dir.join(COMMENTARIES_CATEGORY).join(&commentary.id);
+        write_commentary_module(&commentary, &commentary_dir)?;
+        modules.push((COMMENTARIES_CATEGORY, commentary_dir));
+    }
+
+    write_package_file(&bible.name, bible.license.as_deref(), &modules, dir)?;
+    Ok(modules)
+}
+
+/// Writes the commentary as a biblio_json package in `dir`, returning the folder of the module
+pub fn write_commentary_package(commentary: &ImportedCommentary, dir: &Path) -> Result<Vec<(&'static str, PathBuf)>, String>
+{
+    if commentary.entries.is_empty()
+    {
+        return Err(format!("'{}' does not contain any entries", commentary.name));
+    }
+
+    let commentary_dir = dir.join(COMMENTARIES_CATEGORY).join(&commentary.id);
+    write_commentary_module(commentary, &commentary_dir)?;
+
+    let modules = vec![(COMMENTARIES_CATEGORY, commentary_dir)];
+    write_package_file(&commentary.name, commentary.license.as_deref(), &modules, dir)?;
+    Ok(modules)
+}
+
+/// Writes the dictionary as a biblio_json package in `dir`, returning the folder of the module
+pub fn write_dictionary_package(dictionary: &ImportedDictionary, dir: &Path) -> Result<Vec<(&'static str, PathBuf)>, String>
+{
+    if dictionary.entries.is_empty()
+    {
+        return Err(format!("'{}' does not contain any entries", dictionary.name));
+    }
+
+    let dictionary_dir = dir.join(DICTIONARIES_CATEGORY).join(&dictionary.id);
+    write_dictionary_module(dictionary, &dictionary_dir)?;
+
+    let modules = vec![(DICTIONARIES_CATEGORY, dictionary_dir)];
+    write_package_file(&dictionary.name, dictionary.license.as_deref(), &modules, dir)?;
+    Ok(modules)
+}
+
+fn write_package_file(name: &str, license: Option<&str>, modules: &[(&'static str, PathBuf)], dir: &Path) -> Result<(), String>
+{
+    let mut module_paths = toml::Table::new();
+    for (category, _) in modules
+    {
+        module_paths.insert(category.to_string(), toml::Value::String(format!("/{}/**/*", category)));
+    }
+
+    let mut config = toml::Table::new();
+    config.insert("name".into(), toml::Value::String(name.to_string()));
+    config.insert("authors".into(), toml::Value::Array(vec![]));
+    config.insert("license".into(), toml::Value::String(license.unwrap_or("Unknown").to_string()));
+    config.insert("module_paths".into(), toml::Value::Table(module_paths));
+
+    let config = toml::to_string(&config).map_err(|e| e.to_string())?;
+    fs::write(dir.join(PACKAGE_FILE), config).map_err(|e| e.to_string())
+}
+
+fn write_bible_module(bible: &ImportedBible, dir: &Path) -> Result<(), String>
+{
+    fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+
+    let books = bible.books.iter()
+        .map(|(book, name)| Ok((osis_book_name(*book)?, toml::Value::String(name.clone()))))
+        .collect::<Result<toml::Table, String>>()?;
+
+    let config = toml::to_string(&BibleConfigToml {
+        name: &bible.name,
+        short_name: bible.short_name.as_deref(),
+        id: &bible.id,
+        description: bible.description.as_deref(),
+        language: bible.language.as_deref(),
+        license: bible.license.as_deref(),
+        data_source: bible.data_source.as_deref(),
+        book_aliases: bible.language.as_deref().filter(|l| BOOK_ALIAS_LANGUAGES.contains(l)),
+        books,
+    }).map_err(|e| e.to_string())?;
+
+    let lines = bible.verses.iter()
+        .map(|v| serde_json::to_string(&VerseJson { id: v.id, words: &v.words }))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+
+    fs::write(dir.join(format!("{}.toml", bible.id)), config).map_err(|e| e.to_string())?;
+    fs::write(dir.join(format!("{}.jsonl", bible.id)), lines.join("\n")).map_err(|e| e.to_string())
+}
+
+fn write_strongs_links_module(bible: &ImportedBible, dir: &Path) -> Result<(), String>
+{
+    fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+
+    let id = bible.strongs_links_id();
+    let config = toml::to_string(&StrongsLinksConfigToml {
+        name: format!("{} Strongs Links", bible.display_name()),
+        id: id.clone(),
+        license: bible.license.as_deref(),
+        data_source: bible.data_source.as_deref(),
+        language: bible.language.as_deref(),
+        bible: &bible.id,
+    }).map_err(|e| e.to_string())?;
+
+    let lines = bible.verses.iter()
+        .filter_map(get_strongs_links)
+        .map(|e| serde_json::to_string(&e))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+
+    fs::write(dir.join(format!("{}.toml", id)), config).map_err(|e| e.to_string())?;
+    fs::write(dir.join(format!("{}.jsonl", id)), lines.join("\n")).map_err(|e| e.to_string())
+}
+
+fn write_commentary_module(commentary: &ImportedCommentary, dir: &Path) -> Result<(), String>
+{
+    fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+
+    let config = toml::to_string(&EntryModuleConfigToml {
+        name: &commentary.name,
+        short_name: commentary.short_name.as_deref(),
+        id: &commentary.id,
+        description: commentary.description.as_deref(),
+        license: commentary.license.as_deref(),
+        data_source: commentary.data_source.as_deref(),
+        language: commentary.language.as_deref(),
+        bible: commentary.bible.as_deref(),
+    }).map_err(|e| e.to_string())?;
+
+    let lines = commentary.entries.iter().enumerate()
+        .map(|(i, e)| {
+            let entry = CommentaryEntryJson {
+                references: e.references.iter().map(|r| format_osis_verse(*r)).collect::<Result<_, _>>()?,
+                comment: e.html.clone(),
+                id: i as u32,
+            };
+
+            serde_json::to_string(&entry).map_err(|e| e.to_string())
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+
+    fs::write(dir.join(format!("{}.toml", commentary.id)), config).map_err(|e| e.to_string())?;
+    fs::write(dir.join(format!("{}.jsonl", commentary.id)), lines.join("\n")).map_err(|e| e.to_string())
+}
+
+fn write_dictionary_module(dictionary: &ImportedDictionary, dir: &Path) -> Result<(), String>
+{
+    fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+
+    let config = toml::to_string(&EntryModuleConfigToml {
+        name: &dictionary.name,
+        short_name: dictionary.short_name.as_deref(),
+        id: &dictionary.id,
+        description: dictionary.description.as_deref(),
+        license: dictionary.license.as_deref(),
+        data_source: dictionary.data_source.as_deref(),
+        language: dictionary.language.as_deref(),
+        bible: None,
+    }).map_err(|e| e.to_string())?;
+
+    let lines = dictionary.entries.iter().enumerate()
+        .map(|(i, e)| serde_json::to_string(&DictionaryEntryJson {
+            term: &e.term,
+            definition: &e.html,
+            aliases: &e.aliases,
+            id: i as u32,
+        }))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+
+    fs::write(dir.join(format!("{}.toml", dictionary.id)), config).map_err(|e| e.to_string())?;
+    fs::write(dir.join(format!("{}.jsonl", dictionary.id)), lines.join("\n")).map_err(|e| e.to_string())
+}
+
+/// Links each run of words from the same tagged phrase to its strongs numbers
+fn get_strongs_links(verse: &ImportedVerse) -> Option<StrongsLinkEntryJson>
+{
+    let mut words: Vec<StrongsLinkWordJson> = vec![];
+    let mut last_group = None;
+
+    for (i, word) in verse.words.iter().enumerate()
+    {
+        if word.strongs.is_empty()
+        {
+            last_group = None;
+            continue;
+        }
+
+        let index = NonZeroU32::new(i as u32 + 1).unwrap();
+        let extends_last = word.strongs_group.is_some() && word.strongs_group == last_group;
+        match words.last_mut()
+        {
+            Some(last) if extends_last => {
+                let start = match last.range
+                {
+                    WordRange::Single(s) => s,
+                    WordRange::Range(s, _) => s,
+                };
+
+                last.range = WordRange::Range(start, index);
+            },
+            _ => words.push(StrongsLinkWordJson {
+                range: WordRange::Single(index),
+                strongs: word.strongs.clone()
+            }),
+        }
+
+        last_group = word.strongs_group;
+    }
+
+    if words.is_empty()
+    {
+        return None;
+    }
+
+    Some(StrongsLinkEntryJson { verse_id: verse.id, words })
+}
+
+/// The books and verse counts of the loaded KJV, or the bible with the most books if it is not loaded
+pub fn get_reference_books(package: &Package) -> Option<Vec<BookInfo>>
+{
+    let reference_id = ModuleId::new(REFERENCE_BIBLE_ID.into());
+    package.get_mod(&reference_id)
+        .and_then(Module::as_bible)
+        .or_else(|| package.modules.values().filter_map(|m| m.as_bible()).max_by_key(|b| b.source.book_infos.len()))
+        .map(|b| b.source.book_infos.clone())
+}
+
+/// Lowercases the text and replaces anything that is not a letter or number with `_`
+pub fn to_module_id(text: &str) -> String
+{
+    text.to_ascii_lowercase().replace(|c: char| !c.is_ascii_alphanumeric(), "_")
+}
+
+/// A module id made from a short name and language, such as `kjv_en`
+pub fn to_language_module_id(short_name: &str, language: Option<&str>) -> String
+{
+    match language
+    {
+        Some(language) => to_module_id(&format!("{}_{}", short_name, language)),
+        None => to_module_id(short_name),
+    }
+}
+
+pub fn parse_osis_book(book: &str) -> Option<OsisBook>
+{
+    serde_json::from_value(serde_json::Value::String(book.to_string())).ok()
+}
+
+pub fn osis_book_name(book: OsisBook) -> Result<String, String>
+{
+    match serde_json::to_value(book).map_err(|e| e.to_string())?
+    {
+        serde_json::Value::String(s) => Ok(s),
+        v => Err(format!("Unexpected book name {}", v)),
+    }
+}
+
+/// Parses a verse id in the OSIS form `Gen.1.1`
+pub fn parse_osis_verse(id: &str) -> Option<VerseId>
+{
+    let (book, chapter, verse) = id.split('.').collect_tuple()?;
+    let book = parse_osis_book(book)?;
+    let chapter = chapter.parse::<NonZeroU32>().ok()?;
+    let verse = verse.parse::<NonZeroU32>().ok()?;
+    Some(VerseId::new(book, chapter, verse))
+}
+
+fn format_osis_verse(verse: VerseId) -> Result<String, String>
+{
+    Ok(format!("{}.{}.{}", osis_book_name(verse.book)?, verse.chapter, verse.verse))
+}
+
+/// Parses strongs numbers as they are usually tagged, such as `H07225`, `G2316` or `H1254a`
+pub fn parse_strongs(tag: &str) -> Option<StrongsNumber>
+{
+    let tag = tag.trim();
+    let prefix = tag.chars().next()?;
+    let digits = tag[prefix.len_utf8()..].chars().take_while(|c| c.is_ascii_digit()).collect::<String>();
+    let number = digits.parse::<u32>().ok()?;
+    StrongsNumber::from_str(&format!("{}{}", prefix.to_ascii_uppercase(), number)).ok()
+}
+
+/// Splits text into words, separating out the punctuation around each one.
+/// Punctuation on its own is attached to the word before it, or the word after it at the start of the text
+pub fn split_words(text: &str, style: WordStyle, strongs: &[StrongsNumber], strongs_group: Option<u32>, words: &mut Vec<ImportedWord>)
+{
+    let mut leading_punc: Option<String> = None;
+    for token in text.split_whitespace()
+    {
+        let start = token.find(|c: char| c.is_alphanumeric());
+        let Some(start) = start else {
+            match words.last_mut()
+            {
+                Some(last) => last.end_punc.get_or_insert_default().push_str(token),
+                None => leading_punc.get_or_insert_default().push_str(token),
+            }
+
+            continue;
+        };
+
+        let end = token.rfind(|c: char| c.is_alphanumeric()).unwrap();
+        let end = end + token[end..].chars().next().unwrap().len_utf8();
+
+        let mut begin_punc = leading_punc.take();
+        if start > 0
+        {
+            begin_punc.get_or_insert_default().push_str(&token[..start]);
+        }
+
+        words.push(ImportedWord {
+            text: token[start..end].to_string(),
+            begin_punc,
+            end_punc: Some(token[end..].to_string()).filter(|p| !p.is_empty()),
+            red: Some(true).filter(|_| style.red),
+            italics: Some(true).filter(|_| style.italics),
+            strongs_group,
+            strongs: strongs.to_vec(),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests 
+{
+    use super::*;
+    use biblio_json::core::StrongsLang;
+
+    fn split(text: &str) -> Vec<ImportedWord>
+    {
+        let mut words = vec![];
+        split_words(text, WordStyle::default(), &[], None, &mut words);
+        words
+    }
+
+    #[test]
+    fn test_parse_strongs_tags() 
+    {
+        assert_eq!(parse_strongs("H07225"), Some(StrongsNumber { lang: StrongsLang::Hebrew, number: 7225 }));
+        assert_eq!(parse_strongs("g2316"), Some(StrongsNumber { lang: StrongsLang::Greek, number: 2316 }));
+        assert_eq!(parse_strongs("H1254a"), Some(StrongsNumber { lang: StrongsLang::Hebrew, number: 1254 }));
+        assert_eq!(parse_strongs("X123"), None);
+        assert_eq!(parse_strongs("H"), None);
+        assert_eq!(parse_strongs(""), None);
+        assert_eq!(parse_strongs("ה123"), None);
+        assert_eq!(parse_strongs("Ω"), None);
+    }
+
+    #[test]
+    fn test_parse_osis_verse_ids() 
+    {
+        assert_eq!(parse_osis_verse("Gen.1.1"), Some(VerseId::new(OsisBook::Gen, NonZeroU32::new(1).unwrap(), NonZeroU32::new(1).unwrap())));
+        assert_eq!(parse_osis_verse("Gen.1"), None);
+        assert_eq!(parse_osis_verse("Gen.0.1"), None);
+        assert_eq!(parse_osis_verse("Nope.1.1"), None);
+        assert_eq!(parse_osis_verse("Gen.1.1.1"), None);
+    }
+
+    #[test]
+    fn test_split_words_separates_punctuation() 
+    {
+        let words = split(r#""In the beginning, God"#);
+        let texts = words.iter().map(|w| w.text.as_str()).collect_vec();
+
+        assert_eq!(texts, ["In", "the", "beginning", "God"]);
+        assert_eq!(words[0].begin_punc.as_deref(), Some("\""));
+        assert_eq!(words[2].end_punc.as_deref(), Some(","));
+        assert_eq!(words[3].end_punc, None);
+    }
+
+    #[test]
+    fn test_split_words_attaches_lone_punctuation() 
+    {
+        let words = split("— And he said : Amen");
+
+        assert_eq!(words[0].text, "And");
+        assert_eq!(words[0].begin_punc.as_deref(), Some("—"));
+        assert_eq!(words[2].text, "said");
+        assert_eq!(words[2].end_punc.as_deref(), Some(":"));
+    }
+
+    #[test]
+    fn test_split_words_keeps_style_and_strongs() 
+    {
+        let strongs = [StrongsNumber { lang: StrongsLang::Greek, number: 3056 }];
+        let mut words = vec![];
+        split_words("the Word", WordStyle { red: true, italics: false }, &strongs, Some(4), &mut words);
+
+        assert!(words.iter().all(|w| w.red == Some(true) && w.italics.is_none()));
+        assert!(words.iter().all(|w| w.strongs == strongs && w.strongs_group == Some(4)));
+    }
+}
@@ -0,0 +1,305 @@
+use std::collections::{HashMap, HashSet};
+
+use biblio_json::core::{StrongsNumber, VerseId};
+use itertools::Itertools;
+use roxmltree::{Document, Node};
+
+use crate::bible::importing::{ImportedBible, WordStyle, parse_osis_book, parse_osis_verse, parse_strongs, split_words, to_module_id};
+
+/// Elements whose text is not part of the verse text
+const SKIPPED_ELEMENTS: &[&str] = &["note", "title", "header", "rdg", "figure"];
+
+/// Converts an OSIS XML document into a bible, keeping red letter text, supplied words, strongs tags and book names
+pub fn parse_osis(text: &str) -> Result<ImportedBible, String>
+{
+    let document = Document::parse(text).map_err(|e| e.to_string())?;
+    let osis_text = document.descendants()
+        .find(|n| n.has_tag_name("osisText"))
+        .ok_or("The document does not contain an osisText element")?;
+
+    let mut bible = ImportedBible::default();
+    read_header(&osis_text, &mut bible);
+
+    let mut reader = OsisReader::default();
+    reader.read_node(osis_text, &mut bible);
+
+    if bible.verses.is_empty()
+    {
+        return Err("The document does not contain any verses".into());
+    }
+
+    Ok(bible)
+}
+
+fn read_header(osis_text: &Node, bible: &mut ImportedBible)
+{
+    let work_id = osis_text.attribute("osisIDWork").unwrap_or("osis_bible");
+    let work = osis_text.descendants()
+        .filter(|n| n.has_tag_name("work"))
+        .find(|n| n.attribute("osisWork") == Some(work_id))
+        .or_else(|| osis_text.descendants().find(|n| n.has_tag_name("work")));
+
+    let child_text = |name: &str| work
+        .and_then(|w| w.children().find(|c| c.has_tag_name(name)))
+        .map(|c| get_text(&c))
+        .filter(|t| !t.is_empty());
+
+    bible.id = to_module_id(work_id);
+    bible.name = child_text("title").unwrap_or(work_id.to_string());
+    bible.short_name = Some(work_id.to_string());
+    bible.description = child_text("description");
+    bible.license = child_text("rights");
+    bible.data_source = child_text("source").or(child_text("identifier"));
+    bible.language = child_text("language")
+        .or(osis_text.attribute(("http://www.w3.org/XML/1998/namespace", "lang")).map(str::to_string));
+}
+
+#[derive(Debug, Default)]
+struct OsisReader
+{
+    verse: Option<VerseId>,
+    verse_indices: HashMap<VerseId, usize>,
+    style: WordStyle,
+    /// The `sID`s of red letter quote milestones that have not been closed yet
+    open_red_quotes: HashSet<String>,
+    strongs: Vec<StrongsNumber>,
+    strongs_group: Option<u32>,
+    next_strongs_group: u32,
+}
+
+impl OsisReader
+{
+    fn read_node(&mut self, node: Node, bible: &mut ImportedBible)
+    {
+        if node.is_text()
+        {
+            self.read_text(node.text().unwrap_or_default(), bible);
+            return;
+        }
+
+        if !node.is_element()
+        {
+            return;
+        }
+
+        let name = node.tag_name().name();
+        match name
+        {
+            "div" if node.attribute("type") == Some("book") => self.read_book(&node, bible),
+            "verse" => return self.read_verse(node, bible),
+            "q" => return self.read_quote(node, bible),
+            "w" => return self.read_tagged_word(node, bible),
+            "transChange" => return self.read_styled(node, WordStyle { italics: true, ..self.style }, bible),
+            "hi" if node.attribute("type") == Some("italic") => return self.read_styled(node, WordStyle { italics: true, ..self.style }, bible),
+            _ if SKIPPED_ELEMENTS.contains(&name) => return,
+            _ => {},
+        }
+
+        self.read_children(node, bible);
+    }
+
+    fn read_children(&mut self, node: Node, bible: &mut ImportedBible)
+    {
+        for child in node.children()
+        {
+            self.read_node(child, bible);
+        }
+    }
+
+    fn read_text(&mut self, text: &str, bible: &mut ImportedBible)
+    {
+        let Some(verse) = self.verse else { return; };
+        let words = bible.get_verse_words(verse, &mut self.verse_indices);
+        split_words(text, self.style, &self.strongs, self.strongs_group, words);
+    }
+
+    fn read_book(&mut self, node: &Node, bible: &mut ImportedBible)
+    {
+        let Some(book) = node.attribute("osisID").and_then(parse_osis_book) else { return; };
+        let title = node.children()
+            .filter(|c| c.has_tag_name("title"))
+            .find(|c| c.attribute("type") == Some("main") || c.attribute("short").is_some());
+
+        let name = title
+            .and_then(|t| t.attribute("short").map(str::to_string).or(Some(get_text(&t))))
+            .filter(|n| !n.is_empty())
+            .unwrap_or(node.attribute("osisID").unwrap().to_string());
+
+        bible.push_book(book, name);
+    }
+
+    /// Verses are either containers, or milestones marked with `sID` and `eID`
+    fn read_verse(&mut self, node: Node, bible: &mut ImportedBible)
+    {
+        if node.attribute("eID").is_some()
+        {
+            self.verse = None;
+            return;
+        }
+
+        let id = node.attribute("osisID")
+            .and_then(|id| id.split_whitespace().next())
+            .and_then(parse_osis_verse);
+
+        if node.attribute("sID").is_some() || !node.has_children()
+        {
+            self.verse = id;
+            return;
+        }
+
+        let previous = self.verse;
+        self.verse = id;
+        self.read_children(node, bible);
+        self.verse = previous;
+    }
+
+    fn read_quote(&mut self, node: Node, bible: &mut ImportedBible)
+    {
+        let is_red = node.attribute("who") == Some("Jesus");
+        if let Some(id) = node.attribute("sID")
+        {
+            if is_red
+            {
+                self.open_red_quotes.insert(id.to_string());
+                self.style.red = true;
+            }
+
+            return;
+        }
+
+        if let Some(id) = node.attribute("eID")
+        {
+            if self.open_red_quotes.remove(id)
+            {
+                self.style.red = !self.open_red_quotes.is_empty();
+            }
+
+            return;
+        }
+
+        let style = WordStyle { red: self.style.red || is_red, ..self.style };
+        self.read_styled(node, style, bible);
+    }
+
+    fn read_styled(&mut self, node: Node, style: WordStyle, bible: &mut ImportedBible)
+    {
+        let previous = self.style;
+        self.style = style;
+        self.read_children(node, bible);
+        self.style = previous;
+    }
+
+    fn read_tagged_word(&mut self, node: Node, bible: &mut ImportedBible)
+    {
+        let strongs = node.attribute("lemma")
+            .map(|lemma| lemma.split_whitespace()
+                .filter_map(|l| l.strip_prefix("strong:"))
+                .filter_map(parse_strongs)
+                .collect_vec())
+            .unwrap_or_default();
+
+        let previous = (std::mem::replace(&mut self.strongs, strongs), self.strongs_group);
+        self.strongs_group = Some(self.next_strongs_group);
+        self.next_strongs_group += 1;
+
+        self.read_children(node, bible);
+
+        (self.strongs, self.strongs_group) = previous;
+    }
+}
+
+fn get_text(node: &Node) -> String
+{
+    node.descendants()
+        .filter(|n| n.is_text())
+        .filter_map(|n| n.text())
+        .join("")
+        .split_whitespace()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests 
+{
+    use std::num::NonZeroU32;
+
+    use biblio_json::core::OsisBook;
+
+    use super::*;
+    use crate::bible::importing::ImportedWord;
+
+    const OSIS: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<osis xmlns="http://www.bibletechnologies.net/2003/OSIS/namespace">
+  <osisText osisIDWork="TestBible" xml:lang="en">
+    <header>
+      <work osisWork="TestBible">
+        <title>Test Bible</title>
+        <rights>Public Domain</rights>
+      </work>
+    </header>
+    <div type="book" osisID="Gen">
+      <title type="main" short="Genesis">The First Book of Moses</title>
+      <chapter osisID="Gen.1">
+        <verse osisID="Gen.1.1"><w lemma="strong:H07225">In the beginning</w> <w lemma="strong:H0430">God</w> created<note>A note</note>.</verse>
+        <verse sID="Gen.1.2" osisID="Gen.1.2"/>And the earth was <transChange type="added">without</transChange> form<verse eID="Gen.1.2"/>
+        <verse osisID="Gen.1.3"><q who="Jesus">Let there be light</q></verse>
+      </chapter>
+    </div>
+  </osisText>
+</osis>"#;
+
+    fn verse_words(bible: &ImportedBible, verse: u32) -> &[ImportedWord]
+    {
+        let id = VerseId::new(OsisBook::Gen, NonZeroU32::new(1).unwrap(), NonZeroU32::new(verse).unwrap());
+        &bible.verses.iter().find(|v| v.id == id).unwrap().words
+    }
+
+    #[test]
+    fn test_parse_osis_header() 
+    {
+        let bible = parse_osis(OSIS).unwrap();
+
+        assert_eq!(bible.id, "testbible");
+        assert_eq!(bible.name, "Test Bible");
+        assert_eq!(bible.license.as_deref(), Some("Public Domain"));
+        assert_eq!(bible.language.as_deref(), Some("en"));
+        assert_eq!(bible.books, [(OsisBook::Gen, "Genesis".to_string())]);
+    }
+
+    #[test]
+    fn test_parse_osis_container_and_milestone_verses() 
+    {
+        let bible = parse_osis(OSIS).unwrap();
+        let texts = |verse| verse_words(&bible, verse).iter().map(|w| w.text.as_str()).join(" ");
+
+        assert_eq!(bible.verses.len(), 3);
+        assert_eq!(texts(1), "In the beginning God created");
+        assert_eq!(texts(2), "And the earth was without form");
+    }
+
+    #[test]
+    fn test_parse_osis_strongs_and_styles() 
+    {
+        let bible = parse_osis(OSIS).unwrap();
+
+        let first = verse_words(&bible, 1);
+        assert_eq!(first[0].strongs, first[2].strongs);
+        assert_eq!(first[0].strongs_group, first[2].strongs_group);
+        assert_ne!(first[2].strongs_group, first[3].strongs_group);
+        assert!(first[4].strongs.is_empty());
+        assert_eq!(first[4].end_punc.as_deref(), Some("."));
+
+        let second = verse_words(&bible, 2);
+        assert_eq!(second[4].italics, Some(true));
+        assert_eq!(second[5].italics, None);
+
+        assert!(verse_words(&bible, 3).iter().all(|w| w.red == Some(true)));
+    }
+
+    #[test]
+    fn test_parse_osis_without_verses() 
+    {
+        assert!(parse_osis("<osis><osisText osisIDWork=\"Empty\"></osisText></osis>").is_err());
+        assert!(parse_osis("<osis>").is_err());
+    }
+}
@@ -0,0 +1,419 @@
+use std::{collections::{HashMap, HashSet}, num::NonZeroU32, path::Path};
+
+use biblio_json::{core::VerseId, modules::bible::BookInfo};
+use itertools::Itertools;
+use rusqlite::{Connection, OpenFlags, types::ValueRef};
+
+use crate::bible::importing::{ImportedBible, ImportedCommentary, ImportedCommentaryEntry, ImportedDictionary, ImportedDictionaryEntry, ImportedModule, markup::{MarkupKind, is_rtf, markup_to_html, read_bible_markup, rtf_to_html}, parse_osis_book, to_language_module_id, usfm::BOOK_CODES};
+
+/// The number of unmapped verses listed in the warning about them, before the rest are counted
+const LISTED_UNMAPPED_VERSES: usize = 5;
+
+/// The program a database was made for, which decides how its text is marked up
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SqliteFlavor
+{
+    /// `.bblx`, `.cmtx` and `.dctx` files, written in RTF or html
+    ESword,
+    /// `.bbl.mybible`, `.cmt.mybible` and `.dct.mybible` files, with GBF tags in bibles and html elsewhere
+    MySword,
+}
+
+/// The description of a database, from its `Details` table
+#[derive(Debug, Clone, Default)]
+struct SqliteDetails
+{
+    values: HashMap<String, String>,
+}
+
+impl SqliteDetails
+{
+    fn get(&self, key: &str) -> Option<&str>
+    {
+        self.values.get(key).map(String::as_str).filter(|v| !v.trim().is_empty())
+    }
+
+    /// The plain text of a value, as descriptions are often written in RTF or html
+    fn get_text(&self, key: &str) -> Option<String>
+    {
+        let html = entry_to_html(self.get(key)?).replace("</p>", "\n");
+        let mut text = String::new();
+        let mut in_tag = false;
+        for c in html.chars()
+        {
+            match c
+            {
+                '<' => in_tag = true,
+                '>' => in_tag = false,
+                c if !in_tag => text.push(c),
+                _ => {},
+            }
+        }
+
+        let text = text.replace("&lt;", "<").replace("&gt;", ">").replace("&amp;", "&");
+        let text = text.lines().map(|l| l.split_whitespace().join(" ")).filter(|l| !l.is_empty()).join("\n");
+        Some(text).filter(|t| !t.is_empty())
+    }
+}
+
+/// Reads an e-Sword or MySword database, returning the module along with anything that may have been imported incorrectly.
+/// Books are numbered 1 to 66 in both formats, `books` is used for the names of the books in a bible
+pub fn read_sqlite_module(path: &Path, books: &[BookInfo]) -> Result<(ImportedModule, Vec<String>), String>
+{
+    let file_name = path.file_name().map(|n| n.to_string_lossy().to_lowercase()).unwrap_or_default();
+    let flavor = match file_name.ends_with(".mybible")
+    {
+        true => SqliteFlavor::MySword,
+        false => SqliteFlavor::ESword,
+    };
+
+    let connection = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY).map_err(|e| e.to_string())?;
+    let tables = read_table_names(&connection)?;
+    let details = read_details(&connection, &tables)?;
+    let short_name = details.get("abbreviation")
+        .map(str::to_string)
+        .unwrap_or(file_name.split('.').next().unwrap_or_default().to_string());
+
+    let mut unmapped = vec![];
+    let module = if tables.contains("bible")
+    {
+        ImportedModule::Bible(read_bible(&connection, &details, short_name, flavor, books, &mut unmapped)?)
+    }
+    else if tables.contains("verses") || tables.contains("commentary")
+    {
+        ImportedModule::Commentary(read_commentary(&connection, &tables, &details, short_name, books, &mut unmapped)?)
+    }
+    else if tables.contains("dictionary")
+    {
+        ImportedModule::Dictionary(read_dictionary(&connection, &details, short_name, flavor)?)
+    }
+    else
+    {
+        return Err(format!("'{}' is not an e-Sword or MySword bible, commentary or dictionary", file_name));
+    };
+
+    let mut warnings = vec![];
+    if !unmapped.is_empty()
+    {
+        let listed = unmapped.iter().take(LISTED_UNMAPPED_VERSES).join(", ");
+        let rest = unmapped.len().saturating_sub(LISTED_UNMAPPED_VERSES);
+        warnings.push(match rest
+        {
+            0 => format!("{} verses of '{}' could not be mapped: {}", unmapped.len(), module.id(), listed),
+            _ => format!("{} verses of '{}' could not be mapped: {} and {} more", unmapped.len(), module.id(), listed, rest),
+        });
+    }
+
+    Ok((module, warnings))
+}
+
+/// The names of every table in the database, in lower case as SQLite table names are case insensitive
+fn read_table_names(connection: &Connection) -> Result<HashSet<String>, String>
+{
+    let mut statement = connection.prepare("SELECT name FROM sqlite_master WHERE type = 'table'").map_err(|e| e.to_string())?;
+    let names = statement.query_map([], |row| row.get::<_, String>(0))
+        .map_err(|e| e.to_string())?
+        .map_ok(|n| n.to_lowercase())
+        .collect::<Result<HashSet<_>, _>>()
+        .map_err(|e| e.to_string())?;
+
+    Ok(names)
+}
+
+fn read_details(connection: &Connection, tables: &HashSet<String>) -> Result<SqliteDetails, String>
+{
+    if !tables.contains("details")
+    {
+        return Ok(SqliteDetails::default());
+    }
+
+    let mut statement = connection.prepare("SELECT * FROM Details LIMIT 1").map_err(|e| e.to_string())?;
+    let columns = statement.column_names().into_iter().map(str::to_lowercase).collect_vec();
+    let mut rows = statement.query([]).map_err(|e| e.to_string())?;
+
+    let mut details = SqliteDetails::default();
+    if let Some(row) = rows.next().map_err(|e| e.to_string())?
+    {
+        for (i, column) in columns.into_iter().enumerate()
+        {
+            let value = match row.get_ref(i).map_err(|e| e.to_string())?
+            {
+                ValueRef::Text(text) => String::from_utf8_lossy(text).into_owned(),
+                ValueRef::Integer(value) => value.to_string(),
+                ValueRef::Real(value) => value.to_string(),
+                ValueRef::Null | ValueRef::Blob(_) => continue,
+            };
+
+            details.values.insert(column, value);
+        }
+    }
+
+    Ok(details)
+}
+
+fn read_bible(connection: &Connection, details: &SqliteDetails, short_name: String, flavor: SqliteFlavor, books: &[BookInfo], unmapped: &mut Vec<String>) -> Result<ImportedBible, String>
+{
+    let name = details.get_text("title").or(details.get_text("description")).unwrap_or(short_name.clone());
+    let language = details.get("language").map(str::to_lowercase);
+
+    let mut bible = ImportedBible::new(name, short_name, language);
+    bible.description = details.get_text("comments").or(details.get_text("description"));
+    bible.license = details.get_text("rights");
+    bible.data_source = details.get_text("source").or(details.get_text("publisher"));
+
+    let mut statement = connection.prepare("SELECT Book, Chapter, Verse, Scripture FROM Bible ORDER BY Book, Chapter, Verse")
+        .map_err(|e| e.to_string())?;
+
+    let rows = statement.query_map([], |row| Ok((row.get::<_, i64>(0)?, row.get::<_, i64>(1)?, row.get::<_, i64>(2)?, row.get::<_, Option<String>>(3)?)))
+        .map_err(|e| e.to_string())?;
+
+    let mut verse_indices = HashMap::new();
+    for row in rows
+    {
+        let (book, chapter, verse, text) = row.map_err(|e| e.to_string())?;
+        let Some(text) = text.filter(|t| !t.trim().is_empty()) else { continue; };
+        let Some(id) = to_verse_id(book, chapter, verse) else {
+            unmapped.push(format!("book {} {}:{}", book, chapter, verse));
+            continue;
+        };
+
+        let book_name = books.iter()
+            .find(|b| b.osis_book == id.book)
+            .map(|b| b.name.clone())
+            .unwrap_or(BOOK_CODES[book as usize - 1].1.to_string());
+
+        bible.push_book(id.book, book_name);
+        match flavor
+        {
+            SqliteFlavor::MySword => read_bible_markup(id, &text, MarkupKind::Gbf, &mut bible, &mut verse_indices),
+            SqliteFlavor::ESword if is_rtf(&text) => read_bible_markup(id, &rtf_to_html(&text), MarkupKind::Thml, &mut bible, &mut verse_indices),
+            SqliteFlavor::ESword => read_bible_markup(id, &text, MarkupKind::Thml, &mut bible, &mut verse_indices),
+        }
+    }
+
+    bible.verses.retain(|v| !v.words.is_empty());
+    if bible.verses.is_empty()
+    {
+        return Err(format!("'{}' does not contain any verses", bible.name));
+    }
+
+    Ok(bible)
+}
+
+fn read_commentary(connection: &Connection, tables: &HashSet<String>, details: &SqliteDetails, short_name: String, books: &[BookInfo], unmapped: &mut Vec<String>) -> Result<ImportedCommentary, String>
+{
+    let language = details.get("language").map(str::to_lowercase);
+    let mut commentary = ImportedCommentary {
+        id: to_language_module_id(&short_name, language.as_deref()),
+        name: details.get_text("title").or(details.get_text("description")).unwrap_or(short_name.clone()),
+        short_name: Some(short_name),
+        description: details.get_text("comments").or(details.get_text("description")),
+        language,
+        license: details.get_text("rights"),
+        data_source: details.get_text("source").or(details.get_text("publisher")),
+        ..Default::default()
+    };
+
+    // e-Sword keeps comments on whole books and chapters apart from those on verses, these are put on the first verse
+    let mut queries = vec![];
+    if tables.contains("books")
+    {
+        queries.push("SELECT Book, 1, 1, 1, 1, Comments FROM Books");
+    }
+
+    if tables.contains("chapters")
+    {
+        queries.push("SELECT Book, Chapter, 1, Chapter, 1, Comments FROM Chapters");
+    }
+
+    if tables.contains("verses")
+    {
+        queries.push("SELECT Book, ChapterBegin, VerseBegin, ChapterEnd, VerseEnd, Comments FROM Verses");
+    }
+
+    if tables.contains("commentary")
+    {
+        queries.push("SELECT book, chapter, fromverse, chapter, toverse, data FROM commentary");
+    }
+
+    for query in queries
+    {
+        let mut statement = connection.prepare(query).map_err(|e| e.to_string())?;
+        let rows = statement.query_map([], |row| Ok((
+                row.get::<_, i64>(0)?,
+                (row.get::<_, Option<i64>>(1)?.unwrap_or(1), row.get::<_, Option<i64>>(2)?.unwrap_or(1)),
+                (row.get::<_, Option<i64>>(3)?, row.get::<_, Option<i64>>(4)?),
+                row.get::<_, Option<String>>(5)?,
+            )))
+            .map_err(|e| e.to_string())?;
+
+        for row in rows
+        {
+            let (book, (chapter, verse), (end_chapter, end_verse), text) = row.map_err(|e| e.to_string())?;
+            let Some(text) = text.filter(|t| !t.trim().is_empty()) else { continue; };
+            let Some(start) = to_verse_id(book, chapter, verse) else {
+                unmapped.push(format!("book {} {}:{}", book, chapter, verse));
+                continue;
+            };
+
+            let end = to_verse_id(book, end_chapter.unwrap_or(chapter), end_verse.unwrap_or(verse)).unwrap_or(start);
+            let html = entry_to_html(&text);
+            if !html.is_empty()
+            {
+                commentary.entries.push(ImportedCommentaryEntry { references: get_verse_range(start, end, books), html });
+            }
+        }
+    }
+
+    if commentary.entries.is_empty()
+    {
+        return Err(format!("'{}' does not contain any comments", commentary.name));
+    }
+
+    Ok(commentary)
+}
+
+fn read_dictionary(connection: &Connection, details: &SqliteDetails, short_name: String, flavor: SqliteFlavor) -> Result<ImportedDictionary, String>
+{
+    let query = match flavor
+    {
+        SqliteFlavor::ESword => "SELECT Topic, Definition FROM Dictionary",
+        SqliteFlavor::MySword => "SELECT word, data FROM dictionary ORDER BY relativeorder",
+    };
+
+    let mut statement = connection.prepare(query).map_err(|e| e.to_string())?;
+    let entries = statement.query_map([], |row| Ok((row.get::<_, Option<String>>(0)?, row.get::<_, Option<String>>(1)?)))
+        .map_err(|e| e.to_string())?
+        .filter_map_ok(|(term, definition)| {
+            let term = term?.trim().to_string();
+            let html = entry_to_html(&definition?);
+            Some(ImportedDictionaryEntry { term, aliases: vec![], html })
+                .filter(|e| !e.term.is_empty() && !e.html.is_empty())
+        })
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+
+    let language = details.get("language").map(str::to_lowercase);
+    Ok(ImportedDictionary {
+        id: to_language_module_id(&short_name, language.as_deref()),
+        name: details.get_text("title").or(details.get_text("description")).unwrap_or(short_name.clone()),
+        short_name: Some(short_name),
+        description: details.get_text("comments").or(details.get_text("description")),
+        language,
+        license: details.get_text("rights"),
+        data_source: details.get_text("source").or(details.get_text("publisher")),
+        entries,
+    })
+}
+
+/// Maps the book numbers both formats use, 1 to 66 in the protestant canon, to a verse
+fn to_verse_id(book: i64, chapter: i64, verse: i64) -> Option<VerseId>
+{
+    let book = usize::try_from(book).ok()
+        .and_then(|b| b.checked_sub(1))
+        .and_then(|b| BOOK_CODES.get(b))
+        .and_then(|(_, osis)| parse_osis_book(osis))?;
+
+    let chapter = NonZeroU32::new(u32::try_from(chapter).ok()?)?;
+    let verse = NonZeroU32::new(u32::try_from(verse).ok()?)?;
+    Some(VerseId::new(book, chapter, verse))
+}
+
+/// Every verse from `start` to `end`, or just `start` if the range crosses verses the reference books do not have
+fn get_verse_range(start: VerseId, end: VerseId, books: &[BookInfo]) -> Vec<VerseId>
+{
+    let chapters = books.iter().find(|b| b.osis_book == start.book).map(|b| &b.chapters);
+    let (Some(chapters), true) = (chapters, end.chapter >= start.chapter) else { return vec![start]; };
+
+    let mut verses = vec![];
+    for chapter in start.chapter.get()..=end.chapter.get()
+    {
+        let Some(verse_count) = chapters.get(chapter as usize - 1) else { break; };
+        let first = if chapter == start.chapter.get() { start.verse.get() } else { 1 };
+        let last = if chapter == end.chapter.get() { end.verse.get().min(*verse_count) } else { *verse_count };
+
+        verses.extend((first..=last)
+            .filter_map(|v| Some(VerseId::new(start.book, NonZeroU32::new(chapter)?, NonZeroU32::new(v)?))));
+    }
+
+    if verses.is_empty()
+    {
+        return vec![start];
+    }
+
+    verses
+}
+
+fn entry_to_html(text: &str) -> String
+{
+    match is_rtf(text)
+    {
+        true => markup_to_html(&rtf_to_html(text), MarkupKind::Thml),
+        false => markup_to_html(text, MarkupKind::Thml),
+    }
+}
+
+#[cfg(test)]
+mod tests 
+{
+    use biblio_json::Package;
+
+    use super::*;
+    use crate::bible::importing::get_reference_books;
+
+    fn verse(book: &str, chapter: u32, verse: u32) -> VerseId
+    {
+        VerseId::new(parse_osis_book(book).unwrap(), NonZeroU32::new(chapter).unwrap(), NonZeroU32::new(verse).unwrap())
+    }
+
+    fn reference_books() -> Vec<BookInfo>
+    {
+        let package = Package::load("./resources/biblio-json-pkg").unwrap();
+        get_reference_books(&package).unwrap()
+    }
+
+    #[test]
+    fn test_book_numbers_to_verse_ids() 
+    {
+        assert_eq!(to_verse_id(1, 1, 1), Some(verse("Gen", 1, 1)));
+        assert_eq!(to_verse_id(43, 3, 16), Some(verse("John", 3, 16)));
+        assert_eq!(to_verse_id(66, 22, 21), Some(verse("Rev", 22, 21)));
+        assert_eq!(to_verse_id(0, 1, 1), None);
+        assert_eq!(to_verse_id(67, 1, 1), None);
+        assert_eq!(to_verse_id(1, 0, 1), None);
+        assert_eq!(to_verse_id(1, 1, -1), None);
+    }
+
+    #[test]
+    fn test_verse_range_across_chapters() 
+    {
+        let books = reference_books();
+        let range = get_verse_range(verse("Gen", 1, 30), verse("Gen", 2, 2), &books);
+        assert_eq!(range, [verse("Gen", 1, 30), verse("Gen", 1, 31), verse("Gen", 2, 1), verse("Gen", 2, 2)]);
+    }
+
+    #[test]
+    fn test_verse_range_past_the_end_of_a_chapter() 
+    {
+        let books = reference_books();
+        let range = get_verse_range(verse("Gen", 1, 30), verse("Gen", 1, 40), &books);
+        assert_eq!(range, [verse("Gen", 1, 30), verse("Gen", 1, 31)]);
+    }
+
+    #[test]
+    fn test_backwards_verse_range() 
+    {
+        let books = reference_books();
+        assert_eq!(get_verse_range(verse("Gen", 2, 1), verse("Gen", 1, 1), &books), [verse("Gen", 2, 1)]);
+        assert_eq!(get_verse_range(verse("Gen", 1, 5), verse("Gen", 1, 5), &[]), [verse("Gen", 1, 5)]);
+    }
+
+    #[test]
+    fn test_rtf_entries_to_html() 
+    {
+        let rtf = r"{\rtf1\ansi{\fonttbl{\f0 Arial;}}\b Bold\b0  text\par Next \i line\i0\line end}";
+        assert_eq!(rtf_to_html(rtf), "<p><b>Bold</b> text</p><p>Next <i>line</i><br>end</p>");
+        assert_eq!(rtf_to_html(r"caf\u233? \'e9t\'e9"), "<p>café été</p>");
+        assert_eq!(entry_to_html(r"\b Grace\b0"), "<p><b>Grace</b></p>");
+    }
+}
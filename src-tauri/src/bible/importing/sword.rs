@@ -0,0 +1,574 @@
+use std::{collections::HashMap, ffi::OsString, fs, io::Read, num::NonZeroU32, ops::Range, path::{Path, PathBuf}};
+
+use biblio_json::{core::VerseId, modules::bible::BookInfo};
+use flate2::read::ZlibDecoder;
+use itertools::Itertools;
+
+use crate::bible::importing::{ImportedBible, ImportedCommentary, ImportedCommentaryEntry, ImportedDictionary, ImportedDictionaryEntry, ImportedModule, markup::{MarkupKind, markup_to_html, read_bible_markup}, parse_osis_book, to_language_module_id};
+
+/// The versification every verse is mapped with, others are reported as a warning
+const SUPPORTED_VERSIFICATION: &str = "KJV";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SwordModuleKind
+{
+    Bible,
+    Commentary,
+    Dictionary,
+}
+
+/// How the entries of a module are stored, with `wide` drivers (such as `zText4`) using 4 byte entry sizes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SwordStorage
+{
+    Compressed
+    {
+        wide: bool,
+    },
+    Raw
+    {
+        wide: bool,
+    },
+}
+
+/// A module's `.conf` file from the `mods.d` folder of a SWORD library
+#[derive(Debug, Clone, Default)]
+struct SwordConf
+{
+    name: String,
+    entries: HashMap<String, String>,
+}
+
+impl SwordConf
+{
+    fn parse(text: &str) -> Result<Self, String>
+    {
+        let mut conf = Self::default();
+        let mut last_key: Option<String> = None;
+        for line in text.lines()
+        {
+            let line = line.trim_end_matches('\r');
+            if let Some(name) = line.trim().strip_prefix('[').and_then(|l| l.strip_suffix(']'))
+            {
+                conf.name = name.to_string();
+                continue;
+            }
+
+            // Values ending in `\` continue on the next line
+            if let Some(key) = &last_key
+            {
+                let value = conf.entries.get_mut(key).unwrap();
+                value.push('\n');
+                value.push_str(line.trim().trim_end_matches('\\'));
+                last_key = Some(key.clone()).filter(|_| line.ends_with('\\'));
+                continue;
+            }
+
+            let Some((key, value)) = line.split_once('=') else { continue; };
+            let key = key.trim().to_string();
+            let continues = value.ends_with('\\');
+
+            // Keys such as `GlobalOptionFilter` are repeated, only the first of each is needed here
+            if !conf.entries.contains_key(&key)
+            {
+                conf.entries.insert(key.clone(), value.trim().trim_end_matches('\\').to_string());
+                last_key = Some(key).filter(|_| continues);
+            }
+        }
+
+        if conf.name.is_empty()
+        {
+            return Err("The module config does not have a [name] section".into());
+        }
+
+        Ok(conf)
+    }
+
+    fn get(&self, key: &str) -> Option<&str>
+    {
+        self.entries.get(key).map(String::as_str).filter(|v| !v.is_empty())
+    }
+
+    fn markup_kind(&self) -> MarkupKind
+    {
+        match self.get("SourceType").map(str::to_ascii_uppercase).as_deref()
+        {
+            Some("OSIS") | Some("TEI") => MarkupKind::Osis,
+            Some("THML") => MarkupKind::Thml,
+            Some("GBF") => MarkupKind::Gbf,
+            _ => MarkupKind::Plain,
+        }
+    }
+
+    fn is_utf8(&self) -> bool
+    {
+        self.get("Encoding").is_some_and(|e| e.eq_ignore_ascii_case("UTF-8"))
+    }
+
+    fn decode(&self, bytes: &[u8]) -> String
+    {
+        let text = match self.is_utf8()
+        {
+            true => String::from_utf8_lossy(bytes).into_owned(),
+            false => bytes.iter().map(|b| *b as char).collect(),
+        };
+
+        text.trim_matches(|c: char| c == '\0' || c.is_whitespace()).to_string()
+    }
+
+    /// The `About` text with its RTF paragraph breaks turned into lines
+    fn about(&self) -> Option<String>
+    {
+        self.get("About").map(|a| a.replace("\\par", "\n").lines().map(str::trim).filter(|l| !l.is_empty()).join("\n"))
+    }
+}
+
+/// Finds the config of every module in a SWORD library, which is a folder holding `mods.d` and `modules`.
+/// The library may be nested in another folder, as it often is in archives
+pub fn find_sword_confs(path: &Path) -> Result<Vec<PathBuf>, String>
+{
+    let pattern = path.join("**").join("mods.d").join("*.conf");
+    let confs = glob::glob(&pattern.to_string_lossy())
+        .map_err(|e| e.to_string())?
+        .filter_map(Result::ok)
+        .collect_vec();
+
+    if confs.is_empty()
+    {
+        return Err(format!("No SWORD modules were found in '{}'", path.display()));
+    }
+
+    Ok(confs)
+}
+
+/// Reads the module the config describes, returning it along with anything that may have been imported incorrectly
+pub fn read_sword_module(conf_path: &Path, books: &[BookInfo]) -> Result<(ImportedModule, Vec<String>), String>
+{
+    let text = fs::read(conf_path).map_err(|e| e.to_string())?;
+    let conf = SwordConf::parse(&String::from_utf8_lossy(&text))?;
+    let root = conf_path.parent().and_then(Path::parent).ok_or("The module config is not in a mods.d folder")?;
+
+    if conf.entries.contains_key("CipherKey")
+    {
+        return Err(format!("'{}' is locked, and cannot be imported", conf.name));
+    }
+
+    let driver = conf.get("ModDrv").ok_or(format!("'{}' does not have a ModDrv", conf.name))?;
+    let (kind, storage) = parse_driver(driver)
+        .ok_or(format!("'{}' uses the {} driver, which is not supported", conf.name, driver))?;
+
+    if let SwordStorage::Compressed { .. } = storage
+    {
+        let compression = conf.get("CompressType").unwrap_or("ZIP");
+        if !compression.eq_ignore_ascii_case("ZIP")
+        {
+            return Err(format!("'{}' is compressed with {}, which is not supported", conf.name, compression));
+        }
+    }
+
+    let data_path = conf.get("DataPath").ok_or(format!("'{}' does not have a DataPath", conf.name))?;
+    let data_path = root.join(data_path.trim_start_matches("./").trim_end_matches('/'));
+
+    let mut warnings = vec![];
+    let module = match kind
+    {
+        SwordModuleKind::Bible => ImportedModule::Bible(read_bible(&conf, &data_path, storage, books, &mut warnings)?),
+        SwordModuleKind::Commentary => ImportedModule::Commentary(read_commentary(&conf, &data_path, storage, books, &mut warnings)?),
+        SwordModuleKind::Dictionary => ImportedModule::Dictionary(read_dictionary(&conf, &data_path, storage)?),
+    };
+
+    Ok((module, warnings))
+}
+
+fn parse_driver(driver: &str) -> Option<(SwordModuleKind, SwordStorage)>
+{
+    match driver.to_ascii_lowercase().as_str()
+    {
+        "ztext" => Some((SwordModuleKind::Bible, SwordStorage::Compressed { wide: false })),
+        "ztext4" => Some((SwordModuleKind::Bible, SwordStorage::Compressed { wide: true })),
+        "rawtext" => Some((SwordModuleKind::Bible, SwordStorage::Raw { wide: false })),
+        "rawtext4" => Some((SwordModuleKind::Bible, SwordStorage::Raw { wide: true })),
+        "zcom" => Some((SwordModuleKind::Commentary, SwordStorage::Compressed { wide: false })),
+        "zcom4" => Some((SwordModuleKind::Commentary, SwordStorage::Compressed { wide: true })),
+        "rawcom" => Some((SwordModuleKind::Commentary, SwordStorage::Raw { wide: false })),
+        "rawcom4" => Some((SwordModuleKind::Commentary, SwordStorage::Raw { wide: true })),
+        "rawld" => Some((SwordModuleKind::Dictionary, SwordStorage::Raw { wide: false })),
+        "rawld4" => Some((SwordModuleKind::Dictionary, SwordStorage::Raw { wide: true })),
+        _ => None,
+    }
+}
+
+fn read_bible(conf: &SwordConf, dir: &Path, storage: SwordStorage, books: &[BookInfo], warnings: &mut Vec<String>) -> Result<ImportedBible, String>
+{
+    let name = conf.get("Description").unwrap_or(&conf.name).to_string();
+    let mut bible = ImportedBible::new(name, conf.name.clone(), conf.get("Lang").map(str::to_string));
+    bible.description = conf.about();
+    bible.license = conf.get("DistributionLicense").map(str::to_string);
+    bible.data_source = conf.get("TextSource").map(str::to_string);
+
+    let kind = conf.markup_kind();
+    let mut verse_indices = HashMap::new();
+    for (verse, text) in read_verse_entries(conf, dir, storage, books, warnings)?
+    {
+        let book = books.iter().find(|b| b.osis_book == verse.book).unwrap();
+        bible.push_book(verse.book, book.name.clone());
+        read_bible_markup(verse, &text, kind, &mut bible, &mut verse_indices);
+    }
+
+    bible.verses.retain(|v| !v.words.is_empty());
+    if bible.verses.is_empty()
+    {
+        return Err(format!("'{}' does not contain any verses", conf.name));
+    }
+
+    Ok(bible)
+}
+
+fn read_commentary(conf: &SwordConf, dir: &Path, storage: SwordStorage, books: &[BookInfo], warnings: &mut Vec<String>) -> Result<ImportedCommentary, String>
+{
+    let language = conf.get("Lang");
+    let mut commentary = ImportedCommentary {
+        id: to_language_module_id(&conf.name, language),
+        name: conf.get("Description").unwrap_or(&conf.name).to_string(),
+        short_name: Some(conf.name.clone()),
+        description: conf.about(),
+        language: language.map(str::to_string),
+        license: conf.get("DistributionLicense").map(str::to_string),
+        data_source: conf.get("TextSource").map(str::to_string),
+        ..Default::default()
+    };
+
+    // Comments on a range of verses are stored once, with every verse in the range pointing at it
+    let kind = conf.markup_kind();
+    let mut entry_indices: HashMap<String, usize> = HashMap::new();
+    for (verse, text) in read_verse_entries(conf, dir, storage, books, warnings)?
+    {
+        match entry_indices.get(&text)
+        {
+            Some(index) => commentary.entries[*index].references.push(verse),
+            None => {
+                let html = markup_to_html(&text, kind);
+                if html.is_empty()
+                {
+                    continue;
+                }
+
+                entry_indices.insert(text, commentary.entries.len());
+                commentary.entries.push(ImportedCommentaryEntry { references: vec![verse], html });
+            },
+        }
+    }
+
+    Ok(commentary)
+}
+
+fn read_dictionary(conf: &SwordConf, path: &Path, storage: SwordStorage) -> Result<ImportedDictionary, String>
+{
+    let wide = match storage
+    {
+        SwordStorage::Raw { wide } => wide,
+        SwordStorage::Compressed { .. } => return Err(format!("'{}' is a compressed dictionary, which is not supported", conf.name)),
+    };
+
+    let index = fs::read(with_extension(path, "idx")).map_err(|e| e.to_string())?;
+    let data = fs::read(with_extension(path, "dat")).map_err(|e| e.to_string())?;
+
+    let mut entries: Vec<ImportedDictionaryEntry> = vec![];
+    let mut links: Vec<(String, String)> = vec![];
+    for (offset, size) in read_index(&index, wide)
+    {
+        let Some(bytes) = data.get(byte_range(offset, size)) else { continue; };
+        let text = conf.decode(bytes);
+        let Some((term, body)) = text.split_once('\n') else { continue; };
+        let (term, body) = (term.trim().to_string(), body.trim());
+
+        // Alternative spellings are stored as entries that link to the main one
+        if let Some(target) = body.strip_prefix("@LINK")
+        {
+            links.push((term, target.trim().to_string()));
+            continue;
+        }
+
+        let html = markup_to_html(body, conf.markup_kind());
+        if !term.is_empty() && !html.is_empty()
+        {
+            entries.push(ImportedDictionaryEntry { term, aliases: vec![], html });
+        }
+    }
+
+    for (alias, target) in links
+    {
+        if let Some(entry) = entries.iter_mut().find(|e| e.term == target)
+        {
+            entry.aliases.push(alias);
+        }
+    }
+
+    let language = conf.get("Lang");
+    Ok(ImportedDictionary {
+        id: to_language_module_id(&conf.name, language),
+        name: conf.get("Description").unwrap_or(&conf.name).to_string(),
+        short_name: Some(conf.name.clone()),
+        description: conf.about(),
+        language: language.map(str::to_string),
+        license: conf.get("DistributionLicense").map(str::to_string),
+        data_source: conf.get("TextSource").map(str::to_string),
+        entries,
+    })
+}
+
+/// Reads the text of every verse with an entry, in both testaments
+fn read_verse_entries(conf: &SwordConf, dir: &Path, storage: SwordStorage, books: &[BookInfo], warnings: &mut Vec<String>) -> Result<Vec<(VerseId, String)>, String>
+{
+    let versification = conf.get("Versification").unwrap_or(SUPPORTED_VERSIFICATION);
+    if versification != SUPPORTED_VERSIFICATION
+    {
+        warnings.push(format!(
+            "'{}' uses the {} versification, but was read with the KJV versification, so some verses may be misplaced",
+            conf.name, versification
+        ));
+    }
+
+    let nt_start = parse_osis_book("Matt")
+        .and_then(|matt| books.iter().position(|b| b.osis_book == matt))
+        .unwrap_or(books.len());
+
+    let mut verses = vec![];
+    for (testament, books) in [("ot", &books[..nt_start]), ("nt", &books[nt_start..])]
+    {
+        let Some(mut file) = TestamentFile::read(dir, testament, storage)? else { continue; };
+
+        let layout = get_testament_layout(books);
+        if file.entries.len() != layout.len()
+        {
+            warnings.push(format!(
+                "The {} index of '{}' has {} entries, but the KJV versification has {}, so some verses may be misplaced",
+                testament.to_uppercase(), conf.name, file.entries.len(), layout.len()
+            ));
+        }
+
+        for (verse, entry) in layout.into_iter().zip(file.entries.clone())
+        {
+            let Some(verse) = verse else { continue; };
+            let Some(bytes) = file.read_entry(entry)? else { continue; };
+
+            let text = conf.decode(&bytes);
+            if !text.is_empty()
+            {
+                verses.push((verse, text));
+            }
+        }
+    }
+
+    if verses.is_empty() && !dir.exists()
+    {
+        return Err(format!("The data folder of '{}' was not found at '{}'", conf.name, dir.display()));
+    }
+
+    Ok(verses)
+}
+
+/// The verse at each entry of a testament's index. The first two entries are the module and testament
+/// introductions, then each book and each chapter has an introduction before its verses
+fn get_testament_layout(books: &[BookInfo]) -> Vec<Option<VerseId>>
+{
+    let mut layout = vec![None, None];
+    for book in books
+    {
+        layout.push(None);
+        for (chapter, verse_count) in book.chapters.iter().enumerate()
+        {
+            layout.push(None);
+
+            let chapter = NonZeroU32::new(chapter as u32 + 1).unwrap();
+            layout.extend((1..=*verse_count)
+                .filter_map(NonZeroU32::new)
+                .map(|verse| Some(VerseId::new(book.osis_book, chapter, verse))));
+        }
+    }
+
+    layout
+}
+
+/// Where an entry is stored, `block` being the compressed block it is in for compressed modules
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct EntryPointer
+{
+    block: u32,
+    offset: u32,
+    size: u32,
+}
+
+/// The index and data files of one testament
+#[derive(Debug)]
+struct TestamentFile
+{
+    entries: Vec<EntryPointer>,
+    /// The offset and size of each block in `data`, for compressed modules
+    blocks: Option<Vec<(u32, u32)>>,
+    data: Vec<u8>,
+    decompressed: HashMap<u32, Vec<u8>>,
+}
+
+impl TestamentFile
+{
+    /// Reads the files of the testament, if the module has it
+    fn read(dir: &Path, testament: &str, storage: SwordStorage) -> Result<Option<Self>, String>
+    {
+        let (index_path, data_path) = match storage
+        {
+            SwordStorage::Compressed { .. } => (dir.join(format!("{}.bzv", testament)), dir.join(format!("{}.bzz", testament))),
+            SwordStorage::Raw { .. } => (dir.join(format!("{}.vss", testament)), dir.join(testament)),
+        };
+
+        if !index_path.exists() || !data_path.exists()
+        {
+            return Ok(None);
+        }
+
+        let index = fs::read(&index_path).map_err(|e| e.to_string())?;
+        let data = fs::read(&data_path).map_err(|e| e.to_string())?;
+
+        let file = match storage
+        {
+            SwordStorage::Compressed { wide } => {
+                let blocks = fs::read(dir.join(format!("{}.bzs", testament))).map_err(|e| e.to_string())?;
+                let blocks = blocks.chunks_exact(12)
+                    .map(|b| (read_u32(b, 0), read_u32(b, 4)))
+                    .collect_vec();
+
+                let entry_size = if wide { 12 } else { 10 };
+                let entries = index.chunks_exact(entry_size)
+                    .map(|e| EntryPointer {
+                        block: read_u32(e, 0),
+                        offset: read_u32(e, 4),
+                        size: if wide { read_u32(e, 8) } else { read_u16(e, 8) },
+                    })
+                    .collect();
+
+                Self { entries, blocks: Some(blocks), data, decompressed: HashMap::new() }
+            },
+            SwordStorage::Raw { wide } => {
+                let entries = read_index(&index, wide)
+                    .map(|(offset, size)| EntryPointer { block: 0, offset, size })
+                    .collect();
+
+                Self { entries, blocks: None, data, decompressed: HashMap::new() }
+            },
+        };
+
+        Ok(Some(file))
+    }
+
+    fn read_entry(&mut self, entry: EntryPointer) -> Result<Option<Vec<u8>>, String>
+    {
+        if entry.size == 0
+        {
+            return Ok(None);
+        }
+
+        let range = byte_range(entry.offset, entry.size);
+        let Some(blocks) = &self.blocks else {
+            return Ok(self.data.get(range).map(<[u8]>::to_vec));
+        };
+
+        if !self.decompressed.contains_key(&entry.block)
+        {
+            let Some((offset, size)) = blocks.get(entry.block as usize) else { return Ok(None); };
+            let compressed = self.data.get(byte_range(*offset, *size))
+                .ok_or(format!("Block {} is outside of the compressed data", entry.block))?;
+
+            let mut block = vec![];
+            ZlibDecoder::new(compressed).read_to_end(&mut block).map_err(|e| e.to_string())?;
+            self.decompressed.insert(entry.block, block);
+        }
+
+        Ok(self.decompressed[&entry.block].get(range).map(<[u8]>::to_vec))
+    }
+}
+
+/// Reads an index of 4 byte offsets, followed by 2 byte sizes, or 4 byte sizes for `wide` indices
+fn read_index(index: &[u8], wide: bool) -> impl Iterator<Item = (u32, u32)> + '_
+{
+    let entry_size = if wide { 8 } else { 6 };
+    index.chunks_exact(entry_size)
+        .map(move |e| (read_u32(e, 0), if wide { read_u32(e, 4) } else { read_u16(e, 4) }))
+}
+
+/// Adds the size as a `usize`, as offsets near the end of a large file overflow a `u32` when the two are added
+fn byte_range(offset: u32, size: u32) -> Range<usize>
+{
+    offset as usize..offset as usize + size as usize
+}
+
+fn read_u32(bytes: &[u8], at: usize) -> u32
+{
+    u32::from_le_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]])
+}
+
+fn read_u16(bytes: &[u8], at: usize) -> u32
+{
+    u16::from_le_bytes([bytes[at], bytes[at + 1]]) as u32
+}
+
+/// Appends an extension, as SWORD data paths for dictionaries end in the file name without one
+fn with_extension(path: &Path, extension: &str) -> PathBuf
+{
+    let mut path = OsString::from(path);
+    path.push(".");
+    path.push(extension);
+    PathBuf::from(path)
+}
+
+#[cfg(test)]
+mod tests 
+{
+    use super::*;
+
+    const CONF: &str = "[KJV]\r
+DataPath=./modules/texts/ztext/kjv/\r
+ModDrv=zText\r
+Encoding=UTF-8\r
+GlobalOptionFilter=OSISStrongs\r
+GlobalOptionFilter=OSISFootnotes\r
+Description=\r
+About=The King James Version\\\r
+\\par of 1769\r
+Versification=KJV\r
+";
+
+    #[test]
+    fn test_parse_sword_conf() 
+    {
+        let conf = SwordConf::parse(CONF).unwrap();
+
+        assert_eq!(conf.name, "KJV");
+        assert_eq!(conf.get("ModDrv"), Some("zText"));
+        assert_eq!(conf.get("GlobalOptionFilter"), Some("OSISStrongs"));
+        assert_eq!(conf.get("Description"), None);
+        assert_eq!(conf.get("Versification"), Some("KJV"));
+        assert!(conf.is_utf8());
+        assert_eq!(conf.about().as_deref(), Some("The King James Version\nof 1769"));
+    }
+
+    #[test]
+    fn test_parse_sword_conf_without_name() 
+    {
+        assert!(SwordConf::parse("ModDrv=zText\n").is_err());
+    }
+
+    #[test]
+    fn test_decode_latin1_conf() 
+    {
+        let conf = SwordConf::parse("[Test]\nEncoding=Latin-1\n").unwrap();
+        assert_eq!(conf.decode(&[0x43, 0x61, 0x66, 0xE9, 0x00, 0x00]), "Café");
+    }
+
+    #[test]
+    fn test_byte_range_does_not_overflow() 
+    {
+        let range = byte_range(u32::MAX, 16);
+        assert_eq!(range.start, u32::MAX as usize);
+        assert_eq!(range.end, u32::MAX as usize + 16);
+    }
+}
@@ -0,0 +1,520 @@
+use std::{collections::HashMap, fs, num::NonZeroU32, path::Path};
+
+use biblio_json::core::{OsisBook, VerseId};
+use itertools::Itertools;
+
+use crate::bible::importing::{ImportedBible, ImportedComment, WordStyle, parse_osis_book, parse_strongs, split_words};
+
+/// Paratext book codes, as used by `\id`, and the OSIS book each one maps to
+pub const BOOK_CODES: &[(&str, &str)] = &[
+    ("GEN", "Gen"), ("EXO", "Exod"), ("LEV", "Lev"), ("NUM", "Num"), ("DEU", "Deut"), ("JOS", "Josh"),
+    ("JDG", "Judg"), ("RUT", "Ruth"), ("1SA", "1Sam"), ("2SA", "2Sam"), ("1KI", "1Kgs"), ("2KI", "2Kgs"),
+    ("1CH", "1Chr"), ("2CH", "2Chr"), ("EZR", "Ezra"), ("NEH", "Neh"), ("EST", "Esth"), ("JOB", "Job"),
+    ("PSA", "Ps"), ("PRO", "Prov"), ("ECC", "Eccl"), ("SNG", "Song"), ("ISA", "Isa"), ("JER", "Jer"),
+    ("LAM", "Lam"), ("EZK", "Ezek"), ("DAN", "Dan"), ("HOS", "Hos"), ("JOL", "Joel"), ("AMO", "Amos"),
+    ("OBA", "Obad"), ("JON", "Jonah"), ("MIC", "Mic"), ("NAM", "Nah"), ("HAB", "Hab"), ("ZEP", "Zeph"),
+    ("HAG", "Hag"), ("ZEC", "Zech"), ("MAL", "Mal"), ("MAT", "Matt"), ("MRK", "Mark"), ("LUK", "Luke"),
+    ("JHN", "John"), ("ACT", "Acts"), ("ROM", "Rom"), ("1CO", "1Cor"), ("2CO", "2Cor"), ("GAL", "Gal"),
+    ("EPH", "Eph"), ("PHP", "Phil"), ("COL", "Col"), ("1TH", "1Thess"), ("2TH", "2Thess"), ("1TI", "1Tim"),
+    ("2TI", "2Tim"), ("TIT", "Titus"), ("PHM", "Phlm"), ("HEB", "Heb"), ("JAS", "Jas"), ("1PE", "1Pet"),
+    ("2PE", "2Pet"), ("1JN", "1John"), ("2JN", "2John"), ("3JN", "3John"), ("JUD", "Jude"), ("REV", "Rev"),
+];
+
+/// Paragraph markers that carry on the verse text
+const PARAGRAPH_MARKERS: &[&str] = &[
+    "p", "m", "po", "pr", "cls", "pmo", "pm", "pmc", "pmr", "pi", "mi", "nb", "pc", "ph", "b",
+    "q", "qr", "qc", "qm", "lh", "li", "lf", "lim", "tr", "th", "thr", "tc", "tcr",
+];
+
+/// Paragraph markers whose text is not part of the bible text
+const SKIPPED_PARAGRAPH_MARKERS: &[&str] = &[
+    "rem", "ide", "sts", "usfm", "restore", "toc", "toca", "periph", "cd", "lit", "r", "sr", "mr", "sp", "cl", "cp", "mte", "qa", "ip", "is",
+    "imt", "io", "iot", "ipi", "im", "imi", "ipq", "imq", "ipr", "iq", "ib", "ili", "ie", "iex", "imte",
+];
+
+/// Character markers whose text is dropped, along with everything up to their closing marker
+const SKIPPED_CHARACTER_MARKERS: &[&str] = &["x", "fig", "va", "vp", "ca", "rq", "ef", "ior", "iqt", "cat", "jmp"];
+
+const USFM_EXTENSIONS: &[&str] = &["usfm", "sfm", "ptx"];
+
+const HEADING_MARKERS: &[&str] = &["s", "ms", "d"];
+const ITALIC_MARKERS: &[&str] = &["add", "it", "tl"];
+const FOOTNOTE_MARKERS: &[&str] = &["f", "fe"];
+/// Footnote markers whose text is shown, the others being references back to the verse
+const FOOTNOTE_TEXT_MARKERS: &[&str] = &["ft", "fq", "fqa", "fk", "fp", "fw", "fl"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Token<'a>
+{
+    Marker
+    {
+        name: &'a str,
+        closing: bool,
+    },
+    Text(&'a str),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum LineState
+{
+    #[default]
+    Skip,
+    Verse,
+    BookCode,
+    ChapterNumber,
+    VerseNumber,
+    Header(&'static str),
+    Heading,
+}
+
+/// Converts a set of USFM books into a bible, one file per book as they are usually distributed
+pub fn parse_usfm_books<'a>(books: impl IntoIterator<Item = &'a str>, bible: &mut ImportedBible) -> Result<(), String>
+{
+    let mut reader = UsfmReader::default();
+    for book in books
+    {
+        for token in tokenize(book)
+        {
+            reader.read_token(token, bible);
+        }
+
+        reader.finish_book(bible);
+    }
+
+    bible.books.sort_by_key(|(book, _)| BOOK_CODES.iter().position(|(_, osis)| parse_osis_book(osis) == Some(*book)));
+
+    if bible.verses.is_empty()
+    {
+        return Err("The USFM files do not contain any verses".into());
+    }
+
+    Ok(())
+}
+
+/// Reads the books of a USFM bundle, which is either a single file, or a folder of files
+pub fn read_usfm_files(path: &Path) -> Result<Vec<String>, String>
+{
+    if path.is_file()
+    {
+        return Ok(vec![fs::read_to_string(path).map_err(|e| e.to_string())?]);
+    }
+
+    let entries = fs::read_dir(path)
+        .map_err(|e| e.to_string())?
+        .filter_map(Result::ok)
+        .map(|e| e.path())
+        .sorted()
+        .collect_vec();
+
+    let files = entries.iter()
+        .filter(|p| p.is_file())
+        .filter(|p| p.extension().is_some_and(|e| USFM_EXTENSIONS.contains(&e.to_string_lossy().to_lowercase().as_str())))
+        .collect_vec();
+
+    match files.as_slice()
+    {
+        [] if entries.len() == 1 && entries[0].is_dir() => read_usfm_files(&entries[0]),
+        [] => Err(format!("No USFM files were found in '{}'", path.display())),
+        files => files.iter().map(|f| fs::read_to_string(f).map_err(|e| e.to_string())).collect(),
+    }
+}
+
+fn tokenize(text: &str) -> Vec<Token<'_>>
+{
+    let mut tokens = vec![];
+    let mut rest = text;
+    while !rest.is_empty()
+    {
+        match rest.strip_prefix('\\')
+        {
+            Some(after) => {
+                let len = after.find(|c: char| c.is_whitespace() || c == '\\' || c == '*').unwrap_or(after.len());
+                let name = &after[..len];
+                let mut after = &after[len..];
+
+                let closing = after.starts_with('*');
+                if closing
+                {
+                    after = &after[1..];
+                }
+                else if let Some(c) = after.chars().next().filter(|c| c.is_whitespace())
+                {
+                    // The single space after an opening marker is part of the marker
+                    after = &after[c.len_utf8()..];
+                }
+
+                tokens.push(Token::Marker { name: name.trim_start_matches('+'), closing });
+                rest = after;
+            },
+            None => {
+                let len = rest.find('\\').unwrap_or(rest.len());
+                tokens.push(Token::Text(&rest[..len]));
+                rest = &rest[len..];
+            }
+        }
+    }
+
+    tokens
+}
+
+/// Strips the number off of markers such as `q1` or `s2`
+fn base_marker(name: &str) -> &str
+{
+    name.trim_end_matches(|c: char| c.is_ascii_digit())
+}
+
+#[derive(Debug, Default)]
+struct UsfmReader
+{
+    line: LineState,
+    book: Option<OsisBook>,
+    chapter: Option<NonZeroU32>,
+    verse: Option<VerseId>,
+    verse_indices: HashMap<VerseId, usize>,
+
+    headers: HashMap<&'static str, String>,
+    headings: Vec<String>,
+
+    red_depth: u32,
+    italics_depth: u32,
+    /// The text of the `\w` word being read, including its attributes
+    tagged_word: Option<String>,
+    next_strongs_group: u32,
+
+    footnote: Option<String>,
+    footnote_caller: bool,
+    footnote_text: bool,
+    skipped_marker: Option<String>,
+}
+
+impl UsfmReader
+{
+    fn read_token(&mut self, token: Token, bible: &mut ImportedBible)
+    {
+        match token
+        {
+            Token::Marker { name, closing: true } => self.read_closing_marker(name, bible),
+            Token::Marker { name, closing: false } => self.read_marker(name, bible),
+            Token::Text(text) => self.read_text(text, bible),
+        }
+    }
+
+    fn read_marker(&mut self, name: &str, bible: &mut ImportedBible)
+    {
+        if self.skipped_marker.is_some()
+        {
+            return;
+        }
+
+        if self.footnote.is_some()
+        {
+            self.footnote_text = FOOTNOTE_TEXT_MARKERS.contains(&name);
+            return;
+        }
+
+        let base = base_marker(name);
+        match name
+        {
+            "id" => {
+                self.finish_book(bible);
+                self.line = LineState::BookCode;
+            },
+            "c" => self.line = LineState::ChapterNumber,
+            "v" => self.line = LineState::VerseNumber,
+            "h" => self.line = LineState::Header("h"),
+            "toc1" => self.line = LineState::Header("toc1"),
+            "toc2" => self.line = LineState::Header("toc2"),
+            "mt" | "mt1" => self.line = LineState::Header("mt"),
+            "w" => self.tagged_word = Some(String::new()),
+            "wj" => self.red_depth += 1,
+            _ if ITALIC_MARKERS.contains(&name) => self.italics_depth += 1,
+            _ if FOOTNOTE_MARKERS.contains(&name) => {
+                self.footnote = Some(String::new());
+                self.footnote_caller = true;
+                self.footnote_text = true;
+            },
+            _ if SKIPPED_CHARACTER_MARKERS.contains(&name) => self.skipped_marker = Some(name.to_string()),
+            _ if HEADING_MARKERS.contains(&base) => {
+                self.line = LineState::Heading;
+                self.headings.push(String::new());
+            },
+            _ if PARAGRAPH_MARKERS.contains(&base) => self.line = LineState::Verse,
+            _ if SKIPPED_PARAGRAPH_MARKERS.contains(&base) || base.starts_with("mt") => self.line = LineState::Skip,
+            _ => {},
+        }
+    }
+
+    fn read_closing_marker(&mut self, name: &str, bible: &mut ImportedBible)
+    {
+        if let Some(skipped) = &self.skipped_marker
+        {
+            if skipped == name
+            {
+                self.skipped_marker = None;
+            }
+
+            return;
+        }
+
+        match name
+        {
+            _ if FOOTNOTE_MARKERS.contains(&name) => {
+                let Some(footnote) = self.footnote.take() else { return; };
+                let text = footnote.split_whitespace().join(" ");
+                if let (Some(verse), false) = (self.verse, text.is_empty())
+                {
+                    bible.footnotes.push(ImportedComment { verse, text });
+                }
+            },
+            _ if self.footnote.is_some() => self.footnote_text = true,
+            "w" => self.finish_tagged_word(bible),
+            "wj" => self.red_depth = self.red_depth.saturating_sub(1),
+            _ if ITALIC_MARKERS.contains(&name) => self.italics_depth = self.italics_depth.saturating_sub(1),
+            _ => {},
+        }
+    }
+
+    fn read_text(&mut self, text: &str, bible: &mut ImportedBible)
+    {
+        if self.skipped_marker.is_some()
+        {
+            return;
+        }
+
+        if let Some(footnote) = &mut self.footnote
+        {
+            let mut text = text;
+            if self.footnote_caller
+            {
+                self.footnote_caller = false;
+                text = text.trim_start().split_once(char::is_whitespace).map_or("", |(_, rest)| rest);
+            }
+
+            if self.footnote_text
+            {
+                footnote.push_str(text);
+            }
+
+            return;
+        }
+
+        if let Some(word) = &mut self.tagged_word
+        {
+            word.push_str(text);
+            return;
+        }
+
+        match self.line
+        {
+            LineState::Skip => {},
+            LineState::Verse => self.push_verse_text(text, bible),
+            LineState::BookCode => {
+                let code = text.split_whitespace().next().unwrap_or_default().to_uppercase();
+                self.book = BOOK_CODES.iter()
+                    .find(|(c, _)| *c == code)
+                    .and_then(|(_, osis)| parse_osis_book(osis));
+
+                self.chapter = None;
+                self.verse = None;
+                self.line = LineState::Skip;
+            },
+            LineState::ChapterNumber => {
+                self.chapter = text.split_whitespace().next().and_then(|c| c.parse().ok());
+                self.verse = None;
+                self.line = LineState::Skip;
+            },
+            LineState::VerseNumber => {
+                let text = text.trim_start();
+                let (number, rest) = text.split_once(char::is_whitespace).unwrap_or((text, ""));
+                self.start_verse(number, bible);
+                self.line = LineState::Verse;
+                self.push_verse_text(rest, bible);
+            },
+            LineState::Header(header) => {
+                self.headers.entry(header).or_default().push_str(text);
+            },
+            LineState::Heading => {
+                if let Some(heading) = self.headings.last_mut()
+                {
+                    heading.push_str(text);
+                }
+            },
+        }
+    }
+
+    /// Verse numbers may be ranges, such as `1-2`, in which case the text is kept under the first verse
+    fn start_verse(&mut self, number: &str, bible: &mut ImportedBible)
+    {
+        let number = number.split(|c: char| !c.is_ascii_digit()).next().unwrap_or_default();
+        self.verse = match (self.book, self.chapter, number.parse::<NonZeroU32>().ok())
+        {
+            (Some(book), Some(chapter), Some(verse)) => Some(VerseId::new(book, chapter, verse)),
+            _ => None,
+        };
+
+        let Some(verse) = self.verse else { return; };
+        for heading in self.headings.drain(..)
+        {
+            let text = heading.split_whitespace().join(" ");
+            if !text.is_empty()
+            {
+                bible.headings.push(ImportedComment { verse, text });
+            }
+        }
+    }
+
+    fn push_verse_text(&mut self, text: &str, bible: &mut ImportedBible)
+    {
+        let Some(verse) = self.verse else { return; };
+        let style = self.get_style();
+        let words = bible.get_verse_words(verse, &mut self.verse_indices);
+        split_words(text, style, &[], None, words);
+    }
+
+    /// Tagged words look like `\w gracious|strong="H2603"\w*`, where a bare attribute is the lemma
+    fn finish_tagged_word(&mut self, bible: &mut ImportedBible)
+    {
+        let Some(word) = self.tagged_word.take() else { return; };
+        let (text, attributes) = word.split_once('|').unwrap_or((&word, ""));
+        if self.line != LineState::Verse
+        {
+            return self.read_text(text, bible);
+        }
+
+        let Some(verse) = self.verse else { return; };
+        let strongs = attributes.split_whitespace()
+            .filter_map(|a| a.strip_prefix("strong="))
+            .flat_map(|a| a.trim_matches('"').split(',').filter_map(parse_strongs).collect_vec())
+            .collect_vec();
+
+        let group = Some(self.next_strongs_group);
+        self.next_strongs_group += 1;
+
+        let style = self.get_style();
+        let words = bible.get_verse_words(verse, &mut self.verse_indices);
+        split_words(text, style, &strongs, group, words);
+    }
+
+    /// Names the book from its headers, preferring the short table of contents name
+    fn finish_book(&mut self, bible: &mut ImportedBible)
+    {
+        let headers = std::mem::take(&mut self.headers);
+        self.headings.clear();
+
+        let Some(book) = self.book.take() else { return; };
+        let name = ["toc2", "h", "toc1", "mt"].iter()
+            .filter_map(|h| headers.get(h))
+            .map(|h| h.split_whitespace().join(" "))
+            .find(|h| !h.is_empty());
+
+        let code = BOOK_CODES.iter().find(|(_, osis)| parse_osis_book(osis) == Some(book));
+        let name = name.or(code.map(|(_, osis)| osis.to_string())).unwrap_or_default();
+        bible.push_book(book, name);
+    }
+
+    fn get_style(&self) -> WordStyle
+    {
+        WordStyle {
+            red: self.red_depth > 0,
+            italics: self.italics_depth > 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests 
+{
+    use super::*;
+    use crate::bible::importing::ImportedWord;
+
+    const USFM: &str = r#"\id GEN Test
+\h Genesis
+\toc2 Gen
+\mt1 The First Book of Moses
+\c 1
+\s1 The Creation
+\p
+\v 1 In the beginning \w God|strong="H430"\w* created\f + \fr 1:1 \ft Or formed\f*.
+\v 2 And the earth was \add without\add* form.
+\c 2
+\v 4-5 \wj Let there be light\wj*
+"#;
+
+    fn verse_id(chapter: u32, verse: u32) -> VerseId
+    {
+        VerseId::new(OsisBook::Gen, NonZeroU32::new(chapter).unwrap(), NonZeroU32::new(verse).unwrap())
+    }
+
+    fn verse_words(bible: &ImportedBible, chapter: u32, verse: u32) -> &[ImportedWord]
+    {
+        &bible.verses.iter().find(|v| v.id == verse_id(chapter, verse)).unwrap().words
+    }
+
+    #[test]
+    fn test_tokenize_markers() 
+    {
+        let tokens = tokenize(r"\v 1 text\w word\w*\+add more\+add*");
+
+        assert_eq!(tokens, [
+            Token::Marker { name: "v", closing: false },
+            Token::Text("1 text"),
+            Token::Marker { name: "w", closing: false },
+            Token::Text("word"),
+            Token::Marker { name: "w", closing: true },
+            Token::Marker { name: "add", closing: false },
+            Token::Text("more"),
+            Token::Marker { name: "add", closing: true },
+        ]);
+    }
+
+    #[test]
+    fn test_parse_usfm_verses() 
+    {
+        let mut bible = ImportedBible::default();
+        parse_usfm_books([USFM], &mut bible).unwrap();
+
+        let texts = |chapter, verse| verse_words(&bible, chapter, verse).iter().map(|w| w.text.as_str()).join(" ");
+        assert_eq!(bible.books, [(OsisBook::Gen, "Gen".to_string())]);
+        assert_eq!(bible.verses.len(), 3);
+        assert_eq!(texts(1, 1), "In the beginning God created");
+        assert_eq!(texts(1, 2), "And the earth was without form");
+        assert_eq!(texts(2, 4), "Let there be light");
+    }
+
+    #[test]
+    fn test_parse_usfm_styles_and_strongs() 
+    {
+        let mut bible = ImportedBible::default();
+        parse_usfm_books([USFM], &mut bible).unwrap();
+
+        let first = verse_words(&bible, 1, 1);
+        assert_eq!(first[3].strongs, [parse_strongs("H430").unwrap()]);
+        assert!(first[4].strongs.is_empty());
+        assert_eq!(first[4].end_punc.as_deref(), Some("."));
+
+        let second = verse_words(&bible, 1, 2);
+        assert_eq!(second[4].italics, Some(true));
+        assert_eq!(second[5].italics, None);
+
+        assert!(verse_words(&bible, 2, 4).iter().all(|w| w.red == Some(true)));
+    }
+
+    #[test]
+    fn test_parse_usfm_footnotes_and_headings() 
+    {
+        let mut bible = ImportedBible::default();
+        parse_usfm_books([USFM], &mut bible).unwrap();
+
+        let footnotes = bible.footnotes.iter().map(|c| (c.verse, c.text.as_str())).collect_vec();
+        let headings = bible.headings.iter().map(|c| (c.verse, c.text.as_str())).collect_vec();
+        assert_eq!(footnotes, [(verse_id(1, 1), "Or formed")]);
+        assert_eq!(headings, [(verse_id(1, 1), "The Creation")]);
+    }
+
+    #[test]
+    fn test_parse_usfm_without_verses() 
+    {
+        let mut bible = ImportedBible::default();
+        assert!(parse_usfm_books([r"\id GEN \h Genesis"], &mut bible).is_err());
+    }
+}
@@ -0,0 +1,93 @@
+pub mod ref_index;
+pub mod strongs_index;
+pub mod word_index;
+
+use std::collections::HashMap;
+
+use biblio_json::{Package, modules::{Module, ModuleId}};
+
+use crate::bible::{indexing::{ref_index::RefIndex, strongs_index::StrongsIndex, word_index::WordIndex}, versification::Versification};
+
+/// Lookup tables over the loaded package, built once when the package is initialized, 
+/// so that fetching and rendering do not have to scan every module entry
+#[derive(Debug, Clone)]
+pub struct PackageIndex
+{
+    pub refs: RefIndex,
+    pub strongs: StrongsIndex,
+    /// `None` until the background build has finished, searches fall back to scanning every verse until then
+    pub words: Option<WordIndex>,
+    /// Incremented whenever a module is reindexed, so that anything derived from the package can tell that it is out of date
+    pub generation: u64,
+    /// The generation at which each module was last reindexed
+    reindexed_at: HashMap<ModuleId, u64>,
+    /// The verse numbering scheme detected for each bible
+    versifications: HashMap<ModuleId, Versification>,
+}
+
+impl PackageIndex
+{
+    pub fn build(package: &Package) -> Self 
+    {
+        Self {
+            refs: RefIndex::build(package),
+            strongs: StrongsIndex::build(package),
+            words: None,
+            generation: 0,
+            reindexed_at: HashMap::new(),
+            versifications: package.modules.values()
+                .filter_map(Module::as_bible)
+                .map(|b| (b.config.id.clone(), Versification::detect(b)))
+                .collect(),
+        }
+    }
+
+    /// Rebuilds the parts of the index that belong to a single module, needs to be called whenever a module is edited, added or removed
+    pub fn reindex_module(&mut self, package: &Package, id: &ModuleId)
+    {
+        // The words of a bible are indexed along with their strongs numbers, so change with the bible's strongs links
+        let mut linked_bibles = self.strongs.get_links_bible(id).cloned().into_iter().collect::<Vec<_>>();
+
+        self.refs.reindex_module(package, id);
+        self.strongs.reindex_module(package, id);
+        linked_bibles.extend(self.strongs.get_links_bible(id).cloned());
+        linked_bibles.retain(|b| b != id);
+        linked_bibles.dedup();
+
+        match package.get_mod(id).and_then(Module::as_bible)
+        {
+            Some(bible) => { self.versifications.insert(id.clone(), Versification::detect(bible)); },
+            None => { self.versifications.remove(id); },
+        }
+
+        if let Some(words) = &mut self.words
+        {
+            words.reindex_module(package, &self.strongs, id);
+            for bible in &linked_bibles
+            {
+                words.reindex_module(package, &self.strongs, bible);
+            }
+        }
+
+        self.generation += 1;
+        self.reindexed_at.insert(id.clone(), self.generation);
+        for bible in linked_bibles
+        {
+            self.reindexed_at.insert(bible, self.generation);
+        }
+    }
+
+    /// The verse numbering scheme of a bible, falling back to the KJV scheme for anything else
+    pub fn get_versification(&self, bible: &ModuleId) -> Versification
+    {
+        self.versifications.get(bible).copied().unwrap_or(Versification::Kjv)
+    }
+
+    /// The modules that were added, edited or removed after the given generation
+    pub fn get_reindexed_since(&self, generation: u64) -> impl Iterator<Item = &ModuleId>
+    {
+        self.reindexed_at.iter()
+            .filter(move |(_, g)| **g > generation)
+            .map(|(id, _)| id)
+    }
+}
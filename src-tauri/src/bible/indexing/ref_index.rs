@@ -0,0 +1,411 @@
+use std::{collections::{HashMap, HashSet}, num::NonZeroU32};
+
+use biblio_json::{Package, core::{Atom, ChapterId, OsisBook, RefId, RefIdInner, VerseId}, modules::{Module, ModuleId, notebook::NotebookEntry, xrefs::XRefEntry}};
+
+use crate::bible::fetching::is_word_ref_id;
+
+/// Maps verses, chapters and books to the indices of the entries that reference them, for every cross reference, commentary and notebook module.
+/// The index is conservative, so lookups may return entries that do not match exactly, and still need to be filtered
+#[derive(Debug, Clone)]
+pub struct RefIndex
+{
+    layout: BookLayout,
+    modules: HashMap<ModuleId, ModuleRefIndex>,
+}
+
+#[derive(Debug, Clone, Default)]
+struct ModuleRefIndex
+{
+    /// Entries with a word level reference, keyed by the verse the words are in
+    words: HashMap<VerseId, Vec<usize>>,
+    verses: HashMap<VerseId, Vec<usize>>,
+    chapters: HashMap<ChapterId, Vec<usize>>,
+    books: HashMap<OsisBook, Vec<usize>>,
+}
+
+impl RefIndex
+{
+    pub fn build(package: &Package) -> Self 
+    {
+        let layout = BookLayout::new(package);
+        let modules = package.modules.values()
+            .filter_map(|m| Some((m.id().clone(), ModuleRefIndex::build(m, &layout)?)))
+            .collect();
+
+        Self { 
+            layout, 
+            modules 
+        }
+    }
+
+    pub fn reindex_module(&mut self, package: &Package, id: &ModuleId)
+    {
+        // Adding or removing a bible can change which books, chapters and verses ranges expand to, so every module is rebuilt
+        let is_bible = package.get_mod(id).and_then(Module::as_bible).is_some();
+        if is_bible || self.layout.bibles.contains(id)
+        {
+            let layout = BookLayout::new(package);
+            if layout.books != self.layout.books
+            {
+                *self = Self::build(package);
+                return;
+            }
+
+            self.layout = layout;
+        }
+
+        match package.get_mod(id).and_then(|m| ModuleRefIndex::build(m, &self.layout))
+        {
+            Some(index) => { self.modules.insert(id.clone(), index); },
+            None => { self.modules.remove(id); },
+        }
+    }
+
+    pub fn get_word_entries(&self, module: &ModuleId, verse: VerseId) -> &[usize]
+    {
+        self.modules.get(module)
+            .and_then(|m| m.words.get(&verse))
+            .map_or(&[], |e| e.as_slice())
+    }
+
+    pub fn get_verse_entries(&self, module: &ModuleId, verse: VerseId) -> &[usize]
+    {
+        self.modules.get(module)
+            .and_then(|m| m.verses.get(&verse))
+            .map_or(&[], |e| e.as_slice())
+    }
+
+    pub fn get_chapter_entries(&self, module: &ModuleId, chapter: ChapterId) -> &[usize]
+    {
+        self.modules.get(module)
+            .and_then(|m| m.chapters.get(&chapter))
+            .map_or(&[], |e| e.as_slice())
+    }
+
+    pub fn get_book_entries(&self, module: &ModuleId, book: OsisBook) -> &[usize]
+    {
+        self.modules.get(module)
+            .and_then(|m| m.books.get(&book))
+            .map_or(&[], |e| e.as_slice())
+    }
+}
+
+impl ModuleRefIndex
+{
+    /// Returns `None` if the module type does not have entries with references
+    fn build(module: &Module, layout: &BookLayout) -> Option<Self>
+    {
+        let mut index = Self::default();
+
+        match module 
+        {
+            Module::XRef(xrefs) => {
+                for (i, entry) in xrefs.entries.iter().enumerate()
+                {
+                    match entry
+                    {
+                        XRefEntry::Directed { source, .. } => index.insert(i, source, layout),
+                        XRefEntry::Mutual { refs, .. } => refs.iter().for_each(|r| index.insert(i, r, layout)),
+                    }
+                }
+            },
+            Module::Commentary(commentary) => {
+                for (i, entry) in commentary.entries.iter().enumerate()
+                {
+                    entry.references.iter().for_each(|r| index.insert(i, r, layout));
+                }
+            },
+            Module::Notebook(notebook) => {
+                for (i, entry) in notebook.entries.iter().enumerate()
+                {
+                    let references = match entry
+                    {
+                        NotebookEntry::Highlight { references, .. } => references,
+                        NotebookEntry::Note { references, .. } => references,
+                    };
+
+                    references.iter().for_each(|r| index.insert(i, r, layout));
+                }
+            },
+            _ => return None,
+        }
+
+        Some(index)
+    }
+
+    fn insert(&mut self, entry: usize, r: &RefId, layout: &BookLayout)
+    {
+        let (from, to) = match &r.id
+        {
+            RefIdInner::Single(atom) => (atom, atom),
+            RefIdInner::Range { from, to } => (from, to),
+        };
+
+        if is_word_ref_id(r)
+        {
+            for verse in layout.get_verses(from, to)
+            {
+                push_unique(self.words.entry(verse).or_default(), entry);
+            }
+        }
+        else if r.is_verse()
+        {
+            for verse in layout.get_verses(from, to)
+            {
+                push_unique(self.verses.entry(verse).or_default(), entry);
+            }
+        }
+        else if r.is_chapter()
+        {
+            for chapter in layout.get_chapters(from, to)
+            {
+                push_unique(self.chapters.entry(chapter).or_default(), entry);
+            }
+        }
+        else if r.is_book()
+        {
+            for book in layout.get_books(from, to)
+            {
+                push_unique(self.books.entry(book).or_default(), entry);
+            }
+        }
+    }
+}
+
+/// Entries are inserted in order, so a duplicate can only ever be the last element
+fn push_unique(entries: &mut Vec<usize>, entry: usize)
+{
+    if entries.last() != Some(&entry)
+    {
+        entries.push(entry);
+    }
+}
+
+/// The union of the book, chapter and verse layouts of every bible in the package, used to expand reference ranges
+#[derive(Debug, Clone)]
+struct BookLayout
+{
+    books: Vec<(OsisBook, Vec<u32>)>,
+    /// The bibles the layout was built from
+    bibles: HashSet<ModuleId>,
+}
+
+impl BookLayout
+{
+    fn new(package: &Package) -> Self 
+    {
+        let mut books: Vec<(OsisBook, Vec<u32>)> = vec![];
+        let mut bibles = HashSet::new();
+        for bible in package.modules.values().filter_map(Module::as_bible)
+        {
+            bibles.insert(bible.config.id.clone());
+            for info in &bible.source.book_infos
+            {
+                let index = match books.iter().position(|(b, _)| *b == info.osis_book)
+                {
+                    Some(index) => index,
+                    None => {
+                        books.push((info.osis_book, vec![]));
+                        books.len() - 1
+                    }
+                };
+
+                let chapters = &mut books[index].1;
+
+                for (i, count) in info.chapters.iter().enumerate()
+                {
+                    match chapters.get_mut(i)
+                    {
+                        Some(c) => *c = (*c).max(*count),
+                        None => chapters.push(*count),
+                    }
+                }
+            }
+        }
+
+        Self { books, bibles }
+    }
+
+    fn get_book_range(&self, from: &Atom, to: &Atom) -> Option<(usize, usize)>
+    {
+        let from = self.books.iter().position(|(b, _)| *b == from.book())?;
+        let to = self.books.iter().position(|(b, _)| *b == to.book())?;
+        Some((from, to))
+    }
+
+    fn get_books(&self, from: &Atom, to: &Atom) -> Vec<OsisBook>
+    {
+        let Some((from, to)) = self.get_book_range(from, to) else {
+            return vec![];
+        };
+
+        (from..=to).map(|b| self.books[b].0).collect()
+    }
+
+    fn get_chapters(&self, from: &Atom, to: &Atom) -> Vec<ChapterId>
+    {
+        let Some((from_book, to_book)) = self.get_book_range(from, to) else {
+            return vec![];
+        };
+
+        let mut result = vec![];
+        for b in from_book..=to_book
+        {
+            let (book, chapters) = &self.books[b];
+            let first = if b == from_book { from.chapter().map_or(1, |c| c.get()) } else { 1 };
+            let last = if b == to_book { to.chapter().map_or(chapters.len() as u32, |c| c.get()) } else { chapters.len() as u32 };
+
+            result.extend((first..=last).filter_map(NonZeroU32::new).map(|chapter| ChapterId {
+                book: *book,
+                chapter,
+            }));
+        }
+
+        result
+    }
+
+    fn get_verses(&self, from: &Atom, to: &Atom) -> Vec<VerseId>
+    {
+        let Some((from_book, _)) = self.get_book_range(from, to) else {
+            return vec![];
+        };
+
+        let to_chapter = ChapterId { 
+            book: to.book(), 
+            chapter: to.chapter().unwrap_or(NonZeroU32::MIN) 
+        };
+
+        let mut result = vec![];
+        for chapter in self.get_chapters(from, to)
+        {
+            let verse_count = self.books.iter()
+                .find(|(b, _)| *b == chapter.book)
+                .and_then(|(_, chapters)| chapters.get(chapter.chapter.get() as usize - 1))
+                .copied()
+                .unwrap_or(0);
+
+            let is_first = self.books[from_book].0 == chapter.book && Some(chapter.chapter) == from.chapter();
+            let is_last = to.chapter().is_some() && chapter == to_chapter;
+
+            let first = if is_first { from.verse().map_or(1, |v| v.get()) } else { 1 };
+            let last = if is_last { to.verse().map_or(verse_count, |v| v.get()) } else { verse_count };
+
+            result.extend((first..=last).filter_map(NonZeroU32::new).map(|verse| VerseId {
+                book: chapter.book,
+                chapter: chapter.chapter,
+                verse,
+            }));
+        }
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests 
+{
+    use super::*;
+
+    fn create_test_package() -> Package 
+    {
+        Package::load("./resources/biblio-json-pkg").unwrap()
+    }
+
+    fn get_book(package: &Package, index: usize) -> OsisBook
+    {
+        let bible = package.get_mod(&ModuleId::new("kjv_eng".into())).and_then(Module::as_bible).unwrap();
+        bible.source.book_infos[index].osis_book
+    }
+
+    fn verse(book: OsisBook, chapter: u32, verse: u32) -> Atom
+    {
+        Atom::Verse { book, chapter: NonZeroU32::new(chapter).unwrap(), verse: NonZeroU32::new(verse).unwrap() }
+    }
+
+    fn chapter(book: OsisBook, chapter: u32) -> Atom
+    {
+        Atom::Chapter { book, chapter: NonZeroU32::new(chapter).unwrap() }
+    }
+
+    fn verse_id(book: OsisBook, chapter: u32, verse: u32) -> VerseId
+    {
+        VerseId::new(book, NonZeroU32::new(chapter).unwrap(), NonZeroU32::new(verse).unwrap())
+    }
+
+    #[test]
+    fn test_expand_ranges()
+    {
+        let package = create_test_package();
+        let layout = BookLayout::new(&package);
+        let (gen, exod, lev) = (get_book(&package, 0), get_book(&package, 1), get_book(&package, 2));
+
+        assert_eq!(layout.get_verses(&verse(gen, 1, 30), &verse(gen, 2, 2)), [
+            verse_id(gen, 1, 30), verse_id(gen, 1, 31), verse_id(gen, 2, 1), verse_id(gen, 2, 2)
+        ]);
+        assert_eq!(layout.get_verses(&verse(gen, 50, 25), &verse(exod, 1, 2)), [
+            verse_id(gen, 50, 25), verse_id(gen, 50, 26), verse_id(exod, 1, 1), verse_id(exod, 1, 2)
+        ]);
+        assert_eq!(layout.get_verses(&verse(gen, 1, 1), &verse(gen, 1, 1)), [verse_id(gen, 1, 1)]);
+        assert!(layout.get_verses(&verse(gen, 1, 3), &verse(gen, 1, 2)).is_empty());
+
+        assert_eq!(layout.get_chapters(&chapter(gen, 49), &chapter(exod, 2)), [
+            ChapterId { book: gen, chapter: NonZeroU32::new(49).unwrap() },
+            ChapterId { book: gen, chapter: NonZeroU32::new(50).unwrap() },
+            ChapterId { book: exod, chapter: NonZeroU32::new(1).unwrap() },
+            ChapterId { book: exod, chapter: NonZeroU32::new(2).unwrap() },
+        ]);
+        assert_eq!(layout.get_verses(&chapter(gen, 50), &chapter(gen, 50)).len(), 26);
+
+        assert_eq!(layout.get_books(&Atom::Book { book: gen }, &Atom::Book { book: lev }), [gen, exod, lev]);
+    }
+
+    #[test]
+    fn test_index_entries()
+    {
+        let package = create_test_package();
+        let layout = BookLayout::new(&package);
+        let gen = get_book(&package, 0);
+
+        let mut index = ModuleRefIndex::default();
+        index.insert(0, &RefId { bible: None, id: RefIdInner::Range { from: verse(gen, 1, 31), to: verse(gen, 2, 1) } }, &layout);
+        index.insert(0, &RefId { bible: None, id: RefIdInner::Single(verse(gen, 2, 1)) }, &layout);
+        index.insert(1, &RefId { bible: None, id: RefIdInner::Single(chapter(gen, 3)) }, &layout);
+
+        assert_eq!(index.verses[&verse_id(gen, 1, 31)], [0]);
+        assert_eq!(index.verses[&verse_id(gen, 2, 1)], [0]);
+        assert!(!index.verses.contains_key(&verse_id(gen, 2, 2)));
+        assert_eq!(index.chapters[&ChapterId { book: gen, chapter: NonZeroU32::new(3).unwrap() }], [1]);
+    }
+
+    #[test]
+    fn test_reindex_added_bible()
+    {
+        let mut package = create_test_package();
+        let bible_ids = package.modules.values()
+            .filter_map(Module::as_bible)
+            .map(|b| b.config.id.clone())
+            .collect::<Vec<_>>();
+
+        let mut bibles = bible_ids.iter().filter_map(|id| package.modules.remove(id)).collect::<Vec<_>>();
+        let xrefs = package.modules.values()
+            .find(|m| matches!(m, Module::XRef(_)))
+            .map(|m| m.id().clone())
+            .unwrap();
+
+        let mut index = RefIndex::build(&package);
+        assert!(index.layout.books.is_empty());
+        assert!(index.modules[&xrefs].verses.is_empty());
+
+        let bible = bibles.pop().unwrap();
+        let bible_id = bible.id().clone();
+        package.modules.insert(bible_id.clone(), bible);
+        index.reindex_module(&package, &bible_id);
+        assert!(!index.layout.books.is_empty());
+        assert!(!index.modules[&xrefs].verses.is_empty());
+
+        package.modules.remove(&bible_id);
+        index.reindex_module(&package, &bible_id);
+        assert!(index.layout.books.is_empty());
+        assert!(index.modules[&xrefs].verses.is_empty());
+    }
+}
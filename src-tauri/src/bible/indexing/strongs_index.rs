@@ -0,0 +1,220 @@
+use std::{collections::HashMap, num::NonZeroU32};
+
+use biblio_json::{Package, core::{StrongsNumber, VerseId, WordRange}, modules::{Module, ModuleId, strongs::StrongsLinkEntry}};
+
+/// The strongs links of every bible, keyed by verse, with each word already resolved to the strongs numbers attached to it
+#[derive(Debug, Clone, Default)]
+pub struct StrongsIndex
+{
+    bibles: HashMap<ModuleId, BibleStrongsLinks>,
+}
+
+#[derive(Debug, Clone)]
+struct BibleStrongsLinks
+{
+    /// Always a [`Module::StrongsLinks`]
+    module: Module,
+    verses: HashMap<VerseId, VerseLinkIndices>,
+}
+
+#[derive(Debug, Clone)]
+struct VerseLinkIndices
+{
+    entry: usize,
+    /// For each word in the verse, the index of the linked word in the entry
+    words: Vec<Option<usize>>,
+}
+
+/// The strongs links for a single verse
+#[derive(Debug, Clone, Copy)]
+pub struct VerseStrongsLinks<'a>
+{
+    pub entry: &'a StrongsLinkEntry,
+    words: &'a [Option<usize>],
+}
+
+impl<'a> VerseStrongsLinks<'a>
+{
+    /// `index` is the 0 based index of the word in the verse
+    pub fn get_word_strongs(&self, index: usize) -> Option<&'a [StrongsNumber]>
+    {
+        let word = (*self.words.get(index)?)?;
+        Some(&self.entry.words[word].strongs)
+    }
+
+    /// The index of the linked word in the entry, shared by every word of a `WordRange::Range`
+    pub fn get_word_link(&self, index: usize) -> Option<usize>
+    {
+        *self.words.get(index)?
+    }
+}
+
+impl StrongsIndex
+{
+    pub fn build(package: &Package) -> Self 
+    {
+        let mut bibles = HashMap::new();
+        for module in package.modules.values().filter(|m| m.is_strongs_links())
+        {
+            let Some(links) = module.as_strongs_links() else { continue; };
+            if bibles.contains_key(&links.config.bible)
+            {
+                continue;
+            }
+
+            let verses = links.entries.iter().enumerate().map(|(i, entry)| {
+                (entry.verse_id, VerseLinkIndices { entry: i, words: resolve_words(entry) })
+            }).collect();
+
+            bibles.insert(links.config.bible.clone(), BibleStrongsLinks { 
+                module: module.clone(), 
+                verses 
+            });
+        }
+
+        Self { bibles }
+    }
+
+    pub fn reindex_module(&mut self, package: &Package, id: &ModuleId)
+    {
+        let was_indexed = self.bibles.values().any(|b| b.module.id() == id);
+        let is_links = package.get_mod(id).is_some_and(|m| m.is_strongs_links());
+
+        if was_indexed || is_links
+        {
+            *self = Self::build(package);
+        }
+    }
+
+    /// The bible that a strongs links module is indexed for, if it is the one used for that bible
+    pub fn get_links_bible(&self, links: &ModuleId) -> Option<&ModuleId>
+    {
+        self.bibles.iter()
+            .find(|(_, b)| b.module.id() == links)
+            .map(|(bible, _)| bible)
+    }
+
+    pub fn get_links(&self, bible: &ModuleId, verse: VerseId) -> Option<VerseStrongsLinks<'_>>
+    {
+        let links = self.bibles.get(bible)?;
+        let indices = links.verses.get(&verse)?;
+        let Module::StrongsLinks(module) = &links.module else {
+            return None;
+        };
+
+        Some(VerseStrongsLinks { 
+            entry: &module.entries[indices.entry], 
+            words: &indices.words 
+        })
+    }
+
+    pub fn get_word_strongs(&self, bible: &ModuleId, verse: VerseId, word: NonZeroU32) -> Option<&[StrongsNumber]>
+    {
+        self.get_links(bible, verse)?.get_word_strongs(word.get() as usize - 1)
+    }
+}
+
+fn resolve_words(entry: &StrongsLinkEntry) -> Vec<Option<usize>>
+{
+    let mut words = vec![];
+    for (i, word) in entry.words.iter().enumerate()
+    {
+        let (start, end) = match word.range
+        {
+            WordRange::Single(s) => (s, s),
+            WordRange::Range(s, e) => (s, e),
+        };
+
+        let end = end.get() as usize;
+        if words.len() < end
+        {
+            words.resize(end, None);
+        }
+
+        // The first link covering a word wins, matching the previous linear lookup
+        for w in start.get() as usize..=end
+        {
+            words[w - 1].get_or_insert(i);
+        }
+    }
+
+    words
+}
+
+#[cfg(test)]
+mod tests 
+{
+    use std::str::FromStr;
+
+    use biblio_json::core::OsisBook;
+
+    use super::*;
+
+    fn n(value: u32) -> NonZeroU32
+    {
+        NonZeroU32::new(value).unwrap()
+    }
+
+    /// An entry for Gen 1:1 linking each range of words to a single strongs number, written the way the importer writes it
+    fn create_entry(words: &[(WordRange, &str)]) -> StrongsLinkEntry
+    {
+        let words = words.iter().map(|(range, strongs)| serde_json::json!({
+            "range": range,
+            "strongs": [StrongsNumber::from_str(strongs).unwrap()],
+        })).collect::<Vec<_>>();
+
+        serde_json::from_value(serde_json::json!({
+            "verse_id": VerseId::new(OsisBook::Gen, n(1), n(1)),
+            "words": words,
+        })).unwrap()
+    }
+
+    #[test]
+    fn test_resolve_words()
+    {
+        let entry = create_entry(&[
+            (WordRange::Range(n(1), n(3)), "H7225"),
+            (WordRange::Single(n(5)), "H1254"),
+            (WordRange::Single(n(3)), "H430"),
+        ]);
+
+        assert_eq!(resolve_words(&entry), vec![Some(0), Some(0), Some(0), None, Some(1)]);
+    }
+
+    #[test]
+    fn test_word_strongs()
+    {
+        let entry = create_entry(&[
+            (WordRange::Range(n(1), n(2)), "H7225"),
+            (WordRange::Single(n(4)), "H430"),
+        ]);
+
+        let words = resolve_words(&entry);
+        let links = VerseStrongsLinks { entry: &entry, words: &words };
+        let get_strongs = |index| links.get_word_strongs(index).map(|s| s.iter().map(|s| s.to_string()).collect::<Vec<_>>());
+
+        assert_eq!(get_strongs(0), Some(vec!["H7225".to_string()]));
+        assert_eq!(get_strongs(1), Some(vec!["H7225".to_string()]));
+        assert_eq!(get_strongs(2), None);
+        assert_eq!(get_strongs(3), Some(vec!["H430".to_string()]));
+        assert_eq!(get_strongs(10), None);
+    }
+
+    #[test]
+    fn test_word_link()
+    {
+        let entry = create_entry(&[
+            (WordRange::Range(n(1), n(2)), "H7225"),
+            (WordRange::Single(n(4)), "H430"),
+        ]);
+
+        let words = resolve_words(&entry);
+        let links = VerseStrongsLinks { entry: &entry, words: &words };
+
+        assert_eq!(links.get_word_link(0), Some(0));
+        assert_eq!(links.get_word_link(1), Some(0));
+        assert_eq!(links.get_word_link(2), None);
+        assert_eq!(links.get_word_link(3), Some(1));
+        assert_eq!(links.get_word_link(10), None);
+    }
+}
@@ -0,0 +1,384 @@
+use std::{collections::{BTreeMap, HashMap}, num::NonZeroU32};
+
+use biblio_json::{Package, core::{StrongsNumber, VerseId, lang::Language}, modules::{Module, ModuleId, bible::BibleModule}};
+use itertools::Itertools;
+
+use crate::{bible::indexing::strongs_index::StrongsIndex, searching::word_search_engine::{WordSearchOptions, WordSearchPart, is_fuzzy_match}};
+
+/// Inverted word and strongs indices for every bible in the package. 
+/// As this takes a while to build, it is built in the background after the package is loaded
+#[derive(Debug, Clone, Default)]
+pub struct WordIndex
+{
+    bibles: HashMap<ModuleId, BibleWordIndex>,
+}
+
+impl WordIndex
+{
+    pub fn build(bibles: &[&BibleModule], strongs: &StrongsIndex) -> Self 
+    {
+        let bibles = bibles.iter()
+            .map(|b| (b.config.id.clone(), BibleWordIndex::build(b, strongs)))
+            .collect();
+
+        Self { bibles }
+    }
+
+    pub fn reindex_module(&mut self, package: &Package, strongs: &StrongsIndex, id: &ModuleId)
+    {
+        match package.get_mod(id).and_then(Module::as_bible)
+        {
+            Some(bible) => { self.bibles.insert(id.clone(), BibleWordIndex::build(&bible, strongs)); },
+            None => { self.bibles.remove(id); },
+        }
+    }
+
+    pub fn get_bible(&self, bible: &ModuleId) -> Option<&BibleWordIndex>
+    {
+        self.bibles.get(bible)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct WordPosition
+{
+    /// The index of the verse in canonical order
+    verse: u32,
+    /// The 0 based index of the word in the verse
+    word: u32,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct BibleWordIndex
+{
+    verses: Vec<VerseId>,
+    verse_indices: HashMap<VerseId, u32>,
+    language: Option<Language>,
+    /// Lowercased tokens, kept sorted so that prefixes can be looked up as a range
+    tokens: BTreeMap<String, Vec<WordPosition>>,
+    /// Keyed by the formatted strongs number, eg: `H1234`
+    strongs: HashMap<String, Vec<WordPosition>>,
+}
+
+impl BibleWordIndex
+{
+    fn build(bible: &BibleModule, strongs_index: &StrongsIndex) -> Self 
+    {
+        let mut index = Self {
+            language: bible.config.language,
+            ..Default::default()
+        };
+
+        for book in &bible.source.book_infos
+        {
+            for (chapter, verse_count) in book.chapters.iter().enumerate()
+            {
+                for verse in 1..=*verse_count
+                {
+                    let verse_id = VerseId::new(
+                        book.osis_book, 
+                        NonZeroU32::new(chapter as u32 + 1).unwrap(), 
+                        NonZeroU32::new(verse).unwrap()
+                    );
+
+                    let Some(verse) = bible.source.verses.get(&verse_id) else { continue; };
+                    let verse_index = index.verses.len() as u32;
+                    index.verses.push(verse_id);
+                    index.verse_indices.insert(verse_id, verse_index);
+
+                    let links = strongs_index.get_links(&bible.config.id, verse_id);
+                    for (i, word) in verse.words.iter().enumerate()
+                    {
+                        let position = WordPosition { verse: verse_index, word: i as u32 };
+                        index.tokens.entry(word.text.to_lowercase()).or_default().push(position);
+
+                        let strongs = links.and_then(|l| l.get_word_strongs(i)).unwrap_or_default();
+                        for s in strongs
+                        {
+                            index.strongs.entry(s.to_string()).or_default().push(position);
+                        }
+                    }
+                }
+            }
+        }
+
+        index
+    }
+
+    /// Narrows down the verses between `start` and `end` (inclusive) to ones that could match `part`, in canonical order.
+    /// Returns `None` if either end of the range is not in the bible.
+    pub fn get_candidate_verses(&self, part: &WordSearchPart, options: &WordSearchOptions, start: VerseId, end: VerseId) -> Option<Vec<VerseId>>
+    {
+        let start = *self.verse_indices.get(&start)?;
+        let end = *self.verse_indices.get(&end)?;
+        if start > end
+        {
+            return Some(vec![]);
+        }
+
+        let verses = match self.get_part_candidates(part, options)
+        {
+            Some(candidates) => candidates.into_iter()
+                .filter(|v| *v >= start && *v <= end)
+                .map(|v| self.verses[v as usize])
+                .collect_vec(),
+            None => self.verses[start as usize..=end as usize].to_vec(),
+        };
+
+        Some(verses)
+    }
+
+    pub fn verse_count(&self) -> usize
+    {
+        self.verses.len()
+    }
+
+    /// The verses with words linked to the strongs number, in canonical order
+    pub fn get_strongs_verses(&self, strongs: &StrongsNumber) -> Vec<VerseId>
+    {
+        get_positions_verses(self.strongs.get(&strongs.to_string())).into_iter()
+            .map(|v| self.verses[v as usize])
+            .collect_vec()
+    }
+
+    /// The number of verses that a single term of a query is in, or `None` if the term cannot be looked up in the index
+    pub fn get_term_verse_count(&self, term: &WordSearchPart, options: &WordSearchOptions) -> Option<usize>
+    {
+        self.get_part_candidates(term, options).map(|verses| verses.len())
+    }
+
+    /// Returns the sorted indices of every verse that could match the part, or `None` if the part cannot be narrowed down
+    fn get_part_candidates(&self, part: &WordSearchPart, options: &WordSearchOptions) -> Option<Vec<u32>>
+    {
+        match part
+        {
+            // Tokens are only lowercased, so with diacritics ignored or stemming every token has to be normalized and compared
+            WordSearchPart::Word(word) if options.ignore_diacritics || options.stem => {
+                let stemmer = options.get_stemmer(self.language);
+                let word = options.normalize_word(word, stemmer.as_ref());
+                Some(self.get_matching_candidates(|token| options.normalize_word(token, stemmer.as_ref()) == word))
+            },
+            WordSearchPart::StartsWith(prefix) if options.ignore_diacritics => {
+                let prefix = options.normalize(prefix);
+                Some(self.get_matching_candidates(|token| options.normalize(token).starts_with(&prefix)))
+            },
+            WordSearchPart::Word(word) => {
+                Some(get_positions_verses(self.tokens.get(&word.to_lowercase())))
+            },
+            WordSearchPart::StartsWith(prefix) => {
+                let prefix = prefix.to_lowercase();
+                let matches = self.tokens.range(prefix.clone()..)
+                    .take_while(|(token, _)| token.starts_with(&prefix))
+                    .map(|(_, positions)| get_positions_verses(Some(positions)))
+                    .collect_vec();
+
+                Some(union_verses(matches))
+            },
+            WordSearchPart::EndsWith(suffix) => {
+                let suffix = options.normalize(suffix);
+                Some(self.get_matching_candidates(|token| options.normalize(token).ends_with(&suffix)))
+            },
+            WordSearchPart::Fuzzy(word) => {
+                let word = options.normalize(word);
+                Some(self.get_matching_candidates(|token| is_fuzzy_match(&word, &options.normalize(token))))
+            },
+            WordSearchPart::Regex(regex) if !regex.whole_verse => {
+                Some(self.get_matching_candidates(|token| regex.is_match(&options.normalize(token))))
+            },
+            WordSearchPart::Regex(_) => None,
+            WordSearchPart::Strongs(strongs) => {
+                Some(get_positions_verses(self.strongs.get(&strongs.to_string())))
+            },
+            WordSearchPart::And(parts) | WordSearchPart::Sequence(parts) => {
+                self.get_all_candidates(parts.iter(), options)
+            },
+            // Within a verse window only one side has to hit in the verse, the other can be in a neighbouring one
+            WordSearchPart::Near { .. } | WordSearchPart::Before { .. } if options.verse_window > 0 => {
+                self.get_window_candidates(part, options)
+            },
+            WordSearchPart::Near { left, right, .. } | WordSearchPart::Before { left, right, .. } => {
+                self.get_all_candidates([left.as_ref(), right.as_ref()].into_iter(), options)
+            },
+            WordSearchPart::Or(parts) => {
+                let matches = parts.iter()
+                    .map(|p| self.get_part_candidates(p, options))
+                    .collect::<Option<Vec<_>>>()?;
+
+                Some(union_verses(matches))
+            },
+            WordSearchPart::Not(_) => None,
+        }
+    }
+
+    /// Candidates for parts that all have to match in the same verse
+    fn get_all_candidates<'a>(&self, parts: impl Iterator<Item = &'a WordSearchPart>, options: &WordSearchOptions) -> Option<Vec<u32>>
+    {
+        parts.filter_map(|p| self.get_part_candidates(p, options))
+            .reduce(|a, b| intersect_verses(&a, &b))
+    }
+
+    /// The verses that a part searched across a whole verse window could hit in, which are any that one of its words could be in
+    fn get_window_candidates(&self, part: &WordSearchPart, options: &WordSearchOptions) -> Option<Vec<u32>>
+    {
+        let candidates = match part
+        {
+            // Parts under a `NOT` never hit, so they are left out unless nothing else is left
+            WordSearchPart::And(parts) | WordSearchPart::Sequence(parts) => {
+                parts.iter().filter_map(|p| self.get_window_candidates(p, options)).collect_vec()
+            },
+            WordSearchPart::Or(parts) => {
+                parts.iter().map(|p| self.get_window_candidates(p, options)).collect::<Option<Vec<_>>>()?
+            },
+            WordSearchPart::Near { left, right, .. } | WordSearchPart::Before { left, right, .. } => {
+                vec![self.get_window_candidates(left, options)?, self.get_window_candidates(right, options)?]
+            },
+            WordSearchPart::Not(_) => return None,
+            _ => return self.get_part_candidates(part, options),
+        };
+
+        if candidates.is_empty() { None } else { Some(union_verses(candidates)) }
+    }
+
+    /// The verses of every token that passes `matches`, found by checking each token in the index
+    fn get_matching_candidates(&self, matches: impl Fn(&str) -> bool) -> Vec<u32>
+    {
+        let matches = self.tokens.iter()
+            .filter(|(token, _)| matches(token.as_str()))
+            .map(|(_, positions)| get_positions_verses(Some(positions)))
+            .collect_vec();
+
+        union_verses(matches)
+    }
+}
+
+fn get_positions_verses(positions: Option<&Vec<WordPosition>>) -> Vec<u32>
+{
+    positions.map(|p| p.iter().map(|p| p.verse).dedup().collect_vec()).unwrap_or_default()
+}
+
+fn union_verses(verses: Vec<Vec<u32>>) -> Vec<u32>
+{
+    verses.into_iter().kmerge().dedup().collect_vec()
+}
+
+fn intersect_verses(a: &[u32], b: &[u32]) -> Vec<u32>
+{
+    let mut result = vec![];
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() && j < b.len()
+    {
+        match a[i].cmp(&b[j])
+        {
+            std::cmp::Ordering::Less => i += 1,
+            std::cmp::Ordering::Greater => j += 1,
+            std::cmp::Ordering::Equal => {
+                result.push(a[i]);
+                i += 1;
+                j += 1;
+            }
+        }
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests 
+{
+    use std::str::FromStr;
+
+    use biblio_json::core::OsisBook;
+
+    use super::*;
+
+    /// An index of Genesis 1 with the given verses, one word per space
+    fn index_verses(verses: &[&str]) -> BibleWordIndex
+    {
+        let mut index = BibleWordIndex::default();
+        for (v, text) in verses.iter().enumerate()
+        {
+            let verse_id = VerseId::new(OsisBook::Gen, NonZeroU32::new(1).unwrap(), NonZeroU32::new(v as u32 + 1).unwrap());
+            index.verses.push(verse_id);
+            index.verse_indices.insert(verse_id, v as u32);
+
+            for (w, word) in text.split_whitespace().enumerate()
+            {
+                let position = WordPosition { verse: v as u32, word: w as u32 };
+                index.tokens.entry(word.to_string()).or_default().push(position);
+            }
+        }
+
+        index
+    }
+
+    fn near(left: &str, right: &str, distance: u32) -> WordSearchPart
+    {
+        WordSearchPart::Near { 
+            left: Box::new(WordSearchPart::Word(left.into())), 
+            right: Box::new(WordSearchPart::Word(right.into())), 
+            distance 
+        }
+    }
+
+    #[test]
+    fn test_and_candidates()
+    {
+        let index = index_verses(&["in the beginning", "god created", "the beginning of god"]);
+        let query = WordSearchPart::And(vec![WordSearchPart::Word("beginning".into()), WordSearchPart::Word("god".into())]);
+
+        assert_eq!(index.get_part_candidates(&query, &WordSearchOptions::default()), Some(vec![2]));
+    }
+
+    #[test]
+    fn test_near_candidates_across_verses()
+    {
+        let index = index_verses(&["in the beginning", "god created", "the heaven"]);
+        let query = near("beginning", "god", 3);
+
+        assert_eq!(index.get_part_candidates(&query, &WordSearchOptions::default()), Some(vec![]));
+
+        let options = WordSearchOptions { verse_window: 1, ..Default::default() };
+        assert_eq!(index.get_part_candidates(&query, &options), Some(vec![0, 1]));
+
+        let query = WordSearchPart::And(vec![WordSearchPart::Word("beginning".into()), WordSearchPart::Word("god".into())]);
+        assert_eq!(index.get_part_candidates(&query, &options), Some(vec![]));
+    }
+
+    #[test]
+    fn test_candidates_skip_unnarrowed_parts()
+    {
+        let index = index_verses(&["in the beginning", "god created"]);
+        let query = WordSearchPart::And(vec![WordSearchPart::Word("god".into()), WordSearchPart::Not(Box::new(WordSearchPart::Word("in".into())))]);
+
+        assert_eq!(index.get_part_candidates(&query, &WordSearchOptions::default()), Some(vec![1]));
+        assert_eq!(index.get_part_candidates(&WordSearchPart::Not(Box::new(WordSearchPart::Word("in".into()))), &WordSearchOptions::default()), None);
+    }
+
+    #[test]
+    fn test_strongs_verses()
+    {
+        let mut index = index_verses(&["in the beginning", "god created", "the beginning of god"]);
+        index.strongs.insert("H430".into(), vec![WordPosition { verse: 1, word: 0 }, WordPosition { verse: 2, word: 3 }]);
+
+        let verses = index.get_strongs_verses(&StrongsNumber::from_str("H430").unwrap());
+        assert_eq!(verses, vec![index.verses[1], index.verses[2]]);
+        assert!(index.get_strongs_verses(&StrongsNumber::from_str("H7225").unwrap()).is_empty());
+    }
+
+    #[test]
+    fn test_combine_verses()
+    {
+        assert_eq!(union_verses(vec![vec![0, 3, 5], vec![1, 3], vec![]]), vec![0, 1, 3, 5]);
+        assert_eq!(intersect_verses(&[0, 3, 5, 8], &[1, 3, 8]), vec![3, 8]);
+        assert_eq!(intersect_verses(&[0, 3], &[]), Vec::<u32>::new());
+
+        let positions = vec![
+            WordPosition { verse: 0, word: 1 }, 
+            WordPosition { verse: 0, word: 4 }, 
+            WordPosition { verse: 2, word: 0 },
+        ];
+
+        assert_eq!(get_positions_verses(Some(&positions)), vec![0, 2]);
+        assert_eq!(get_positions_verses(None), Vec::<u32>::new());
+    }
+}
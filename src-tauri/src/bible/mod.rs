@@ -4,35 +4,201 @@ pub mod render;
 pub mod fetching;
 pub mod ref_id_parsing;
 pub mod printing;
-
-use std::{collections::HashSet, num::NonZeroU32, sync::{Arc, Mutex, RwLock}, thread::spawn};
-
-use biblio_json::{self, Package, core::{Atom, ChapterId, RefIdInner}, modules::{ModuleId, ModuleType, bible::{BibleModule, BookInfo}}};
+pub mod notebooks;
+pub mod notebook_export;
+pub mod notebook_import;
+pub mod indexing;
+pub mod package_loading;
+pub mod user_modules;
+pub mod importing;
+pub mod versification;
+pub mod concordance;
+
+use std::{collections::{HashMap, HashSet}, num::NonZeroU32, path::Path, sync::{Arc, Mutex, RwLock}, thread::spawn};
+
+use biblio_json::{self, Package, core::{Atom, ChapterId, RefIdInner, lang::Language}, modules::{Module, ModuleId, ModuleType, bible::{BibleModule, BookInfo}}};
 use itertools::Itertools;
 use serde::{Deserialize, Serialize};
-use tauri::{AppHandle, Emitter, Listener, Manager, utils::platform::resource_dir};
+use tauri::{AppHandle, Emitter, Listener, Manager, path::BaseDirectory, utils::platform::resource_dir};
 
-use crate::core::app::AppState;
+use crate::{bible::{indexing::{PackageIndex, word_index::WordIndex}, notebooks::{NotebookStore, USER_PACKAGE_PATH}, package_loading::{FailedModuleLoad, PACKAGE_LOAD_REPORT_EVENT_NAME, PackageLoadReport, load_package}, user_modules::{USER_MODULES_PATH, UserModuleStore}}, core::app::AppState};
 
 pub const BIBLIO_JSON_PACKAGE_INITIALIZED_EVENT_NAME: &str = "bible-package-initialized";
 pub const BIBLE_DISPLAY_SETTINGS_CHANGED_EVENT_NAME: &str = "bible-display-settings-changed";
 pub const BIBLE_PACKAGE_PATH: &str = "resources/biblio-json-pkg";
+pub const PACKAGE_STAGING_PATH: &str = "package-staging";
+pub const PACKAGE_NOT_LOADED_ERROR: &str = "The bible package has not been loaded";
+
+#[derive(Debug)]
+struct LoadedPackage
+{
+    package: Package,
+    index: PackageIndex,
+    /// Modules taken out of the package so that nothing fetches or searches them
+    disabled: HashMap<ModuleId, Module>,
+}
 
 #[derive(Debug, Clone)]
-pub struct BiblioJsonPackageHandle(Arc<RwLock<Option<Package>>>);
+pub struct BiblioJsonPackageHandle
+{
+    loaded: Arc<RwLock<Option<LoadedPackage>>>,
+    report: Arc<RwLock<Option<PackageLoadReport>>>,
+}
 
 impl BiblioJsonPackageHandle
 {
-    pub fn visit<R>(&self, f: impl Fn(&Package) -> R) -> R 
+    /// Returns `None` if the package has not finished loading, or failed to load
+    pub fn visit<R>(&self, f: impl Fn(&Package) -> R) -> Option<R> 
     {
-        let binding = self.0.try_read().unwrap();
-        let package = binding.as_ref().unwrap();
-        f(package)
+        let binding = self.loaded.read().ok()?;
+        let loaded = binding.as_ref()?;
+        Some(f(&loaded.package))
+    }
+
+    pub fn visit_indexed<R>(&self, f: impl Fn(&Package, &PackageIndex) -> R) -> Option<R> 
+    {
+        let binding = self.loaded.read().ok()?;
+        let loaded = binding.as_ref()?;
+        Some(f(&loaded.package, &loaded.index))
+    }
+
+    /// Any module changed through this must then be passed to [`Self::reindex_module`].
+    /// Changes to modules that are already indexed should go through [`Self::edit_module`] instead
+    pub fn visit_mut<R>(&self, f: impl FnOnce(&mut Package) -> R) -> Option<R> 
+    {
+        let mut binding = self.loaded.write().ok()?;
+        let loaded = binding.as_mut()?;
+        Some(f(&mut loaded.package))
+    }
+
+    /// Changes a module and reindexes it before releasing the lock, so that nothing can fetch through an index that no longer matches its entries.
+    /// `f` returns the id of the module it changed
+    pub fn edit_module<R>(&self, f: impl FnOnce(&mut Package) -> Result<(ModuleId, R), String>) -> Option<Result<(ModuleId, R), String>> 
+    {
+        let mut binding = self.loaded.write().ok()?;
+        let loaded = binding.as_mut()?;
+        let result = f(&mut loaded.package);
+
+        if let Ok((id, _)) = &result
+        {
+            loaded.index.reindex_module(&loaded.package, id);
+        }
+
+        Some(result)
+    }
+
+    pub fn visit_disabled<R>(&self, f: impl Fn(&HashMap<ModuleId, Module>) -> R) -> Option<R> 
+    {
+        let binding = self.loaded.read().ok()?;
+        let loaded = binding.as_ref()?;
+        Some(f(&loaded.disabled))
+    }
+
+    /// Whether the module is in the package, either enabled or disabled.
+    /// Returns `None` if the package has not finished loading, or failed to load
+    pub fn contains_module(&self, id: &ModuleId) -> Option<bool>
+    {
+        let binding = self.loaded.read().ok()?;
+        let loaded = binding.as_ref()?;
+        Some(loaded.package.modules.contains_key(id) || loaded.disabled.contains_key(id))
+    }
+
+    pub fn disable_module(&self, id: &ModuleId) -> Result<(), String>
+    {
+        let mut binding = self.loaded.write().map_err(|e| e.to_string())?;
+        let loaded = binding.as_mut().ok_or(PACKAGE_NOT_LOADED_ERROR)?;
+        let module = loaded.package.modules.remove(id)
+            .ok_or(format!("Module '{}' is not enabled", id))?;
+
+        loaded.disabled.insert(id.clone(), module);
+        loaded.index.reindex_module(&loaded.package, id);
+        Ok(())
+    }
+
+    pub fn enable_module(&self, id: &ModuleId) -> Result<(), String>
+    {
+        let mut binding = self.loaded.write().map_err(|e| e.to_string())?;
+        let loaded = binding.as_mut().ok_or(PACKAGE_NOT_LOADED_ERROR)?;
+        let module = loaded.disabled.remove(id)
+            .ok_or(format!("Module '{}' is not disabled", id))?;
+
+        loaded.package.modules.insert(id.clone(), module);
+        loaded.index.reindex_module(&loaded.package, id);
+        Ok(())
+    }
+
+    /// Removes a module from the package, whether or not it is disabled
+    pub fn remove_module(&self, id: &ModuleId) -> Result<Module, String>
+    {
+        let mut binding = self.loaded.write().map_err(|e| e.to_string())?;
+        let loaded = binding.as_mut().ok_or(PACKAGE_NOT_LOADED_ERROR)?;
+        let module = loaded.package.modules.remove(id)
+            .or_else(|| loaded.disabled.remove(id))
+            .ok_or(format!("Module '{}' does not exist", id))?;
+
+        loaded.index.reindex_module(&loaded.package, id);
+        Ok(module)
+    }
+
+    /// Adds modules to the package and indexes them under the same lock, so that nothing can see them before they are indexed
+    pub fn add_modules(&self, modules: Vec<Module>) -> Result<(), String>
+    {
+        let mut binding = self.loaded.write().map_err(|e| e.to_string())?;
+        let loaded = binding.as_mut().ok_or(PACKAGE_NOT_LOADED_ERROR)?;
+
+        let ids = modules.iter().map(|m| m.id().clone()).collect_vec();
+        loaded.package.modules.extend(modules.into_iter().map(|m| (m.id().clone(), m)));
+        for id in &ids
+        {
+            loaded.index.reindex_module(&loaded.package, id);
+        }
+
+        Ok(())
+    }
+
+    pub fn reindex_module(&self, id: &ModuleId)
+    {
+        let Ok(mut binding) = self.loaded.write() else { return; };
+        let Some(loaded) = binding.as_mut() else { return; };
+        loaded.index.reindex_module(&loaded.package, id);
+    }
+
+    /// Returns `None` if the package has not finished loading yet
+    pub fn get_load_report(&self) -> Option<PackageLoadReport>
+    {
+        self.report.read().ok()?.clone()
+    }
+
+    /// Builds the word index without holding the lock, only taking it again to store the result
+    fn build_word_index(&self)
+    {
+        let Some((bibles, strongs, generation)) = self.visit_indexed(|p, index| {
+            let bibles = p.modules.values().filter_map(Module::as_bible).collect_vec();
+            (bibles, index.strongs.clone(), index.generation)
+        }) else { return; };
+
+        let bible_refs = bibles.iter().map(|b| b.as_ref()).collect_vec();
+        let mut words = WordIndex::build(&bible_refs, &strongs);
+
+        let Ok(mut binding) = self.loaded.write() else { return; };
+        let Some(loaded) = binding.as_mut() else { return; };
+        
+        // Bibles added, changed or removed while the index was building would otherwise be out of date
+        let changed = loaded.index.get_reindexed_since(generation).cloned().collect_vec();
+        for id in changed
+        {
+            words.reindex_module(&loaded.package, &loaded.index.strongs, &id);
+        }
+
+        loaded.index.words = Some(words);
+
+        // Searches ranked by relevance before this measured term rarity across their hits alone, so cached ones are out of date
+        loaded.index.generation += 1;
     }
 
     pub fn is_initialized(&self) -> bool
     {
-        self.0.read().unwrap().is_some()
+        self.loaded.read().is_ok_and(|l| l.is_some())
     }
 
     pub fn init(app_handle: tauri::AppHandle) -> Self 
@@ -41,28 +207,108 @@ impl BiblioJsonPackageHandle
             .unwrap()
             .join(BIBLE_PACKAGE_PATH);
 
-        let bible_package = Self(Arc::new(RwLock::new(None)));
-        let package_ref = bible_package.0.clone();
+        let staging_dir = app_handle.path()
+            .resolve(PACKAGE_STAGING_PATH, BaseDirectory::AppCache)
+            .unwrap();
+
+        let bible_package = Self {
+            loaded: Arc::new(RwLock::new(None)),
+            report: Arc::new(RwLock::new(None)),
+        };
+
+        let handle = bible_package.clone();
 
         spawn(move || {
-            let s = path.to_string_lossy();
-            let path = s.strip_prefix(r"\\?\")
-                .unwrap_or(&s)
-                .to_string();
+            let (package, mut report) = load_package(&path, &staging_dir, &[]);
+
+            let Some(mut package) = package else {
+                log::error!("Failed to load the bible package: {}", report.fatal_error.as_deref().unwrap_or_default());
+                handle.set_load_report(&app_handle, report);
+                return;
+            };
 
-            let package = match Package::load(&path) {
-                Ok(ok) => ok,
+            match app_handle.state::<NotebookStore>().load_notebooks()
+            {
+                Ok(notebooks) => package.modules.extend(notebooks.into_iter().map(|n| (n.id().clone(), n))),
                 Err(e) => {
-                    panic!("Package Loaded with errors {}:\n{}", e.len(), e.iter().map(|e| e.to_string()).join("\n---------------------------------------------\n"))
+                    log::error!("Failed to load user notebooks:\n{}", e);
+                    report.failed_modules.push(FailedModuleLoad { 
+                        path: USER_PACKAGE_PATH.into(), 
+                        errors: vec![e] 
+                    });
+                },
+            }
+
+            let (user_modules, user_report) = app_handle.state::<UserModuleStore>().load_modules();
+            package.modules.extend(user_modules.into_iter().map(|m| (m.id().clone(), m)));
+            report.failed_modules.extend(user_report.failed_modules);
+            if let Some(e) = user_report.fatal_error
+            {
+                log::error!("Failed to load user modules:\n{}", e);
+                report.failed_modules.push(FailedModuleLoad { 
+                    path: USER_MODULES_PATH.into(), 
+                    errors: vec![e] 
+                });
+            }
+
+            let disabled_ids = app_handle.state::<Mutex<AppState>>().lock().unwrap().disabled_modules.clone();
+            let disabled = disabled_ids.iter()
+                .filter_map(|id| package.modules.remove(id).map(|m| (id.clone(), m)))
+                .collect();
+
+            let index = PackageIndex::build(&package);
+            match handle.loaded.write()
+            {
+                Ok(mut loaded) => *loaded = Some(LoadedPackage { package, index, disabled }),
+                Err(e) => {
+                    handle.set_load_report(&app_handle, PackageLoadReport::fatal(e.to_string()));
+                    return;
                 }
-            };
+            }
 
-            *package_ref.try_write().unwrap() = Some(package);
-            app_handle.emit(BIBLIO_JSON_PACKAGE_INITIALIZED_EVENT_NAME, ())
+            handle.set_load_report(&app_handle, report);
+            if let Err(e) = app_handle.emit(BIBLIO_JSON_PACKAGE_INITIALIZED_EVENT_NAME, ())
+            {
+                log::error!("Failed to emit package initialized event: {}", e);
+            }
+
+            handle.build_word_index();
         });
 
         bible_package
     }
+
+    fn set_load_report(&self, app_handle: &AppHandle, report: PackageLoadReport)
+    {
+        if let Ok(mut binding) = self.report.write()
+        {
+            *binding = Some(report.clone());
+        }
+
+        if let Err(e) = app_handle.emit(PACKAGE_LOAD_REPORT_EVENT_NAME, report)
+        {
+            log::error!("Failed to emit package load report: {}", e);
+        }
+    }
+}
+
+/// The first bible in the package written in `language`
+pub fn get_language_default_bible(package: &Package, language: Option<Language>) -> Option<ModuleId>
+{
+    let language = language?;
+    package.modules.values()
+        .filter_map(Module::as_bible)
+        .find(|b| b.config.language == Some(language))
+        .map(|b| b.config.id.clone())
+}
+
+/// Converts a path into the form `Package::load` expects, stripping the windows extended length prefix
+pub fn to_package_path(path: &Path) -> String
+{
+    let s = path.to_string_lossy();
+    s.strip_prefix(r"\\?\")
+        .unwrap_or(&s)
+        .to_string()
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -351,6 +597,34 @@ impl BibleDisplaySettings
         }
     }
 
+    /// Stops showing modules that are no longer in the package, and moves the bible versions that 
+    /// are gone over to the default bible for `language`, or any bible if there is none
+    pub fn remove_missing_modules(&mut self, package: &Package, language: Option<Language>)
+    {
+        self.shown_modules.retain(|id| package.modules.contains_key(id));
+
+        let is_bible = |id: &ModuleId| package.get_mod(id).is_some_and(|m| m.as_bible().is_some());
+        if is_bible(&self.bible_version) && is_bible(&self.parallel_version)
+        {
+            return;
+        }
+
+        let Some(fallback) = get_language_default_bible(package, language)
+            .or_else(|| package.modules.values().filter_map(Module::as_bible).map(|b| b.config.id.clone()).min_by_key(|id| id.get().to_string())) else {
+            return;
+        };
+
+        if !is_bible(&self.bible_version)
+        {
+            self.bible_version = fallback.clone();
+        }
+
+        if !is_bible(&self.parallel_version)
+        {
+            self.parallel_version = fallback;
+        }
+    }
+
     pub fn add_on_package_init_listener(handle: AppHandle)
     {
         let handle_copy = handle.clone();
@@ -361,9 +635,13 @@ impl BibleDisplaySettings
             let package = handle.state::<BiblioJsonPackageHandle>();
 
             let old = state.bible_display_settings.clone();
-            state.bible_display_settings = package.visit(|p| {
-                Self::new(p)
-            });
+            let Some(settings) = package.visit(|p| {
+                let mut settings = Self::new(p);
+                settings.remove_missing_modules(p, None);
+                settings
+            }) else { return; };
+
+            state.bible_display_settings = settings;
 
             handle.emit(BIBLE_DISPLAY_SETTINGS_CHANGED_EVENT_NAME, BibleDisplaySettingsChangedEvent {
                 old: old,
@@ -0,0 +1,394 @@
+use std::io::{Cursor, Write};
+
+use biblio_json::{core::{Atom, RefId, RefIdInner}, html_text::{HtmlText, ast::{HRefSrc, Node}}, modules::{bible::BibleModule, notebook::{NotebookEntry, NotebookModule}}};
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+use zip::{ZipWriter, write::SimpleFileOptions};
+
+use crate::{bible::{fetching::get_first_verse, importing::osis_book_name, notebooks::{write_notebook_config, write_notebook_entries}}, core::utils::escape_html};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NotebookExportFormat
+{
+    Markdown,
+    /// A zip of the notebook's biblio_json config and entries files
+    Json,
+    Osis,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "type")]
+pub enum NotebookExportResult
+{
+    Exported
+    {
+        path: String,
+    },
+    Error
+    {
+        message: String,
+    }
+}
+
+impl From<Result<String, String>> for NotebookExportResult
+{
+    fn from(value: Result<String, String>) -> Self
+    {
+        match value
+        {
+            Ok(path) => Self::Exported { path },
+            Err(message) => Self::Error { message },
+        }
+    }
+}
+
+/// Writes the notebook out in the format, with the Markdown and OSIS entries ordered canonically by their first reference.
+/// References in Markdown are written with the book names of `bible`
+pub fn export_notebook(notebook: &NotebookModule, bible: &BibleModule, format: NotebookExportFormat) -> Result<Vec<u8>, String>
+{
+    let entries = get_ordered_entries(notebook, bible);
+    match format
+    {
+        NotebookExportFormat::Markdown => Ok(export_markdown(notebook, &entries, bible).into_bytes()),
+        NotebookExportFormat::Json => export_biblio_json(notebook),
+        NotebookExportFormat::Osis => export_osis(notebook, &entries).map(String::into_bytes),
+    }
+}
+
+/// Zips the notebook's config and entries files exactly as the notebook store writes them,
+/// so that the archive can be imported again as a notebook module
+fn export_biblio_json(notebook: &NotebookModule) -> Result<Vec<u8>, String>
+{
+    let id = &notebook.config.id;
+    let files = [
+        (format!("{}.toml", id), write_notebook_config(notebook)?),
+        (format!("{}.jsonl", id), write_notebook_entries(&notebook.entries)?),
+    ];
+
+    let mut zip = ZipWriter::new(Cursor::new(vec![]));
+    for (name, contents) in files
+    {
+        zip.start_file(name, SimpleFileOptions::default()).map_err(|e| e.to_string())?;
+        zip.write_all(contents.as_bytes()).map_err(|e| e.to_string())?;
+    }
+
+    Ok(zip.finish().map_err(|e| e.to_string())?.into_inner())
+}
+
+fn get_ordered_entries<'a>(notebook: &'a NotebookModule, bible: &BibleModule) -> Vec<&'a NotebookEntry>
+{
+    notebook.entries.iter()
+        .sorted_by_key(|e| {
+            get_entry_references(e).first().map(|r| {
+                let (verse, _) = get_first_verse(r);
+                let book = bible.source.book_infos.iter().position(|b| b.osis_book == verse.book).unwrap_or(usize::MAX);
+                (book, verse.chapter, verse.verse)
+            })
+            // Entries without references go last
+            .map_or((1, None), |key| (0, Some(key)))
+        })
+        .collect()
+}
+
+fn get_entry_references(entry: &NotebookEntry) -> &[RefId]
+{
+    match entry
+    {
+        NotebookEntry::Note { references, .. } => references,
+        NotebookEntry::Highlight { references, .. } => references,
+    }
+}
+
+fn export_markdown(notebook: &NotebookModule, entries: &[&NotebookEntry], bible: &BibleModule) -> String
+{
+    let mut markdown = format!("# {}\n\n", notebook.config.name);
+    if let Some(description) = &notebook.config.description
+    {
+        markdown.push_str(&html_to_markdown(description, bible));
+        markdown.push('\n');
+    }
+
+    for entry in entries
+    {
+        let references = get_entry_references(entry).iter().map(|r| format_ref_id(r, bible)).join("; ");
+        match entry
+        {
+            NotebookEntry::Note { name, content, .. } => {
+                let title = name.as_deref().filter(|n| !n.is_empty()).unwrap_or(&references);
+                markdown.push_str(&format!("## {}\n\n", title));
+                if name.is_some() && !references.is_empty()
+                {
+                    markdown.push_str(&format!("*{}*\n\n", references));
+                }
+
+                markdown.push_str(&html_to_markdown(content, bible));
+            },
+            NotebookEntry::Highlight { name, description, color, .. } => {
+                markdown.push_str(&format!("## {}\n\n", name));
+                if !references.is_empty()
+                {
+                    markdown.push_str(&format!("*{}*\n\n", references));
+                }
+
+                markdown.push_str(&format!("Highlight: `{}`\n\n", color));
+                if let Some(description) = description
+                {
+                    markdown.push_str(&html_to_markdown(description, bible));
+                }
+            },
+        }
+
+        markdown.push('\n');
+    }
+
+    markdown.trim_end().to_string() + "\n"
+}
+
+/// Formats a reference with the full book names of the bible, such as `John 3:16-18`
+pub fn format_ref_id(id: &RefId, bible: &BibleModule) -> String
+{
+    let book_name = |atom: &Atom| {
+        let book = atom.book();
+        bible.config.books.get(&book).cloned().unwrap_or_else(|| osis_book_name(book).unwrap_or_default())
+    };
+
+    // The chapter and verse of an atom, without its book
+    let location = |atom: &Atom| match atom
+    {
+        Atom::Book { .. } => None,
+        Atom::Chapter { chapter, .. } => Some(chapter.to_string()),
+        Atom::Verse { chapter, verse, .. } | Atom::Word { chapter, verse, .. } => Some(format!("{}:{}", chapter, verse)),
+    };
+
+    let format_atom = |atom: &Atom| match location(atom)
+    {
+        Some(location) => format!("{} {}", book_name(atom), location),
+        None => book_name(atom),
+    };
+
+    match &id.id
+    {
+        RefIdInner::Single(atom) => format_atom(atom),
+        RefIdInner::Range { from, to } if from.book() == to.book() => {
+            match (from, to, location(to))
+            {
+                (Atom::Verse { chapter: a, .. } | Atom::Word { chapter: a, .. }, Atom::Verse { chapter: b, verse, .. } | Atom::Word { chapter: b, verse, .. }, _) if a == b => {
+                    format!("{}-{}", format_atom(from), verse)
+                },
+                (_, _, Some(end)) if location(from).is_some() => format!("{}-{}", format_atom(from), end),
+                _ => format!("{} - {}", format_atom(from), format_atom(to)),
+            }
+        },
+        RefIdInner::Range { from, to } => format!("{} - {}", format_atom(from), format_atom(to)),
+    }
+}
+
+/// Formats a reference as an OSIS reference, such as `John.3.16-John.3.18`
+fn format_osis_ref_id(id: &RefId) -> Result<String, String>
+{
+    let format_atom = |atom: &Atom| -> Result<String, String> {
+        let book = osis_book_name(atom.book())?;
+        Ok(match atom
+        {
+            Atom::Book { .. } => book,
+            Atom::Chapter { chapter, .. } => format!("{}.{}", book, chapter),
+            Atom::Verse { chapter, verse, .. } | Atom::Word { chapter, verse, .. } => format!("{}.{}.{}", book, chapter, verse),
+        })
+    };
+
+    match &id.id
+    {
+        RefIdInner::Single(atom) => format_atom(atom),
+        RefIdInner::Range { from, to } => Ok(format!("{}-{}", format_atom(from)?, format_atom(to)?)),
+    }
+}
+
+fn html_to_markdown(html: &HtmlText, bible: &BibleModule) -> String
+{
+    html.nodes.iter().map(|n| node_to_markdown(n, bible)).join("")
+}
+
+fn node_to_markdown(node: &Node, bible: &BibleModule) -> String
+{
+    let content = |nodes: &[Node]| nodes.iter().map(|n| node_to_markdown(n, bible)).join("");
+    match node
+    {
+        Node::Paragraph(nodes) => format!("{}\n\n", content(nodes).trim()),
+        Node::Heading { content: nodes, .. } => format!("### {}\n\n", content(nodes).trim()),
+        Node::List { ordered, items } => {
+            let items = items.iter().enumerate().map(|(i, item)| {
+                let text = node_to_markdown(item, bible);
+                match ordered
+                {
+                    true => format!("{}. {}", i + 1, text.trim()),
+                    false => format!("- {}", text.trim()),
+                }
+            });
+
+            format!("{}\n\n", items.join("\n"))
+        },
+        Node::ListItem(nodes) => content(nodes),
+        Node::HorizontalRule => "---\n\n".into(),
+        Node::Text(text) => text.clone(),
+        Node::Underline(nodes) => format!("<u>{}</u>", content(nodes)),
+        Node::Italic(nodes) => format!("*{}*", content(nodes)),
+        Node::Bold(nodes) => format!("**{}**", content(nodes)),
+        Node::Strike(nodes) => format!("~~{}~~", content(nodes)),
+        Node::Image { alt, .. } => alt.as_ref().map(|a| format!("*[{}]*", a)).unwrap_or_default(),
+        Node::Anchor { href: HRefSrc::RefId(id), content: nodes } if nodes.is_empty() => format_ref_id(id, bible),
+        Node::Anchor { content: nodes, .. } => content(nodes),
+        Node::LineBreak => "  \n".into(),
+    }
+}
+
+/// Writes the notebook as an OSIS commentary, with one `div` per entry annotating the verses it references
+fn export_osis(notebook: &NotebookModule, entries: &[&NotebookEntry]) -> Result<String, String>
+{
+    let id = &notebook.config.id;
+    let mut osis = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    osis.push_str("<osis xmlns=\"http://www.bibletechnologies.net/2003/OSIS/namespace\">\n");
+    osis.push_str(&format!("<osisText osisIDWork=\"{}\" osisRefWork=\"commentary\">\n", id));
+    osis.push_str("<header>\n");
+    osis.push_str(&format!("<work osisWork=\"{}\">\n<title>{}</title>\n<type type=\"OSIS\">Commentary</type>\n</work>\n", id, escape_html(&notebook.config.name)));
+    osis.push_str("</header>\n");
+
+    if let Some(description) = &notebook.config.description
+    {
+        osis.push_str(&format!("<div type=\"introduction\">\n{}\n</div>\n", html_to_osis(description)?));
+    }
+
+    for entry in entries
+    {
+        let references = get_entry_references(entry).iter().map(format_osis_ref_id).collect::<Result<Vec<_>, _>>()?;
+        let (title, body) = match entry
+        {
+            NotebookEntry::Note { name, content, .. } => (name.clone(), Some(content)),
+            NotebookEntry::Highlight { name, description, .. } => (Some(name.clone()), description.as_ref()),
+        };
+
+        osis.push_str(&format!("<div type=\"section\" annotateType=\"commentary\" annotateRef=\"{}\">\n", references.join(" ")));
+        if let Some(title) = title.filter(|t| !t.is_empty())
+        {
+            osis.push_str(&format!("<title>{}</title>\n", escape_html(&title)));
+        }
+
+        if let Some(body) = body
+        {
+            osis.push_str(&html_to_osis(body)?);
+            osis.push('\n');
+        }
+
+        osis.push_str("</div>\n");
+    }
+
+    osis.push_str("</osisText>\n</osis>\n");
+    Ok(osis)
+}
+
+fn html_to_osis(html: &HtmlText) -> Result<String, String>
+{
+    Ok(html.nodes.iter().map(node_to_osis).collect::<Result<Vec<_>, _>>()?.join(""))
+}
+
+fn node_to_osis(node: &Node) -> Result<String, String>
+{
+    let content = |nodes: &[Node]| -> Result<String, String> {
+        Ok(nodes.iter().map(node_to_osis).collect::<Result<Vec<_>, _>>()?.join(""))
+    };
+
+    Ok(match node
+    {
+        Node::Paragraph(nodes) => format!("<p>{}</p>", content(nodes)?),
+        Node::Heading { content: nodes, .. } => format!("<title>{}</title>", content(nodes)?),
+        Node::List { items, .. } => format!("<list>{}</list>", content(items)?),
+        Node::ListItem(nodes) => format!("<item>{}</item>", content(nodes)?),
+        Node::HorizontalRule => "<lb/>".into(),
+        Node::Text(text) => escape_html(text),
+        Node::Underline(nodes) => format!("<hi type=\"underline\">{}</hi>", content(nodes)?),
+        Node::Italic(nodes) => format!("<hi type=\"italic\">{}</hi>", content(nodes)?),
+        Node::Bold(nodes) => format!("<hi type=\"bold\">{}</hi>", content(nodes)?),
+        Node::Strike(nodes) => format!("<hi type=\"line-through\">{}</hi>", content(nodes)?),
+        Node::Image { .. } => String::new(),
+        Node::Anchor { href: HRefSrc::RefId(id), content: nodes } => format!("<reference osisRef=\"{}\">{}</reference>", format_osis_ref_id(id)?, content(nodes)?),
+        Node::Anchor { content: nodes, .. } => content(nodes)?,
+        Node::LineBreak => "<lb/>".into(),
+    })
+}
+
+#[cfg(test)]
+mod tests 
+{
+    use std::num::NonZeroU32;
+
+    use biblio_json::{Package, core::OsisBook, modules::{Module, ModuleId}};
+
+    use super::*;
+    use crate::{bible::importing::parse_osis_book, repr::parse_html_text};
+
+    fn create_test_package() -> Package 
+    {
+        Package::load("./resources/biblio-json-pkg").unwrap()
+    }
+
+    fn get_kjv(package: &Package) -> &BibleModule
+    {
+        package.get_mod(&ModuleId::new("kjv_eng".into())).and_then(Module::as_bible).unwrap()
+    }
+
+    fn n(value: u32) -> NonZeroU32
+    {
+        NonZeroU32::new(value).unwrap()
+    }
+
+    fn verse(book: OsisBook, chapter: u32, verse: u32) -> Atom
+    {
+        Atom::Verse { book, chapter: n(chapter), verse: n(verse) }
+    }
+
+    fn range(from: Atom, to: Atom) -> RefId
+    {
+        RefId { bible: None, id: RefIdInner::Range { from, to } }
+    }
+
+    fn single(atom: Atom) -> RefId
+    {
+        RefId { bible: None, id: RefIdInner::Single(atom) }
+    }
+
+    #[test]
+    fn test_format_ref_id()
+    {
+        let package = create_test_package();
+        let kjv = get_kjv(&package);
+        let gen = OsisBook::Gen;
+        let exod = parse_osis_book("Exod").unwrap();
+
+        assert_eq!(format_ref_id(&single(Atom::Book { book: gen }), kjv), "Genesis");
+        assert_eq!(format_ref_id(&single(verse(gen, 1, 1)), kjv), "Genesis 1:1");
+        assert_eq!(format_ref_id(&range(verse(gen, 1, 1), verse(gen, 1, 3)), kjv), "Genesis 1:1-3");
+        assert_eq!(format_ref_id(&range(verse(gen, 1, 31), verse(gen, 2, 3)), kjv), "Genesis 1:31-2:3");
+        assert_eq!(format_ref_id(&range(Atom::Chapter { book: gen, chapter: n(1) }, Atom::Chapter { book: gen, chapter: n(2) }), kjv), "Genesis 1-2");
+        assert_eq!(format_ref_id(&range(verse(gen, 50, 26), verse(exod, 1, 1)), kjv), "Genesis 50:26 - Exodus 1:1");
+    }
+
+    #[test]
+    fn test_format_osis_ref_id()
+    {
+        let gen = OsisBook::Gen;
+
+        assert_eq!(format_osis_ref_id(&single(verse(gen, 1, 1))), Ok("Gen.1.1".into()));
+        assert_eq!(format_osis_ref_id(&single(Atom::Chapter { book: gen, chapter: n(3) })), Ok("Gen.3".into()));
+        assert_eq!(format_osis_ref_id(&range(verse(gen, 1, 31), verse(gen, 2, 3))), Ok("Gen.1.31-Gen.2.3".into()));
+    }
+
+    #[test]
+    fn test_html_to_markdown()
+    {
+        let package = create_test_package();
+        let kjv = get_kjv(&package);
+        let html = parse_html_text("<p>In the <b>beginning</b> <i>God</i></p><ol><li>created</li><li>the heaven</li></ol>").unwrap();
+
+        assert_eq!(html_to_markdown(&html, kjv), "In the **beginning** *God*\n\n1. created\n2. the heaven\n\n");
+    }
+}
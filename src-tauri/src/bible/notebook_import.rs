@@ -0,0 +1,389 @@
+use std::{fs, path::Path};
+
+use biblio_json::{Package, modules::ModuleId};
+use itertools::Itertools;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::{bible::{notebooks::NotebookEntryArgs, ref_id_parsing::parse_ref_ids}, core::utils::escape_html};
+
+lazy_static::lazy_static!
+{
+    static ref MARKDOWN_HEADING_REGEX: Regex = Regex::new(r"^(?P<level>#{1,6})\s+(?P<text>.+?)\s*#*$").unwrap();
+    static ref MARKDOWN_HIGHLIGHT_REGEX: Regex = Regex::new(r"^(?i:highlight|colou?r):\s*`?(?P<color>[^`\s]+)`?$").unwrap();
+    static ref MARKDOWN_BOLD_REGEX: Regex = Regex::new(r"\*\*(?P<text>.+?)\*\*").unwrap();
+    static ref MARKDOWN_ITALIC_REGEX: Regex = Regex::new(r"(?:\*(?P<a>[^*]+?)\*|\b_(?P<b>[^_]+?)_\b)").unwrap();
+}
+
+const REFERENCE_COLUMNS: &[&str] = &["references", "reference", "refs", "ref", "verses", "verse"];
+const CONTENT_COLUMNS: &[&str] = &["note", "notes", "content", "text", "description"];
+const NAME_COLUMNS: &[&str] = &["name", "title"];
+const COLOR_COLUMNS: &[&str] = &["color", "colour", "highlight"];
+const PRIORITY_COLUMNS: &[&str] = &["priority"];
+
+/// A row of a spreadsheet, or a section of a Markdown file, before its references are parsed
+#[derive(Debug, Clone, Default)]
+pub struct NotebookRow
+{
+    pub line: usize,
+    pub references: String,
+    pub name: Option<String>,
+    /// Raw html
+    pub content: String,
+    pub color: Option<String>,
+    pub priority: Option<u32>,
+}
+
+/// A row that could not be imported, `line` is 1-based
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct NotebookImportError
+{
+    pub line: usize,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "type")]
+pub enum NotebookImportResult
+{
+    Success
+    {
+        module: ModuleId,
+        imported: usize,
+        errors: Vec<NotebookImportError>,
+    },
+    Error
+    {
+        message: String,
+        errors: Vec<NotebookImportError>,
+    }
+}
+
+/// Reads the rows of a `.csv` file, which needs a header naming its columns, or the sections of a Markdown file.
+/// Records that cannot be read are returned as errors rather than failing the import
+pub fn read_notebook_rows(path: &Path) -> Result<(Vec<NotebookRow>, Vec<NotebookImportError>), String>
+{
+    let extension = path.extension().map(|e| e.to_string_lossy().to_lowercase()).unwrap_or_default();
+    let text = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    match extension.as_str()
+    {
+        "csv" => read_csv_rows(&text, b','),
+        "tsv" => read_csv_rows(&text, b'\t'),
+        "md" | "markdown" | "txt" => Ok((read_markdown_rows(&text), vec![])),
+        _ => Err(format!("Cannot import notes from a '.{}' file, only CSV and Markdown files are supported", extension)),
+    }
+}
+
+/// Parses the references of each row against `bible`, turning rows with a color into highlights.
+/// Rows that fail are returned as errors rather than failing the import
+pub fn parse_notebook_rows(rows: &[NotebookRow], bible: &ModuleId, package: &Package) -> (Vec<NotebookEntryArgs>, Vec<NotebookImportError>)
+{
+    let mut entries = vec![];
+    let mut errors = vec![];
+    for row in rows
+    {
+        match parse_notebook_row(row, bible, package)
+        {
+            Ok(entry) => entries.push(entry),
+            Err(message) => errors.push(NotebookImportError { line: row.line, message }),
+        }
+    }
+
+    (entries, errors)
+}
+
+fn parse_notebook_row(row: &NotebookRow, bible: &ModuleId, package: &Package) -> Result<NotebookEntryArgs, String>
+{
+    if row.references.trim().is_empty()
+    {
+        return Err("The row does not have any references".into());
+    }
+
+    let references = parse_ref_ids(&row.references, bible, package)
+        .map_err(|e| format!("Invalid references '{}': {}", row.references, e))?;
+
+    let args = match &row.color
+    {
+        Some(color) => NotebookEntryArgs::Highlight {
+            name: row.name.clone().unwrap_or_else(|| row.references.clone()),
+            description: Some(row.content.clone()).filter(|c| !c.is_empty()),
+            color: color.clone(),
+            priority: row.priority.unwrap_or_default(),
+            references,
+        },
+        None if row.content.is_empty() => return Err("The note is empty".into()),
+        None => NotebookEntryArgs::Note {
+            name: row.name.clone(),
+            content: row.content.clone(),
+            references,
+        },
+    };
+
+    // Checks the color and html of the entry, so that one bad row does not fail the whole import
+    args.clone().into_entry(0)?;
+    Ok(args)
+}
+
+fn read_csv_rows(text: &str, delimiter: u8) -> Result<(Vec<NotebookRow>, Vec<NotebookImportError>), String>
+{
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(delimiter)
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .from_reader(text.as_bytes());
+
+    let headers = reader.headers().map_err(|e| e.to_string())?.clone();
+    let find_column = |names: &[&str]| headers.iter().position(|h| names.contains(&h.to_lowercase().as_str()));
+
+    let references = find_column(REFERENCE_COLUMNS)
+        .ok_or(format!("The header needs a references column, named one of: {}", REFERENCE_COLUMNS.join(", ")))?;
+
+    let (content, name, color, priority) = (find_column(CONTENT_COLUMNS), find_column(NAME_COLUMNS), find_column(COLOR_COLUMNS), find_column(PRIORITY_COLUMNS));
+
+    let mut rows = vec![];
+    let mut errors = vec![];
+    for record in reader.records()
+    {
+        let record = match record
+        {
+            Ok(record) => record,
+            Err(e) => {
+                let line = e.position().map_or(0, |p| p.line() as usize);
+                errors.push(NotebookImportError { line, message: e.to_string() });
+                continue;
+            },
+        };
+
+        let line = record.position().map_or(0, |p| p.line() as usize);
+        let get = |column: Option<usize>| column.and_then(|c| record.get(c)).filter(|v| !v.is_empty()).map(str::to_string);
+
+        if record.iter().all(str::is_empty)
+        {
+            continue;
+        }
+
+        rows.push(NotebookRow {
+            line,
+            references: get(Some(references)).unwrap_or_default(),
+            name: get(name),
+            content: get(content).map(|c| text_to_html(&c)).unwrap_or_default(),
+            color: get(color),
+            priority: get(priority).and_then(|p| p.parse().ok()),
+        });
+    }
+
+    Ok((rows, errors))
+}
+
+/// Every heading at the level of the notes starts a note, with its references either in the heading, or on the line after it.
+/// A line such as `Highlight: #ffcc00` makes the section a highlight, as the Markdown export writes them
+fn read_markdown_rows(text: &str) -> Vec<NotebookRow>
+{
+    let Some(note_level) = get_note_heading_level(text) else { return vec![]; };
+    let mut rows: Vec<(usize, NotebookRow)> = vec![];
+    let mut body: Vec<&str> = vec![];
+
+    let finish = |row: Option<&mut (usize, NotebookRow)>, body: &mut Vec<&str>| {
+        if let Some((_, row)) = row
+        {
+            row.content = markdown_to_html(&body.join("\n"));
+        }
+
+        body.clear();
+    };
+
+    for (i, line) in text.lines().enumerate()
+    {
+        let trimmed = line.trim();
+        let heading = MARKDOWN_HEADING_REGEX.captures(trimmed).filter(|h| h["level"].len() <= note_level);
+        if let Some(heading) = heading
+        {
+            finish(rows.last_mut(), &mut body);
+            rows.push((heading["level"].len(), NotebookRow {
+                line: i + 1,
+                references: heading["text"].to_string(),
+                ..Default::default()
+            }));
+
+            continue;
+        }
+
+        let Some((_, row)) = rows.last_mut() else { continue; };
+        if let Some(highlight) = MARKDOWN_HIGHLIGHT_REGEX.captures(trimmed)
+        {
+            row.color = Some(highlight["color"].to_string());
+            continue;
+        }
+
+        // A heading followed by an emphasised line is a named note, as the Markdown export writes them
+        let is_first_line = body.iter().all(|l| l.trim().is_empty()) && row.name.is_none();
+        if is_first_line && trimmed.len() > 2 && trimmed.starts_with('*') && trimmed.ends_with('*') && !trimmed.starts_with("**")
+        {
+            row.name = Some(std::mem::take(&mut row.references));
+            row.references = trimmed.trim_matches('*').to_string();
+            continue;
+        }
+
+        body.push(line);
+    }
+
+    finish(rows.last_mut(), &mut body);
+
+    rows.into_iter()
+        .filter(|(level, _)| *level == note_level)
+        .map(|(_, row)| row)
+        .collect_vec()
+}
+
+/// The level of the headings that start notes, or `None` if there are no headings.
+/// When the first heading is the only one at its level it is the title of the whole file, and the notes are at the next level down,
+/// as the Markdown export writes them. Headings below the notes are part of their content
+fn get_note_heading_level(text: &str) -> Option<usize>
+{
+    let levels = text.lines()
+        .filter_map(|l| MARKDOWN_HEADING_REGEX.captures(l.trim()))
+        .map(|h| h["level"].len())
+        .collect_vec();
+
+    let first = *levels.first()?;
+    let is_title = first == *levels.iter().min()? && levels.iter().filter(|l| **l == first).count() == 1;
+    match is_title
+    {
+        true => levels.iter().filter(|l| **l > first).min().copied().or(Some(first)),
+        false => levels.iter().min().copied(),
+    }
+}
+
+fn text_to_html(text: &str) -> String
+{
+    text.split("\n\n")
+        .map(str::trim)
+        .filter(|p| !p.is_empty())
+        .map(|p| format!("<p>{}</p>", escape_html(p).replace('\n', "<br>")))
+        .join("")
+}
+
+/// Converts the headings, paragraphs, lists, bold and italic text of a Markdown section into html
+fn markdown_to_html(markdown: &str) -> String
+{
+    let mut html = String::new();
+    for block in markdown.split("\n\n").map(str::trim).filter(|b| !b.is_empty())
+    {
+        let mut lines = block.lines().map(str::trim).collect_vec();
+        if let Some(heading) = MARKDOWN_HEADING_REGEX.captures(lines[0])
+        {
+            let level = heading["level"].len();
+            html.push_str(&format!("<h{}>{}</h{}>", level, inline_markdown_to_html(&heading["text"]), level));
+            lines.remove(0);
+            if lines.is_empty()
+            {
+                continue;
+            }
+        }
+
+        let is_list = lines.iter().all(|l| l.starts_with("- ") || l.starts_with("* "));
+        if is_list
+        {
+            let items = lines.iter().map(|l| format!("<li>{}</li>", inline_markdown_to_html(&l[2..]))).join("");
+            html.push_str(&format!("<ul>{}</ul>", items));
+        }
+        else
+        {
+            let text = lines.iter().map(|l| inline_markdown_to_html(l.trim_end_matches("  "))).join("<br>");
+            html.push_str(&format!("<p>{}</p>", text));
+        }
+    }
+
+    html
+}
+
+fn inline_markdown_to_html(text: &str) -> String
+{
+    let html = escape_html(text);
+    let html = MARKDOWN_BOLD_REGEX.replace_all(&html, "<b>$text</b>");
+    MARKDOWN_ITALIC_REGEX.replace_all(&html, "<i>$a$b</i>").into_owned()
+}
+
+#[cfg(test)]
+mod tests 
+{
+    use super::*;
+
+    const EXPORTED_MARKDOWN: &str = "# My Notes
+
+Notes on creation.
+
+## Genesis 1:1
+
+In the beginning.
+
+### Thoughts
+
+More **bold** text.
+
+## Creation
+
+*John 1:1-3*
+
+Highlight: `#ffcc00`
+
+The Word.
+";
+
+    #[test]
+    fn read_exported_markdown() 
+    {
+        let rows = read_markdown_rows(EXPORTED_MARKDOWN);
+        assert_eq!(rows.len(), 2);
+
+        assert_eq!(rows[0].line, 5);
+        assert_eq!(rows[0].references, "Genesis 1:1");
+        assert_eq!(rows[0].name, None);
+        assert_eq!(rows[0].content, "<p>In the beginning.</p><h3>Thoughts</h3><p>More <b>bold</b> text.</p>");
+
+        assert_eq!(rows[1].references, "John 1:1-3");
+        assert_eq!(rows[1].name.as_deref(), Some("Creation"));
+        assert_eq!(rows[1].color.as_deref(), Some("#ffcc00"));
+        assert_eq!(rows[1].content, "<p>The Word.</p>");
+    }
+
+    #[test]
+    fn read_markdown_without_title() 
+    {
+        let rows = read_markdown_rows("# John 3:16\nFor God so loved\n\n# Romans 8:28\n- all\n- things");
+
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0].content, "<p>For God so loved</p>");
+        assert_eq!(rows[1].references, "Romans 8:28");
+        assert_eq!(rows[1].content, "<ul><li>all</li><li>things</li></ul>");
+    }
+
+    #[test]
+    fn read_markdown_without_headings() 
+    {
+        assert!(read_markdown_rows("Just some text").is_empty());
+    }
+
+    #[test]
+    fn read_csv() 
+    {
+        let csv = "Reference,Note,Color\nJohn 3:16,\"For God so loved\",\nGen 1:1,,#ff0000\n,,\nRom 8:28,All things,\n";
+        let (rows, errors) = read_csv_rows(csv, b',').unwrap();
+
+        assert!(errors.is_empty());
+        assert_eq!(rows.len(), 3);
+        assert_eq!(rows[0].line, 2);
+        assert_eq!(rows[0].references, "John 3:16");
+        assert_eq!(rows[0].content, "<p>For God so loved</p>");
+        assert_eq!(rows[1].color.as_deref(), Some("#ff0000"));
+        assert_eq!(rows[1].content, "");
+        assert_eq!(rows[2].line, 5);
+    }
+
+    #[test]
+    fn read_csv_without_references() 
+    {
+        assert!(read_csv_rows("Note,Color\nText,\n", b',').is_err());
+    }
+}
@@ -0,0 +1,537 @@
+use std::{fs, path::{Path, PathBuf}, sync::Arc};
+
+use biblio_json::{Package, core::RefId, modules::{Module, ModuleId, notebook::{NotebookEntry, NotebookModule}}};
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+use tauri::{Runtime, path::{BaseDirectory, PathResolver}};
+
+use crate::{bible::{PACKAGE_STAGING_PATH, package_loading::load_module, to_package_path}, core::utils::get_uuid, repr::{parse_html_text, write_html_text}};
+
+pub const NOTEBOOK_CHANGED_EVENT_NAME: &str = "notebook-changed";
+
+pub const USER_PACKAGE_PATH: &str = "ascribe-data/user-package";
+const USER_PACKAGE_FILE: &str = "biblio-json.toml";
+const NOTEBOOKS_DIR: &str = "notebooks";
+
+const USER_PACKAGE_CONFIG: &str = r#"name = "Ascribe User Package"
+authors = []
+license = "MIT"
+
+[module_paths]
+notebooks = "/notebooks/*"
+"#;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct NotebookChangedEvent
+{
+    pub module: ModuleId,
+    pub deleted: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "type")]
+pub enum NotebookEditResult
+{
+    Success
+    {
+        module: ModuleId,
+        entry: Option<u32>,
+    },
+    Error
+    {
+        message: String,
+    }
+}
+
+impl From<Result<(ModuleId, Option<u32>), String>> for NotebookEditResult
+{
+    fn from(value: Result<(ModuleId, Option<u32>), String>) -> Self
+    {
+        match value
+        {
+            Ok((module, entry)) => Self::Success { module, entry },
+            Err(message) => Self::Error { message },
+        }
+    }
+}
+
+/// The arguments shared by the note and highlight editing commands, `content` and `description` are raw html
+#[derive(Debug, Clone)]
+pub enum NotebookEntryArgs
+{
+    Note
+    {
+        name: Option<String>,
+        content: String,
+        references: Vec<RefId>,
+    },
+    Highlight
+    {
+        name: String,
+        description: Option<String>,
+        color: String,
+        priority: u32,
+        references: Vec<RefId>,
+    }
+}
+
+impl NotebookEntryArgs
+{
+    pub fn into_entry(self, id: u32) -> Result<NotebookEntry, String>
+    {
+        match self
+        {
+            NotebookEntryArgs::Note { name, content, references } => {
+                Ok(NotebookEntry::Note {
+                    id,
+                    name,
+                    content: parse_html_text(&content)?,
+                    references
+                })
+            },
+            NotebookEntryArgs::Highlight { name, description, color, priority, references } => {
+                let description = match description
+                {
+                    Some(d) => Some(parse_html_text(&d)?),
+                    None => None,
+                };
+
+                Ok(NotebookEntry::Highlight {
+                    id,
+                    name,
+                    description,
+                    priority,
+                    color: color.parse().map_err(|e| format!("Invalid highlight color '{}': {}", color, e))?,
+                    references
+                })
+            },
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct NotebookConfigToml<'a>
+{
+    name: &'a str,
+    id: &'a ModuleId,
+    description: Option<&'a str>,
+    bible: Option<&'a ModuleId>,
+}
+
+/// The user writable notebooks, stored as a biblio_json package in the app data directory.
+/// Every edit is applied to the loaded [`Package`] and then written back to disk.
+#[derive(Debug, Clone)]
+pub struct NotebookStore
+{
+    dir: PathBuf,
+    staging_dir: PathBuf,
+}
+
+impl NotebookStore
+{
+    pub fn new<R>(resolver: &PathResolver<R>) -> Self
+        where R : Runtime
+    {
+        let dir = resolver.resolve(USER_PACKAGE_PATH, BaseDirectory::AppData).unwrap();
+        let staging_dir = resolver.resolve(PACKAGE_STAGING_PATH, BaseDirectory::AppCache).unwrap().join("notebooks");
+        Self { dir, staging_dir }
+    }
+
+    pub fn is_user_notebook(&self, id: &ModuleId) -> bool
+    {
+        self.config_path(id).exists()
+    }
+
+    /// Loads all of the notebooks in the store, so that they can be merged into the main package
+    pub fn load_notebooks(&self) -> Result<Vec<Module>, String>
+    {
+        if !self.dir.join(USER_PACKAGE_FILE).exists()
+        {
+            return Ok(vec![]);
+        }
+
+        let package = Package::load(&to_package_path(&self.dir)).map_err(|e| {
+            e.iter().map(|e| e.to_string()).join("\n")
+        })?;
+
+        Ok(package.modules.into_values().filter(|m| matches!(m, Module::Notebook(_))).collect_vec())
+    }
+
+    pub fn create_notebook(&self, package: &mut Package, name: &str, description: Option<&str>, bible: Option<&ModuleId>) -> Result<ModuleId, String>
+    {
+        self.ensure_package_file()?;
+
+        // The description is written the same way as when the notebook is saved later on
+        let description = description.map(|d| write_html_text(&parse_html_text(d)?)).transpose()?;
+        let id = ModuleId::new(format!("user_notebook_{}", get_uuid().replace('-', "_")));
+        let config = toml::to_string(&NotebookConfigToml {
+            name,
+            id: &id,
+            description: description.as_deref(),
+            bible,
+        }).map_err(|e| e.to_string())?;
+
+        let notebook = fs::write(self.config_path(&id), config)
+            .and_then(|_| fs::write(self.entries_path(&id), ""))
+            .map_err(|e| e.to_string())
+            .and_then(|_| self.load_notebook(&id));
+
+        match notebook
+        {
+            Ok(notebook) => {
+                package.modules.insert(id.clone(), notebook);
+                Ok(id)
+            },
+            Err(e) => {
+                // Otherwise the notebook would still be loaded on the next start
+                let _ = fs::remove_file(self.config_path(&id));
+                let _ = fs::remove_file(self.entries_path(&id));
+                Err(e)
+            },
+        }
+    }
+
+    /// Loads one notebook on its own, so that other notebooks in the store that are broken cannot stop it from loading
+    fn load_notebook(&self, id: &ModuleId) -> Result<Module, String>
+    {
+        let staging_dir = self.staging_dir.join(format!("notebook_{}", get_uuid()));
+        let mut package = load_module(NOTEBOOKS_DIR, &self.config_path(id), &staging_dir, &[])
+            .map_err(|e| e.join("\n"))?;
+
+        package.modules.remove(id).ok_or(format!("Notebook '{}' failed to load", id))
+    }
+
+    pub fn delete_notebook(&self, package: &mut Package, id: &ModuleId) -> Result<(), String>
+    {
+        if !self.is_user_notebook(id)
+        {
+            return Err(format!("Notebook '{}' is not a user notebook", id));
+        }
+
+        // The entries are moved out of the way first, so that they can be put back if the config cannot be removed
+        let staged = self.staging_path(id);
+        fs::rename(self.entries_path(id), &staged).map_err(|e| e.to_string())?;
+        if let Err(e) = fs::remove_file(self.config_path(id))
+        {
+            let _ = fs::rename(&staged, self.entries_path(id));
+            return Err(e.to_string());
+        }
+
+        package.modules.remove(id);
+        if let Err(e) = fs::remove_file(staged)
+        {
+            log::error!("Failed to remove the entries of deleted notebook '{}': {}", id, e);
+        }
+
+        Ok(())
+    }
+
+    pub fn add_entry(&self, package: &mut Package, id: &ModuleId, args: NotebookEntryArgs) -> Result<u32, String>
+    {
+        let ids = self.add_entries(package, id, vec![args])?;
+        Ok(ids[0])
+    }
+
+    /// Adds every entry and saves the notebook once, failing without adding any if one of them is invalid
+    pub fn add_entries(&self, package: &mut Package, id: &ModuleId, args: Vec<NotebookEntryArgs>) -> Result<Vec<u32>, String>
+    {
+        self.edit_notebook(package, id, |notebook| {
+            let first_id = notebook.entries.iter()
+                .map(notebook_entry_id)
+                .max()
+                .map_or(0, |id| id + 1);
+
+            let entries = args.into_iter()
+                .zip(first_id..)
+                .map(|(args, entry_id)| args.into_entry(entry_id))
+                .collect::<Result<Vec<_>, _>>()?;
+
+            let ids = entries.iter().map(notebook_entry_id).collect_vec();
+            notebook.entries.extend(entries);
+            Ok(ids)
+        })
+    }
+
+    pub fn update_entry(&self, package: &mut Package, id: &ModuleId, entry_id: u32, args: NotebookEntryArgs) -> Result<(), String>
+    {
+        self.edit_notebook(package, id, |notebook| {
+            let entry = notebook.entries.iter_mut()
+                .find(|e| notebook_entry_id(e) == entry_id)
+                .ok_or(format!("Entry {} does not exist in notebook '{}'", entry_id, id))?;
+
+            *entry = args.into_entry(entry_id)?;
+            Ok(())
+        })
+    }
+
+    pub fn delete_entry(&self, package: &mut Package, id: &ModuleId, entry_id: u32) -> Result<(), String>
+    {
+        self.edit_notebook(package, id, |notebook| {
+            let index = notebook.entries.iter()
+                .position(|e| notebook_entry_id(e) == entry_id)
+                .ok_or(format!("Entry {} does not exist in notebook '{}'", entry_id, id))?;
+
+            notebook.entries.remove(index);
+            Ok(())
+        })
+    }
+
+    fn edit_notebook<R>(&self, package: &mut Package, id: &ModuleId, f: impl FnOnce(&mut NotebookModule) -> Result<R, String>) -> Result<R, String>
+    {
+        if !self.is_user_notebook(id)
+        {
+            return Err(format!("Notebook '{}' is not a user notebook", id));
+        }
+
+        let Some(Module::Notebook(notebook)) = package.modules.get_mut(id) else {
+            return Err(format!("Notebook '{}' is not loaded", id));
+        };
+
+        // The edit is only swapped into the package once it has been saved, so a failed save changes nothing
+        let mut edited = NotebookModule::clone(notebook);
+        let result = f(&mut edited)?;
+        self.save_entries(&edited)?;
+        *notebook = Arc::new(edited);
+        Ok(result)
+    }
+
+    fn save_entries(&self, notebook: &NotebookModule) -> Result<(), String>
+    {
+        let entries = write_notebook_entries(&notebook.entries)?;
+        replace_file(&self.entries_path(&notebook.config.id), &self.staging_path(&notebook.config.id), &entries)
+    }
+
+    fn ensure_package_file(&self) -> Result<(), String>
+    {
+        fs::create_dir_all(self.dir.join(NOTEBOOKS_DIR)).map_err(|e| e.to_string())?;
+
+        let package_file = self.dir.join(USER_PACKAGE_FILE);
+        if !package_file.exists()
+        {
+            fs::write(package_file, USER_PACKAGE_CONFIG).map_err(|e| e.to_string())?;
+        }
+
+        Ok(())
+    }
+
+    fn config_path(&self, id: &ModuleId) -> PathBuf
+    {
+        self.dir.join(NOTEBOOKS_DIR).join(format!("{}.toml", id))
+    }
+
+    fn entries_path(&self, id: &ModuleId) -> PathBuf
+    {
+        self.dir.join(NOTEBOOKS_DIR).join(format!("{}.jsonl", id))
+    }
+
+    /// Where a notebook's entries are written before replacing its `.jsonl` file, outside of the notebooks folder so it is never loaded as a module
+    fn staging_path(&self, id: &ModuleId) -> PathBuf
+    {
+        self.dir.join(format!("{}.jsonl.staged", id))
+    }
+}
+
+/// The notebook's config, as it is written to its `.toml` file
+pub fn write_notebook_config(notebook: &NotebookModule) -> Result<String, String>
+{
+    let description = notebook.config.description.as_ref().map(write_html_text).transpose()?;
+    toml::to_string(&NotebookConfigToml {
+        name: &notebook.config.name,
+        id: &notebook.config.id,
+        description: description.as_deref(),
+        bible: notebook.config.bible.as_ref(),
+    }).map_err(|e| e.to_string())
+}
+
+/// The notebook's entries, as they are written to its `.jsonl` file with one entry per line
+pub fn write_notebook_entries(entries: &[NotebookEntry]) -> Result<String, String>
+{
+    let lines = entries.iter()
+        .map(serde_json::to_string)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+
+    Ok(lines.join("\n"))
+}
+
+/// Writes `contents` to `staged` and renames it over `path`, so that the file is never left half written
+fn replace_file(path: &Path, staged: &Path, contents: &str) -> Result<(), String>
+{
+    fs::write(staged, contents).map_err(|e| e.to_string())?;
+    fs::rename(staged, path).map_err(|e| {
+        let _ = fs::remove_file(staged);
+        e.to_string()
+    })
+}
+
+pub fn notebook_entry_id(entry: &NotebookEntry) -> u32
+{
+    match entry
+    {
+        NotebookEntry::Highlight { id, .. } => *id,
+        NotebookEntry::Note { id, .. } => *id,
+    }
+}
+
+
+#[cfg(test)]
+mod tests 
+{
+    use super::*;
+    use crate::bible::ref_id_parsing::parse_ref_ids;
+
+    fn create_test_package() -> Package 
+    {
+        Package::load("./resources/biblio-json-pkg").unwrap()
+    }
+
+    fn create_test_store() -> NotebookStore
+    {
+        let dir = std::env::temp_dir().join(format!("notebooks_{}", get_uuid()));
+        NotebookStore { staging_dir: dir.join("staging"), dir }
+    }
+
+    fn note(content: &str) -> NotebookEntryArgs
+    {
+        NotebookEntryArgs::Note { name: None, content: content.into(), references: vec![] }
+    }
+
+    fn get_entry_ids(package: &Package, id: &ModuleId) -> Vec<u32>
+    {
+        match package.get_mod(id)
+        {
+            Some(Module::Notebook(notebook)) => notebook.entries.iter().map(notebook_entry_id).collect(),
+            _ => panic!("Expected notebook '{}'", id),
+        }
+    }
+
+    #[test]
+    fn test_entry_ids()
+    {
+        let store = create_test_store();
+        let mut package = create_test_package();
+        let id = store.create_notebook(&mut package, "Notes", None, None).unwrap();
+
+        assert_eq!(store.add_entries(&mut package, &id, vec![note("<p>One</p>"), note("<p>Two</p>")]), Ok(vec![0, 1]));
+
+        store.delete_entry(&mut package, &id, 0).unwrap();
+        assert_eq!(store.add_entry(&mut package, &id, note("<p>Three</p>")), Ok(2));
+        assert_eq!(get_entry_ids(&package, &id), vec![1, 2]);
+
+        let invalid = NotebookEntryArgs::Highlight { 
+            name: "Invalid".into(), 
+            description: None, 
+            color: "not a color".into(), 
+            priority: 0, 
+            references: vec![],
+        };
+
+        assert!(store.add_entries(&mut package, &id, vec![note("<p>Four</p>"), invalid]).is_err());
+        assert_eq!(get_entry_ids(&package, &id), vec![1, 2]);
+        assert!(store.delete_entry(&mut package, &id, 0).is_err());
+
+        fs::remove_dir_all(&store.dir).unwrap();
+    }
+
+    #[test]
+    fn test_entries_round_trip()
+    {
+        let store = create_test_store();
+        let mut package = create_test_package();
+        let bible = ModuleId::new("kjv_eng".into());
+        let id = store.create_notebook(&mut package, "Notes", Some("A description"), Some(&bible)).unwrap();
+
+        let note = NotebookEntryArgs::Note { 
+            name: Some("Love".into()), 
+            content: "<p>For God so <b>loved</b> the world</p>".into(), 
+            references: parse_ref_ids("John 3:16; Romans 8:28-30", &bible, &package).unwrap(),
+        };
+
+        let highlight = NotebookEntryArgs::Highlight { 
+            name: "Creation".into(), 
+            description: Some("<p>In the beginning</p>".into()), 
+            color: "#ffcc00".into(), 
+            priority: 2, 
+            references: parse_ref_ids("Genesis 1", &bible, &package).unwrap(),
+        };
+
+        store.add_entries(&mut package, &id, vec![note, highlight]).unwrap();
+
+        let Some(Module::Notebook(written)) = package.get_mod(&id) else {
+            panic!("Expected notebook '{}'", id);
+        };
+
+        let Some(Module::Notebook(loaded)) = store.load_notebooks().unwrap().into_iter().find(|m| *m.id() == id) else {
+            panic!("Expected notebook '{}' to load", id);
+        };
+
+        assert_eq!(loaded.entries.len(), 2);
+        assert_eq!(write_notebook_entries(&loaded.entries), write_notebook_entries(&written.entries));
+        assert_eq!(loaded.config.bible, Some(bible));
+
+        fs::remove_dir_all(&store.dir).unwrap();
+    }
+
+    #[test]
+    fn test_failed_save_keeps_notebook()
+    {
+        let store = create_test_store();
+        let mut package = create_test_package();
+        let id = store.create_notebook(&mut package, "Notes", None, None).unwrap();
+        store.add_entry(&mut package, &id, note("<p>One</p>")).unwrap();
+
+        // A folder in the way of the staged entries makes writing them fail
+        fs::create_dir(store.staging_path(&id)).unwrap();
+        assert!(store.add_entry(&mut package, &id, note("<p>Two</p>")).is_err());
+        assert_eq!(get_entry_ids(&package, &id), vec![0]);
+        assert_eq!(fs::read_to_string(store.entries_path(&id)).unwrap().lines().count(), 1);
+
+        fs::remove_dir_all(&store.dir).unwrap();
+    }
+
+    #[test]
+    fn test_delete_notebook()
+    {
+        let store = create_test_store();
+        let mut package = create_test_package();
+        let id = store.create_notebook(&mut package, "Notes", None, None).unwrap();
+        store.add_entry(&mut package, &id, note("<p>One</p>")).unwrap();
+
+        // A folder in place of the config cannot be removed as a file, so the entries are put back
+        fs::remove_file(store.config_path(&id)).unwrap();
+        fs::create_dir(store.config_path(&id)).unwrap();
+        assert!(store.delete_notebook(&mut package, &id).is_err());
+        assert!(store.entries_path(&id).exists());
+        assert_eq!(get_entry_ids(&package, &id), vec![0]);
+
+        fs::remove_dir(store.config_path(&id)).unwrap();
+        let id = store.create_notebook(&mut package, "Notes", None, None).unwrap();
+        store.delete_notebook(&mut package, &id).unwrap();
+        assert!(!store.config_path(&id).exists());
+        assert!(!store.entries_path(&id).exists());
+        assert!(!store.staging_path(&id).exists());
+        assert!(package.get_mod(&id).is_none());
+
+        fs::remove_dir_all(&store.dir).unwrap();
+    }
+
+    #[test]
+    fn test_create_notebook_beside_broken_notebook()
+    {
+        let store = create_test_store();
+        let mut package = create_test_package();
+        store.ensure_package_file().unwrap();
+        fs::write(store.dir.join(NOTEBOOKS_DIR).join("broken.toml"), "not a notebook").unwrap();
+
+        let id = store.create_notebook(&mut package, "Notes", Some("<p>A <b>description</b></p>"), None).unwrap();
+        let Some(Module::Notebook(notebook)) = package.get_mod(&id) else {
+            panic!("Expected notebook '{}'", id);
+        };
+
+        // Saving the config again writes it just as it was created
+        assert_eq!(fs::read_to_string(store.config_path(&id)).unwrap(), write_notebook_config(notebook).unwrap());
+
+        fs::remove_dir_all(&store.dir).unwrap();
+    }
+}
@@ -0,0 +1,314 @@
+use std::{fs, path::{Path, PathBuf}};
+
+use biblio_json::Package;
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+
+use crate::{bible::to_package_path, core::utils::get_uuid};
+
+pub const PACKAGE_LOAD_REPORT_EVENT_NAME: &str = "package-load-report";
+
+pub const PACKAGE_FILE: &str = "biblio-json.toml";
+pub const BIBLES_CATEGORY: &str = "bibles";
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct PackageLoadReport
+{
+    pub failed_modules: Vec<FailedModuleLoad>,
+    /// Set if not even a partial package could be loaded
+    pub fatal_error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct FailedModuleLoad
+{
+    pub path: String,
+    pub errors: Vec<String>,
+}
+
+impl PackageLoadReport
+{
+    pub fn fatal(error: String) -> Self 
+    {
+        Self {
+            failed_modules: vec![],
+            fatal_error: Some(error),
+        }
+    }
+}
+
+/// Loads the package at `path`. If the package fails to load as a whole, each module is loaded 
+/// on its own from staging packages in a new folder in `staging_dir`, so that only the broken modules are skipped.
+/// `dependencies` are the bible configs from other packages that the modules in this one may reference
+pub fn load_package(path: &Path, staging_dir: &Path, dependencies: &[(String, PathBuf)]) -> (Option<Package>, PackageLoadReport)
+{
+    let errors = match Package::load(&to_package_path(path))
+    {
+        Ok(package) => return (Some(package), PackageLoadReport::default()),
+        Err(e) => format_errors(&e),
+    };
+
+    log::error!("Package loaded with {} errors, loading modules individually:\n{}", errors.len(), errors.join("\n---------------------------------------------\n"));
+
+    // Only this load's own folder is cleaned up, as other loads and imports can be staging modules at the same time
+    let staging_dir = staging_dir.join(format!("package_{}", get_uuid()));
+    let result = load_modules_individually(path, &staging_dir, dependencies);
+    if staging_dir.exists()
+    {
+        if let Err(e) = fs::remove_dir_all(&staging_dir)
+        {
+            log::error!("Failed to clean up the package staging directory: {}", e);
+        }
+    }
+
+    match result
+    {
+        Ok((package, report)) => (Some(package), report),
+        Err(e) => (None, PackageLoadReport::fatal(e)),
+    }
+}
+
+fn load_modules_individually(path: &Path, staging_dir: &Path, dependencies: &[(String, PathBuf)]) -> Result<(Package, PackageLoadReport), String>
+{
+    let config = fs::read_to_string(path.join(PACKAGE_FILE)).map_err(|e| e.to_string())?;
+    let config: toml::Table = toml::from_str(&config).map_err(|e| e.to_string())?;
+    let modules = find_module_configs(path, &config)?;
+
+    // An empty package, which all the modules that load get merged into
+    let mut package = load_staged(&config, &staging_dir.join("base"), &[])
+        .map_err(|e| e.join("\n"))?;
+
+    let mut failed = vec![];
+    for (i, module) in modules.iter().enumerate()
+    {
+        match load_staged(&config, &staging_dir.join(format!("module_{}", i)), &[module.clone()])
+        {
+            Ok(loaded) => package.modules.extend(loaded.modules),
+            Err(errors) => failed.push((module.clone(), errors)),
+        }
+    }
+
+    let bibles = modules.iter()
+        .filter(|(category, _)| category == BIBLES_CATEGORY)
+        .filter(|m| !failed.iter().any(|(f, _)| f == *m))
+        .chain(dependencies)
+        .cloned()
+        .collect_vec();
+
+    // Modules that reference a bible, such as strongs links, can fail when loaded alone, so are retried once along side the bible they reference
+    let mut report = PackageLoadReport::default();
+    for (i, (module, errors)) in failed.into_iter().enumerate()
+    {
+        let bible = match module.0.as_str()
+        {
+            BIBLES_CATEGORY => None,
+            _ => find_dependency_bible(&module.1, &bibles),
+        };
+
+        let result = match bible
+        {
+            Some(bible) => load_staged(&config, &staging_dir.join(format!("retry_{}", i)), &[bible, module.clone()]),
+            None => Err(errors),
+        };
+
+        match result
+        {
+            Ok(loaded) => {
+                let new_modules = loaded.modules.into_iter()
+                    .filter(|(id, _)| !package.modules.contains_key(id))
+                    .collect_vec();
+
+                package.modules.extend(new_modules);
+            },
+            Err(errors) => report.failed_modules.push(FailedModuleLoad { 
+                path: module.1.to_string_lossy().to_string(), 
+                errors 
+            }),
+        }
+    }
+
+    Ok((package, report))
+}
+
+/// Loads a single module from its config file, along side the bible it references from `dependencies` if it cannot be loaded on its own
+pub fn load_module(category: &str, config: &Path, staging_dir: &Path, dependencies: &[(String, PathBuf)]) -> Result<Package, Vec<String>>
+{
+    let module = (category.to_string(), config.to_path_buf());
+    let mut package_config = toml::Table::new();
+    package_config.insert("name".into(), toml::Value::String("Staged Module".into()));
+    package_config.insert("authors".into(), toml::Value::Array(vec![]));
+    package_config.insert("license".into(), toml::Value::String("Unknown".into()));
+
+    let result = load_staged(&package_config, &staging_dir.join("module"), &[module.clone()]);
+    let result = match result
+    {
+        Ok(package) => Ok(package),
+        Err(e) if category != BIBLES_CATEGORY => match find_dependency_bible(config, dependencies)
+        {
+            Some(bible) => load_staged(&package_config, &staging_dir.join("with_dependencies"), &[bible, module]),
+            None => Err(e),
+        },
+        Err(e) => Err(e),
+    };
+
+    if staging_dir.exists()
+    {
+        if let Err(e) = fs::remove_dir_all(staging_dir)
+        {
+            log::error!("Failed to clean up the package staging directory: {}", e);
+        }
+    }
+
+    result
+}
+
+/// The config of the bible that a module names with its `bible` key, out of `bibles`
+fn find_dependency_bible(module: &Path, bibles: &[(String, PathBuf)]) -> Option<(String, PathBuf)>
+{
+    let bible = read_config_string(module, "bible")?;
+    bibles.iter()
+        .find(|(_, config)| read_config_string(config, "id").is_some_and(|id| id == bible))
+        .cloned()
+}
+
+fn read_config_string(config: &Path, key: &str) -> Option<String>
+{
+    let text = fs::read_to_string(config).ok()?;
+    let table: toml::Table = toml::from_str(&text).ok()?;
+    table.get(key)?.as_str().map(str::to_string)
+}
+
+/// Returns the module category (eg: `bibles`) and config path of every module in the package
+pub fn find_module_configs(path: &Path, config: &toml::Table) -> Result<Vec<(String, PathBuf)>, String>
+{
+    let Some(module_paths) = config.get("module_paths").and_then(|p| p.as_table()) else {
+        return Ok(vec![]);
+    };
+
+    let mut modules = vec![];
+    for (category, pattern) in module_paths
+    {
+        let Some(pattern) = pattern.as_str() else { continue; };
+        let pattern = path.join(pattern.trim_start_matches('/'));
+        let paths = glob::glob(&pattern.to_string_lossy()).map_err(|e| e.to_string())?;
+
+        modules.extend(paths.filter_map(Result::ok)
+            .filter(|p| p.extension().is_some_and(|e| e == "toml"))
+            .map(|p| (category.clone(), p)));
+    }
+
+    Ok(modules)
+}
+
+/// Creates a package in `dir` containing only `modules`, and loads it
+fn load_staged(config: &toml::Table, dir: &Path, modules: &[(String, PathBuf)]) -> Result<Package, Vec<String>>
+{
+    fs::create_dir_all(dir).map_err(|e| vec![e.to_string()])?;
+
+    let mut module_paths = toml::Table::new();
+    for (i, (category, module)) in modules.iter().enumerate()
+    {
+        let module_dir = dir.join(category).join(i.to_string());
+        stage_module_files(module, &module_dir).map_err(|e| vec![e])?;
+        module_paths.insert(category.clone(), toml::Value::String(format!("/{}/**/*", category)));
+    }
+
+    let mut config = config.clone();
+    config.insert("module_paths".into(), toml::Value::Table(module_paths));
+    let config = toml::to_string(&config).map_err(|e| vec![e.to_string()])?;
+    fs::write(dir.join(PACKAGE_FILE), config).map_err(|e| vec![e.to_string()])?;
+
+    Package::load(&to_package_path(dir)).map_err(|e| format_errors(&e))
+}
+
+fn stage_module_files(module: &Path, dir: &Path) -> Result<(), String>
+{
+    copy_module_files(module, dir, true)
+}
+
+/// Copies the module config, and the data files next to it with the same name, into `dir`.
+/// If `link` is set, the files are hard linked instead where possible
+pub fn copy_module_files(module: &Path, dir: &Path, link: bool) -> Result<(), String>
+{
+    fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+
+    let (Some(parent), Some(stem)) = (module.parent(), module.file_stem()) else {
+        return Err(format!("Invalid module path '{}'", module.display()));
+    };
+
+    for entry in fs::read_dir(parent).map_err(|e| e.to_string())?
+    {
+        let source = entry.map_err(|e| e.to_string())?.path();
+        if !source.is_file() || source.file_stem() != Some(stem)
+        {
+            continue;
+        }
+
+        let target = dir.join(source.file_name().unwrap());
+        let linked = link && fs::hard_link(&source, &target).is_ok();
+        if !linked
+        {
+            fs::copy(&source, &target).map_err(|e| e.to_string())?;
+        }
+    }
+
+    Ok(())
+}
+
+fn format_errors<E>(errors: &[E]) -> Vec<String>
+    where E : std::fmt::Display
+{
+    errors.iter().map(|e| e.to_string()).collect_vec()
+}
+
+#[cfg(test)]
+mod tests 
+{
+    use super::*;
+
+    const TEST_PACKAGE: &str = "./resources/biblio-json-pkg";
+
+    #[test]
+    fn test_find_dependency_bible()
+    {
+        let package = Path::new(TEST_PACKAGE);
+        let bibles = vec![
+            (BIBLES_CATEGORY.to_string(), package.join("bibles/english/kjv.toml")),
+            (BIBLES_CATEGORY.to_string(), package.join("bibles/english/asv.toml")),
+        ];
+
+        let footnotes = package.join("commentaries/asv-footnotes.toml");
+        assert_eq!(find_dependency_bible(&footnotes, &bibles), Some(bibles[1].clone()));
+        assert_eq!(find_dependency_bible(&footnotes, &bibles[..1]), None);
+        assert_eq!(find_dependency_bible(&package.join("readings/proverbs_monthly.toml"), &bibles), None);
+    }
+
+    #[test]
+    fn test_load_package_skips_broken_modules()
+    {
+        let dir = std::env::temp_dir().join(format!("package_loading_{}", get_uuid()));
+        let package_dir = dir.join("package");
+        let staging_dir = dir.join("staging");
+
+        fs::create_dir_all(package_dir.join("readings")).unwrap();
+        fs::write(package_dir.join(PACKAGE_FILE), "name = \"Test\"\nauthors = []\nlicense = \"MIT\"\n\n[module_paths]\nreadings = \"/readings/*\"\n").unwrap();
+        copy_module_files(&Path::new(TEST_PACKAGE).join("readings/proverbs_monthly.toml"), &package_dir.join("readings"), false).unwrap();
+        fs::write(package_dir.join("readings/broken.toml"), "name = \"Broken\"").unwrap();
+
+        let (package, report) = load_package(&package_dir, &staging_dir, &[]);
+        let package = package.unwrap();
+
+        assert!(package.modules.keys().any(|id| id.to_string() == "proverbs_monthly_readings"));
+        assert_eq!(report.fatal_error, None);
+        assert_eq!(report.failed_modules.len(), 1);
+        assert!(report.failed_modules[0].path.ends_with("broken.toml"));
+        assert!(!report.failed_modules[0].errors.is_empty());
+
+        // Only the folder staged for this load is removed, leaving the staging directory itself
+        assert_eq!(fs::read_dir(&staging_dir).unwrap().count(), 0);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -4,14 +4,17 @@ pub mod print_bible_format;
 pub mod writer;
 pub mod fonts;
 
+use std::collections::HashSet;
+
 use biblio_json::core::VerseRangeIter;
 use biblio_json::modules::Module;
 use biblio_json::{Package, core::VerseId, modules::ModuleId};
 use itertools::Itertools;
 
+use crate::bible::indexing::PackageIndex;
 use crate::bible::printing::print_bible_format::PrintBibleFormat;
 use crate::bible::printing::writer::BiblePdfWriter;
-use crate::bible::render::{VerseRenderData, fetch_verse_render_data};
+use crate::bible::render::{VerseRenderData, fetch_interlinear_columns, fetch_verse_render_data};
 
 #[derive(Debug, Clone)]
 pub struct PrintBibleRange
@@ -26,6 +29,9 @@ pub struct PrintBibleArgs<'a>
     pub format: &'a PrintBibleFormat,
     pub ranges: &'a [PrintBibleRange],
     pub package: &'a Package,
+    pub index: &'a PackageIndex,
+    /// The modules whose highlights and interlinear columns are printed
+    pub shown_modules: &'a HashSet<ModuleId>,
 }
 
 pub fn print_bible(args: PrintBibleArgs) -> Result<Vec<u8>, String>
@@ -33,7 +39,9 @@ pub fn print_bible(args: PrintBibleArgs) -> Result<Vec<u8>, String>
     let PrintBibleArgs { 
         format, 
         ranges, 
-        package 
+        package,
+        index,
+        shown_modules,
     } = args;
 
     let mut writer = BiblePdfWriter::new(format, package);
@@ -50,7 +58,7 @@ pub fn print_bible(args: PrintBibleArgs) -> Result<Vec<u8>, String>
             writer.header_return();
         }
 
-        let render_data = fetch_range_render_data(range, package);
+        let render_data = fetch_range_render_data(range, package, index, shown_modules);
         writer.write_title(range);
         for (i, verse) in render_data.iter().enumerate()
         {
@@ -58,14 +66,23 @@ pub fn print_bible(args: PrintBibleArgs) -> Result<Vec<u8>, String>
             {
                 writer.verse_return();
             }
-            writer.write_verse(&verse);
+
+            if format.interlinear
+            {
+                let columns = fetch_interlinear_columns(package, index, verse, shown_modules);
+                writer.write_interlinear_verse(verse, &columns);
+            }
+            else 
+            {
+                writer.write_verse(&verse);
+            }
         }
     }
 
     writer.build()
 }
 
-fn fetch_range_render_data(range: &PrintBibleRange, package: &Package) -> Vec<VerseRenderData>
+fn fetch_range_render_data(range: &PrintBibleRange, package: &Package, index: &PackageIndex, shown_modules: &HashSet<ModuleId>) -> Vec<VerseRenderData>
 {
     let bible = package.modules.get(&range.bible)
         .map(Module::as_bible)
@@ -73,11 +90,7 @@ fn fetch_range_render_data(range: &PrintBibleRange, package: &Package) -> Vec<Ve
         .unwrap();
 
     let verses = VerseRangeIter::from_verses(&bible.source.book_infos, range.from, range.to).collect_vec();
-    
-    let module_ids = package.modules.keys()
-        .map(Clone::clone)
-        .collect();
 
     let bible_id = &bible.config.id;
-    fetch_verse_render_data(package, &verses, bible_id, &module_ids)
+    fetch_verse_render_data(package, index, &verses, bible_id, shown_modules)
 }
\ No newline at end of file
@@ -267,6 +267,9 @@ pub struct PrintBibleFormat
     pub strongs_format: Option<StrongsFormat>,
     pub new_page_per_section: bool,
     pub footer: Option<FooterFormat>,
+    /// Stacks each word over its strongs numbers, lemmas and glosses, using the strongs format for them
+    #[serde(default)]
+    pub interlinear: bool,
 }
 
 impl Default for PrintBibleFormat
@@ -325,6 +328,7 @@ impl Default for PrintBibleFormat
             }),
             new_page_per_section: true,
             footer: None,
+            interlinear: false,
         }
     }
 }
\ No newline at end of file
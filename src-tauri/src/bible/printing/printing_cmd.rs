@@ -1,4 +1,4 @@
-use std::fs;
+use std::{fs, sync::Mutex};
 
 use base64::{Engine, engine::general_purpose};
 use itertools::Itertools;
@@ -6,7 +6,7 @@ use rfd::FileDialog;
 use serde::{Deserialize, Serialize};
 use tauri::{AppHandle, Emitter, State};
 
-use crate::{bible::{BiblioJsonPackageHandle, printing::{PrintBibleArgs, PrintBibleFormat, PrintBibleRange, print_bible, printing_state::PrintBibleState}}, repr::PrintBibleRangeJson};
+use crate::{bible::{BiblioJsonPackageHandle, PACKAGE_NOT_LOADED_ERROR, printing::{PrintBibleArgs, PrintBibleFormat, PrintBibleRange, print_bible, printing_state::PrintBibleState}}, core::{app::AppState, journal::AppStateChange}, repr::PrintBibleRangeJson};
 
 pub const PRINT_BIBLE_FORMAT_CHANGED_EVENT_NAME: &str = "print-bible-format-changed";
 
@@ -80,6 +80,7 @@ pub fn run_print_command(
     command: PrintingCommand,
     package: State<'_, BiblioJsonPackageHandle>,
     state: State<'_, PrintBibleState>,
+    app_state: State<'_, Mutex<AppState>>,
     app_handle: AppHandle,
 ) -> Option<String>
 {
@@ -87,7 +88,7 @@ pub fn run_print_command(
     {
         PrintingCommand::Preview => {
             let ranges = state.visit(|s| s.ranges.iter().map(PrintBibleRangeJson::from).collect_vec());
-            let result = generate_pdf(&ranges, &state, &package);
+            let result = generate_pdf(&ranges, &state, &app_state, &package);
 
             let bytes = match result
             {
@@ -115,9 +116,14 @@ pub fn run_print_command(
             });
 
             app_handle
-                .emit(PRINT_BIBLE_FORMAT_CHANGED_EVENT_NAME, event)
+                .emit(PRINT_BIBLE_FORMAT_CHANGED_EVENT_NAME, event.clone())
                 .unwrap();
 
+            AppStateChange::PrintBibleFormat {
+                old: event.old,
+                new: event.new,
+            }.record(&app_handle);
+
             None
         },
         PrintingCommand::GetFormat => {
@@ -129,7 +135,7 @@ pub fn run_print_command(
         },
         PrintingCommand::Download => {
             let ranges = state.visit(|s| s.ranges.iter().map(PrintBibleRangeJson::from).collect_vec());
-            let result = generate_pdf(&ranges, &state, &package);
+            let result = generate_pdf(&ranges, &state, &app_state, &package);
 
             let bytes = match result
             {
@@ -185,9 +191,14 @@ pub fn run_print_command(
             });
 
             app_handle
-                .emit(PRINT_BIBLE_RANGES_CHANGED_EVENT_NAME, event)
+                .emit(PRINT_BIBLE_RANGES_CHANGED_EVENT_NAME, event.clone())
                 .unwrap();
 
+            AppStateChange::PrintBibleRanges {
+                old: event.old,
+                new: event.new,
+            }.record(&app_handle);
+
             None
         },
         PrintingCommand::GetRanges => {
@@ -205,12 +216,14 @@ pub fn run_print_command(
 fn generate_pdf(
     ranges: &Vec<PrintBibleRangeJson>, 
     state: &PrintBibleState, 
+    app_state: &Mutex<AppState>,
     package: &BiblioJsonPackageHandle
 ) -> Result<Vec<u8>, String>
 {
     let format = state.visit(|s| s.format.clone());
+    let shown_modules = app_state.lock().unwrap().bible_display_settings.shown_modules.clone();
 
-    let result = package.visit(move |package| {
+    let result = package.visit_indexed(move |package, index| {
         let ranges = ranges.iter()
             .map(PrintBibleRange::from)
             .collect_vec();
@@ -219,10 +232,12 @@ fn generate_pdf(
             format: &format,
             ranges: &ranges,
             package: package,
+            index,
+            shown_modules: &shown_modules,
         };
 
         print_bible(args)
-    });
+    }).unwrap_or(Err(PACKAGE_NOT_LOADED_ERROR.into()));
 
     result
 }
\ No newline at end of file
@@ -5,7 +5,11 @@ use itertools::Itertools;
 use pdf_oxide::writer::{DocumentBuilder, FluentPageBuilder};
 use ttf_parser::{Face, GlyphId};
 
-use crate::bible::{printing::{PrintBibleRange, fonts::{Font, FontVariant}, print_bible_format::{BookFormatter, PageNumbers, PrintBibleFormat, TextAlign, TextFormat, VerseNumberFormatType}}, render::{VerseRenderData, WordRenderData}};
+use crate::{bible::{printing::{PrintBibleRange, fonts::{Font, FontVariant}, print_bible_format::{BookFormatter, PageNumbers, PrintBibleFormat, TextAlign, TextFormat, VerseNumberFormatType}}, render::{InterlinearColumnRenderData, VerseRenderData, WordRenderData}}, core::color::Color};
+
+/// Lines written under each column of an interlinear verse, for its strongs numbers, lemmas and glosses
+const INTERLINEAR_GLOSS_LINES: usize = 3;
+const INTERLINEAR_GLOSS_LINE_HEIGHT: f32 = 1.25;
 
 pub struct Curser
 {
@@ -25,6 +29,14 @@ pub enum WriterOp
         bold: bool,
         italic: bool,
     },
+    Rect
+    {
+        x: f32,
+        y: f32,
+        width: f32,
+        height: f32,
+        color: Color,
+    },
     NewPage,
 }
 
@@ -159,6 +171,102 @@ impl<'a> BiblePdfWriter<'a>
         }
     }
 
+    /// Writes the verse as columns of words, with lines of strongs numbers, lemmas and glosses under each column
+    pub fn write_interlinear_verse(&mut self, render_data: &VerseRenderData, columns: &[InterlinearColumnRenderData])
+    {
+        if let Some(verse_title) = self.format_verse_title(&render_data.bible, render_data.id.into())
+        {
+            self.write_space(self.format.verse_format.verse_indent);
+            self.write_word(&verse_title, &self.format.verse_format.verse_number_format.text_format, self.format.verse_format.line_height);
+            self.write_space(self.format.verse_format.verse_number_format.spacing);
+        }
+
+        let word_spacing = self.format.verse_format.word_spacing;
+        let text_format = &self.format.verse_format.text_format;
+        let word_height = measure_text_height(text_format.get_font_face(), text_format.font_size);
+
+        let gloss_format = self.get_gloss_format();
+        let gloss_face = gloss_format.get_font_face();
+        let gloss_line_height = measure_text_height(gloss_face, gloss_format.font_size) * INTERLINEAR_GLOSS_LINE_HEIGHT;
+        let glosses_height = INTERLINEAR_GLOSS_LINES as f32 * gloss_line_height;
+        let row_height = word_height * self.format.verse_format.line_height + glosses_height;
+        let right = self.format.page_size.width() - self.format.margin.right;
+        let bottom = self.format.page_size.height() - self.format.margin.bottom;
+
+        for (i, column) in columns.iter().enumerate()
+        {
+            if i != 0
+            {
+                self.write_space(word_spacing);
+            }
+
+            let words = column.words.iter().map(|w| {
+                let format = self.get_word_format(w);
+                (w, format, measure_text_width(format.get_font_face(), &w.word, format.font_size))
+            }).collect_vec();
+
+            let strongs = column.glosses.iter().map(|g| StrongsNumber::from(&g.strongs).to_string()).join("; ");
+            let lemmas = column.glosses.iter().filter_map(|g| g.lemma.as_deref()).join("; ");
+            let glosses = column.glosses.iter().filter_map(|g| g.gloss.as_deref()).join("; ");
+            let lines: [String; INTERLINEAR_GLOSS_LINES] = [strongs, lemmas, glosses];
+
+            let words_width = words.iter().map(|(_, _, width)| width).sum::<f32>() + word_spacing * words.len().saturating_sub(1) as f32;
+            let width = lines.iter()
+                .map(|l| measure_text_width(gloss_face, l, gloss_format.font_size))
+                .fold(words_width, f32::max);
+
+            if self.curser.x + width > right
+            {
+                self.new_line_raw(row_height);
+            }
+
+            if self.curser.y + word_height + glosses_height > bottom
+            {
+                self.new_page();
+            }
+
+            let x = self.curser.x;
+            for (j, (word, format, word_width)) in words.into_iter().enumerate()
+            {
+                if j != 0
+                {
+                    self.curser.x += word_spacing;
+                }
+
+                self.write_highlight(word, format.get_font_face(), format.font_size, word_width);
+                self.ops.push(WriterOp::Text { 
+                    text: word.word.clone(), 
+                    font: format.font, 
+                    size: format.font_size, 
+                    x: self.curser.x, 
+                    y: self.curser.y, 
+                    bold: format.bold, 
+                    italic: format.italic,
+                });
+
+                self.curser.x += word_width;
+            }
+
+            for (k, line) in lines.into_iter().enumerate().filter(|(_, l)| !l.is_empty())
+            {
+                self.ops.push(WriterOp::Text { 
+                    text: line, 
+                    font: gloss_format.font, 
+                    size: gloss_format.font_size, 
+                    x, 
+                    y: self.curser.y + word_height + k as f32 * gloss_line_height, 
+                    bold: gloss_format.bold, 
+                    italic: gloss_format.italic,
+                });
+            }
+
+            self.curser.x = x + width;
+        }
+
+        // Leaves room for the glosses under the last row before the next verse
+        self.curser.y += glosses_height;
+    }
+
     pub fn verse_return(&mut self)
     {
         self.new_line_raw(self.format.verse_format.verse_spacing);
@@ -311,15 +419,39 @@ impl<'a> BiblePdfWriter<'a>
         }
     }
     
-    fn write_verse_word_render_data(&mut self, render_data: &WordRenderData)
+    fn get_word_format(&self, render_data: &WordRenderData) -> &'a TextFormat
     {
-        let word_format = if render_data.italics {
+        if render_data.italics
+        {
             &self.format.verse_format.alt_text_format
         }
         else 
         {
             &self.format.verse_format.text_format    
-        };
+        }
+    }
+
+    /// The strongs format, or a smaller verse text format if strongs are not printed
+    fn get_gloss_format(&self) -> TextFormat
+    {
+        match &self.format.strongs_format
+        {
+            Some(strongs) => TextFormat {
+                font: strongs.font,
+                font_size: strongs.font_size,
+                bold: strongs.bold,
+                italic: strongs.italic,
+            },
+            None => TextFormat {
+                font_size: self.format.verse_format.text_format.font_size * 0.75,
+                ..self.format.verse_format.text_format.clone()
+            },
+        }
+    }
+
+    fn write_verse_word_render_data(&mut self, render_data: &WordRenderData)
+    {
+        let word_format = self.get_word_format(render_data);
         let word_face = word_format.get_font_face();
         let word = &render_data.word;
         let word_width = measure_text_width(word_face, word, word_format.font_size);
@@ -346,6 +478,8 @@ impl<'a> BiblePdfWriter<'a>
                 self.new_line(word_face, word_format.font_size, self.format.verse_format.line_height);
             }
 
+            self.write_highlight(render_data, word_face, word_format.font_size, word_width);
+
             // word text
             self.ops.push(WriterOp::Text { 
                 text: word.to_owned(), 
@@ -380,6 +514,8 @@ impl<'a> BiblePdfWriter<'a>
                 self.new_line(word_face, word_format.font_size, self.format.verse_format.line_height);
             }
 
+            self.write_highlight(render_data, word_face, word_format.font_size, word_width);
+
             // word text
             self.ops.push(WriterOp::Text { 
                 text: word.to_owned(), 
@@ -396,6 +532,21 @@ impl<'a> BiblePdfWriter<'a>
 
     }
 
+    fn write_highlight(&mut self, render_data: &WordRenderData, face: &Face, font_size: f32, width: f32)
+    {
+        let Some(color) = render_data.highlight_color.as_ref().and_then(|c| parse_highlight_color(c)) else {
+            return;
+        };
+
+        self.ops.push(WriterOp::Rect { 
+            x: self.curser.x, 
+            y: self.curser.y, 
+            width, 
+            height: measure_text_height(face, font_size), 
+            color,
+        });
+    }
+
     fn write_word(&mut self, word: &str, format: &TextFormat, line_height: f32)
     {
         let face = format.get_font_face();
@@ -479,6 +630,7 @@ impl<'a> BiblePdfWriter<'a>
                     let variant = FontVariant::new(*bold, *italic);
                     Some((*font, variant))
                 },
+                WriterOp::Rect { .. } => None,
                 WriterOp::NewPage => None,
             }
         }).collect::<HashSet<_>>();
@@ -516,6 +668,26 @@ impl<'a> BiblePdfWriter<'a>
                     );
                         
                 },
+                WriterOp::Rect { x, y, width, height, color } => {
+                    if page.is_none()
+                    {
+                        return Err("Cannot draw a rect on an empty page".into())
+                    }
+
+                    // Un-inverts Y-Coord
+                    let page_height = self.format.page_size.height();
+                    page = page.map(|p|
+                        p.filled_rect(
+                            x, 
+                            page_height - y - height, 
+                            width, 
+                            height, 
+                            color.r as f32 / 255.0, 
+                            color.g as f32 / 255.0, 
+                            color.b as f32 / 255.0,
+                        )
+                    );
+                },
                 WriterOp::NewPage => {
                     let page_size = self.format.page_size.to_pdf_size();
                     match page
@@ -545,6 +717,12 @@ impl<'a> BiblePdfWriter<'a>
     }
 }
 
+/// Highlight colors are stored as hex strings, eg: `#ffff00`
+fn parse_highlight_color(color: &str) -> Option<Color>
+{
+    serde_json::from_value(serde_json::Value::String(color.to_owned())).ok()
+}
+
 pub fn measure_text_width(face: &Face, text: &str, font_size: f32) -> f32 
 {
     let units_per_em = face.units_per_em() as f32;
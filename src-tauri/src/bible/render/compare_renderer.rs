@@ -0,0 +1,275 @@
+use std::collections::HashSet;
+
+use biblio_json::{Package, core::VerseId, modules::{Module, ModuleId}};
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+
+use crate::{bible::{indexing::PackageIndex, render::{RenderedVerseContent, VerseRenderData, WordRenderData, WrapTagArgs, fetch_verse_render_data, render_word, wrap_tag}}, core::utils::escape_html, repr::VerseIdJson};
+
+const WORD_SPACE: &str = "<span class=\"bible-space\"> </span>";
+
+/// A verse of the base bible, compared with the same verse in each of the other bibles
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct RenderedComparisonRow
+{
+    /// Numbered by the base bible
+    pub id: VerseIdJson,
+    /// One cell per bible, with an empty cell where a bible does not have the verse
+    pub cells: Vec<Option<RenderedVerseContent>>,
+}
+
+pub struct RenderComparisonArgs<'a>
+{
+    pub package: &'a Package,
+    pub index: &'a PackageIndex,
+    /// Numbered by the base bible
+    pub verses: &'a [VerseId],
+    pub bibles: &'a [ModuleId],
+    pub base: &'a ModuleId,
+    pub show_strongs: bool,
+    pub shown_modules: &'a HashSet<ModuleId>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DiffOp
+{
+    Equal
+    {
+        word: usize,
+    },
+    Insert
+    {
+        word: usize,
+    },
+    Delete
+    {
+        base_word: usize,
+    },
+}
+
+/// Renders verses across several bibles, marking the words of each that differ from the base bible
+/// with `diff-insert`, `diff-delete` and `diff-changed` spans
+pub fn render_verse_comparison(args: RenderComparisonArgs) -> Vec<RenderedComparisonRow>
+{
+    let RenderComparisonArgs { package, index, verses, bibles, base, show_strongs, shown_modules } = args;
+
+    let Some(base_versification) = package.get_mod(base).and_then(Module::as_bible).map(|b| index.get_versification(&b.config.id)) else {
+        return vec![];
+    };
+
+    let base_data = fetch_existing_render_data(package, index, verses, base, shown_modules);
+
+    // Each bible's verse data, in the same order as `verses`
+    let bible_data = bibles.iter().map(|bible| {
+        if package.get_mod(bible).and_then(Module::as_bible).is_none()
+        {
+            return vec![None; verses.len()];
+        }
+
+        if bible == base
+        {
+            return base_data.clone();
+        }
+
+        let versification = index.get_versification(bible);
+        let mapped = verses.iter().map(|v| base_versification.map_verse(*v, versification)).collect_vec();
+        fetch_existing_render_data(package, index, &mapped, bible, shown_modules)
+    }).collect_vec();
+
+    verses.iter().enumerate().map(|(i, verse)| {
+        let base_words = base_data[i].as_ref().map(|rd| rd.words.as_slice());
+        let cells = bibles.iter().zip(&bible_data).map(|(bible, data)| {
+            let rd = data[i].as_ref()?;
+            let html = match base_words
+            {
+                Some(base_words) if bible != base => render_diff(base_words, &rd.words, show_strongs),
+                _ => rd.words.iter().map(|w| render_word(w, show_strongs)).join(WORD_SPACE),
+            };
+
+            Some(RenderedVerseContent {
+                failed: false,
+                bible: bible.clone(),
+                id: rd.id.clone(),
+                html,
+                word_count: rd.words.len() as u32,
+            })
+        }).collect_vec();
+
+        RenderedComparisonRow {
+            id: verse.into(),
+            cells,
+        }
+    }).collect()
+}
+
+/// Fetches the render data of the verses that exist in the bible, leaving the missing ones empty
+fn fetch_existing_render_data(package: &Package, index: &PackageIndex, verses: &[VerseId], bible: &ModuleId, shown_modules: &HashSet<ModuleId>) -> Vec<Option<VerseRenderData>>
+{
+    let Some(module) = package.get_mod(bible).and_then(Module::as_bible) else {
+        return vec![None; verses.len()];
+    };
+
+    let existing = verses.iter().filter(|v| module.source.verses.contains_key(v)).copied().collect_vec();
+    let mut fetched = fetch_verse_render_data(package, index, &existing, bible, shown_modules).into_iter();
+
+    verses.iter()
+        .map(|v| module.source.verses.contains_key(v).then(|| fetched.next()).flatten())
+        .collect()
+}
+
+/// Renders `words`, wrapping runs of words that were added to, removed from or changed from `base_words`
+fn render_diff(base_words: &[WordRenderData], words: &[WordRenderData], show_strongs: bool) -> String
+{
+    let ops = diff_words(base_words, words);
+    let mut parts = vec![];
+
+    for (is_equal, run) in &ops.into_iter().chunk_by(|op| matches!(op, DiffOp::Equal { .. }))
+    {
+        let run = run.collect_vec();
+        if is_equal
+        {
+            parts.extend(run.iter().filter_map(|op| match op
+            {
+                DiffOp::Equal { word } => Some(render_word(&words[*word], show_strongs)),
+                _ => None,
+            }));
+
+            continue;
+        }
+
+        let inserted = run.iter().filter_map(|op| match op
+        {
+            DiffOp::Insert { word } => Some(render_word(&words[*word], show_strongs)),
+            _ => None,
+        }).join(WORD_SPACE);
+
+        let deleted = run.iter().filter_map(|op| match op
+        {
+            DiffOp::Delete { base_word } => Some(word_text(&base_words[*base_word])),
+            _ => None,
+        }).join(" ");
+
+        let (class, content, base_text) = match (inserted.is_empty(), deleted.is_empty())
+        {
+            (false, true) => ("diff-insert", inserted, None),
+            (true, false) => ("diff-delete", escape_html(&deleted), None),
+            _ => ("diff-changed", inserted, Some(escape_html(&deleted).replace('"', "&quot;"))),
+        };
+
+        let data = base_text.as_ref().map(|t| vec![("data-base-text", t.as_str())]);
+        parts.push(wrap_tag(WrapTagArgs {
+            tag: "span",
+            classes: Some(&[class]),
+            data: data.as_deref(),
+            content: &content,
+        }));
+    }
+
+    parts.join(WORD_SPACE)
+}
+
+/// Lines up the words of two verses by their longest common subsequence, ignoring case and punctuation
+fn diff_words(base_words: &[WordRenderData], words: &[WordRenderData]) -> Vec<DiffOp>
+{
+    let normalize = |w: &WordRenderData| w.word.chars().filter(|c| c.is_alphanumeric()).flat_map(char::to_lowercase).collect::<String>();
+    let base = base_words.iter().map(normalize).collect_vec();
+    let other = words.iter().map(normalize).collect_vec();
+
+    // lengths[i][j] is the length of the common subsequence of base[i..] and other[j..]
+    let mut lengths = vec![vec![0u32; other.len() + 1]; base.len() + 1];
+    for i in (0..base.len()).rev()
+    {
+        for j in (0..other.len()).rev()
+        {
+            lengths[i][j] = if base[i] == other[j]
+            {
+                lengths[i + 1][j + 1] + 1
+            }
+            else
+            {
+                lengths[i + 1][j].max(lengths[i][j + 1])
+            };
+        }
+    }
+
+    let (mut i, mut j) = (0, 0);
+    let mut ops = vec![];
+    while i < base.len() || j < other.len()
+    {
+        if i < base.len() && j < other.len() && base[i] == other[j]
+        {
+            ops.push(DiffOp::Equal { word: j });
+            i += 1;
+            j += 1;
+        }
+        else if j < other.len() && (i == base.len() || lengths[i][j + 1] >= lengths[i + 1][j])
+        {
+            ops.push(DiffOp::Insert { word: j });
+            j += 1;
+        }
+        else
+        {
+            ops.push(DiffOp::Delete { base_word: i });
+            i += 1;
+        }
+    }
+
+    ops
+}
+
+fn word_text(word: &WordRenderData) -> String
+{
+    format!("{}{}{}", word.begin_punc.as_deref().unwrap_or_default(), word.word, word.end_punc.as_deref().unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests 
+{
+    use super::*;
+
+    fn create_words(text: &str) -> Vec<WordRenderData>
+    {
+        text.split_whitespace().enumerate().map(|(i, word)| WordRenderData {
+            begin_punc: None,
+            word: word.into(),
+            end_punc: None,
+            red: false,
+            italics: false,
+            strongs: vec![],
+            highlight_color: None,
+            has_data: false,
+            index: i as u32,
+            heb_sub: false,
+        }).collect()
+    }
+
+    fn diff(base: &str, other: &str) -> Vec<DiffOp>
+    {
+        diff_words(&create_words(base), &create_words(other))
+    }
+
+    #[test]
+    fn test_diff_words()
+    {
+        use DiffOp::*;
+
+        assert_eq!(diff("In the beginning", "in the Beginning,"), vec![Equal { word: 0 }, Equal { word: 1 }, Equal { word: 2 }]);
+        assert_eq!(diff("a c", "a b c"), vec![Equal { word: 0 }, Insert { word: 1 }, Equal { word: 2 }]);
+        assert_eq!(diff("a b c", "a c"), vec![Equal { word: 0 }, Delete { base_word: 1 }, Equal { word: 1 }]);
+        assert_eq!(diff("a b c", "a x c"), vec![Equal { word: 0 }, Insert { word: 1 }, Delete { base_word: 1 }, Equal { word: 2 }]);
+        assert_eq!(diff("", "a"), vec![Insert { word: 0 }]);
+        assert_eq!(diff("a", ""), vec![Delete { base_word: 0 }]);
+    }
+
+    #[test]
+    fn test_render_diff()
+    {
+        let render = |base: &str, other: &str| render_diff(&create_words(base), &create_words(other), false);
+
+        assert!(render("a b c", "a x c").contains("<span class=\"diff-changed\" data-base-text=\"b\">"));
+        assert!(render("a b c", "a c").contains("<span class=\"diff-delete\">b</span>"));
+        assert!(render("a c", "a b c").contains("<span class=\"diff-insert\">"));
+        assert!(!render("a b c", "A b c").contains("diff-"));
+    }
+}
@@ -1,11 +1,15 @@
 pub mod verse_renderer;
 pub mod search_renderer;
 pub mod render_data;
+pub mod parallel_renderer;
+pub mod compare_renderer;
 
 use itertools::Itertools;
 pub use verse_renderer::*;
 pub use search_renderer::*;
 pub use render_data::*;
+pub use parallel_renderer::*;
+pub use compare_renderer::*;
 
 struct WrapTagArgs<'a>
 {
@@ -37,3 +41,10 @@ fn wrap_tag(args: WrapTagArgs) -> String
         }
     }
 }
+
+
+/// The inline style used to draw a word's highlight behind it, if it has one
+fn get_highlight_style(word: &WordRenderData) -> Option<String>
+{
+    word.highlight_color.as_ref().map(|c| format!("background-color: {};", c))
+}
@@ -0,0 +1,209 @@
+use std::{collections::{HashMap, HashSet}, num::NonZeroU32};
+
+use biblio_json::{Package, core::{ChapterId, OsisBook, VerseId}, modules::{Module, ModuleId, bible::BibleModule}};
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+
+use crate::{bible::{indexing::PackageIndex, render::{RenderedVerseContent, render_verses}}, repr::VerseIdJson};
+
+/// A row of verses that line up across the rendered bibles, with an empty cell where a bible does not have the verse
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct RenderedParallelRow
+{
+    /// The verse in the KJV numbering, which the cells are aligned through
+    pub id: VerseIdJson,
+    pub cells: Vec<Option<RenderedVerseContent>>,
+}
+
+pub struct RenderParallelArgs<'a>
+{
+    pub package: &'a Package,
+    pub index: &'a PackageIndex,
+    /// Numbered by the first bible
+    pub chapter: ChapterId,
+    pub bibles: &'a [ModuleId],
+    pub show_strongs: bool,
+    pub shown_modules: &'a HashSet<ModuleId>,
+}
+
+/// Renders a chapter of the first bible alongside the same verses in the others, which may be split over different chapters
+pub fn render_parallel_chapter(args: RenderParallelArgs) -> Vec<RenderedParallelRow>
+{
+    let RenderParallelArgs { package, index, chapter, bibles, show_strongs, shown_modules } = args;
+
+    let Some(primary) = bibles.first().and_then(|b| package.get_mod(b)).and_then(Module::as_bible) else {
+        return vec![];
+    };
+
+    let primary_versification = index.get_versification(&primary.config.id);
+    let primary_verses = get_chapter_verses(primary, chapter.book, chapter.chapter.get());
+    let keys = primary_verses.iter().map(|v| primary_versification.to_kjv(*v)).collect_vec();
+    let (Some(first), Some(last)) = (keys.iter().map(verse_key).min(), keys.iter().map(verse_key).max()) else {
+        return vec![];
+    };
+
+    let mut rows: Vec<(VerseId, Vec<Option<VerseId>>)> = vec![];
+    for (i, bible) in bibles.iter().enumerate()
+    {
+        let versification = index.get_versification(bible);
+        let Some(bible) = package.get_mod(bible).and_then(Module::as_bible) else { continue; };
+
+        // Every verse of the chapters that the primary chapter maps to, as some of them may fall outside of it
+        let verses = keys.iter()
+            .map(|k| versification.from_kjv(*k).chapter.get())
+            .unique()
+            .flat_map(|c| get_chapter_verses(bible, chapter.book, c))
+            .map(|v| (v, versification.to_kjv(v)))
+            .filter(|(_, k)| (first..=last).contains(&verse_key(k)))
+            .collect_vec();
+
+        // Verses that the KJV maps back to go first, so that a Hebrew psalm title gets its own row, rather than the first verse's
+        let (canonical, extra): (Vec<_>, Vec<_>) = verses.into_iter()
+            .partition(|(v, k)| versification.from_kjv(*k) == *v);
+
+        for (verse, key) in canonical
+        {
+            let row = rows.iter().position(|(k, cells)| *k == key && cells.get(i).is_none_or(Option::is_none));
+            match row
+            {
+                Some(row) => set_cell(&mut rows[row].1, i, verse),
+                None => insert_row(&mut rows, key, i, verse, false),
+            }
+        }
+
+        for (verse, key) in extra
+        {
+            insert_row(&mut rows, key, i, verse, true);
+        }
+    }
+
+    // Renders each bible's verses in one go, then puts them into their cells
+    let rendered = bibles.iter().enumerate().map(|(i, bible)| {
+        let Some(bible_module) = package.get_mod(bible).and_then(Module::as_bible) else {
+            return HashMap::new();
+        };
+
+        // Verses left out of a translation, such as textual variants, are kept as empty cells
+        let verses = rows.iter()
+            .filter_map(|(_, cells)| cells.get(i).copied().flatten())
+            .filter(|v| bible_module.source.verses.contains_key(v))
+            .collect_vec();
+
+        let contents = render_verses(package, index, &verses, bible, show_strongs, shown_modules);
+        verses.into_iter().zip(contents).collect::<HashMap<_, _>>()
+    }).collect_vec();
+
+    rows.into_iter().map(|(key, cells)| {
+        let cells = (0..bibles.len())
+            .map(|i| cells.get(i).copied().flatten().and_then(|v| rendered[i].get(&v).cloned()))
+            .collect_vec();
+
+        RenderedParallelRow {
+            id: key.into(),
+            cells,
+        }
+    }).collect()
+}
+
+fn get_chapter_verses(bible: &BibleModule, book: OsisBook, chapter: u32) -> Vec<VerseId>
+{
+    let count = bible.source.book_infos.iter()
+        .find(|b| b.osis_book == book)
+        .and_then(|b| b.chapters.get((chapter as usize).checked_sub(1)?))
+        .copied()
+        .unwrap_or_default();
+
+    (1..=count).filter_map(|verse| Some(VerseId {
+        book,
+        chapter: NonZeroU32::new(chapter)?,
+        verse: NonZeroU32::new(verse)?,
+    })).collect()
+}
+
+/// Orders verses within a book, as versification never maps a verse into another book
+fn verse_key(verse: &VerseId) -> (u32, u32)
+{
+    (verse.chapter.get(), verse.verse.get())
+}
+
+fn set_cell(cells: &mut Vec<Option<VerseId>>, index: usize, verse: VerseId)
+{
+    if cells.len() <= index
+    {
+        cells.resize(index + 1, None);
+    }
+
+    cells[index] = Some(verse);
+}
+
+/// Adds a row for a verse, before the other rows with the same key if `before`, or after them otherwise
+fn insert_row(rows: &mut Vec<(VerseId, Vec<Option<VerseId>>)>, key: VerseId, index: usize, verse: VerseId, before: bool)
+{
+    let position = rows.iter()
+        .position(|(k, _)| if before { verse_key(k) >= verse_key(&key) } else { verse_key(k) > verse_key(&key) })
+        .unwrap_or(rows.len());
+
+    let mut cells = vec![];
+    set_cell(&mut cells, index, verse);
+    rows.insert(position, (key, cells));
+}
+
+#[cfg(test)]
+mod tests 
+{
+    use super::*;
+
+    fn create_test_package() -> Package 
+    {
+        Package::load("./resources/biblio-json-pkg").unwrap()
+    }
+
+    fn verse(chapter: u32, verse: u32) -> VerseId
+    {
+        VerseId::new(OsisBook::Gen, NonZeroU32::new(chapter).unwrap(), NonZeroU32::new(verse).unwrap())
+    }
+
+    #[test]
+    fn test_insert_row()
+    {
+        let mut rows = vec![];
+        insert_row(&mut rows, verse(1, 1), 0, verse(1, 1), false);
+        insert_row(&mut rows, verse(1, 2), 0, verse(1, 2), false);
+        insert_row(&mut rows, verse(1, 1), 1, verse(1, 1), true);
+        insert_row(&mut rows, verse(1, 1), 2, verse(1, 2), false);
+
+        let cells = rows.iter().map(|(k, cells)| (verse_key(k), cells.clone())).collect_vec();
+        assert_eq!(cells, vec![
+            ((1, 1), vec![None, Some(verse(1, 1))]),
+            ((1, 1), vec![Some(verse(1, 1))]),
+            ((1, 1), vec![None, None, Some(verse(1, 2))]),
+            ((1, 2), vec![Some(verse(1, 2))]),
+        ]);
+    }
+
+    #[test]
+    fn test_render_parallel_chapter()
+    {
+        let package = create_test_package();
+        let index = PackageIndex::build(&package);
+        let bibles = [ModuleId::new("kjv_eng".into()), ModuleId::new("bsb_eng".into())];
+        let shown_modules = HashSet::new();
+
+        let rows = render_parallel_chapter(RenderParallelArgs { 
+            package: &package, 
+            index: &index, 
+            chapter: ChapterId { book: OsisBook::Gen, chapter: NonZeroU32::new(1).unwrap() }, 
+            bibles: &bibles, 
+            show_strongs: false, 
+            shown_modules: &shown_modules,
+        });
+
+        assert_eq!(rows.len(), 31);
+        for (i, row) in rows.iter().enumerate()
+        {
+            assert_eq!(VerseId::from(&row.id), verse(1, i as u32 + 1));
+            assert!(row.cells.iter().all(Option::is_some));
+        }
+    }
+}
@@ -1,11 +1,11 @@
 use std::{collections::HashSet, num::NonZeroU32};
 
-use biblio_json::{Package, core::{VerseId, WordRange}, modules::{Module, ModuleId}};
+use biblio_json::{Package, core::{ChapterId, RefId, StrongsNumber, VerseId}, html_text::{HtmlText, ast::Node}, modules::{Module, ModuleId, notebook::NotebookEntry}};
 use itertools::Itertools;
 use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
 use serde::{Deserialize, Serialize};
 
-use crate::{bible::fetching::PackageEx, repr::{StrongsNumberJson, VerseIdJson}};
+use crate::{bible::{fetching::{PackageEx, is_word_ref_id}, indexing::PackageIndex}, repr::{StrongsNumberJson, VerseIdJson}};
 
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -17,6 +17,23 @@ pub struct VerseRenderData
     pub failed: bool,
 }
 
+/// One or more words, stacked over the strongs numbers they translate, as a column of an interlinear verse
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InterlinearColumnRenderData
+{
+    pub words: Vec<WordRenderData>,
+    pub glosses: Vec<InterlinearGlossRenderData>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InterlinearGlossRenderData
+{
+    pub strongs: StrongsNumberJson,
+    /// The original language word, from the first shown strongs definitions module that has it
+    pub lemma: Option<String>,
+    pub gloss: Option<String>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct WordRenderData
 {
@@ -32,7 +49,7 @@ pub struct WordRenderData
     pub heb_sub: bool,
 }
 
-pub fn fetch_verse_render_data(package: &Package, verses: &[VerseId], bible: &ModuleId, shown_modules: &HashSet<ModuleId>) -> Vec<VerseRenderData>
+pub fn fetch_verse_render_data(package: &Package, index: &PackageIndex, verses: &[VerseId], bible: &ModuleId, shown_modules: &HashSet<ModuleId>) -> Vec<VerseRenderData>
 {
     let bible = package.get_mod(bible).and_then(Module::as_bible).unwrap();
     
@@ -40,31 +57,19 @@ pub fn fetch_verse_render_data(package: &Package, verses: &[VerseId], bible: &Mo
         let words = &bible.source.verses.get(v).unwrap().words;
         let word_count = words.len();
         let word_ids = (1..=word_count).map(|w| (*v, NonZeroU32::new(w as u32).unwrap())).collect_vec();
-        let words_have_data: HashSet<_> = package.fetch_words_have_entries(&word_ids, &bible.config.id, shown_modules).into_iter().collect();
+        let words_have_data: HashSet<_> = package.fetch_words_have_entries(index, &word_ids, &bible.config.id, shown_modules).into_iter().collect();
+        let highlights = fetch_verse_highlights(package, index, *v, shown_modules);
         
         let words = words.iter().enumerate().map(|(i, w)| {
             let i = i as u32;
             
-            let strongs = if let Some(links) = package.modules.values().
-                filter_map(Module::as_strongs_links)
-                .find(|l| l.config.bible == bible.config.id)
-            {
-                links.entries.iter().find(|l| l.verse_id == *v).as_ref().map(|s| {
-                    s.words.iter().find(|w| match w.range {
-                        WordRange::Single(s) => s.get() == i + 1,
-                        WordRange::Range(s, e) => s.get() <= i + 1 && e.get() >= i + 1,
-                    }).map(|s| s.strongs.iter().map(|s| -> StrongsNumberJson {
-                        s.into()
-                    }).collect_vec())
-                }).flatten().unwrap_or_default()
-            }
-            else 
-            {
-                vec![]    
-            };
-
             let word_id = (*v, NonZeroU32::new(i as u32 + 1).unwrap());
+            let strongs = index.strongs.get_word_strongs(&bible.config.id, word_id.0, word_id.1)
+                .map(|s| s.iter().map(StrongsNumberJson::from).collect_vec())
+                .unwrap_or_default();
+
             let has_data = words_have_data.contains(&word_id);
+            let highlight_color = get_word_highlight_color(&highlights, word_id.0, word_id.1);
 
             WordRenderData {
                 begin_punc: w.begin_punc.clone(),
@@ -73,7 +78,7 @@ pub fn fetch_verse_render_data(package: &Package, verses: &[VerseId], bible: &Mo
                 red: w.red.unwrap_or_default(),
                 italics: w.italics.unwrap_or_default(),
                 strongs,
-                highlight_color: None,
+                highlight_color,
                 has_data,
                 index: i as u32,
                 heb_sub: w.heb_sub.unwrap_or_default(),
@@ -87,4 +92,176 @@ pub fn fetch_verse_render_data(package: &Package, verses: &[VerseId], bible: &Mo
             failed: false,
         }
     }).collect::<Vec<_>>()
-}
\ No newline at end of file
+}
+
+/// Groups a verse's words into interlinear columns, keeping the words of a linked word range together
+pub fn fetch_interlinear_columns(package: &Package, index: &PackageIndex, verse: &VerseRenderData, shown_modules: &HashSet<ModuleId>) -> Vec<InterlinearColumnRenderData>
+{
+    let strongs_defs = package.modules.values()
+        .filter(|m| shown_modules.contains(m.id()))
+        .filter_map(Module::as_strongs_defs)
+        .collect_vec();
+
+    let links = index.strongs.get_links(&verse.bible, verse.id.into());
+    let chunks = verse.words.iter().enumerate()
+        // Unlinked words get a key of their own, so that each is its own column
+        .chunk_by(|(i, _)| links.and_then(|l| l.get_word_link(*i)).ok_or(*i));
+
+    let mut columns = vec![];
+    for (_, words) in &chunks
+    {
+        let words = words.map(|(_, w)| w.clone()).collect_vec();
+        let glosses = words[0].strongs.iter().map(|s| {
+            let def = strongs_defs.iter().find_map(|defs| defs.get_def(&StrongsNumber::from(s)));
+            InterlinearGlossRenderData {
+                strongs: s.clone(),
+                lemma: def.map(|d| d.word.clone()).filter(|w| !w.is_empty()),
+                gloss: def.and_then(|d| get_short_gloss(&d.definition)),
+            }
+        }).collect_vec();
+
+        columns.push(InterlinearColumnRenderData { words, glosses });
+    }
+
+    columns
+}
+
+/// Glosses longer than this are cut short
+const MAX_GLOSS_CHARS: usize = 32;
+
+/// The first phrase of a strongs definition, which is usually its short meaning
+fn get_short_gloss(definition: &HtmlText) -> Option<String>
+{
+    fn push_text(node: &Node, text: &mut String)
+    {
+        match node
+        {
+            Node::Text(t) => text.push_str(t),
+            Node::Paragraph(nodes) | Node::ListItem(nodes) | Node::Underline(nodes) | Node::Italic(nodes) | Node::Bold(nodes) | Node::Strike(nodes) => {
+                nodes.iter().for_each(|n| push_text(n, text));
+                text.push(' ');
+            },
+            Node::Heading { content, .. } | Node::Anchor { content, .. } => content.iter().for_each(|n| push_text(n, text)),
+            Node::List { items, .. } => items.iter().for_each(|n| push_text(n, text)),
+            Node::LineBreak | Node::HorizontalRule => text.push('\n'),
+            Node::Image { .. } => {},
+        }
+    }
+
+    let mut text = String::new();
+    definition.nodes.iter().for_each(|n| push_text(n, &mut text));
+
+    let phrase = text.split(['\n', ';', '.', ':']).map(str::trim).find(|p| !p.is_empty())?;
+    let phrase = phrase.split_whitespace().join(" ");
+    if phrase.chars().count() <= MAX_GLOSS_CHARS
+    {
+        return Some(phrase);
+    }
+
+    Some(format!("{}…", phrase.chars().take(MAX_GLOSS_CHARS - 1).collect::<String>().trim_end()))
+}
+
+struct HighlightRenderData<'a>
+{
+    priority: u32,
+    color: String,
+    references: &'a [RefId],
+}
+
+fn fetch_verse_highlights<'a>(package: &'a Package, index: &PackageIndex, verse: VerseId, shown_modules: &HashSet<ModuleId>) -> Vec<HighlightRenderData<'a>>
+{
+    package.modules.values()
+        .filter(|m| shown_modules.contains(m.id()))
+        .filter_map(|m| match m {
+            Module::Notebook(notebook) => Some(notebook),
+            _ => None,
+        })
+        .flat_map(|notebook| {
+            let id = &notebook.config.id;
+            let chapter = ChapterId { book: verse.book, chapter: verse.chapter };
+            index.refs.get_word_entries(id, verse).iter()
+                .chain(index.refs.get_verse_entries(id, verse))
+                .chain(index.refs.get_chapter_entries(id, chapter))
+                .chain(index.refs.get_book_entries(id, verse.book))
+                .unique()
+                .filter_map(|i| notebook.entries.get(*i))
+        })
+        .filter_map(|e| match e {
+            NotebookEntry::Highlight { priority, color, references, .. } => Some(HighlightRenderData {
+                priority: *priority,
+                color: color.to_string(),
+                references,
+            }),
+            NotebookEntry::Note { .. } => None,
+        })
+        .collect_vec()
+}
+
+/// Finds the color of the highest priority highlight covering the word, either directly or through a verse, chapter or book range containing it
+fn get_word_highlight_color(highlights: &[HighlightRenderData], verse: VerseId, word: NonZeroU32) -> Option<String>
+{
+    highlights.iter()
+        .filter(|h| h.references.iter().any(|r| {
+            if is_word_ref_id(r)
+            {
+                r.has_verse_word(verse, word)
+            }
+            else 
+            {
+                r.has_verse(verse)
+            }
+        }))
+        .max_by_key(|h| h.priority)
+        .map(|h| h.color.clone())
+}
+
+#[cfg(test)]
+mod tests 
+{
+    use biblio_json::core::{Atom, OsisBook, RefIdInner};
+
+    use super::*;
+
+    fn n(value: u32) -> NonZeroU32
+    {
+        NonZeroU32::new(value).unwrap()
+    }
+
+    fn ref_id(atom: Atom) -> RefId
+    {
+        RefId { bible: None, id: RefIdInner::Single(atom) }
+    }
+
+    #[test]
+    fn test_word_highlight_color()
+    {
+        let verse = VerseId::new(OsisBook::Gen, n(1), n(1));
+        let chapter_refs = [ref_id(Atom::Chapter { book: OsisBook::Gen, chapter: n(1) })];
+        let verse_refs = [ref_id(Atom::Verse { book: OsisBook::Gen, chapter: n(1), verse: n(1) })];
+        let word_refs = [ref_id(Atom::Word { book: OsisBook::Gen, chapter: n(1), verse: n(1), word: n(2) })];
+
+        let highlights = [
+            HighlightRenderData { priority: 0, color: "chapter".into(), references: &chapter_refs },
+            HighlightRenderData { priority: 2, color: "word".into(), references: &word_refs },
+            HighlightRenderData { priority: 1, color: "verse".into(), references: &verse_refs },
+        ];
+
+        assert_eq!(get_word_highlight_color(&highlights, verse, n(1)), Some("verse".into()));
+        assert_eq!(get_word_highlight_color(&highlights, verse, n(2)), Some("word".into()));
+        assert_eq!(get_word_highlight_color(&highlights[..1], verse, n(2)), Some("chapter".into()));
+
+        let other_verse = VerseId::new(OsisBook::Gen, n(2), n(1));
+        assert_eq!(get_word_highlight_color(&highlights, other_verse, n(1)), None);
+    }
+
+    #[test]
+    fn test_short_gloss()
+    {
+        let gloss = |html: &str| get_short_gloss(&crate::repr::parse_html_text(html).unwrap());
+
+        assert_eq!(gloss("<p>beginning, chief; first</p>"), Some("beginning, chief".into()));
+        assert_eq!(gloss("<p>  </p><p><b>God</b>. The supreme God</p>"), Some("God".into()));
+        assert_eq!(gloss("<p>a very long definition without any stops in it</p>"), Some("a very long definition without…".into()));
+        assert_eq!(gloss("<p></p>"), None);
+    }
+}
@@ -1,10 +1,10 @@
-use std::collections::{HashMap, HashSet};
+use std::{collections::{HashMap, HashSet}, sync::Mutex};
 
-use biblio_json::{Package, core::{StrongsNumber, VerseId}, modules::ModuleId};
+use biblio_json::{Package, core::{StrongsNumber, VerseId, lang::Language}, modules::{Module, ModuleId}};
 use itertools::Itertools;
 use serde::{Deserialize, Serialize};
 
-use crate::{bible::render::{WordRenderData, WrapTagArgs, fetch_verse_render_data, verse_renderer::RenderedVerseContent, wrap_tag}, searching::{VerseWordSearchHit, module_searching::WordSearchMode, word_search_engine::{WordSearchPart, WordSearchQuery}}};
+use crate::{bible::{indexing::PackageIndex, render::{WordRenderData, WrapTagArgs, fetch_verse_render_data, get_highlight_style, verse_renderer::RenderedVerseContent, wrap_tag}}, searching::{VerseWordSearchHit, module_searching::WordSearchMode, ranking::{SearchSortMode, TermFrequencies, rank_hits}, word_search_engine::{WordSearchOptions, WordSearchPart, WordSearchQuery}}};
 
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -19,27 +19,72 @@ pub struct RenderSearchArgs<'a>
 {
     pub query: &'a WordSearchQuery,
     pub package: &'a Package,
+    pub index: &'a PackageIndex,
     pub show_strongs: bool,
     pub page_index: u32,
     pub page_size: u32,
     pub shown_modules: &'a HashSet<ModuleId>,
+    pub cache: &'a WordSearchCache,
+    pub sort: SearchSortMode,
+}
+
+/// Keeps the sorted hits of the last rendered word search, so that changing pages does not re-run the search.
+/// The hits are tied to the [`PackageIndex::generation`] they were found at, so any change to the modules invalidates them
+#[derive(Debug, Default)]
+pub struct WordSearchCache(Mutex<Option<(WordSearchQuery, SearchSortMode, u64, Vec<VerseWordSearchHit>)>>);
+
+impl WordSearchCache
+{
+    pub fn new() -> Self 
+    {
+        Self::default()
+    }
+
+    fn get_or_run(&self, query: &WordSearchQuery, sort: SearchSortMode, generation: u64, run: impl FnOnce() -> Vec<VerseWordSearchHit>) -> Vec<VerseWordSearchHit>
+    {
+        if let Some((cached_query, cached_sort, cached_generation, hits)) = self.0.lock().unwrap().as_ref()
+        {
+            if cached_query == query && *cached_sort == sort && *cached_generation == generation
+            {
+                return hits.clone();
+            }
+        }
+
+        // The search runs without holding the lock, so that a slow search does not hold up others
+        let hits = run();
+        *self.0.lock().unwrap() = Some((query.clone(), sort, generation, hits.clone()));
+        hits
+    }
 }
 
 pub fn render_word_search_verses(args: RenderSearchArgs) -> RenderWordSearchResult
 {
-    let RenderSearchArgs { query, package, show_strongs, page_index, page_size, shown_modules } = args;
-
-    let modules = query.ranges.iter().map(|r| r.bible.clone()).collect_vec();
-    let mut hits = query.run_query(package, &modules, WordSearchMode::Body).iter().map(|h| {
-        let verse_id = package.fetch_entry(h.entry_ref.clone()).unwrap().as_verse().unwrap().verse_id;
-        VerseWordSearchHit {
-            bible: h.entry_ref.module.clone(),
-            verse: verse_id.into(),
-            hits: h.body_hits.clone(),
+    let RenderSearchArgs { query, package, index, show_strongs, page_index, page_size, shown_modules, cache, sort } = args;
+
+    let mut hits = cache.get_or_run(query, sort, index.generation, || {
+        let modules = query.ranges.iter().map(|r| r.bible.clone()).collect_vec();
+        let mut module_hits = query.run_query(package, index, &modules, WordSearchMode::Body, sort);
+        if sort == SearchSortMode::Relevance
+        {
+            rank_hits(&mut module_hits, &TermFrequencies::new(index, &modules, query));
         }
-    }).collect_vec();
 
-    sort_hits(&mut hits);
+        let mut hits = module_hits.iter().map(|h| {
+            let verse_id = package.fetch_entry(h.entry_ref.clone()).unwrap().as_verse().unwrap().verse_id;
+            VerseWordSearchHit {
+                bible: h.entry_ref.module.clone(),
+                verse: verse_id.into(),
+                hits: h.body_hits.clone(),
+            }
+        }).collect_vec();
+
+        if sort == SearchSortMode::Canonical
+        {
+            sort_hits(&mut hits);
+        }
+
+        hits
+    });
 
     let start = (page_index * page_size) as usize;
     let end = std::cmp::min(start + page_size as usize, hits.len());
@@ -58,10 +103,17 @@ pub fn render_word_search_verses(args: RenderSearchArgs) -> RenderWordSearchResu
     }
 
     let mut rendered = grouped_hits.into_iter().map(|(id, group)| {
-        render_searched_hits(package, &group, args.query.root.as_ref(), &id, show_strongs, shown_modules)
+        render_searched_hits(package, index, &group, args.query.root.as_ref(), &args.query.options, &id, show_strongs, shown_modules)
     }).flatten().collect_vec();
 
-    sort_rendered_content(&mut rendered);
+    match sort
+    {
+        SearchSortMode::Canonical => sort_rendered_content(&mut rendered),
+        SearchSortMode::Relevance => {
+            // Kept in the ranked order of the page's hits
+            rendered.sort_by_key(|r| rendered_hits.iter().position(|h| h.bible == r.bible && h.verse == r.id));
+        },
+    }
     
     RenderWordSearchResult { 
         verses: rendered, 
@@ -70,11 +122,12 @@ pub fn render_word_search_verses(args: RenderSearchArgs) -> RenderWordSearchResu
 }
 
 /// We assume all hits have the same `bible`
-fn render_searched_hits(package: &Package, hits: &[VerseWordSearchHit], query_root: Option<&WordSearchPart>, bible: &ModuleId, show_strongs: bool, shown_modules: &HashSet<ModuleId>) -> Vec<RenderedVerseContent>
+fn render_searched_hits(package: &Package, index: &PackageIndex, hits: &[VerseWordSearchHit], query_root: Option<&WordSearchPart>, options: &WordSearchOptions, bible: &ModuleId, show_strongs: bool, shown_modules: &HashSet<ModuleId>) -> Vec<RenderedVerseContent>
 {
     let verses = hits.iter().map(|h| VerseId::from(h.verse)).collect_vec();
+    let language = package.get_mod(bible).and_then(Module::as_bible).and_then(|b| b.config.language);
 
-    fetch_verse_render_data(package, &verses, bible, shown_modules).into_iter().zip_eq(hits).map(|(rd, hit)| {
+    fetch_verse_render_data(package, index, &verses, bible, shown_modules).into_iter().zip_eq(hits).map(|(rd, hit)| {
         if rd.failed
         {
             RenderedVerseContent {
@@ -105,7 +158,7 @@ fn render_searched_hits(package: &Package, hits: &[VerseWordSearchHit], query_ro
                     html.push_str("[[");
                 }
 
-                html.push_str(&render_word(&rd.words[i], query_root, hit, show_strongs));
+                html.push_str(&render_word(&rd.words[i], query_root, options, language, hit, show_strongs));
 
                 if i == rd.words.len() - 1 && rd.words[i].heb_sub
                 {
@@ -128,9 +181,9 @@ fn render_searched_hits(package: &Package, hits: &[VerseWordSearchHit], query_ro
     }).collect()
 }
 
-fn render_word(word: &WordRenderData, query_root: Option<&WordSearchPart>, hit: &VerseWordSearchHit, show_strongs: bool) -> String 
+fn render_word(word: &WordRenderData, query_root: Option<&WordSearchPart>, options: &WordSearchOptions, language: Option<Language>, hit: &VerseWordSearchHit, show_strongs: bool) -> String 
 {
-    let selected_word = hit.hits.contains(&word.index) && query_root.as_ref().map(|r| r.contains_word(&word.word)).unwrap_or_default();
+    let selected_word = hit.hits.contains(&word.index) && query_root.as_ref().map(|r| r.contains_word(&word.word, options, language)).unwrap_or_default();
     let selected_strongs = if hit.hits.contains(&word.index) 
     {
         word.strongs.iter()
@@ -151,11 +204,22 @@ fn render_word(word: &WordRenderData, query_root: Option<&WordSearchPart>, hit:
     if word.red { classes.push("red"); }
     if can_click { classes.push("clickable"); }
     if selected_word { classes.push("searched"); }
+    // Words between the parts of a NEAR or BEFORE hit
+    else if hit.hits.contains(&word.index) && selected_strongs.is_empty() { classes.push("spanned"); }
+
+    let word_index = format!("{}", word.index + 1);
+    let highlight_style = get_highlight_style(word);
+    let mut data = vec![("data-word-index", word_index.as_str())];
+    if let Some(style) = &highlight_style
+    {
+        classes.push("highlighted");
+        data.push(("style", style));
+    }
 
     let mut content = wrap_tag(WrapTagArgs { 
         tag: "span", 
         classes: Some(&classes), 
-        data: Some(&[("data-word-index", &format!("{}", word.index + 1))]), 
+        data: Some(&data), 
         content: &text,
     });
 
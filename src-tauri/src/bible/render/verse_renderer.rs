@@ -4,7 +4,7 @@ use biblio_json::{Package, core::{StrongsNumber, VerseId}, modules::ModuleId};
 use itertools::Itertools;
 use serde::{Deserialize, Serialize};
 
-use crate::{bible::render::{WordRenderData, WrapTagArgs, fetch_verse_render_data, wrap_tag}, repr::VerseIdJson};
+use crate::{bible::{indexing::PackageIndex, render::{InterlinearColumnRenderData, WordRenderData, WrapTagArgs, fetch_interlinear_columns, fetch_verse_render_data, get_highlight_style, wrap_tag}}, core::utils::escape_html, repr::VerseIdJson};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
@@ -17,9 +17,9 @@ pub struct RenderedVerseContent
     pub word_count: u32,
 }
 
-pub fn render_verses(package: &Package, verses: &Vec<VerseId>, bible: &ModuleId, show_strongs: bool, shown_modules: &HashSet<ModuleId>) -> Vec<RenderedVerseContent>
+pub fn render_verses(package: &Package, index: &PackageIndex, verses: &Vec<VerseId>, bible: &ModuleId, show_strongs: bool, shown_modules: &HashSet<ModuleId>) -> Vec<RenderedVerseContent>
 {
-    fetch_verse_render_data(package, verses, bible, shown_modules).into_iter().map(|rd| {
+    fetch_verse_render_data(package, index, verses, bible, shown_modules).into_iter().map(|rd| {
         if rd.failed
         {
             RenderedVerseContent {
@@ -73,7 +73,62 @@ pub fn render_verses(package: &Package, verses: &Vec<VerseId>, bible: &ModuleId,
     }).collect()
 }
 
-fn render_word(word: &WordRenderData, show_strongs: bool) -> String 
+/// Renders each verse as columns of words stacked over their strongs numbers, lemmas and glosses
+pub fn render_interlinear_verses(package: &Package, index: &PackageIndex, verses: &Vec<VerseId>, bible: &ModuleId, shown_modules: &HashSet<ModuleId>) -> Vec<RenderedVerseContent>
+{
+    fetch_verse_render_data(package, index, verses, bible, shown_modules).into_iter().map(|rd| {
+        let html = fetch_interlinear_columns(package, index, &rd, shown_modules).iter()
+            .map(render_interlinear_column)
+            .join("<span class=\"bible-space\"> </span>");
+
+        RenderedVerseContent {
+            failed: rd.failed,
+            id: rd.id,
+            word_count: rd.words.len() as u32,
+            html,
+            bible: bible.clone(),
+        }
+    }).collect()
+}
+
+fn render_interlinear_column(column: &InterlinearColumnRenderData) -> String
+{
+    let words = column.words.iter()
+        .map(|w| render_word(w, false))
+        .join("<span class=\"bible-space\"> </span>");
+
+    let strongs = column.glosses.iter().map(|g| {
+        let number = StrongsNumber::from(&g.strongs).to_string();
+        wrap_tag(WrapTagArgs {
+            tag: "span",
+            classes: Some(&["bible-strongs-link"]),
+            data: Some(&[("data-strongs-number", &number)]),
+            content: &number,
+        })
+    }).join(";");
+
+    let lemmas = column.glosses.iter().filter_map(|g| g.lemma.as_deref()).map(escape_html).join("; ");
+    let glosses = column.glosses.iter().filter_map(|g| g.gloss.as_deref()).map(escape_html).join("; ");
+
+    let rows = [("interlinear-words", words), ("interlinear-strongs", strongs), ("interlinear-lemma", lemmas), ("interlinear-gloss", glosses)];
+    let content = rows.iter().map(|(class, content)| {
+        wrap_tag(WrapTagArgs {
+            tag: "span",
+            classes: Some(&[*class]),
+            data: None,
+            content,
+        })
+    }).join("");
+
+    wrap_tag(WrapTagArgs {
+        tag: "span",
+        classes: Some(&["interlinear-column"]),
+        data: None,
+        content: &content,
+    })
+}
+
+pub(super) fn render_word(word: &WordRenderData, show_strongs: bool) -> String 
 {
     let begin_punc = word.begin_punc.clone().unwrap_or_default();
     let end_punc = word.end_punc.clone().unwrap_or_default();
@@ -87,10 +142,19 @@ fn render_word(word: &WordRenderData, show_strongs: bool) -> String
     if word.red { classes.push("red"); }
     if can_click { classes.push("clickable"); }
 
+    let word_index = format!("{}", word.index + 1);
+    let highlight_style = get_highlight_style(word);
+    let mut data = vec![("data-word-index", word_index.as_str())];
+    if let Some(style) = &highlight_style
+    {
+        classes.push("highlighted");
+        data.push(("style", style));
+    }
+
     let mut content = wrap_tag(WrapTagArgs { 
         tag: "span", 
         classes: Some(&classes), 
-        data: Some(&[("data-word-index", &format!("{}", word.index + 1))]), 
+        data: Some(&data), 
         content: &text,
     });
 
@@ -0,0 +1,424 @@
+use std::{fs::{self, File}, path::{Component, Path, PathBuf}};
+
+use biblio_json::modules::{Module, ModuleId};
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+use tauri::{Runtime, path::{BaseDirectory, PathResolver}};
+
+use crate::{bible::{BIBLE_PACKAGE_PATH, PACKAGE_STAGING_PATH, importing::{ImportedBible, ImportedModule, write_bible_package}, package_loading::{BIBLES_CATEGORY, PACKAGE_FILE, PackageLoadReport, copy_module_files, find_module_configs, load_module, load_package}}, core::utils::get_uuid, repr::ModuleTypeJson};
+
+pub const MODULES_CHANGED_EVENT_NAME: &str = "modules-changed";
+
+pub const USER_MODULES_PATH: &str = "ascribe-data/user-modules";
+
+const USER_MODULES_CONFIG: &str = r#"name = "Ascribe User Modules"
+authors = []
+license = "MIT"
+
+[module_paths]
+bibles = "/bibles/**/*"
+dictionaries = "/dictionaries/**/*"
+xrefs = "/xrefs/**/*"
+strongs_defs = "/strongs_defs/**/*"
+strongs_links = "/strongs_links/**/*"
+readings = "/readings/**/*"
+commentaries = "/commentaries/**/*"
+notebooks = "/notebooks/**/*"
+"#;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct ModulesChangedEvent
+{
+    pub added: Vec<ModuleId>,
+    pub removed: Vec<ModuleId>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "type")]
+pub enum ModuleChangeResult
+{
+    Success
+    {
+        module: ModuleId,
+        /// Anything that may have been imported incorrectly, such as verses from a different versification
+        warnings: Vec<String>,
+    },
+    Error
+    {
+        message: String,
+    }
+}
+
+impl From<Result<ModuleId, String>> for ModuleChangeResult
+{
+    fn from(value: Result<ModuleId, String>) -> Self
+    {
+        match value
+        {
+            Ok(module) => Self::Success { module, warnings: vec![] },
+            Err(message) => Self::Error { message },
+        }
+    }
+}
+
+/// Modules imported by the user, stored as a biblio_json package in the app data directory, 
+/// with every module in its own folder: `<category>/<module id>/`
+#[derive(Debug, Clone)]
+pub struct UserModuleStore
+{
+    dir: PathBuf,
+    resource_dir: PathBuf,
+    staging_dir: PathBuf,
+}
+
+impl UserModuleStore
+{
+    pub fn new<R>(resolver: &PathResolver<R>) -> Self
+        where R : Runtime
+    {
+        Self { 
+            dir: resolver.resolve(USER_MODULES_PATH, BaseDirectory::AppData).unwrap(),
+            resource_dir: resolver.resolve(BIBLE_PACKAGE_PATH, BaseDirectory::Resource).unwrap(),
+            staging_dir: resolver.resolve(PACKAGE_STAGING_PATH, BaseDirectory::AppCache).unwrap().join("user-modules"),
+        }
+    }
+
+    /// Loads every user module, skipping any that fail to load
+    pub fn load_modules(&self) -> (Vec<Module>, PackageLoadReport)
+    {
+        if !self.dir.join(PACKAGE_FILE).exists()
+        {
+            return (vec![], PackageLoadReport::default());
+        }
+
+        let (package, report) = load_package(&self.dir, &self.staging_dir, &self.get_resource_bibles());
+        let modules = package.map(|p| p.modules.into_values().collect_vec()).unwrap_or_default();
+        (modules, report)
+    }
+
+    /// Validates and copies a module folder into the store, returning the loaded module.
+    /// Fails if a module with the same id already exists, according to `exists`
+    pub fn import_folder(&self, path: &Path, module_type: ModuleTypeJson, exists: impl Fn(&ModuleId) -> bool) -> Result<Module, String>
+    {
+        self.import_module_dir(path, get_module_category(module_type), &exists)
+    }
+
+    /// Writes a bible converted from another format into the store, along with its strongs links if it has any.
+    /// The bible is kept even if its links fail to import
+    pub fn import_bible(&self, bible: &ImportedBible, exists: impl Fn(&ModuleId) -> bool) -> Result<Vec<Module>, String>
+    {
+        self.import_converted(&bible.id, |dir| write_bible_package(bible, dir), exists)
+    }
+
+    /// Writes a single module converted from another format into the store
+    pub fn import_converted_module(&self, module: &ImportedModule, exists: impl Fn(&ModuleId) -> bool) -> Result<Vec<Module>, String>
+    {
+        self.import_converted(module.id(), |dir| module.write_package(dir), exists)
+    }
+
+    /// Writes modules converted from another format into the staging directory with `write`, then imports them.
+    /// Only failing to import a bible fails the import, the modules written alongside it are optional
+    pub fn import_converted(&self, name: &str, write: impl FnOnce(&Path) -> Result<Vec<(&'static str, PathBuf)>, String>, exists: impl Fn(&ModuleId) -> bool) -> Result<Vec<Module>, String>
+    {
+        let converted_dir = self.new_staging_dir("converted");
+        let result = write(&converted_dir).and_then(|modules| {
+            let single = modules.len() == 1;
+            let mut imported = vec![];
+            for (category, dir) in modules
+            {
+                match self.import_module_dir(&dir, category, &exists)
+                {
+                    Ok(module) => imported.push(module),
+                    Err(e) if single || category == BIBLES_CATEGORY => return Err(e),
+                    Err(e) => log::error!("Failed to import '{}' for '{}':\n{}", category, name, e),
+                }
+            }
+
+            Ok(imported)
+        });
+
+        if let Err(e) = fs::remove_dir_all(&converted_dir)
+        {
+            log::error!("Failed to clean up converted modules: {}", e);
+        }
+
+        result
+    }
+
+    fn import_module_dir(&self, path: &Path, category: &str, exists: &impl Fn(&ModuleId) -> bool) -> Result<Module, String>
+    {
+        let config = find_module_config(path)?;
+        let id = read_module_id(&config)?;
+        if exists(&id)
+        {
+            return Err(format!("A module with the id '{}' already exists", id));
+        }
+
+        let mut dependencies = self.get_resource_bibles();
+        dependencies.extend(self.get_user_bibles());
+
+        let mut package = load_module(category, &config, &self.new_staging_dir("import"), &dependencies)
+            .map_err(|e| e.join("\n"))?;
+
+        let module = package.modules.remove(&id)
+            .ok_or(format!("Module '{}' was not loaded from '{}'", id, config.display()))?;
+
+        self.ensure_package_file()?;
+        let target = self.get_module_dir(category, &id)?;
+        copy_module_files(&config, &target, false)?;
+
+        Ok(module)
+    }
+
+    /// Extracts the archive into the staging directory, then imports it as a folder
+    pub fn import_archive(&self, path: &Path, module_type: ModuleTypeJson, exists: impl Fn(&ModuleId) -> bool) -> Result<Module, String>
+    {
+        self.with_extracted_archive(path, |dir| self.import_folder(dir, module_type, exists))
+    }
+
+    /// Extracts the zip archive into the staging directory, which is cleaned up again once `f` is done with it
+    pub fn with_extracted_archive<R>(&self, path: &Path, f: impl FnOnce(&Path) -> Result<R, String>) -> Result<R, String>
+    {
+        let file = File::open(path).map_err(|e| e.to_string())?;
+        let mut archive = zip::ZipArchive::new(file).map_err(|e| e.to_string())?;
+
+        // An extraction that fails part way can still leave files behind, so it is cleaned up along with `f`
+        let extract_dir = self.new_staging_dir("archive");
+        let result = archive.extract(&extract_dir)
+            .map_err(|e| e.to_string())
+            .and_then(|_| f(&extract_dir));
+
+        if extract_dir.exists()
+        {
+            if let Err(e) = fs::remove_dir_all(&extract_dir)
+            {
+                log::error!("Failed to clean up extracted archive: {}", e);
+            }
+        }
+
+        result
+    }
+
+    /// A staging directory of its own for every import, so that imports running at the same time do not delete each other's files
+    fn new_staging_dir(&self, name: &str) -> PathBuf
+    {
+        self.staging_dir.join(format!("{}_{}", name, get_uuid()))
+    }
+
+    pub fn is_user_module(&self, id: &ModuleId) -> bool
+    {
+        self.find_module_dir(id).is_some()
+    }
+
+    /// Deletes the folder of an imported module, the module still has to be removed from the live package
+    pub fn uninstall_module(&self, id: &ModuleId) -> Result<(), String>
+    {
+        let dir = self.find_module_dir(id)
+            .ok_or(format!("Module '{}' was not imported by the user, and cannot be uninstalled", id))?;
+
+        // Links could still point the folder somewhere else
+        let root = self.dir.canonicalize().map_err(|e| e.to_string())?;
+        let dir = dir.canonicalize().map_err(|e| e.to_string())?;
+        if dir == root || !dir.starts_with(&root)
+        {
+            return Err(format!("The folder of module '{}' is not in the user modules folder", id));
+        }
+
+        fs::remove_dir_all(dir).map_err(|e| e.to_string())
+    }
+
+    fn find_module_dir(&self, id: &ModuleId) -> Option<PathBuf>
+    {
+        validate_module_id(&id.to_string()).ok()?;
+        fs::read_dir(&self.dir).ok()?
+            .filter_map(Result::ok)
+            .map(|e| e.path().join(id.to_string()))
+            .find(|p| p.is_dir())
+    }
+
+    /// The folder a module is stored in, which has to be inside the store
+    fn get_module_dir(&self, category: &str, id: &ModuleId) -> Result<PathBuf, String>
+    {
+        validate_module_id(&id.to_string())?;
+        let dir = self.dir.join(category).join(id.to_string());
+        if !dir.starts_with(&self.dir) || dir.components().any(|c| c == Component::ParentDir)
+        {
+            return Err(format!("Module '{}' would be stored outside of the user modules folder", id));
+        }
+
+        Ok(dir)
+    }
+
+    fn get_resource_bibles(&self) -> Vec<(String, PathBuf)>
+    {
+        get_package_bibles(&self.resource_dir)
+    }
+
+    fn get_user_bibles(&self) -> Vec<(String, PathBuf)>
+    {
+        get_package_bibles(&self.dir)
+    }
+
+    fn ensure_package_file(&self) -> Result<(), String>
+    {
+        fs::create_dir_all(&self.dir).map_err(|e| e.to_string())?;
+
+        let package_file = self.dir.join(PACKAGE_FILE);
+        if !package_file.exists()
+        {
+            fs::write(package_file, USER_MODULES_CONFIG).map_err(|e| e.to_string())?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The name of the `module_paths` entry, and folder, for each module type
+pub fn get_module_category(module_type: ModuleTypeJson) -> &'static str
+{
+    match module_type
+    {
+        ModuleTypeJson::Bible => BIBLES_CATEGORY,
+        ModuleTypeJson::Notebook => "notebooks",
+        ModuleTypeJson::Readings => "readings",
+        ModuleTypeJson::StrongsDefs => "strongs_defs",
+        ModuleTypeJson::StrongsLinks => "strongs_links",
+        ModuleTypeJson::Commentary => "commentaries",
+        ModuleTypeJson::Dictionary => "dictionaries",
+        ModuleTypeJson::CrossRefs => "xrefs",
+    }
+}
+
+fn get_package_bibles(path: &Path) -> Vec<(String, PathBuf)>
+{
+    let Ok(config) = fs::read_to_string(path.join(PACKAGE_FILE)) else {
+        return vec![];
+    };
+
+    let Ok(config) = toml::from_str::<toml::Table>(&config) else {
+        return vec![];
+    };
+
+    find_module_configs(path, &config)
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|(category, _)| category == BIBLES_CATEGORY)
+        .collect_vec()
+}
+
+/// Finds the single module config in a folder, descending into it if the folder only contains another folder, as is common with archives
+fn find_module_config(path: &Path) -> Result<PathBuf, String>
+{
+    let entries = fs::read_dir(path)
+        .map_err(|e| e.to_string())?
+        .filter_map(Result::ok)
+        .map(|e| e.path())
+        .collect_vec();
+
+    let configs = entries.iter()
+        .filter(|p| p.is_file() && p.extension().is_some_and(|e| e == "toml"))
+        .filter(|p| p.file_name().is_some_and(|n| n != PACKAGE_FILE))
+        .collect_vec();
+
+    match configs.as_slice()
+    {
+        [config] => Ok(config.to_path_buf()),
+        [] if entries.len() == 1 && entries[0].is_dir() => find_module_config(&entries[0]),
+        [] => Err(format!("No module config was found in '{}'", path.display())),
+        _ => Err(format!("'{}' contains more than one module config", path.display())),
+    }
+}
+
+fn read_module_id(config: &Path) -> Result<ModuleId, String>
+{
+    let text = fs::read_to_string(config).map_err(|e| e.to_string())?;
+    let table: toml::Table = toml::from_str(&text).map_err(|e| e.to_string())?;
+
+    let id = table.get("id")
+        .and_then(|id| id.as_str())
+        .ok_or(format!("Module config '{}' does not have an id", config.display()))?;
+
+    validate_module_id(id)?;
+    Ok(ModuleId::new(id.to_string()))
+}
+
+/// Module ids are used as folder names, so they may only contain lowercase ASCII letters, digits and underscores
+fn validate_module_id(id: &str) -> Result<(), String>
+{
+    if !id.is_empty() && id.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+    {
+        Ok(())
+    }
+    else 
+    {
+        Err(format!("'{}' is not a valid module id, which may only contain lowercase letters, digits and underscores", id))
+    }
+}
+
+#[cfg(test)]
+mod tests 
+{
+    use super::*;
+
+    #[test]
+    fn test_validate_module_id()
+    {
+        assert!(validate_module_id("kjv_eng").is_ok());
+        assert!(validate_module_id("user_notebook_1").is_ok());
+        assert!(validate_module_id("").is_err());
+        assert!(validate_module_id("../../x").is_err());
+        assert!(validate_module_id("a/b").is_err());
+        assert!(validate_module_id("a\\b").is_err());
+        assert!(validate_module_id("KJV").is_err());
+        assert!(validate_module_id("..").is_err());
+    }
+
+    #[test]
+    fn test_read_module_id()
+    {
+        let dir = std::env::temp_dir().join(format!("module_id_{}", get_uuid()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let config = dir.join("module.toml");
+        fs::write(&config, "id = \"kjv_eng\"").unwrap();
+        assert_eq!(read_module_id(&config), Ok(ModuleId::new("kjv_eng".into())));
+
+        fs::write(&config, "id = \"../../evil\"").unwrap();
+        assert!(read_module_id(&config).is_err());
+
+        fs::write(&config, "name = \"No id\"").unwrap();
+        assert!(read_module_id(&config).is_err());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_failed_extract_is_cleaned_up()
+    {
+        let dir = std::env::temp_dir().join(format!("user_modules_{}", get_uuid()));
+        let store = UserModuleStore { dir: dir.join("modules"), resource_dir: dir.join("resources"), staging_dir: dir.join("staging") };
+        fs::create_dir_all(&dir).unwrap();
+
+        let archive_path = dir.join("module.zip");
+        let mut writer = zip::ZipWriter::new(File::create(&archive_path).unwrap());
+        let options = zip::write::SimpleFileOptions::default().compression_method(zip::CompressionMethod::Stored);
+        writer.start_file("module.toml", options).unwrap();
+        std::io::Write::write_all(&mut writer, b"id = \"kjv_eng\"").unwrap();
+        writer.start_file("entries.jsonl", options).unwrap();
+        std::io::Write::write_all(&mut writer, b"corrupted entries").unwrap();
+        writer.finish().unwrap();
+
+        // Changing the stored bytes of the second file fails its checksum once the first has been extracted
+        let mut bytes = fs::read(&archive_path).unwrap();
+        let start = bytes.windows(9).position(|w| w == b"corrupted").unwrap();
+        bytes[start] = b'C';
+        fs::write(&archive_path, bytes).unwrap();
+
+        let result = store.with_extracted_archive(&archive_path, |_| Ok(()));
+        assert!(result.is_err());
+        assert_eq!(fs::read_dir(&store.staging_dir).map_or(0, |d| d.count()), 0);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
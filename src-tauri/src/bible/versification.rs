@@ -0,0 +1,415 @@
+use std::num::NonZeroU32;
+
+use biblio_json::{core::{ChapterId, OsisBook, VerseId}, modules::{ModuleId, bible::BibleModule}};
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+
+use crate::bible::{importing::parse_osis_book, indexing::PackageIndex};
+
+/// Marks a rule that runs to the end of its chapter
+const END: u32 = u32::MAX;
+
+/// A verse numbering scheme. References are mapped between schemes through the KJV numbering
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Versification
+{
+    Kjv,
+    Nrsv,
+    /// Masoretic numbering, where psalm titles are numbered verses, as used by most Hebrew bibles
+    Hebrew,
+    /// Septuagint numbering, with Hebrew verse numbers but joined and split psalms
+    Lxx,
+}
+
+/// Maps `chapter:start-end` of a book in one scheme to `to_chapter:to_start` onwards in the KJV
+#[derive(Debug, Clone, Copy)]
+struct VerseRule
+{
+    book: OsisBook,
+    chapter: u32,
+    start: u32,
+    end: u32,
+    to_chapter: u32,
+    to_start: u32,
+}
+
+impl VerseRule
+{
+    fn map(&self, book: OsisBook, chapter: u32, verse: u32) -> Option<(u32, u32)>
+    {
+        let matches = self.book == book && self.chapter == chapter && (self.start..=self.end).contains(&verse);
+        matches.then(|| (self.to_chapter, self.to_start + (verse - self.start)))
+    }
+
+    fn map_inverse(&self, book: OsisBook, chapter: u32, verse: u32) -> Option<(u32, u32)>
+    {
+        let to_end = self.to_start.saturating_add(self.end - self.start);
+        let matches = self.book == book && self.to_chapter == chapter && (self.to_start..=to_end).contains(&verse);
+        matches.then(|| (self.chapter, self.start + (verse - self.to_start)))
+    }
+}
+
+/// Chapters that the Hebrew bible starts or ends at a different verse than the KJV, as `book, chapter, start, end, kjv chapter, kjv start`
+const HEBREW_RULES: &[(&str, u32, u32, u32, u32, u32)] = &[
+    ("Gen", 32, 1, 1, 31, 55), ("Gen", 32, 2, END, 32, 1),
+    ("Exod", 7, 26, 29, 8, 1), ("Exod", 8, 1, END, 8, 5),
+    ("Exod", 21, 37, 37, 22, 1), ("Exod", 22, 1, END, 22, 2),
+    ("Lev", 5, 20, 26, 6, 1), ("Lev", 6, 1, END, 6, 8),
+    ("Num", 17, 1, 15, 16, 36), ("Num", 17, 16, END, 17, 1),
+    ("Num", 30, 1, 1, 29, 40), ("Num", 30, 2, END, 30, 1),
+    ("Deut", 13, 1, 1, 12, 32), ("Deut", 13, 2, END, 13, 1),
+    ("Deut", 23, 1, 1, 22, 30), ("Deut", 23, 2, END, 23, 1),
+    ("Deut", 28, 69, 69, 29, 1), ("Deut", 29, 1, END, 29, 2),
+    ("1Sam", 21, 1, 1, 20, 42), ("1Sam", 21, 2, END, 21, 1),
+    ("1Sam", 24, 1, 1, 23, 29), ("1Sam", 24, 2, END, 24, 1),
+    ("2Sam", 19, 1, 1, 18, 33), ("2Sam", 19, 2, END, 19, 1),
+    ("1Kgs", 5, 1, 14, 4, 21), ("1Kgs", 5, 15, END, 5, 1),
+    ("1Kgs", 22, 44, END, 22, 43),
+    ("2Kgs", 12, 1, 1, 11, 21), ("2Kgs", 12, 2, END, 12, 1),
+    ("1Chr", 5, 27, 41, 6, 1), ("1Chr", 6, 1, END, 6, 16),
+    ("1Chr", 12, 5, END, 12, 4),
+    ("2Chr", 1, 18, 18, 2, 1), ("2Chr", 2, 1, END, 2, 2),
+    ("2Chr", 13, 23, 23, 14, 1), ("2Chr", 14, 1, END, 14, 2),
+    ("Neh", 3, 33, 38, 4, 1), ("Neh", 4, 1, END, 4, 7),
+    ("Neh", 10, 1, 1, 9, 38), ("Neh", 10, 2, END, 10, 1),
+    ("Job", 40, 25, 32, 41, 1), ("Job", 41, 1, END, 41, 9),
+    ("Eccl", 4, 17, 17, 5, 1), ("Eccl", 5, 1, END, 5, 2),
+    ("Song", 7, 1, 1, 6, 13), ("Song", 7, 2, END, 7, 1),
+    ("Isa", 8, 23, 23, 9, 1), ("Isa", 9, 1, END, 9, 2),
+    ("Isa", 64, 1, END, 64, 2),
+    ("Jer", 8, 23, 23, 9, 1), ("Jer", 9, 1, END, 9, 2),
+    ("Ezek", 21, 1, 5, 20, 45), ("Ezek", 21, 6, END, 21, 1),
+    ("Dan", 3, 31, 33, 4, 1), ("Dan", 4, 1, END, 4, 4),
+    ("Dan", 6, 1, 1, 5, 31), ("Dan", 6, 2, END, 6, 1),
+    ("Hos", 2, 1, 2, 1, 10), ("Hos", 2, 3, END, 2, 1),
+    ("Hos", 12, 1, 1, 11, 12), ("Hos", 12, 2, END, 12, 1),
+    ("Hos", 14, 1, 1, 13, 16), ("Hos", 14, 2, END, 14, 1),
+    ("Joel", 3, 1, END, 2, 28), ("Joel", 4, 1, END, 3, 1),
+    ("Jonah", 2, 1, 1, 1, 17), ("Jonah", 2, 2, END, 2, 1),
+    ("Mic", 4, 14, 14, 5, 1), ("Mic", 5, 1, END, 5, 2),
+    ("Nah", 2, 1, 1, 1, 15), ("Nah", 2, 2, END, 2, 1),
+    ("Zech", 2, 1, 4, 1, 18), ("Zech", 2, 5, END, 2, 1),
+    ("Mal", 3, 19, END, 4, 1),
+];
+
+/// KJV verses that the Hebrew bible keeps as the second half of a verse, or splits over two verses, mapped to the Hebrew verse
+/// holding most of their text. They only map one way, from the KJV.
+/// Listed as `book, hebrew chapter, hebrew verse, hebrew verse, kjv chapter, kjv verse`
+const HEBREW_PARTIAL_RULES: &[(&str, u32, u32, u32, u32, u32)] = &[
+    ("1Kgs", 22, 43, 43, 22, 43),
+    ("1Chr", 12, 4, 4, 12, 4),
+    ("Isa", 63, 19, 19, 64, 1),
+];
+
+/// Psalms whose title is numbered as the first verse in Hebrew bibles
+const HEBREW_ONE_VERSE_TITLES: &[u32] = &[
+    3, 4, 5, 6, 7, 8, 9, 12, 13, 18, 19, 20, 21, 22, 30, 31, 34, 36, 38, 39, 40, 41, 42, 44, 45, 46, 47, 48, 49,
+    53, 55, 56, 57, 58, 59, 61, 62, 63, 64, 65, 67, 68, 69, 70, 75, 76, 77, 80, 81, 83, 84, 85, 88, 89, 92, 102,
+    108, 140, 142,
+];
+
+/// Psalms whose title is numbered as the first two verses in Hebrew bibles
+const HEBREW_TWO_VERSE_TITLES: &[u32] = &[51, 52, 54, 60];
+
+/// Septuagint psalms that are joined or split differently to the Hebrew, as `book, lxx psalm, start, end, hebrew psalm, hebrew start`.
+/// Later halves of a psalm come first, so that mapping back to the Septuagint finds them before the open ended first half
+const LXX_PSALM_RULES: &[(&str, u32, u32, u32, u32, u32)] = &[
+    ("Ps", 9, 22, END, 10, 1),
+    ("Ps", 113, 1, 8, 114, 1), ("Ps", 113, 9, END, 115, 1),
+    ("Ps", 115, 1, END, 116, 10), ("Ps", 114, 1, END, 116, 1),
+    ("Ps", 147, 1, END, 147, 12), ("Ps", 146, 1, END, 147, 1),
+];
+
+/// English bibles such as the NRSV that split or join a few verses differently to the KJV
+const NRSV_RULES: &[(&str, u32, u32, u32, u32, u32)] = &[
+    ("2Cor", 13, 13, 13, 13, 14),
+    ("3John", 1, 15, 15, 1, 14),
+    ("Rev", 12, 18, 18, 13, 1),
+];
+
+lazy_static::lazy_static!
+{
+    static ref HEBREW_VERSE_RULES: Vec<VerseRule> = get_hebrew_rules();
+    static ref HEBREW_PARTIAL_VERSE_RULES: Vec<VerseRule> = get_rules(HEBREW_PARTIAL_RULES);
+    static ref NRSV_VERSE_RULES: Vec<VerseRule> = get_rules(NRSV_RULES);
+    static ref LXX_PSALM_VERSE_RULES: Vec<VerseRule> = get_rules(LXX_PSALM_RULES);
+}
+
+impl Versification
+{
+    /// Guesses the scheme of a bible from how many chapters and verses a few telling books have
+    pub fn detect(bible: &BibleModule) -> Self
+    {
+        let verse_count = |book: &str, chapter: usize| {
+            let book = parse_osis_book(book)?;
+            let info = bible.source.book_infos.iter().find(|b| b.osis_book == book)?;
+            info.chapters.get(chapter - 1).copied()
+        };
+
+        let chapter_count = |book: &str| {
+            let book = parse_osis_book(book)?;
+            bible.source.book_infos.iter()
+                .find(|b| b.osis_book == book)
+                .map(|b| b.chapters.len())
+        };
+
+        let has_psalm_titles = verse_count("Ps", 3) == Some(9);
+        if has_psalm_titles || chapter_count("Mal") == Some(3) || chapter_count("Joel") == Some(4)
+        {
+            let joined_psalms = verse_count("Ps", 9).is_some_and(|c| c > 30) || chapter_count("Ps").is_some_and(|c| c > 150);
+            return if joined_psalms { Self::Lxx } else { Self::Hebrew };
+        }
+
+        if verse_count("3John", 1) == Some(15) || verse_count("Rev", 12) == Some(18)
+        {
+            return Self::Nrsv;
+        }
+
+        Self::Kjv
+    }
+
+    pub fn to_kjv(self, verse: VerseId) -> VerseId
+    {
+        match self
+        {
+            Self::Kjv => verse,
+            Self::Nrsv => map_with_rules(&NRSV_VERSE_RULES, verse, false),
+            Self::Hebrew => map_with_rules(&HEBREW_VERSE_RULES, verse, false),
+            Self::Lxx => map_with_rules(&HEBREW_VERSE_RULES, map_lxx_psalm(verse, false), false),
+        }
+    }
+
+    pub fn from_kjv(self, verse: VerseId) -> VerseId
+    {
+        match self
+        {
+            Self::Kjv => verse,
+            Self::Nrsv => map_with_rules(&NRSV_VERSE_RULES, verse, true),
+            Self::Hebrew => map_from_kjv_to_hebrew(verse),
+            Self::Lxx => map_lxx_psalm(map_from_kjv_to_hebrew(verse), true),
+        }
+    }
+
+    pub fn map_verse(self, verse: VerseId, to: Self) -> VerseId
+    {
+        if self == to
+        {
+            return verse;
+        }
+
+        to.from_kjv(self.to_kjv(verse))
+    }
+
+    /// Maps a chapter of `verse_count` verses to the chapter that most of its verses are in, or the earlier one if they are split evenly
+    pub fn map_chapter(self, chapter: ChapterId, verse_count: u32, to: Self) -> ChapterId
+    {
+        if self == to
+        {
+            return chapter;
+        }
+
+        let mapped = (1..=verse_count.max(1))
+            .filter_map(NonZeroU32::new)
+            .map(|verse| self.map_verse(VerseId { book: chapter.book, chapter: chapter.chapter, verse }, to).chapter)
+            .counts()
+            .into_iter()
+            .max_by_key(|(c, count)| (*count, std::cmp::Reverse(*c)))
+            .map_or(chapter.chapter, |(c, _)| c);
+
+        ChapterId { book: chapter.book, chapter: mapped }
+    }
+}
+
+/// Maps a verse numbered by the `from` bible to the same verse in the `to` bible
+pub fn map_verse_between(index: &PackageIndex, verse: VerseId, from: &ModuleId, to: &ModuleId) -> VerseId
+{
+    if from == to
+    {
+        return verse;
+    }
+
+    index.get_versification(from).map_verse(verse, index.get_versification(to))
+}
+
+fn map_from_kjv_to_hebrew(verse: VerseId) -> VerseId
+{
+    match find_mapping(&HEBREW_PARTIAL_VERSE_RULES, verse, true)
+    {
+        Some((chapter, number)) => with_numbers(verse, chapter, number),
+        None => map_with_rules(&HEBREW_VERSE_RULES, verse, true),
+    }
+}
+
+fn map_with_rules(rules: &[VerseRule], verse: VerseId, inverse: bool) -> VerseId
+{
+    find_mapping(rules, verse, inverse).map_or(verse, |(chapter, number)| with_numbers(verse, chapter, number))
+}
+
+fn find_mapping(rules: &[VerseRule], verse: VerseId, inverse: bool) -> Option<(u32, u32)>
+{
+    let (chapter, number) = (verse.chapter.get(), verse.verse.get());
+    rules.iter().find_map(|rule| {
+        if inverse
+        {
+            rule.map_inverse(verse.book, chapter, number)
+        }
+        else
+        {
+            rule.map(verse.book, chapter, number)
+        }
+    })
+}
+
+fn with_numbers(verse: VerseId, chapter: u32, number: u32) -> VerseId
+{
+    VerseId {
+        book: verse.book,
+        chapter: NonZeroU32::new(chapter).unwrap_or(verse.chapter),
+        verse: NonZeroU32::new(number).unwrap_or(verse.verse),
+    }
+}
+
+/// Maps a Septuagint psalm to its Hebrew numbering, or back again when `inverse`
+fn map_lxx_psalm(verse: VerseId, inverse: bool) -> VerseId
+{
+    if let Some((chapter, number)) = find_mapping(&LXX_PSALM_VERSE_RULES, verse, inverse)
+    {
+        return with_numbers(verse, chapter, number);
+    }
+
+    // Besides the joined and split psalms, the Septuagint numbers most psalms one lower
+    let is_shifted = |lxx: u32| (10..=112).contains(&lxx) || (116..=145).contains(&lxx);
+    let chapter = verse.chapter.get();
+    match inverse
+    {
+        _ if Some(verse.book) != parse_osis_book("Ps") => verse,
+        false if is_shifted(chapter) => with_numbers(verse, chapter + 1, verse.verse.get()),
+        true if is_shifted(chapter - 1) => with_numbers(verse, chapter - 1, verse.verse.get()),
+        _ => verse,
+    }
+}
+
+fn get_rules(rules: &[(&str, u32, u32, u32, u32, u32)]) -> Vec<VerseRule>
+{
+    rules.iter()
+        .filter_map(|&(book, chapter, start, end, to_chapter, to_start)| Some(VerseRule {
+            book: parse_osis_book(book)?,
+            chapter,
+            start,
+            end,
+            to_chapter,
+            to_start,
+        }))
+        .collect()
+}
+
+fn get_hebrew_rules() -> Vec<VerseRule>
+{
+    let mut rules = get_rules(HEBREW_RULES);
+    let Some(psalms) = parse_osis_book("Ps") else {
+        return rules;
+    };
+
+    let titles = HEBREW_ONE_VERSE_TITLES.iter().map(|&p| (p, 1))
+        .chain(HEBREW_TWO_VERSE_TITLES.iter().map(|&p| (p, 2)));
+
+    for (psalm, title_len) in titles
+    {
+        // The verses after the title come first, so that mapping back to Hebrew lands on the text rather than the title
+        rules.push(VerseRule { book: psalms, chapter: psalm, start: title_len + 1, end: END, to_chapter: psalm, to_start: 1 });
+        for verse in 1..=title_len
+        {
+            rules.push(VerseRule { book: psalms, chapter: psalm, start: verse, end: verse, to_chapter: psalm, to_start: 1 });
+        }
+    }
+
+    rules
+}
+
+#[cfg(test)]
+mod tests 
+{
+    use super::*;
+
+    fn verse(book: &str, chapter: u32, verse: u32) -> VerseId
+    {
+        VerseId::new(parse_osis_book(book).unwrap(), NonZeroU32::new(chapter).unwrap(), NonZeroU32::new(verse).unwrap())
+    }
+
+    /// The first KJV verse each rule maps to, and the one after it if the rule covers more than one verse
+    fn get_kjv_verses(rules: &[VerseRule]) -> Vec<VerseId>
+    {
+        rules.iter()
+            .flat_map(|r| {
+                let count = if r.end == r.start { 1 } else { 2 };
+                (0..count).map(move |i| VerseId::new(r.book, NonZeroU32::new(r.to_chapter).unwrap(), NonZeroU32::new(r.to_start + i).unwrap()))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_hebrew_rules_round_trip()
+    {
+        for kjv in get_kjv_verses(&HEBREW_VERSE_RULES)
+        {
+            assert_eq!(Versification::Hebrew.to_kjv(Versification::Hebrew.from_kjv(kjv)), kjv, "{:?}", kjv);
+        }
+    }
+
+    #[test]
+    fn test_nrsv_rules_round_trip()
+    {
+        for kjv in get_kjv_verses(&NRSV_VERSE_RULES)
+        {
+            assert_eq!(Versification::Nrsv.to_kjv(Versification::Nrsv.from_kjv(kjv)), kjv, "{:?}", kjv);
+        }
+    }
+
+    #[test]
+    fn test_lxx_psalm_rules_round_trip()
+    {
+        for hebrew in get_kjv_verses(&LXX_PSALM_VERSE_RULES)
+        {
+            assert_eq!(map_lxx_psalm(map_lxx_psalm(hebrew, true), false), hebrew, "{:?}", hebrew);
+        }
+    }
+
+    #[test]
+    fn test_hebrew_partial_verses()
+    {
+        assert_eq!(Versification::Hebrew.from_kjv(verse("Isa", 64, 1)), verse("Isa", 63, 19));
+        assert_eq!(Versification::Hebrew.from_kjv(verse("Isa", 64, 2)), verse("Isa", 64, 1));
+        assert_eq!(Versification::Hebrew.to_kjv(verse("Isa", 64, 1)), verse("Isa", 64, 2));
+        assert_eq!(Versification::Hebrew.to_kjv(verse("Isa", 63, 19)), verse("Isa", 63, 19));
+
+        // The Hebrew splits these KJV verses in two, so they map to the first half, which holds most of the text
+        assert_eq!(Versification::Hebrew.from_kjv(verse("1Kgs", 22, 43)), verse("1Kgs", 22, 43));
+        assert_eq!(Versification::Hebrew.from_kjv(verse("1Kgs", 22, 44)), verse("1Kgs", 22, 45));
+        assert_eq!(Versification::Hebrew.from_kjv(verse("1Chr", 12, 4)), verse("1Chr", 12, 4));
+        assert_eq!(Versification::Hebrew.from_kjv(verse("1Chr", 12, 5)), verse("1Chr", 12, 6));
+    }
+
+    #[test]
+    fn test_map_chapter()
+    {
+        let chapter = |book: &str, chapter: u32| ChapterId { book: parse_osis_book(book).unwrap(), chapter: NonZeroU32::new(chapter).unwrap() };
+
+        // Hebrew Malachi 3 has the 18 verses of KJV Malachi 3, and the first 6 of KJV Malachi 4
+        assert_eq!(Versification::Hebrew.map_chapter(chapter("Mal", 3), 24, Versification::Kjv), chapter("Mal", 3));
+        assert_eq!(Versification::Hebrew.map_chapter(chapter("Joel", 4), 21, Versification::Kjv), chapter("Joel", 3));
+        assert_eq!(Versification::Kjv.map_chapter(chapter("Mal", 4), 6, Versification::Hebrew), chapter("Mal", 3));
+        assert_eq!(Versification::Hebrew.map_chapter(chapter("Gen", 1), 31, Versification::Hebrew), chapter("Gen", 1));
+    }
+
+    #[test]
+    fn test_map_verse()
+    {
+        assert_eq!(Versification::Hebrew.to_kjv(verse("Mal", 3, 19)), verse("Mal", 4, 1));
+        assert_eq!(Versification::Kjv.map_verse(verse("Joel", 2, 28), Versification::Hebrew), verse("Joel", 3, 1));
+        assert_eq!(Versification::Hebrew.to_kjv(verse("Ps", 51, 3)), verse("Ps", 51, 1));
+        assert_eq!(Versification::Lxx.to_kjv(verse("Ps", 22, 1)), verse("Ps", 23, 1));
+        assert_eq!(Versification::Nrsv.map_verse(verse("Rev", 12, 18), Versification::Kjv), verse("Rev", 13, 1));
+        assert_eq!(Versification::Kjv.map_verse(verse("John", 3, 16), Versification::Lxx), verse("John", 3, 16));
+    }
+}
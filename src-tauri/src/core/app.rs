@@ -1,5 +1,6 @@
-use std::{fs, io::ErrorKind};
+use std::{collections::HashSet, fs, io::ErrorKind};
 
+use biblio_json::modules::ModuleId;
 use serde::{Deserialize, Serialize};
 use tauri::{Manager, Runtime, path::{BaseDirectory, PathResolver}};
 
@@ -17,6 +18,8 @@ pub struct AppState
     pub bible_display_settings: BibleDisplaySettings,
     pub view_history: ViewHistory,
     pub reader_behavior: BibleReaderBehavior,
+    #[serde(default)]
+    pub disabled_modules: HashSet<ModuleId>,
 }
 
 impl AppState
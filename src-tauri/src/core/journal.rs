@@ -0,0 +1,417 @@
+use std::{collections::VecDeque, fmt::Debug, sync::Mutex};
+
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, Manager, State};
+
+use crate::{bible::{BIBLE_DISPLAY_SETTINGS_CHANGED_EVENT_NAME, BibleDisplaySettings, BibleDisplaySettingsChangedEvent, BiblioJsonPackageHandle, printing::{PrintBibleFormat, PrintBibleRange, printing_cmd::{PRINT_BIBLE_FORMAT_CHANGED_EVENT_NAME, PRINT_BIBLE_RANGES_CHANGED_EVENT_NAME, PrintBibleFormatChangedEvent, PrintBibleRangesChangedEvent}, printing_state::PrintBibleState}}, core::{app::AppState, settings::{AppSettings, SETTINGS_CHANGED_EVENT_NAME, SettingsChangedEvent}, view_history::{ViewHistoryDiff, emit_view_history_changed}}, reader::{BibleReaderBehavior, reader_cmd::{READER_CHANGED_EVENT_NAME, ReaderChangedEvent}}, repr::PrintBibleRangeJson};
+
+pub const JOURNAL_CHANGED_EVENT_NAME: &str = "journal-changed";
+
+/// The oldest changes are forgotten past this many
+const MAX_JOURNAL_ENTRIES: usize = 200;
+
+/// A change that can be undone and redone. Implemented by anything that wants to be part of the
+/// global undo/redo, getting the state it changes from the `AppHandle`
+pub trait JournalEntry : Debug + Send
+{
+    fn undo(&self, app_handle: &AppHandle) -> Result<(), String>;
+    fn redo(&self, app_handle: &AppHandle) -> Result<(), String>;
+}
+
+/// Undo and redo stacks of changes, kept in memory until the app closes
+#[derive(Debug)]
+pub struct Journal
+{
+    inner: Mutex<JournalInner>,
+}
+
+#[derive(Debug, Default)]
+struct JournalInner
+{
+    undo: VecDeque<Box<dyn JournalEntry>>,
+    redo: Vec<Box<dyn JournalEntry>>,
+}
+
+impl Journal
+{
+    pub fn new() -> Self
+    {
+        Self {
+            inner: Mutex::new(JournalInner::default()),
+        }
+    }
+
+    /// Records a change that has already been made, clearing anything that could be redone
+    pub fn record(&self, app_handle: &AppHandle, entry: impl JournalEntry + 'static)
+    {
+        let info = self.visit(|inner| inner.push(Box::new(entry)));
+
+        emit_journal_changed(app_handle, info);
+    }
+
+    /// Undoes the last change, returning false if there was nothing to undo
+    pub fn undo(&self, app_handle: &AppHandle) -> Result<bool, String>
+    {
+        // The lock is not held while the change is applied, as applying it locks the state it changes
+        let Some(entry) = self.visit(|inner| inner.undo.pop_back()).0 else {
+            return Ok(false);
+        };
+
+        let result = entry.undo(app_handle);
+        let info = self.visit(|inner| inner.finish_undo(entry, result.is_ok()));
+
+        emit_journal_changed(app_handle, info);
+        result.map(|_| true)
+    }
+
+    /// Redoes the last undone change, returning false if there was nothing to redo
+    pub fn redo(&self, app_handle: &AppHandle) -> Result<bool, String>
+    {
+        let Some(entry) = self.visit(|inner| inner.redo.pop()).0 else {
+            return Ok(false);
+        };
+
+        let result = entry.redo(app_handle);
+        let info = self.visit(|inner| inner.finish_redo(entry, result.is_ok()));
+
+        emit_journal_changed(app_handle, info);
+        result.map(|_| true)
+    }
+
+    pub fn info(&self) -> JournalInfo
+    {
+        self.visit(|_| ()).1
+    }
+
+    fn visit<R>(&self, f: impl FnOnce(&mut JournalInner) -> R) -> (R, JournalInfo)
+    {
+        let mut inner = self.inner.lock().unwrap();
+        let result = f(&mut inner);
+        let info = JournalInfo {
+            can_undo: !inner.undo.is_empty(),
+            can_redo: !inner.redo.is_empty(),
+        };
+
+        (result, info)
+    }
+}
+
+impl JournalInner
+{
+    fn push(&mut self, entry: Box<dyn JournalEntry>)
+    {
+        self.redo.clear();
+        self.undo.push_back(entry);
+        if self.undo.len() > MAX_JOURNAL_ENTRIES
+        {
+            self.undo.pop_front();
+        }
+    }
+
+    /// A change that fails to apply stays where it was, so that it can be tried again
+    fn finish_undo(&mut self, entry: Box<dyn JournalEntry>, applied: bool)
+    {
+        match applied
+        {
+            true => self.redo.push(entry),
+            false => self.undo.push_back(entry),
+        }
+    }
+
+    fn finish_redo(&mut self, entry: Box<dyn JournalEntry>, applied: bool)
+    {
+        match applied
+        {
+            true => self.undo.push_back(entry),
+            false => self.redo.push(entry),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct JournalInfo
+{
+    pub can_undo: bool,
+    pub can_redo: bool,
+}
+
+fn emit_journal_changed(app_handle: &AppHandle, info: JournalInfo)
+{
+    app_handle.emit(JOURNAL_CHANGED_EVENT_NAME, info).unwrap();
+}
+
+/// A change to the `AppState` or `PrintBibleState`, holding the values from before and after it
+#[derive(Debug, Clone)]
+pub enum AppStateChange
+{
+    Settings
+    {
+        old: AppSettings,
+        new: AppSettings,
+    },
+    BibleDisplaySettings
+    {
+        old: BibleDisplaySettings,
+        new: BibleDisplaySettings,
+    },
+    /// Only the part of the history that changed is kept, as every navigation is recorded
+    ViewHistory
+    {
+        diff: ViewHistoryDiff,
+    },
+    ReaderBehavior
+    {
+        old: BibleReaderBehavior,
+        new: BibleReaderBehavior,
+    },
+    PrintBibleFormat
+    {
+        old: PrintBibleFormat,
+        new: PrintBibleFormat,
+    },
+    PrintBibleRanges
+    {
+        old: Vec<PrintBibleRangeJson>,
+        new: Vec<PrintBibleRangeJson>,
+    },
+}
+
+impl AppStateChange
+{
+    /// Records the change in the app's journal, unless nothing actually changed
+    pub fn record(self, app_handle: &AppHandle)
+    {
+        if !self.is_unchanged()
+        {
+            app_handle.state::<Journal>().record(app_handle, self);
+        }
+    }
+
+    fn is_unchanged(&self) -> bool
+    {
+        fn same<T: Serialize>(old: &T, new: &T) -> bool
+        {
+            serde_json::to_value(old).ok() == serde_json::to_value(new).ok()
+        }
+
+        match self
+        {
+            Self::Settings { old, new } => same(old, new),
+            Self::BibleDisplaySettings { old, new } => same(old, new),
+            Self::ViewHistory { diff } => diff.is_empty(),
+            Self::ReaderBehavior { old, new } => same(old, new),
+            Self::PrintBibleFormat { old, new } => same(old, new),
+            Self::PrintBibleRanges { old, new } => same(old, new),
+        }
+    }
+
+    /// Sets the state to the old value when undoing, or the new one when redoing, emitting the same
+    /// changed event as the command that made the change
+    fn apply(&self, app_handle: &AppHandle, undo: bool) -> Result<(), String>
+    {
+        fn pick<T: Clone>(old: &T, new: &T, undo: bool) -> T
+        {
+            if undo { old.clone() } else { new.clone() }
+        }
+
+        let app_state = app_handle.state::<Mutex<AppState>>();
+        match self
+        {
+            Self::Settings { old, new } => {
+                let mut state = app_state.lock().map_err(|e| e.to_string())?;
+                let old = std::mem::replace(&mut state.settings, pick(old, new, undo));
+                app_handle.emit(SETTINGS_CHANGED_EVENT_NAME, SettingsChangedEvent {
+                    old,
+                    new: state.settings.clone(),
+                }).map_err(|e| e.to_string())
+            },
+            Self::BibleDisplaySettings { old, new } => {
+                // Modules may have been disabled or uninstalled since the change was made
+                let settings = pick(old, new, undo);
+                let settings = app_handle.state::<BiblioJsonPackageHandle>().visit(|p| {
+                    let mut settings = settings.clone();
+                    settings.remove_missing_modules(p, None);
+                    settings
+                }).unwrap_or(settings);
+
+                let mut state = app_state.lock().map_err(|e| e.to_string())?;
+                let old = std::mem::replace(&mut state.bible_display_settings, settings);
+                app_handle.emit(BIBLE_DISPLAY_SETTINGS_CHANGED_EVENT_NAME, BibleDisplaySettingsChangedEvent {
+                    old,
+                    new: state.bible_display_settings.clone(),
+                }).map_err(|e| e.to_string())
+            },
+            Self::ViewHistory { diff } => {
+                let mut state = app_state.lock().map_err(|e| e.to_string())?;
+                let old = state.view_history.clone();
+                diff.apply(&mut state.view_history, undo);
+                emit_view_history_changed(app_handle, &old, &state.view_history);
+                Ok(())
+            },
+            Self::ReaderBehavior { old, new } => {
+                let mut state = app_state.lock().map_err(|e| e.to_string())?;
+                let old = std::mem::replace(&mut state.reader_behavior, pick(old, new, undo));
+                app_handle.emit(READER_CHANGED_EVENT_NAME, ReaderChangedEvent {
+                    old,
+                    new: state.reader_behavior.clone(),
+                }).map_err(|e| e.to_string())
+            },
+            Self::PrintBibleFormat { old, new } => {
+                let event = app_handle.state::<PrintBibleState>().visit(|state| {
+                    let old = std::mem::replace(&mut state.format, pick(old, new, undo));
+                    PrintBibleFormatChangedEvent {
+                        old,
+                        new: state.format.clone(),
+                    }
+                });
+
+                app_handle.emit(PRINT_BIBLE_FORMAT_CHANGED_EVENT_NAME, event).map_err(|e| e.to_string())
+            },
+            Self::PrintBibleRanges { old, new } => {
+                let ranges = pick(old, new, undo);
+                let event = app_handle.state::<PrintBibleState>().visit(|state| {
+                    let old = state.ranges.iter().map(PrintBibleRangeJson::from).collect_vec();
+                    state.ranges = ranges.iter().map(PrintBibleRange::from).collect();
+                    PrintBibleRangesChangedEvent {
+                        old,
+                        new: ranges,
+                    }
+                });
+
+                app_handle.emit(PRINT_BIBLE_RANGES_CHANGED_EVENT_NAME, event).map_err(|e| e.to_string())
+            },
+        }
+    }
+}
+
+impl JournalEntry for AppStateChange
+{
+    fn undo(&self, app_handle: &AppHandle) -> Result<(), String>
+    {
+        self.apply(app_handle, true)
+    }
+
+    fn redo(&self, app_handle: &AppHandle) -> Result<(), String>
+    {
+        self.apply(app_handle, false)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "type")]
+pub enum JournalCommand
+{
+    Undo,
+    Redo,
+    GetInfo,
+}
+
+#[tauri::command(rename_all = "snake_case")]
+pub fn run_journal_command(app_handle: AppHandle, journal: State<'_, Journal>, command: JournalCommand) -> Result<Option<String>, String>
+{
+    match command
+    {
+        JournalCommand::Undo => {
+            journal.undo(&app_handle)?;
+            Ok(None)
+        },
+        JournalCommand::Redo => {
+            journal.redo(&app_handle)?;
+            Ok(None)
+        },
+        JournalCommand::GetInfo => {
+            let json = serde_json::to_string(&journal.info()).map_err(|e| e.to_string())?;
+            Ok(Some(json))
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests 
+{
+    use super::*;
+
+    #[derive(Debug)]
+    struct TestEntry(usize);
+
+    impl JournalEntry for TestEntry
+    {
+        fn undo(&self, _: &AppHandle) -> Result<(), String>
+        {
+            Ok(())
+        }
+
+        fn redo(&self, _: &AppHandle) -> Result<(), String>
+        {
+            Ok(())
+        }
+    }
+
+    fn get_ids<'a>(entries: impl IntoIterator<Item = &'a Box<dyn JournalEntry>>) -> Vec<String>
+    {
+        entries.into_iter().map(|e| format!("{:?}", e)).collect()
+    }
+
+    fn entry_id(entry: &Option<Box<dyn JournalEntry>>) -> Option<String>
+    {
+        entry.as_ref().map(|e| format!("{:?}", e))
+    }
+
+    #[test]
+    fn test_undo_redo_order()
+    {
+        let mut journal = JournalInner::default();
+        (0..3).for_each(|i| journal.push(Box::new(TestEntry(i))));
+
+        let undone = journal.undo.pop_back();
+        assert_eq!(entry_id(&undone), Some("TestEntry(2)".into()));
+        journal.finish_undo(undone.unwrap(), true);
+
+        let undone = journal.undo.pop_back();
+        assert_eq!(entry_id(&undone), Some("TestEntry(1)".into()));
+        journal.finish_undo(undone.unwrap(), true);
+
+        let redone = journal.redo.pop();
+        assert_eq!(entry_id(&redone), Some("TestEntry(1)".into()));
+        journal.finish_redo(redone.unwrap(), true);
+
+        assert_eq!(get_ids(&journal.undo), vec!["TestEntry(0)", "TestEntry(1)"]);
+        assert_eq!(get_ids(&journal.redo), vec!["TestEntry(2)"]);
+
+        // A new change can not be followed by the ones undone before it
+        journal.push(Box::new(TestEntry(3)));
+        assert_eq!(get_ids(&journal.undo), vec!["TestEntry(0)", "TestEntry(1)", "TestEntry(3)"]);
+        assert!(journal.redo.is_empty());
+    }
+
+    #[test]
+    fn test_failed_changes_stay()
+    {
+        let mut journal = JournalInner::default();
+        journal.push(Box::new(TestEntry(0)));
+
+        let undone = journal.undo.pop_back().unwrap();
+        journal.finish_undo(undone, false);
+        assert_eq!(get_ids(&journal.undo), vec!["TestEntry(0)"]);
+        assert!(journal.redo.is_empty());
+
+        let undone = journal.undo.pop_back().unwrap();
+        journal.finish_undo(undone, true);
+        let redone = journal.redo.pop().unwrap();
+        journal.finish_redo(redone, false);
+        assert!(journal.undo.is_empty());
+        assert_eq!(get_ids(&journal.redo), vec!["TestEntry(0)"]);
+    }
+
+    #[test]
+    fn test_journal_is_bounded()
+    {
+        let mut journal = JournalInner::default();
+        (0..MAX_JOURNAL_ENTRIES + 5).for_each(|i| journal.push(Box::new(TestEntry(i))));
+
+        assert_eq!(journal.undo.len(), MAX_JOURNAL_ENTRIES);
+        assert_eq!(format!("{:?}", journal.undo[0]), "TestEntry(5)");
+    }
+}
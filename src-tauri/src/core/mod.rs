@@ -5,3 +5,4 @@ pub mod view_history;
 pub mod color;
 pub mod theme;
 pub mod app_language;
+pub mod journal;
@@ -3,7 +3,7 @@ use std::{collections::HashMap, sync::Mutex};
 use serde::{Deserialize, Serialize};
 use tauri::{Emitter, State};
 
-use crate::{core::{app::AppState, app_language::get_default_language, theme::AppTheme}, sfx::SfxSettings, tts::TtsSettings};
+use crate::{core::{app::AppState, journal::AppStateChange, app_language::get_default_language, theme::AppTheme}, sfx::SfxSettings, tts::TtsSettings};
 
 pub const SETTINGS_CHANGED_EVENT_NAME: &str = "settings-changed";
 
@@ -99,9 +99,14 @@ pub fn run_settings_command(
             state.settings = value.unwrap();
 
             app_handle.emit(SETTINGS_CHANGED_EVENT_NAME, SettingsChangedEvent {
-                old: old,
+                old: old.clone(),
                 new: state.settings.clone(),
             }).unwrap();
+
+            AppStateChange::Settings {
+                old,
+                new: state.settings.clone(),
+            }.record(&app_handle);
             None
         },
         _ => panic!("Unknown settings sub command {}", command)
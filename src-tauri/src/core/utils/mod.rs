@@ -17,6 +17,13 @@ pub fn get_uuid() -> String
     Uuid::new_v4().to_string()
 }
 
+pub fn escape_html(text: &str) -> String
+{
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
 #[derive(Debug)]
 pub struct Shared<T>(Arc<Mutex<T>>);
 
@@ -4,11 +4,11 @@ use biblio_json::{core::{OsisBook, chapter_id::ChapterId}, modules::{EntryId, Mo
 use serde::{Deserialize, Serialize};
 use tauri::{AppHandle, Emitter, State};
 
-use crate::{core::app::AppState, repr::{ChapterIdJson, searching::WordSearchQueryJson}};
+use crate::{core::{app::AppState, journal::AppStateChange}, repr::{ChapterIdJson, searching::WordSearchQueryJson}};
 
 pub const VIEW_HISTORY_CHANGED_EVENT_NAME: &str = "view-history-changed";
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub struct ViewHistory
 {
@@ -90,6 +90,57 @@ impl ViewHistory
     }
 }
 
+/// The entries and index that changed between two view histories, so that a navigation can be
+/// undone without keeping a copy of the whole history
+#[derive(Debug, Clone)]
+pub struct ViewHistoryDiff
+{
+    /// How many entries at the start both histories share
+    start: usize,
+    old_entries: Vec<ViewHistoryEntry>,
+    new_entries: Vec<ViewHistoryEntry>,
+    old_index: usize,
+    new_index: usize,
+}
+
+impl ViewHistoryDiff
+{
+    pub fn between(old: &ViewHistory, new: &ViewHistory) -> Self
+    {
+        let start = old.entries.iter()
+            .zip(&new.entries)
+            .take_while(|(o, n)| o == n)
+            .count();
+
+        Self {
+            start,
+            old_entries: old.entries[start..].to_vec(),
+            new_entries: new.entries[start..].to_vec(),
+            old_index: old.index,
+            new_index: new.index,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool
+    {
+        self.old_index == self.new_index && self.old_entries == self.new_entries
+    }
+
+    /// Turns the new history back into the old one when undoing, or the old one into the new one when redoing
+    pub fn apply(&self, history: &mut ViewHistory, undo: bool)
+    {
+        let (entries, index) = match undo
+        {
+            true => (&self.old_entries, self.old_index),
+            false => (&self.new_entries, self.new_index),
+        };
+
+        history.entries.truncate(self.start);
+        history.entries.extend(entries.iter().cloned());
+        history.set_index(index);
+    }
+}
+
 impl Default for ViewHistory
 {
     fn default() -> Self 
@@ -254,12 +305,86 @@ pub fn run_view_history_command(app_handle: AppHandle, app_state: State<'_, Mute
 
 pub fn update_view_history(view_history: &mut ViewHistory, app_handle: &AppHandle, f: impl FnOnce(&mut ViewHistory))
 {
-    let old = ViewHistoryInfo::from_history(&view_history);
+    let old = view_history.clone();
     f(view_history);
-    let new = ViewHistoryInfo::from_history(&view_history);
+    emit_view_history_changed(app_handle, &old, view_history);
 
+    AppStateChange::ViewHistory {
+        diff: ViewHistoryDiff::between(&old, view_history),
+    }.record(app_handle);
+}
+
+pub fn emit_view_history_changed(app_handle: &AppHandle, old: &ViewHistory, new: &ViewHistory)
+{
     app_handle.emit(VIEW_HISTORY_CHANGED_EVENT_NAME, ViewHistoryChangedEvent {
-        old,
-        new,
+        old: ViewHistoryInfo::from_history(old),
+        new: ViewHistoryInfo::from_history(new),
     }).unwrap();
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests 
+{
+    use super::*;
+
+    fn chapter(chapter: u32) -> ViewHistoryEntry
+    {
+        ViewHistoryEntry::Chapter { 
+            chapter: ChapterId { book: OsisBook::Gen, chapter: NonZeroU32::new(chapter).unwrap() }.into(),
+        }
+    }
+
+    fn assert_same(a: &ViewHistory, b: &ViewHistory)
+    {
+        assert_eq!(a.entries, b.entries);
+        assert_eq!(a.index, b.index);
+    }
+
+    #[test]
+    fn test_diff_round_trip()
+    {
+        let mut old = ViewHistory::new();
+        (2..=4).for_each(|c| old.push_entry(chapter(c)));
+        old.retreat();
+        old.retreat();
+
+        let changes: [fn(&mut ViewHistory); 4] = [
+            |vh| vh.push_entry(chapter(5)),
+            |vh| vh.advance(),
+            |vh| vh.set_index(0),
+            |vh| vh.clear(),
+        ];
+
+        for change in changes
+        {
+            let mut new = ViewHistory { entries: old.entries.clone(), index: old.index };
+            change(&mut new);
+            let diff = ViewHistoryDiff::between(&old, &new);
+
+            let mut history = ViewHistory { entries: new.entries.clone(), index: new.index };
+            diff.apply(&mut history, true);
+            assert_same(&history, &old);
+
+            diff.apply(&mut history, false);
+            assert_same(&history, &new);
+        }
+    }
+
+    #[test]
+    fn test_diff_keeps_only_changes()
+    {
+        let mut old = ViewHistory::new();
+        (2..=4).for_each(|c| old.push_entry(chapter(c)));
+        old.retreat();
+
+        let mut new = ViewHistory { entries: old.entries.clone(), index: old.index };
+        new.push_entry(chapter(5));
+
+        let diff = ViewHistoryDiff::between(&old, &new);
+        assert_eq!(diff.start, 3);
+        assert_eq!(diff.old_entries, vec![chapter(4)]);
+        assert_eq!(diff.new_entries, vec![chapter(5)]);
+
+        assert!(ViewHistoryDiff::between(&old, &old).is_empty());
+    }
+}
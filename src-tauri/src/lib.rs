@@ -1,6 +1,6 @@
 use std::sync::{Arc, Mutex, atomic::{AtomicBool, Ordering}};
 use tauri::{Manager, WindowEvent};
-use crate::{bible::{BibleDisplaySettings, BiblioJsonPackageHandle, printing::printing_state::PrintBibleState}, core::{app::AppState, settings, view_history}, sfx::SfxPlayer, tts::{TtsAudioLibrary, gen_thread::TtsGenThread, init_espeak, player::TtsPlayer, voices::AppVoices}};
+use crate::{bible::{BibleDisplaySettings, BiblioJsonPackageHandle, concordance::ConcordanceCache, notebooks::NotebookStore, user_modules::UserModuleStore, printing::printing_state::PrintBibleState, render::WordSearchCache}, core::{app::AppState, journal::{self, Journal}, settings, view_history}, sfx::SfxPlayer, tts::{TtsAudioLibrary, gen_thread::TtsGenThread, init_espeak, player::TtsPlayer, voices::AppVoices}};
 
 pub mod core;
 pub mod bible;
@@ -37,16 +37,20 @@ pub fn run()
             }
 
             init_espeak(app.path());
+            app.manage(Mutex::new(AppState::load(app.path()).unwrap()));
+            app.manage(NotebookStore::new(app.path()));
+            app.manage(WordSearchCache::new());
+            app.manage(ConcordanceCache::new());
+            app.manage(UserModuleStore::new(app.path()));
             app.manage(BiblioJsonPackageHandle::init(app.handle().clone()));
             app.manage(SfxPlayer::new(app.path()));
             app.manage(PrintBibleState::new());
+            app.manage(Journal::new());
             app.manage(AppVoices::load(app.path()));
             app.manage(TtsGenThread::new(app.handle().clone()));
             app.manage(TtsAudioLibrary::new(app.handle().clone()));
             app.manage(TtsPlayer::new(app.handle().clone()));
 
-            app.manage(Mutex::new(AppState::load(app.path()).unwrap()));
-
             BibleDisplaySettings::add_on_package_init_listener(app.handle().clone());
 
             Ok(())
@@ -79,6 +83,7 @@ pub fn run()
             searching::push_module_word_search_to_view_history,
             searching::push_search_to_view_history,
             view_history::run_view_history_command,
+            journal::run_journal_command,
             tts::tts_cmd::run_tts_command,
             commands::open,
             core::app_language::run_app_language_command,
@@ -4,7 +4,7 @@ use biblio_json::modules::ModuleId;
 use serde::{Deserialize, Serialize};
 use tauri::{Emitter, State};
 
-use crate::{bible::BiblioJsonPackageHandle, core::app::AppState, reader::{BibleReaderBehavior, ReaderNextResult, ReaderQueueResult}};
+use crate::{bible::{BiblioJsonPackageHandle, PACKAGE_NOT_LOADED_ERROR}, core::{app::AppState, journal::AppStateChange}, reader::{BibleReaderBehavior, ReaderNextResult, ReaderQueueResult}};
 
 pub const READER_CHANGED_EVENT_NAME: &str = "reader-changed";
 
@@ -62,18 +62,23 @@ pub fn run_reader_command(
 
             app_handle
                 .emit(READER_CHANGED_EVENT_NAME, ReaderChangedEvent {
-                    old,
-                    new: behavior,
+                    old: old.clone(),
+                    new: behavior.clone(),
                 })
                 .map_err(|e| e.to_string())?;
 
+            AppStateChange::ReaderBehavior {
+                old,
+                new: behavior,
+            }.record(&app_handle);
+
             Ok(None)
         },
         ReaderCommand::Next { bible, index, time } => {
             let state = state.lock().map_err(|e| e.to_string())?;
             let result = package.visit(|p| {
                 state.reader_behavior.next(index, time, &bible, p)
-            });
+            }).ok_or(PACKAGE_NOT_LOADED_ERROR)?;
             
             match result
             {
@@ -87,7 +92,7 @@ pub fn run_reader_command(
             let state = state.lock().map_err(|e| e.to_string())?;
             let result = package.visit(|p| {
                 state.reader_behavior.get_queue(index, offset, &bible, p)
-            });
+            }).ok_or(PACKAGE_NOT_LOADED_ERROR)?;
             
             match result
             {
@@ -10,6 +10,23 @@ pub struct HtmlTextJson
     pub nodes: Vec<NodeJson>
 }
 
+/// Parses raw html into [`HtmlText`], going through the same deserializer biblio_json uses for module entries
+pub fn parse_html_text(html: &str) -> Result<HtmlText, String>
+{
+    serde_json::from_value(serde_json::Value::String(html.to_owned()))
+        .map_err(|e| e.to_string())
+}
+
+/// Writes [`HtmlText`] back out as raw html, going through the same serializer biblio_json uses for module entries
+pub fn write_html_text(html: &HtmlText) -> Result<String, String>
+{
+    match serde_json::to_value(html).map_err(|e| e.to_string())?
+    {
+        serde_json::Value::String(html) => Ok(html),
+        v => Err(format!("Unexpected html text {}", v)),
+    }
+}
+
 impl HtmlTextJson
 {
     pub fn from_html(html: &HtmlText, external: &ExternalModuleData) -> Self 
@@ -1,13 +1,15 @@
 use biblio_json::{Package, modules::ModuleId};
 use serde::{Deserialize, Serialize};
 
-use crate::{bible::fetching::PackageEx, repr::{ModuleEntryJson, StrongsNumberJson, VerseIdJson}, searching::{module_searching::ModuleSearchHit, word_search_engine::{WordSearchPart, WordSearchQuery, WordSearchRange}}};
+use crate::{bible::fetching::PackageEx, repr::{ModuleEntryJson, StrongsNumberJson, VerseIdJson}, searching::{module_searching::ModuleSearchHit, word_search_engine::{SearchRegex, WordSearchOptions, WordSearchPart, WordSearchQuery, WordSearchRange}}};
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub struct WordSearchQueryJson
 {
     pub ranges: Vec<WordSearchRangeJson>,
     pub root: Option<WordSearchPartJson>,
+    #[serde(default)]
+    pub options: WordSearchOptions,
 }
 
 impl From<WordSearchQuery> for WordSearchQueryJson
@@ -16,7 +18,8 @@ impl From<WordSearchQuery> for WordSearchQueryJson
     {
         Self {
             ranges: value.ranges.into_iter().map(Into::into).collect(),
-            root: value.root.map(Into::into)
+            root: value.root.map(Into::into),
+            options: value.options,
         }
     }
 }
@@ -27,7 +30,8 @@ impl From<&WordSearchQuery> for WordSearchQueryJson
     {
         Self {
             ranges: value.ranges.clone().into_iter().map(Into::into).collect(),
-            root: value.root.clone().map(Into::into)
+            root: value.root.clone().map(Into::into),
+            options: value.options.clone(),
         }
     }
 }
@@ -39,6 +43,7 @@ impl From<WordSearchQueryJson> for WordSearchQuery
         Self {
             ranges: value.ranges.into_iter().map(Into::into).collect(),
             root: value.root.map(Into::into),
+            options: value.options,
         }    
     }
 }
@@ -50,6 +55,7 @@ impl From<&WordSearchQueryJson> for WordSearchQuery
         Self {
             ranges: value.ranges.clone().into_iter().map(Into::into).collect(),
             root: value.root.clone().map(Into::into),
+            options: value.options.clone(),
         }    
     }
 }
@@ -89,7 +95,29 @@ pub enum WordSearchPartJson
     Word 
     {
         word: String,
-    }
+    },
+    Fuzzy
+    {
+        word: String,
+    },
+    Regex
+    {
+        pattern: String,
+        #[serde(default)]
+        whole_verse: bool,
+    },
+    Near
+    {
+        left: Box<WordSearchPartJson>,
+        right: Box<WordSearchPartJson>,
+        distance: u32,
+    },
+    Before
+    {
+        left: Box<WordSearchPartJson>,
+        right: Box<WordSearchPartJson>,
+        distance: u32,
+    },
 }
 
 impl From<WordSearchPartJson> for WordSearchPart 
@@ -120,6 +148,20 @@ impl From<WordSearchPartJson> for WordSearchPart
                 WordSearchPart::EndsWith(pattern)
             }
             WordSearchPartJson::Word { word } => WordSearchPart::Word(word),
+            WordSearchPartJson::Fuzzy { word } => WordSearchPart::Fuzzy(word),
+            WordSearchPartJson::Regex { pattern, whole_verse } => {
+                WordSearchPart::Regex(SearchRegex::new(pattern, whole_verse))
+            }
+            WordSearchPartJson::Near { left, right, distance } => WordSearchPart::Near {
+                left: Box::new((*left).into()),
+                right: Box::new((*right).into()),
+                distance,
+            },
+            WordSearchPartJson::Before { left, right, distance } => WordSearchPart::Before {
+                left: Box::new((*left).into()),
+                right: Box::new((*right).into()),
+                distance,
+            },
         }
     }
 }
@@ -152,6 +194,20 @@ impl From<&WordSearchPartJson> for WordSearchPart
                 WordSearchPart::EndsWith(pattern.clone())
             }
             WordSearchPartJson::Word { word } => WordSearchPart::Word(word.clone()),
+            WordSearchPartJson::Fuzzy { word } => WordSearchPart::Fuzzy(word.clone()),
+            WordSearchPartJson::Regex { pattern, whole_verse } => {
+                WordSearchPart::Regex(SearchRegex::new(pattern.clone(), *whole_verse))
+            }
+            WordSearchPartJson::Near { left, right, distance } => WordSearchPart::Near {
+                left: Box::new(left.as_ref().into()),
+                right: Box::new(right.as_ref().into()),
+                distance: *distance,
+            },
+            WordSearchPartJson::Before { left, right, distance } => WordSearchPart::Before {
+                left: Box::new(left.as_ref().into()),
+                right: Box::new(right.as_ref().into()),
+                distance: *distance,
+            },
         }
     }
 }
@@ -186,6 +242,23 @@ impl From<WordSearchPart> for WordSearchPartJson
             WordSearchPart::Word(word) => WordSearchPartJson::Word {
                 word,
             },
+            WordSearchPart::Fuzzy(word) => WordSearchPartJson::Fuzzy {
+                word,
+            },
+            WordSearchPart::Regex(regex) => WordSearchPartJson::Regex {
+                pattern: regex.pattern,
+                whole_verse: regex.whole_verse,
+            },
+            WordSearchPart::Near { left, right, distance } => WordSearchPartJson::Near {
+                left: Box::new((*left).into()),
+                right: Box::new((*right).into()),
+                distance,
+            },
+            WordSearchPart::Before { left, right, distance } => WordSearchPartJson::Before {
+                left: Box::new((*left).into()),
+                right: Box::new((*right).into()),
+                distance,
+            },
         }
     }
 }
@@ -220,6 +293,23 @@ impl From<&WordSearchPart> for WordSearchPartJson
             WordSearchPart::Word(word) => WordSearchPartJson::Word {
                 word: word.clone(),
             },
+            WordSearchPart::Fuzzy(word) => WordSearchPartJson::Fuzzy {
+                word: word.clone(),
+            },
+            WordSearchPart::Regex(regex) => WordSearchPartJson::Regex {
+                pattern: regex.pattern.clone(),
+                whole_verse: regex.whole_verse,
+            },
+            WordSearchPart::Near { left, right, distance } => WordSearchPartJson::Near {
+                left: Box::new(left.as_ref().into()),
+                right: Box::new(right.as_ref().into()),
+                distance: *distance,
+            },
+            WordSearchPart::Before { left, right, distance } => WordSearchPartJson::Before {
+                left: Box::new(left.as_ref().into()),
+                right: Box::new(right.as_ref().into()),
+                distance: *distance,
+            },
         }
     }
 }
@@ -1,6 +1,10 @@
-use biblio_json::{core::{StrongsNumber, WordRange}, html_text::{HtmlText, ast::{HRefSrc, Node}}, modules::{bible::Verse, strongs::StrongsLinkEntry}};
+use std::ops::Range;
+
+use biblio_json::{core::{StrongsNumber, lang::Language}, html_text::{HtmlText, ast::{HRefSrc, Node}}, modules::bible::Verse};
 use itertools::Itertools;
 
+use crate::bible::indexing::strongs_index::VerseStrongsLinks;
+
 pub trait SearchContext {
     /// Number of searchable tokens
     fn len(&self) -> usize;
@@ -10,12 +14,25 @@ pub trait SearchContext {
 
     /// Optional strongs numbers attached to this token
     fn token_strongs(&self, index: usize) -> Option<&[StrongsNumber]>;
+
+    /// The language of the text, if known, used for stemming
+    fn language(&self) -> Option<Language>
+    {
+        None
+    }
+
+    /// The tokens that every part of a query other than `NEAR` and `BEFORE` is searched in, as only those can match outside of them
+    fn scope(&self) -> Range<usize>
+    {
+        0..self.len()
+    }
 }
 
 pub struct VerseSearchContext<'a>
 {
     pub verse: &'a Verse,
-    pub strongs: Option<&'a StrongsLinkEntry>
+    pub strongs: Option<VerseStrongsLinks<'a>>,
+    pub language: Option<Language>,
 }
 
 impl<'a> SearchContext for VerseSearchContext<'a>
@@ -32,20 +49,89 @@ impl<'a> SearchContext for VerseSearchContext<'a>
 
     fn token_strongs(&self, index: usize) -> Option<&[StrongsNumber]> 
     {
-        let Some(strongs) = self.strongs else {
-            return None;
-        };
+        self.strongs?.get_word_strongs(index)
+    }
+
+    fn language(&self) -> Option<Language>
+    {
+        self.language
+    }
+}
 
-        for sw in &strongs.words
+/// A verse and its neighbouring verses searched as one run of tokens, where only `NEAR` and `BEFORE` can match outside of the verse
+pub struct WindowSearchContext<'a>
+{
+    verses: Vec<VerseSearchContext<'a>>,
+    /// The index of the first token of each verse
+    offsets: Vec<usize>,
+    len: usize,
+    /// The index of the searched verse in `verses`
+    focus: usize,
+}
+
+impl<'a> WindowSearchContext<'a>
+{
+    pub fn new(verses: Vec<VerseSearchContext<'a>>, focus: usize) -> Self
+    {
+        let mut offsets = vec![];
+        let mut len = 0;
+        for verse in &verses
         {
-            match sw.range
-            {
-                WordRange::Single(s) => if index + 1 == s.get() as usize { return Some(&sw.strongs) },
-                WordRange::Range(start, end) => if index + 1 >= start.get() as usize && index + 1 <= end.get() as usize { return Some(&sw.strongs) },
-            }
+            offsets.push(len);
+            len += verse.len();
         }
 
-        None
+        Self { verses, offsets, len, focus }
+    }
+
+    /// The hits that fall in the searched verse, as indices into that verse
+    pub fn get_verse_hits(&self, hits: &[u32]) -> Vec<u32>
+    {
+        let start = self.offsets[self.focus];
+        let end = start + self.verses[self.focus].len();
+        hits.iter()
+            .map(|h| *h as usize)
+            .filter(|h| (start..end).contains(h))
+            .map(|h| (h - start) as u32)
+            .collect()
+    }
+
+    fn locate(&self, index: usize) -> (usize, usize)
+    {
+        // Verses without words share an offset with the next verse, so the last verse starting at or before the index is taken
+        let verse = self.offsets.partition_point(|o| *o <= index) - 1;
+        (verse, index - self.offsets[verse])
+    }
+}
+
+impl<'a> SearchContext for WindowSearchContext<'a>
+{
+    fn len(&self) -> usize 
+    {
+        self.len
+    }
+
+    fn token_text(&self, index: usize) -> &str 
+    {
+        let (verse, index) = self.locate(index);
+        self.verses[verse].token_text(index)
+    }
+
+    fn token_strongs(&self, index: usize) -> Option<&[StrongsNumber]> 
+    {
+        let (verse, index) = self.locate(index);
+        self.verses[verse].token_strongs(index)
+    }
+
+    fn language(&self) -> Option<Language>
+    {
+        self.verses.first()?.language
+    }
+
+    fn scope(&self) -> Range<usize>
+    {
+        let start = self.offsets[self.focus];
+        start..start + self.verses[self.focus].len()
     }
 }
 
@@ -53,6 +139,7 @@ impl<'a> SearchContext for VerseSearchContext<'a>
 pub struct HtmlSearchContext 
 {
     tokens: Vec<HtmlToken>,
+    language: Option<Language>,
 }
 
 #[derive(Debug)]
@@ -78,11 +165,16 @@ impl SearchContext for HtmlSearchContext
     {
         Some(&self.tokens[index].strongs)
     }
+
+    fn language(&self) -> Option<Language>
+    {
+        self.language
+    }
 }
 
 impl HtmlSearchContext
 {
-    pub fn from_html_text(html: &HtmlText) -> Self 
+    pub fn from_html_text(html: &HtmlText, language: Option<Language>) -> Self 
     {
         let mut tokens = Vec::new();
         for node in &html.nodes 
@@ -90,13 +182,14 @@ impl HtmlSearchContext
             flatten_node(node, &mut tokens, &[]);
         }
 
-        HtmlSearchContext { tokens }
+        HtmlSearchContext { tokens, language }
     }
 }
 
 pub struct StringSearchContext
 {
     tokens: Vec<String>,
+    language: Option<Language>,
 }
 
 impl SearchContext for StringSearchContext
@@ -115,11 +208,16 @@ impl SearchContext for StringSearchContext
     {
         None
     }
+
+    fn language(&self) -> Option<Language>
+    {
+        self.language
+    }
 }
 
 impl StringSearchContext
 {
-    pub fn new(s: &str) -> Self 
+    pub fn new(s: &str, language: Option<Language>) -> Self 
     {
         let tokens = s.split_whitespace()
             .map(|ss| ss.chars()
@@ -131,7 +229,8 @@ impl StringSearchContext
         
         Self 
         {
-            tokens
+            tokens,
+            language,
         }
     }
 }
@@ -3,6 +3,8 @@ pub mod word_search_engine;
 pub mod word_search_parsing;
 pub mod context;
 pub mod module_searching;
+pub mod stemming;
+pub mod ranking;
 
 use std::sync::Mutex;
 
@@ -97,13 +99,13 @@ pub fn push_search_to_view_history(
             .as_bible()
             .unwrap()
             .clone()
-    });
+    })?;
 
     let parsed = package.visit(|p| {
         SearchType::parse(input_str, &bible_module, p).map_err(|e| {
             Some(e.to_string(&bible_module))
         })
-    });
+    })?;
 
     let parsed = match parsed {
         Ok(ok) => ok,
@@ -176,13 +178,13 @@ pub fn push_module_word_search_to_view_history(
             .as_bible()
             .unwrap()
             .clone()
-    });
+    })?;
 
     let parsed = package.visit(|p| {
         SearchType::parse(input_str, &bible_module, p).map_err(|e| {
             Some(e.to_string(&bible_module))
         })
-    });
+    })?;
 
     let parsed = match parsed {
         Ok(ok) => ok,
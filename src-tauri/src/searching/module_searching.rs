@@ -1,8 +1,10 @@
-use biblio_json::{Package, core::VerseRangeIter, html_text::HtmlText, modules::{Module, ModuleEntry, ModuleEntryRef, ModuleId, notebook::NotebookEntry, xrefs::XRefEntry}};
+use std::collections::HashSet;
+
+use biblio_json::{Package, core::{VerseId, VerseRangeIter, lang::Language}, html_text::HtmlText, modules::{Module, ModuleEntry, ModuleEntryRef, ModuleId, notebook::NotebookEntry, xrefs::XRefEntry}};
 use itertools::Itertools;
 use serde::{Deserialize, Serialize};
 
-use crate::searching::{context::{HtmlSearchContext, StringSearchContext, VerseSearchContext}, word_search_engine::{WordSearchPart, WordSearchQuery, WordSearchRange}};
+use crate::{bible::indexing::PackageIndex, searching::{ranking::{HitTermStats, SearchSortMode}, context::{HtmlSearchContext, StringSearchContext, VerseSearchContext, WindowSearchContext}, word_search_engine::{WordSearchOptions, WordSearchPart, WordSearchQuery, WordSearchRange}}};
 
 pub struct ModuleSearchHit<'a>
 {
@@ -10,6 +12,8 @@ pub struct ModuleSearchHit<'a>
     pub entry_ref: ModuleEntryRef,
     pub body_hits: Vec<u32>,
     pub title_hits: Vec<u32>,
+    pub body_stats: HitTermStats,
+    pub title_stats: HitTermStats,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
@@ -21,8 +25,14 @@ pub enum WordSearchMode
     TitleAndBody,
 }
 
-pub fn search_modules<'a, 'b>(package: &'a Package, modules: &'b [ModuleId], query: &'b WordSearchQuery, mode: WordSearchMode) -> Vec<ModuleSearchHit<'a>>
+/// The hits' term stats are only worked out when they are sorted by relevance, as nothing else uses them
+pub fn search_modules<'a, 'b>(package: &'a Package, index: &'b PackageIndex, modules: &'b [ModuleId], query: &'b WordSearchQuery, mode: WordSearchMode, sort: SearchSortMode) -> Vec<ModuleSearchHit<'a>>
 {
+    let terms = query.root.as_ref()
+        .filter(|_| sort == SearchSortMode::Relevance)
+        .map(|r| r.get_terms());
+    let terms = terms.as_deref();
+
     let modules = modules.iter()
         .filter_map(|m| package.modules.get(m))
         .collect_vec();
@@ -32,6 +42,7 @@ pub fn search_modules<'a, 'b>(package: &'a Package, modules: &'b [ModuleId], que
         .collect_vec();
 
     modules.iter().map(|m| {
+        let language = m.get_info().language;
         match m
         {
             Module::Dictionary(dict_module) => {
@@ -43,7 +54,7 @@ pub fn search_modules<'a, 'b>(package: &'a Package, modules: &'b [ModuleId], que
                     };
 
                     let module_id = dict_module.config.id.clone();
-                    search_entry(query.root.as_ref(), body, title, ModuleEntry::Dictionary(e), module_id, SearchEntryMode::Default)
+                    search_entry(query.root.as_ref(), terms, &query.options, language, body, title, ModuleEntry::Dictionary(e), module_id, SearchEntryMode::Default)
                 }).collect_vec()
             },
             Module::XRef(xref_module) => {
@@ -65,7 +76,7 @@ pub fn search_modules<'a, 'b>(package: &'a Package, modules: &'b [ModuleId], que
                     };
 
                     let module_id = xref_module.config.id.clone();
-                    search_entry(query.root.as_ref(), body, None, ModuleEntry::XRef(e), module_id, SearchEntryMode::Default)
+                    search_entry(query.root.as_ref(), terms, &query.options, language, body, None, ModuleEntry::XRef(e), module_id, SearchEntryMode::Default)
                 }).collect_vec()
             },
             Module::StrongsDefs(strongs_defs_module) => {
@@ -78,7 +89,7 @@ pub fn search_modules<'a, 'b>(package: &'a Package, modules: &'b [ModuleId], que
                     };
 
                     let module_id = strongs_defs_module.config.id.clone();
-                    search_entry(query.root.as_ref(), body, None, ModuleEntry::StrongsDef(e), module_id, SearchEntryMode::Default)
+                    search_entry(query.root.as_ref(), terms, &query.options, language, body, None, ModuleEntry::StrongsDef(e), module_id, SearchEntryMode::Default)
                 }).collect_vec()
             },
             Module::Commentary(commentary_module) => {
@@ -93,7 +104,7 @@ pub fn search_modules<'a, 'b>(package: &'a Package, modules: &'b [ModuleId], que
                         };
 
                         let module_id = commentary_module.config.id.clone();
-                        search_entry(query.root.as_ref(), body, None, ModuleEntry::Commentary(e), module_id, SearchEntryMode::IncludeIfQueryEmpty)
+                        search_entry(query.root.as_ref(), terms, &query.options, language, body, None, ModuleEntry::Commentary(e), module_id, SearchEntryMode::IncludeIfQueryEmpty)
                     }).collect_vec()
             },
             Module::Notebook(notebook_module) => {
@@ -111,7 +122,7 @@ pub fn search_modules<'a, 'b>(package: &'a Package, modules: &'b [ModuleId], que
                             };
 
                             let module_id = notebook_module.config.id.clone();
-                            search_entry(query.root.as_ref(), body, title, ModuleEntry::Notebook(e), module_id, SearchEntryMode::Default)
+                            search_entry(query.root.as_ref(), terms, &query.options, language, body, title, ModuleEntry::Notebook(e), module_id, SearchEntryMode::Default)
                         },
                         NotebookEntry::Note { name, content, ..} => {
                             let (title, body) = match mode {
@@ -121,13 +132,13 @@ pub fn search_modules<'a, 'b>(package: &'a Package, modules: &'b [ModuleId], que
                             };
 
                             let module_id = notebook_module.config.id.clone();
-                            search_entry(query.root.as_ref(), body, title, ModuleEntry::Notebook(e), module_id, SearchEntryMode::Default)
+                            search_entry(query.root.as_ref(), terms, &query.options, language, body, title, ModuleEntry::Notebook(e), module_id, SearchEntryMode::Default)
                         },
                     }).collect_vec()
             },
             Module::Bible(bible_module) => {
                 query.ranges.iter().filter(|r| r.bible == bible_module.config.id).flat_map(|r| {
-                    run_query_on_word_search_range(package, r, query.root.as_ref())
+                    run_query_on_word_search_range(package, index, r, query.root.as_ref(), terms, &query.options)
                 }).collect_vec()
             },
             Module::Readings(_) => vec![],
@@ -136,7 +147,7 @@ pub fn search_modules<'a, 'b>(package: &'a Package, modules: &'b [ModuleId], que
     }).flatten().collect_vec()
 }
 
-fn run_query_on_word_search_range<'a, 'b>(package: &'a Package, range: &'b WordSearchRange, root: Option<&'b WordSearchPart>) -> Vec<ModuleSearchHit<'a>>
+fn run_query_on_word_search_range<'a, 'b>(package: &'a Package, index: &'b PackageIndex, range: &'b WordSearchRange, root: Option<&'b WordSearchPart>, terms: Option<&[&WordSearchPart]>, options: &WordSearchOptions) -> Vec<ModuleSearchHit<'a>>
 {
     let bible = match package.get_mod(&range.bible).unwrap()
     {
@@ -144,28 +155,88 @@ fn run_query_on_word_search_range<'a, 'b>(package: &'a Package, range: &'b WordS
         _ => return vec![],
     };
 
-    let links = package.modules.values()
-        .filter_map(Module::as_strongs_links)
-        .find(|l| l.config.bible == bible.config.id);
+    let Some(root) = root else {
+        return vec![];
+    };
+
+    // Only verses that could match need to be searched, if the word index has finished building
+    let verses = index.words.as_ref()
+        .and_then(|w| w.get_bible(&bible.config.id))
+        .and_then(|w| w.get_candidate_verses(root, options, range.start, range.end))
+        .unwrap_or_else(|| VerseRangeIter::from_verses(&bible.source.book_infos, range.start, range.end).collect_vec());
+
+    if options.verse_window > 0
+    {
+        return run_query_on_verse_windows(package, index, range, root, terms, options, verses);
+    }
 
-    VerseRangeIter::from_verses(&bible.source.book_infos, range.start, range.end).filter_map(|v_id| {
+    verses.into_iter().filter_map(|v_id| {
         let verse = bible.source.verses.get(&v_id).unwrap();
-        let strongs = links.as_ref().map(|l| l.get_links(&v_id)).flatten();
+        let strongs = index.strongs.get_links(&bible.config.id, v_id);
 
-        root?.run_on_context(&VerseSearchContext {
+        let context = VerseSearchContext {
             verse,
-            strongs
-        }).map(|hits| {
+            strongs,
+            language: bible.config.language,
+        };
+
+        root.run_on_context(&context, options).map(|hits| {
             ModuleSearchHit { 
                 entry: ModuleEntry::Verse(verse),
                 entry_ref: ModuleEntryRef { module: bible.config.id.clone(), entry_id: verse.id },
                 body_hits: hits,
                 title_hits: vec![],
+                body_stats: terms.map(|t| HitTermStats::new(&context, t, options)).unwrap_or_default(),
+                title_stats: HitTermStats::default(),
             }
         })
     }).collect_vec()
 }
 
+/// Searches each candidate verse along with `verse_window` verses either side of it, which only `NEAR` and `BEFORE` match in
+fn run_query_on_verse_windows<'a, 'b>(package: &'a Package, index: &'b PackageIndex, range: &'b WordSearchRange, root: &'b WordSearchPart, terms: Option<&[&WordSearchPart]>, options: &WordSearchOptions, candidates: Vec<VerseId>) -> Vec<ModuleSearchHit<'a>>
+{
+    let window = options.verse_window as usize;
+    let Some(bible) = package.get_mod(&range.bible).and_then(Module::as_bible) else {
+        return vec![];
+    };
+
+    let verses = VerseRangeIter::from_verses(&bible.source.book_infos, range.start, range.end)
+        .filter(|v| bible.source.verses.contains_key(v))
+        .collect_vec();
+
+    // Every part of a query only hits in the searched verse, so the candidates are the verses that could match
+    let candidates: HashSet<_> = candidates.into_iter().collect();
+    verses.iter().positions(|v| candidates.contains(v)).filter_map(|i| {
+        let start = i.saturating_sub(window);
+        let end = (i + window).min(verses.len() - 1);
+        let contexts = verses[start..=end].iter().map(|v| VerseSearchContext {
+            verse: bible.source.verses.get(v).unwrap(),
+            strongs: index.strongs.get_links(&bible.config.id, *v),
+            language: bible.config.language,
+        }).collect_vec();
+
+        let context = WindowSearchContext::new(contexts, i - start);
+        let hits = root.run_on_context(&context, options)?;
+        let hits = context.get_verse_hits(&hits);
+
+        let verse = bible.source.verses.get(&verses[i]).unwrap();
+        let body_stats = terms.map(|t| {
+            HitTermStats::new(&context, t, options)
+                .map_hits(verse.words.len() as u32, |hits| context.get_verse_hits(hits))
+        }).unwrap_or_default();
+
+        Some(ModuleSearchHit { 
+            entry: ModuleEntry::Verse(verse),
+            entry_ref: ModuleEntryRef { module: bible.config.id.clone(), entry_id: verse.id },
+            body_hits: hits,
+            title_hits: vec![],
+            body_stats,
+            title_stats: HitTermStats::default(),
+        })
+    }).collect_vec()
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 enum SearchEntryMode
 {
@@ -173,7 +244,7 @@ enum SearchEntryMode
     IncludeIfQueryEmpty,
 }
 
-fn search_entry<'a, 'b>(query: Option<&'b WordSearchPart>, body: Option<&'b HtmlText>, title: Option<&'b str>, entry: ModuleEntry<'a>, module_id: ModuleId, mode: SearchEntryMode) -> Option<ModuleSearchHit<'a>>
+fn search_entry<'a, 'b>(query: Option<&'b WordSearchPart>, terms: Option<&[&WordSearchPart]>, options: &WordSearchOptions, language: Option<Language>, body: Option<&'b HtmlText>, title: Option<&'b str>, entry: ModuleEntry<'a>, module_id: ModuleId, mode: SearchEntryMode) -> Option<ModuleSearchHit<'a>>
 {
     if let (SearchEntryMode::IncludeIfQueryEmpty, None) = (mode, query)
     {
@@ -182,6 +253,8 @@ fn search_entry<'a, 'b>(query: Option<&'b WordSearchPart>, body: Option<&'b Html
             entry_ref: ModuleEntryRef { module: module_id, entry_id: entry.id() },
             body_hits: vec![], 
             title_hits: vec![], 
+            body_stats: HitTermStats::default(),
+            title_stats: HitTermStats::default(),
         })
     }
 
@@ -189,22 +262,27 @@ fn search_entry<'a, 'b>(query: Option<&'b WordSearchPart>, body: Option<&'b Html
         return None;
     };
     
-    let body_hits = match body 
+    // Term stats are only worked out for texts that hit, as most do not
+    let (body_hits, body_stats) = match body 
     {
         Some(body) => {
-            let context = HtmlSearchContext::from_html_text(&body);
-            query.run_on_context(&context)
+            let context = HtmlSearchContext::from_html_text(&body, language);
+            let hits = query.run_on_context(&context, options);
+            let stats = hits.as_ref().and(terms).map(|t| HitTermStats::new(&context, t, options));
+            (hits, stats)
         },
-        None => None,
+        None => (None, None),
     };
 
-    let title_hits = match title 
+    let (title_hits, title_stats) = match title 
     {
         Some(title) => {
-            let context = StringSearchContext::new(title);
-            query.run_on_context(&context)
+            let context = StringSearchContext::new(title, language);
+            let hits = query.run_on_context(&context, options);
+            let stats = hits.as_ref().and(terms).map(|t| HitTermStats::new(&context, t, options));
+            (hits, stats)
         },
-        None => None,
+        None => (None, None),
     };
 
     if title_hits.is_some() || body_hits.is_some()
@@ -214,6 +292,8 @@ fn search_entry<'a, 'b>(query: Option<&'b WordSearchPart>, body: Option<&'b Html
             entry_ref: ModuleEntryRef { module: module_id, entry_id: entry.id() },
             body_hits: body_hits.unwrap_or_default(),
             title_hits: title_hits.unwrap_or_default(),
+            body_stats: body_stats.unwrap_or_default(),
+            title_stats: title_stats.unwrap_or_default(),
         })
     }
     
@@ -0,0 +1,293 @@
+use std::collections::HashMap;
+
+use biblio_json::modules::ModuleId;
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+
+use crate::{bible::indexing::PackageIndex, searching::{context::SearchContext, module_searching::ModuleSearchHit, word_search_engine::{WordSearchOptions, WordSearchPart, WordSearchQuery}}};
+
+/// BM25 term frequency saturation
+const K1: f32 = 1.2;
+/// BM25 length normalization
+const B: f32 = 0.75;
+/// How much a term in an entry's title counts for, compared to one in its body
+const TITLE_WEIGHT: f32 = 2.0;
+/// How much having the terms of the query right next to each other counts for
+const PROXIMITY_WEIGHT: f32 = 1.0;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SearchSortMode
+{
+    /// Verses in canonical order, and module entries in the order of their modules
+    #[default]
+    Canonical,
+    /// Best matches first, keeping the canonical order between equally good matches
+    Relevance,
+}
+
+/// Where each term of a query hit in a searched text, for ranking by relevance
+#[derive(Debug, Clone, Default)]
+pub struct HitTermStats
+{
+    /// For each term, the indices of the words that it hit
+    pub term_hits: Vec<Vec<u32>>,
+    /// Number of words in the text
+    pub length: u32,
+}
+
+impl HitTermStats
+{
+    pub fn new<C>(ctx: &C, terms: &[&WordSearchPart], options: &WordSearchOptions) -> Self
+        where C : SearchContext
+    {
+        Self {
+            term_hits: terms.iter().map(|t| t.run_on_context(ctx, options).unwrap_or_default()).collect(),
+            length: ctx.len() as u32,
+        }
+    }
+
+    /// Only keeps the hits that `map` returns words for, eg: to take the hits in one verse of a window
+    pub fn map_hits(self, length: u32, map: impl Fn(&[u32]) -> Vec<u32>) -> Self
+    {
+        Self {
+            term_hits: self.term_hits.iter().map(|h| map(h)).collect(),
+            length,
+        }
+    }
+
+    fn has_term(&self, term: usize) -> bool
+    {
+        self.term_hits.get(term).is_some_and(|h| !h.is_empty())
+    }
+
+    /// Higher the closer together hits of different terms are, or 0 if less than two terms hit
+    fn proximity(&self) -> f32
+    {
+        let positions = self.term_hits.iter()
+            .enumerate()
+            .flat_map(|(term, hits)| hits.iter().map(move |h| (*h, term)))
+            .sorted()
+            .collect_vec();
+
+        positions.iter()
+            .tuple_windows()
+            .filter(|((_, a), (_, b))| a != b)
+            .map(|((a, _), (b, _))| b - a)
+            .min()
+            .map_or(0.0, |gap| 1.0 / gap.max(1) as f32)
+    }
+}
+
+/// How many verses of each searched bible every term of a query is in, for measuring how rare the terms are
+#[derive(Debug, Clone, Default)]
+pub struct TermFrequencies
+{
+    /// For each bible, the verse count of each term that could be looked up, and the number of verses in the bible
+    bibles: HashMap<ModuleId, (Vec<Option<usize>>, usize)>,
+}
+
+impl TermFrequencies
+{
+    /// Takes the counts from the word index, so is empty while the index is still being built
+    pub fn new(index: &PackageIndex, modules: &[ModuleId], query: &WordSearchQuery) -> Self
+    {
+        let (Some(words), Some(root)) = (index.words.as_ref(), query.root.as_ref()) else {
+            return Self::default();
+        };
+
+        let terms = root.get_terms();
+        let bibles = modules.iter()
+            .filter_map(|m| {
+                let bible = words.get_bible(m)?;
+                let counts = terms.iter().map(|t| bible.get_term_verse_count(t, &query.options)).collect_vec();
+                Some((m.clone(), (counts, bible.verse_count())))
+            })
+            .collect();
+
+        Self { bibles }
+    }
+
+    /// The BM25 inverse document frequency of the term in the module, if its verse count is known
+    fn get_idf(&self, module: &ModuleId, term: usize) -> Option<f32>
+    {
+        let (counts, verse_count) = self.bibles.get(module)?;
+        let containing = (*counts.get(term)?)?;
+        Some(get_idf(*verse_count as f32, containing as f32))
+    }
+}
+
+/// Sorts the hits by a BM25 score of their bodies, with extra weight for terms in their titles and for terms close together.
+/// Term rarity is measured across the whole bible with `frequencies`, falling back to measuring it across the hits
+/// for module entries, and for verses while the word index is still being built.
+/// Entry lengths are compared to others from the same module
+pub fn rank_hits(hits: &mut Vec<ModuleSearchHit>, frequencies: &TermFrequencies)
+{
+    let term_count = hits.iter().map(|h| h.body_stats.term_hits.len()).max().unwrap_or_default();
+    let hit_count = hits.len() as f32;
+
+    let hit_idfs = (0..term_count).map(|t| {
+        let containing = hits.iter().filter(|h| h.body_stats.has_term(t) || h.title_stats.has_term(t)).count() as f32;
+        get_idf(hit_count, containing)
+    }).collect_vec();
+
+    let mut lengths = HashMap::<&ModuleId, (f32, f32)>::new();
+    for hit in hits.iter()
+    {
+        let (total, count) = lengths.entry(&hit.entry_ref.module).or_default();
+        *total += hit.body_stats.length as f32;
+        *count += 1.0;
+    }
+
+    let average_lengths: HashMap<ModuleId, f32> = lengths.into_iter()
+        .map(|(module, (total, count))| (module.clone(), (total / count).max(1.0)))
+        .collect();
+
+    let scores = hits.iter().map(|hit| {
+        let idfs = hit_idfs.iter().enumerate()
+            .map(|(t, idf)| frequencies.get_idf(&hit.entry_ref.module, t).unwrap_or(*idf))
+            .collect_vec();
+
+        let average_length = average_lengths[&hit.entry_ref.module];
+        let length_norm = 1.0 - B + B * hit.body_stats.length as f32 / average_length;
+
+        let body = idfs.iter().enumerate().map(|(t, idf)| {
+            let tf = hit.body_stats.term_hits.get(t).map_or(0, Vec::len) as f32;
+            idf * tf * (K1 + 1.0) / (tf + K1 * length_norm)
+        }).sum::<f32>();
+
+        let title = idfs.iter().enumerate()
+            .filter(|(t, _)| hit.title_stats.has_term(*t))
+            .map(|(_, idf)| idf)
+            .sum::<f32>();
+
+        body + TITLE_WEIGHT * title + PROXIMITY_WEIGHT * hit.body_stats.proximity()
+    }).collect_vec();
+
+    let mut scored = hits.drain(..).zip(scores).collect_vec();
+    scored.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+    hits.extend(scored.into_iter().map(|(hit, _)| hit));
+}
+
+fn get_idf(texts: f32, containing: f32) -> f32
+{
+    (1.0 + (texts - containing + 0.5) / (containing + 0.5)).ln()
+}
+
+#[cfg(test)]
+mod tests 
+{
+    use std::num::NonZeroU32;
+
+    use biblio_json::{core::{OsisBook, VerseId}, modules::{ModuleEntry, ModuleEntryRef, bible::Verse}};
+
+    use super::*;
+
+    /// Empty verses of Gen 1, as only their ids are used when ranking
+    fn create_verses() -> (ModuleId, Vec<Verse>)
+    {
+        let verses: Vec<Verse> = (1..=3).map(|v| serde_json::from_value(serde_json::json!({
+            "id": VerseId::new(OsisBook::Gen, NonZeroU32::new(1).unwrap(), NonZeroU32::new(v).unwrap()),
+            "words": [],
+        })).unwrap()).collect();
+
+        (ModuleId::new("kjv_eng".into()), verses)
+    }
+
+    /// A hit tagged with `tag` as its only body hit, so the order can be checked after ranking
+    fn hit<'a>(verse: &'a Verse, module: &ModuleId, tag: u32, body: Vec<Vec<u32>>, title: Vec<Vec<u32>>) -> ModuleSearchHit<'a>
+    {
+        ModuleSearchHit {
+            entry: ModuleEntry::Verse(verse),
+            entry_ref: ModuleEntryRef { module: module.clone(), entry_id: verse.id },
+            body_hits: vec![tag],
+            title_hits: vec![],
+            body_stats: HitTermStats { term_hits: body, length: 10 },
+            title_stats: HitTermStats { term_hits: title, length: 2 },
+        }
+    }
+
+    fn tags(hits: &[ModuleSearchHit]) -> Vec<u32>
+    {
+        hits.iter().map(|h| h.body_hits[0]).collect()
+    }
+
+    #[test]
+    fn test_rank_by_term_frequency()
+    {
+        let (module, verses) = create_verses();
+
+        let mut hits = vec![
+            hit(&verses[0], &module, 0, vec![vec![0]], vec![]),
+            hit(&verses[1], &module, 1, vec![vec![0, 3, 5]], vec![]),
+            hit(&verses[2], &module, 2, vec![vec![0, 3]], vec![]),
+        ];
+
+        rank_hits(&mut hits, &TermFrequencies::default());
+        assert_eq!(tags(&hits), [1, 2, 0]);
+    }
+
+    #[test]
+    fn test_rank_by_title_and_proximity()
+    {
+        let (module, verses) = create_verses();
+
+        let mut hits = vec![
+            hit(&verses[0], &module, 0, vec![vec![0], vec![8]], vec![]),
+            hit(&verses[1], &module, 1, vec![vec![0], vec![1]], vec![]),
+            hit(&verses[2], &module, 2, vec![vec![0], vec![8]], vec![vec![0]]),
+        ];
+
+        // Terms next to each other count for more than a term in the title
+        rank_hits(&mut hits, &TermFrequencies::default());
+        assert_eq!(tags(&hits), [1, 2, 0]);
+    }
+
+    #[test]
+    fn test_rank_keeps_order_of_equal_hits()
+    {
+        let (module, verses) = create_verses();
+
+        let mut hits = vec![
+            hit(&verses[0], &module, 0, vec![vec![2]], vec![]),
+            hit(&verses[1], &module, 1, vec![vec![2]], vec![]),
+            hit(&verses[2], &module, 2, vec![vec![2]], vec![]),
+        ];
+
+        rank_hits(&mut hits, &TermFrequencies::default());
+        assert_eq!(tags(&hits), [0, 1, 2]);
+
+        let mut hits = vec![];
+        rank_hits(&mut hits, &TermFrequencies::default());
+        assert!(hits.is_empty());
+    }
+
+    #[test]
+    fn test_rank_by_bible_term_rarity()
+    {
+        let (module, verses) = create_verses();
+
+        // Both hits have every term, so rarity measured across the hits alone cannot tell the terms apart
+        let create_hits = || vec![
+            hit(&verses[0], &module, 0, vec![vec![0, 2], vec![5]], vec![]),
+            hit(&verses[1], &module, 1, vec![vec![0], vec![3, 5]], vec![]),
+        ];
+
+        let mut hits = create_hits();
+        rank_hits(&mut hits, &TermFrequencies::default());
+        assert_eq!(tags(&hits), [0, 1]);
+
+        let frequencies = TermFrequencies { bibles: HashMap::from([(module.clone(), (vec![Some(800), Some(5)], 1000))]) };
+        let mut hits = create_hits();
+        rank_hits(&mut hits, &frequencies);
+        assert_eq!(tags(&hits), [1, 0]);
+    }
+
+    #[test]
+    fn test_proximity()
+    {
+        assert_eq!(HitTermStats { term_hits: vec![vec![0], vec![4]], length: 10 }.proximity(), 0.25);
+        assert_eq!(HitTermStats { term_hits: vec![vec![0, 6], vec![5]], length: 10 }.proximity(), 1.0);
+        assert_eq!(HitTermStats { term_hits: vec![vec![0, 6]], length: 10 }.proximity(), 0.0);
+    }
+}
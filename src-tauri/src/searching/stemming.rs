@@ -0,0 +1,191 @@
+use biblio_json::core::lang::Language;
+use rust_stemmers::{Algorithm, Stemmer};
+
+/// Archaic forms that the suffix rules cannot handle, with the modern word they stem like
+const EARLY_MODERN_IRREGULARS: &[(&str, &str)] = &[
+    ("hath", "have"),
+    ("hast", "have"),
+    ("hadst", "had"),
+    ("doth", "do"),
+    ("dost", "do"),
+    ("didst", "did"),
+    ("saith", "say"),
+    ("shalt", "shall"),
+    ("wilt", "will"),
+    ("canst", "can"),
+    ("wouldest", "would"),
+    ("couldest", "could"),
+    ("shouldest", "should"),
+];
+
+/// Words ending in -eth or -est that are not archaic verb forms
+const EARLY_MODERN_EXCEPTIONS: &[&str] = &[
+    "teeth", "chest", "guest", "crest", "quest", "priest", "honest", "harvest", "forest", "request",
+    "interest", "manifest", "conquest", "arrest", "protest", "modest", "digest", "contest", "molest",
+    "behest", "tempest", "earnest",
+];
+
+/// Names ending in -eth, which are lowercased like every other word by the time they are stemmed
+const EARLY_MODERN_NAMES: &[&str] = &[
+    "seth", "heth", "japheth", "nazareth", "elisabeth", "elizabeth", "shibboleth", "ashtoreth", "mephibosheth",
+    "ishbosheth", "jerubbesheth", "chinnereth", "zereth", "jetheth", "dabbasheth",
+];
+
+/// Reduces words to their stems in a bible's language, so that searching for "love" also finds "loved" and "loving"
+pub struct SearchStemmer
+{
+    stemmer: Stemmer,
+    /// Whether the -eth and -est verb endings of the KJV and other Early Modern English texts are handled
+    early_modern_english: bool,
+}
+
+impl SearchStemmer
+{
+    /// Returns `None` if there is no stemmer for the language
+    pub fn new(language: Language) -> Option<Self>
+    {
+        let (algorithm, early_modern_english) = match language.to_639_3()
+        {
+            "eng" | "enm" => (Algorithm::English, true),
+            "ara" => (Algorithm::Arabic, false),
+            "dan" => (Algorithm::Danish, false),
+            "nld" => (Algorithm::Dutch, false),
+            "fin" => (Algorithm::Finnish, false),
+            "fra" => (Algorithm::French, false),
+            "deu" => (Algorithm::German, false),
+            "ell" => (Algorithm::Greek, false),
+            "hun" => (Algorithm::Hungarian, false),
+            "ita" => (Algorithm::Italian, false),
+            "nor" | "nob" | "nno" => (Algorithm::Norwegian, false),
+            "por" => (Algorithm::Portuguese, false),
+            "ron" => (Algorithm::Romanian, false),
+            "rus" => (Algorithm::Russian, false),
+            "spa" => (Algorithm::Spanish, false),
+            "swe" => (Algorithm::Swedish, false),
+            "tam" => (Algorithm::Tamil, false),
+            "tur" => (Algorithm::Turkish, false),
+            _ => return None,
+        };
+
+        Some(Self {
+            stemmer: Stemmer::create(algorithm),
+            early_modern_english,
+        })
+    }
+
+    /// Expects a lowercased word
+    pub fn stem(&self, word: &str) -> String
+    {
+        if self.early_modern_english
+        {
+            if let Some(modern) = modernize_word(word)
+            {
+                return self.stemmer.stem(&modern).into_owned();
+            }
+        }
+
+        self.stemmer.stem(word).into_owned()
+    }
+}
+
+/// Rewrites an Early Modern English verb form as a modern one that stems the same way,
+/// eg: "loveth" and "lovest" as "loving", or `None` if the word is not one
+fn modernize_word(word: &str) -> Option<String>
+{
+    if let Some((_, modern)) = EARLY_MODERN_IRREGULARS.iter().find(|(archaic, _)| *archaic == word)
+    {
+        return Some(modern.to_string());
+    }
+
+    if EARLY_MODERN_EXCEPTIONS.contains(&word) || EARLY_MODERN_NAMES.contains(&word)
+    {
+        return None;
+    }
+
+    // Nouns such as "beast", "feast" and "east"
+    if word.ends_with("east")
+    {
+        return None;
+    }
+
+    // "seeth" and "fleest" are "see" and "flee" with the ending added straight on
+    if let Some(base) = word.strip_suffix("eeth").or_else(|| word.strip_suffix("eest"))
+    {
+        return Some(format!("{}ee", base));
+    }
+
+    // Ordinals such as "twentieth" are not verbs
+    if word.ends_with("ieth")
+    {
+        return None;
+    }
+
+    // A verb is left with at least a syllable once the ending is taken off, unlike "best" or "rest"
+    let base = word.strip_suffix("eth").or_else(|| word.strip_suffix("est"))?;
+    if base.chars().count() < 2 || !base.contains(['a', 'e', 'i', 'o', 'u', 'y'])
+    {
+        return None;
+    }
+
+    // The stemmer already reduces the -ing form, handling doubled consonants such as "sitteth"
+    Some(format!("{}ing", base))
+}
+
+#[cfg(test)]
+mod tests 
+{
+    use super::*;
+
+    fn english_stemmer() -> SearchStemmer
+    {
+        SearchStemmer::new(Language::new("eng").unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_modernize_verbs()
+    {
+        assert_eq!(modernize_word("loveth"), Some("loving".into()));
+        assert_eq!(modernize_word("lovest"), Some("loving".into()));
+        assert_eq!(modernize_word("sitteth"), Some("sitting".into()));
+        assert_eq!(modernize_word("seeth"), Some("see".into()));
+        assert_eq!(modernize_word("fleest"), Some("flee".into()));
+        assert_eq!(modernize_word("hath"), Some("have".into()));
+    }
+
+    #[test]
+    fn test_modernize_non_verbs()
+    {
+        assert_eq!(modernize_word("beast"), None);
+        assert_eq!(modernize_word("beasts"), None);
+        assert_eq!(modernize_word("feast"), None);
+        assert_eq!(modernize_word("least"), None);
+        assert_eq!(modernize_word("breast"), None);
+        assert_eq!(modernize_word("seth"), None);
+        assert_eq!(modernize_word("elisabeth"), None);
+        assert_eq!(modernize_word("rest"), None);
+        assert_eq!(modernize_word("twentieth"), None);
+        assert_eq!(modernize_word("priest"), None);
+        assert_eq!(modernize_word("love"), None);
+    }
+
+    #[test]
+    fn test_stem_early_modern_english()
+    {
+        let stemmer = english_stemmer();
+        assert_eq!(stemmer.stem("loveth"), stemmer.stem("loved"));
+        assert_eq!(stemmer.stem("lovest"), stemmer.stem("loving"));
+        assert_eq!(stemmer.stem("seeth"), "see");
+        assert_eq!(stemmer.stem("goeth"), "go");
+        assert_eq!(stemmer.stem("hath"), stemmer.stem("have"));
+        assert_eq!(stemmer.stem("beasts"), "beast");
+        assert_eq!(stemmer.stem("beast"), "beast");
+        assert_eq!(stemmer.stem("seth"), "seth");
+    }
+
+    #[test]
+    fn test_stemmer_languages()
+    {
+        assert!(!SearchStemmer::new(Language::new("fra").unwrap()).unwrap().early_modern_english);
+        assert!(SearchStemmer::new(Language::new("heb").unwrap()).is_none());
+    }
+}
@@ -1,10 +1,12 @@
-use std::num::NonZeroU32;
+use std::{num::NonZeroU32, ops::Range};
 
-use biblio_json::{Package, core::{Atom, OsisBook, RefId, RefIdInner, StrongsNumber, VerseId}, modules::{Module, ModuleId, bible::BibleModule}};
+use biblio_json::{Package, core::{lang::Language, Atom, OsisBook, RefId, RefIdInner, StrongsNumber, VerseId}, modules::{Module, ModuleId, bible::BibleModule}};
 use itertools::Itertools;
+use regex::{Regex, RegexBuilder};
 use serde::{Deserialize, Serialize};
+use unicode_normalization::{UnicodeNormalization, char::is_combining_mark};
 
-use crate::{bible::ref_id_parsing::{RefIdParseError, parse_ref_ids}, searching::{context::SearchContext, module_searching::{self, ModuleSearchHit, WordSearchMode}, word_search_parsing::WordSearchParser}};
+use crate::{bible::{indexing::PackageIndex, ref_id_parsing::{RefIdParseError, parse_ref_ids}}, searching::{context::SearchContext, module_searching::{self, ModuleSearchHit, WordSearchMode}, ranking::SearchSortMode, stemming::SearchStemmer, word_search_parsing::WordSearchParser}};
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 #[serde(rename_all = "snake_case")]
@@ -105,11 +107,65 @@ impl WordSearchRange
     }
 }
 
-#[derive(Debug, Clone)]
+/// Options that change how the parts of a query are matched
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct WordSearchOptions
+{
+    /// How many verses either side of a verse are searched along with it, letting `NEAR` and `BEFORE` match across verses.
+    /// One of the two words they pair has to be in the verse, and every other part of the query still only matches within it
+    #[serde(default)]
+    pub verse_window: u32,
+    /// Compares words with their accents and other combining marks removed, eg: so that "ἀγάπη" matches "αγαπη"
+    #[serde(default)]
+    pub ignore_diacritics: bool,
+    /// Compares words by their stems in the searched bible's language, eg: so that "love" matches "loveth"
+    #[serde(default)]
+    pub stem: bool,
+}
+
+impl WordSearchOptions
+{
+    /// Lowercases the text, also removing its combining marks if diacritics are ignored
+    pub fn normalize(&self, text: &str) -> String
+    {
+        if self.ignore_diacritics
+        {
+            text.nfd()
+                .filter(|c| !is_combining_mark(*c))
+                .flat_map(char::to_lowercase)
+                .collect()
+        }
+        else 
+        {
+            text.to_lowercase()
+        }
+    }
+
+    /// Normalizes a whole word, then reduces it to its stem if there is a stemmer
+    pub fn normalize_word(&self, word: &str, stemmer: Option<&SearchStemmer>) -> String
+    {
+        let word = self.normalize(word);
+        match stemmer
+        {
+            Some(stemmer) => stemmer.stem(&word),
+            None => word,
+        }
+    }
+
+    /// A stemmer for the language, if words are compared by their stems and there is one for it
+    pub fn get_stemmer(&self, language: Option<Language>) -> Option<SearchStemmer>
+    {
+        if self.stem { SearchStemmer::new(language?) } else { None }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct WordSearchQuery
 {
     pub ranges: Vec<WordSearchRange>,
     pub root: Option<WordSearchPart>,
+    pub options: WordSearchOptions,
 }
 impl WordSearchQuery  
 {
@@ -118,6 +174,7 @@ impl WordSearchQuery
         Self {
             ranges,
             root,
+            options: WordSearchOptions::default(),
         }
     }
 
@@ -145,10 +202,7 @@ impl WordSearchQuery
                 None
             };
 
-            return Ok(Self {
-                ranges,
-                root,
-            })
+            return Ok(Self::new(ranges, root))
         }
 
         if segments.len() == 1
@@ -165,22 +219,19 @@ impl WordSearchQuery
                 None
             };
 
-            return Ok(Self {
-                ranges: vec![],
-                root,
-            })
+            return Ok(Self::new(vec![], root))
         }
         
         Err(WordQueryParseError::InvalidFormat(text.into()))
     }
     
-    pub fn run_query<'s, 'a, 'b>(&'s self, package: &'a Package, modules: &'b [ModuleId], mode: WordSearchMode) -> Vec<ModuleSearchHit<'a>>
+    pub fn run_query<'s, 'a, 'b>(&'s self, package: &'a Package, index: &'b PackageIndex, modules: &'b [ModuleId], mode: WordSearchMode, sort: SearchSortMode) -> Vec<ModuleSearchHit<'a>>
     {
-        module_searching::search_modules(package, modules, self, mode)
+        module_searching::search_modules(package, index, modules, self, mode, sort)
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum WordSearchPart
 {
     Or(Vec<WordSearchPart>),
@@ -191,23 +242,132 @@ pub enum WordSearchPart
     StartsWith(String),
     EndsWith(String),
     Word(String),
+    /// A word within a small edit distance, eg: `~Jehovah` matching "Iehovah"
+    Fuzzy(String),
+    Regex(SearchRegex),
+    /// Both parts within `distance` words of each other, in either order
+    Near
+    {
+        left: Box<WordSearchPart>,
+        right: Box<WordSearchPart>,
+        distance: u32,
+    },
+    /// The left part followed by the right part within `distance` words
+    Before
+    {
+        left: Box<WordSearchPart>,
+        right: Box<WordSearchPart>,
+        distance: u32,
+    },
+}
+
+/// A case insensitive regular expression, matched against each whole token,
+/// or against the text of the whole verse if `whole_verse` is set
+#[derive(Debug, Clone)]
+pub struct SearchRegex
+{
+    pub pattern: String,
+    pub whole_verse: bool,
+    /// `None` if the pattern is invalid, in which case nothing matches
+    regex: Option<Regex>,
+}
+
+impl SearchRegex
+{
+    pub fn new(pattern: String, whole_verse: bool) -> Self
+    {
+        let regex = Self::compile(&pattern, whole_verse).ok();
+        Self { pattern, whole_verse, regex }
+    }
+
+    /// Fails with the regex error if `pattern` is invalid
+    pub fn try_new(pattern: String, whole_verse: bool) -> Result<Self, String>
+    {
+        let regex = Self::compile(&pattern, whole_verse).map_err(|e| e.to_string())?;
+        Ok(Self { pattern, whole_verse, regex: Some(regex) })
+    }
+
+    pub fn is_match(&self, text: &str) -> bool
+    {
+        self.regex.as_ref().is_some_and(|r| r.is_match(text))
+    }
+
+    /// For a whole verse regex, the indices of every token in `scope` that a match overlaps
+    fn find_token_hits<C>(&self, ctx: &C, options: &WordSearchOptions, scope: Range<usize>) -> Vec<u32>
+        where C : SearchContext
+    {
+        let Some(regex) = &self.regex else {
+            return vec![];
+        };
+
+        // Tokens are joined by single spaces, keeping the byte span of each to map matches back to them
+        let mut text = String::new();
+        let mut spans = vec![];
+        for i in scope.clone()
+        {
+            if i != scope.start
+            {
+                text.push(' ');
+            }
+
+            let start = text.len();
+            text.push_str(&options.normalize(ctx.token_text(i)));
+            spans.push(start..text.len());
+        }
+
+        let mut hits = vec![];
+        for m in regex.find_iter(&text)
+        {
+            hits.extend(spans.iter()
+                .positions(|s| (s.start < m.end() && m.start() < s.end) || (m.is_empty() && s.contains(&m.start())))
+                .map(|i| (scope.start + i) as u32));
+        }
+
+        hits.sort_unstable();
+        hits.dedup();
+        hits
+    }
+
+    fn compile(pattern: &str, whole_verse: bool) -> Result<Regex, regex::Error>
+    {
+        let pattern = if whole_verse { pattern.to_owned() } else { format!("^(?:{})$", pattern) };
+        RegexBuilder::new(&pattern)
+            .case_insensitive(true)
+            .build()
+    }
+}
+
+impl PartialEq for SearchRegex
+{
+    fn eq(&self, other: &Self) -> bool
+    {
+        self.pattern == other.pattern && self.whole_verse == other.whole_verse
+    }
 }
 
 impl WordSearchPart
 {
-    pub fn run_on_context<C>(&self, ctx: &C) -> Option<Vec<u32>>
+    pub fn run_on_context<C>(&self, ctx: &C, options: &WordSearchOptions) -> Option<Vec<u32>>
+        where C : SearchContext 
+    {
+        self.run_in_scope(ctx, options, ctx.scope())
+    }
+
+    /// Only hits tokens in `scope`, apart from `NEAR` and `BEFORE`, which search the whole context
+    fn run_in_scope<C>(&self, ctx: &C, options: &WordSearchOptions, scope: Range<usize>) -> Option<Vec<u32>>
         where C : SearchContext 
     {
         match self 
         {
             WordSearchPart::Word(word) => 
             {
-                let target = word.to_lowercase();
+                let stemmer = options.get_stemmer(ctx.language());
+                let target = options.normalize_word(word, stemmer.as_ref());
                 let mut hits = Vec::new();
 
-                for i in 0..ctx.len() 
+                for i in scope 
                 {
-                    if ctx.token_text(i).to_lowercase() == target 
+                    if options.normalize_word(ctx.token_text(i), stemmer.as_ref()) == target 
                     {
                         hits.push(i as u32);
                     }
@@ -217,9 +377,9 @@ impl WordSearchPart
             }
             WordSearchPart::StartsWith(word) => 
             {
-                let target = word.to_lowercase();
-                let hits = (0..ctx.len())
-                    .filter(|&i| ctx.token_text(i).to_lowercase().starts_with(&target))
+                let target = options.normalize(word);
+                let hits = scope
+                    .filter(|&i| options.normalize(ctx.token_text(i)).starts_with(&target))
                     .map(|i| i as u32)
                     .collect::<Vec<_>>();
 
@@ -227,9 +387,19 @@ impl WordSearchPart
             }
             WordSearchPart::EndsWith(word) => 
             {
-                let target = word.to_lowercase();
-                let hits = (0..ctx.len())
-                    .filter(|&i| ctx.token_text(i).to_lowercase().ends_with(&target))
+                let target = options.normalize(word);
+                let hits = scope
+                    .filter(|&i| options.normalize(ctx.token_text(i)).ends_with(&target))
+                    .map(|i| i as u32)
+                    .collect::<Vec<_>>();
+
+                if hits.is_empty() { None } else { Some(hits) }
+            }
+            WordSearchPart::Fuzzy(word) => 
+            {
+                let target = options.normalize(word);
+                let hits = scope
+                    .filter(|&i| is_fuzzy_match(&target, &options.normalize(ctx.token_text(i))))
                     .map(|i| i as u32)
                     .collect::<Vec<_>>();
 
@@ -237,7 +407,7 @@ impl WordSearchPart
             }
             WordSearchPart::Strongs(num) => 
             {
-                let hits = (0..ctx.len())
+                let hits = scope
                     .filter(|&i| {
                         ctx.token_strongs(i)
                             .map(|s| s.iter().any(|n| n == num))
@@ -248,11 +418,27 @@ impl WordSearchPart
 
                 if hits.is_empty() { None } else { Some(hits) }
             }
+            WordSearchPart::Regex(regex) if regex.whole_verse => 
+            {
+                let hits = regex.find_token_hits(ctx, options, scope);
+                if hits.is_empty() { None } else { Some(hits) }
+            }
+            WordSearchPart::Regex(regex) => 
+            {
+                let hits = scope
+                    .filter(|&i| regex.is_match(&options.normalize(ctx.token_text(i))))
+                    .map(|i| i as u32)
+                    .collect::<Vec<_>>();
+
+                if hits.is_empty() { None } else { Some(hits) }
+            }
+            WordSearchPart::Near { left, right, distance } => run_proximity(ctx, options, left, right, *distance, false, scope),
+            WordSearchPart::Before { left, right, distance } => run_proximity(ctx, options, left, right, *distance, true, scope),
             WordSearchPart::And(parts) => 
             {
                 let mut merged = Vec::new();
                 for p in parts {
-                    merged.extend(p.run_on_context(ctx)?);
+                    merged.extend(p.run_in_scope(ctx, options, scope.clone())?);
                 }
                 merged.sort_unstable();
                 merged.dedup();
@@ -262,7 +448,7 @@ impl WordSearchPart
             {
                 for p in parts 
                 {
-                    if let Some(h) = p.run_on_context(ctx) 
+                    if let Some(h) = p.run_in_scope(ctx, options, scope.clone()) 
                     {
                         return Some(h);
                     }
@@ -271,7 +457,7 @@ impl WordSearchPart
             }
             WordSearchPart::Not(inner) => 
             {
-                if inner.run_on_context(ctx).is_none() 
+                if inner.run_in_scope(ctx, options, scope).is_none() 
                 {
                     Some(vec![])
                 } 
@@ -285,7 +471,7 @@ impl WordSearchPart
             {
                 let all_hits = parts
                     .iter()
-                    .map(|p| p.run_on_context(ctx))
+                    .map(|p| p.run_in_scope(ctx, options, scope.clone()))
                     .collect::<Option<Vec<_>>>()?;
 
                 let first = &all_hits[0];
@@ -311,6 +497,22 @@ impl WordSearchPart
         }
     }
     
+    /// The words, strongs numbers and patterns that hits are made from, leaving out any under a `NOT`, which never hit
+    pub fn get_terms(&self) -> Vec<&WordSearchPart>
+    {
+        match self 
+        {
+            WordSearchPart::Or(parts) | WordSearchPart::And(parts) | WordSearchPart::Sequence(parts) => {
+                parts.iter().flat_map(|p| p.get_terms()).collect()
+            },
+            WordSearchPart::Near { left, right, .. } | WordSearchPart::Before { left, right, .. } => {
+                left.get_terms().into_iter().chain(right.get_terms()).collect()
+            },
+            WordSearchPart::Not(_) => vec![],
+            _ => vec![self],
+        }
+    }
+
     pub fn contains_strongs(&self, strongs: &StrongsNumber) -> bool
     {
         match self 
@@ -319,27 +521,81 @@ impl WordSearchPart
             WordSearchPart::And(parts) => parts.iter().any(|p| p.contains_strongs(strongs)),
             WordSearchPart::Not(inner) => inner.contains_strongs(strongs),
             WordSearchPart::Sequence(parts) => parts.iter().any(|p| p.contains_strongs(strongs)),
+            WordSearchPart::Near { left, right, .. } | WordSearchPart::Before { left, right, .. } => {
+                left.contains_strongs(strongs) || right.contains_strongs(strongs)
+            },
             WordSearchPart::Strongs(s) => s == strongs,
             _ => false,
         }
     }
 
-    pub fn contains_word(&self, word: &str) -> bool
+    /// `language` is that of the bible or module the word is from, for stemming
+    pub fn contains_word(&self, word: &str, options: &WordSearchOptions, language: Option<Language>) -> bool
     {
         match self 
         {
-            WordSearchPart::Or(parts) => parts.iter().any(|p| p.contains_word(word)),
-            WordSearchPart::And(parts) => parts.iter().any(|p| p.contains_word(word)),
-            WordSearchPart::Not(part) => part.contains_word(word),
-            WordSearchPart::Sequence(parts) => parts.iter().any(|p| p.contains_word(word)),
-            WordSearchPart::StartsWith(pattern) => word.to_lowercase().starts_with(&pattern.to_lowercase()),
-            WordSearchPart::EndsWith(pattern) => word.to_lowercase().ends_with(&pattern.to_lowercase()),
-            WordSearchPart::Word(w) => w.to_lowercase() == word.to_lowercase(),
+            WordSearchPart::Or(parts) => parts.iter().any(|p| p.contains_word(word, options, language)),
+            WordSearchPart::And(parts) => parts.iter().any(|p| p.contains_word(word, options, language)),
+            WordSearchPart::Not(part) => part.contains_word(word, options, language),
+            WordSearchPart::Sequence(parts) => parts.iter().any(|p| p.contains_word(word, options, language)),
+            WordSearchPart::Near { left, right, .. } | WordSearchPart::Before { left, right, .. } => {
+                left.contains_word(word, options, language) || right.contains_word(word, options, language)
+            },
+            WordSearchPart::StartsWith(pattern) => options.normalize(word).starts_with(&options.normalize(pattern)),
+            WordSearchPart::EndsWith(pattern) => options.normalize(word).ends_with(&options.normalize(pattern)),
+            WordSearchPart::Word(w) => {
+                let stemmer = options.get_stemmer(language);
+                options.normalize_word(w, stemmer.as_ref()) == options.normalize_word(word, stemmer.as_ref())
+            },
+            WordSearchPart::Fuzzy(w) => is_fuzzy_match(&options.normalize(w), &options.normalize(word)),
+            // Words of a whole verse match are only selected through the hits
+            WordSearchPart::Regex(regex) => regex.whole_verse || regex.is_match(&options.normalize(word)),
             _ => false,
         }
     }
 }
 
+/// Allows one edit for short words and two for longer ones, as short words are easily confused with others
+pub fn is_fuzzy_match(target: &str, token: &str) -> bool
+{
+    let max_distance = if target.chars().count() <= 4 { 1 } else { 2 };
+    strsim::levenshtein(target, token) <= max_distance
+}
+
+/// Hits every pair of left and right hits that are close enough, along with the words between them.
+/// The sides are searched in the whole context, but one of each pair has to be in `scope`
+fn run_proximity<C>(ctx: &C, options: &WordSearchOptions, left: &WordSearchPart, right: &WordSearchPart, distance: u32, ordered: bool, scope: Range<usize>) -> Option<Vec<u32>>
+    where C : SearchContext
+{
+    let left = left.run_in_scope(ctx, options, 0..ctx.len())?;
+    let right = right.run_in_scope(ctx, options, 0..ctx.len())?;
+
+    let mut hits = Vec::new();
+    for &l in &left
+    {
+        for &r in &right
+        {
+            let in_range = if ordered
+            {
+                r > l && r - l <= distance
+            }
+            else 
+            {
+                l != r && l.abs_diff(r) <= distance
+            };
+
+            if in_range && (scope.contains(&(l as usize)) || scope.contains(&(r as usize)))
+            {
+                hits.extend(l.min(r)..=l.max(r));
+            }
+        }
+    }
+
+    hits.sort_unstable();
+    hits.dedup();
+    if hits.is_empty() { None } else { Some(hits) }
+}
+
 #[derive(Debug)]
 pub enum SearchError 
 {
@@ -386,3 +642,182 @@ impl std::fmt::Display for WordQueryParseError
         }
     }
 }
+
+#[cfg(test)]
+mod tests 
+{
+    use super::*;
+
+    /// Searches the words of a text split on spaces, with parts other than `NEAR` and `BEFORE` kept to the words in `scope`
+    struct TextContext(Vec<&'static str>, Range<usize>);
+
+    impl TextContext
+    {
+        fn new(text: &'static str) -> Self
+        {
+            let words = text.split_whitespace().collect_vec();
+            let scope = 0..words.len();
+            Self(words, scope)
+        }
+
+        /// Verses split on `|`, searched as a window around the verse at `focus`
+        fn window(text: &'static str, focus: usize) -> Self
+        {
+            let verses = text.split('|').map(|v| v.split_whitespace().collect_vec()).collect_vec();
+            let start = verses[..focus].iter().map(|v| v.len()).sum::<usize>();
+            let scope = start..start + verses[focus].len();
+            Self(verses.concat(), scope)
+        }
+    }
+
+    impl SearchContext for TextContext
+    {
+        fn len(&self) -> usize 
+        {
+            self.0.len()
+        }
+
+        fn token_text(&self, index: usize) -> &str 
+        {
+            self.0[index]
+        }
+
+        fn token_strongs(&self, _index: usize) -> Option<&[StrongsNumber]> 
+        {
+            None
+        }
+
+        fn scope(&self) -> Range<usize>
+        {
+            self.1.clone()
+        }
+    }
+
+    fn word(word: &str) -> WordSearchPart
+    {
+        WordSearchPart::Word(word.into())
+    }
+
+    #[test]
+    fn test_is_fuzzy_match()
+    {
+        assert!(is_fuzzy_match("love", "love"));
+        assert!(is_fuzzy_match("love", "loves"));
+        assert!(is_fuzzy_match("love", "live"));
+        assert!(!is_fuzzy_match("love", "lived"));
+        assert!(is_fuzzy_match("jerusalem", "hierusalem"));
+        assert!(is_fuzzy_match("jerusalem", "ierusalm"));
+        assert!(!is_fuzzy_match("jerusalem", "salem"));
+    }
+
+    #[test]
+    fn test_run_fuzzy()
+    {
+        let ctx = TextContext::new("and he came to hierusalem");
+        let options = WordSearchOptions::default();
+
+        assert_eq!(WordSearchPart::Fuzzy("jerusalem".into()).run_on_context(&ctx, &options), Some(vec![4]));
+        assert_eq!(WordSearchPart::Fuzzy("bethlehem".into()).run_on_context(&ctx, &options), None);
+    }
+
+    #[test]
+    fn test_normalize_diacritics()
+    {
+        let options = WordSearchOptions { ignore_diacritics: true, ..Default::default() };
+        assert_eq!(options.normalize("Ἐν ἀρχῇ"), "εν αρχη");
+        assert_eq!(options.normalize("Élie"), "elie");
+        assert_eq!(WordSearchOptions::default().normalize("Élie"), "élie");
+    }
+
+    #[test]
+    fn test_run_ignoring_diacritics()
+    {
+        let ctx = TextContext::new("ἐν ἀρχῇ ἦν ὁ λόγος");
+
+        let options = WordSearchOptions { ignore_diacritics: true, ..Default::default() };
+        assert_eq!(word("λογος").run_on_context(&ctx, &options), Some(vec![4]));
+        assert_eq!(WordSearchPart::StartsWith("αρχ".into()).run_on_context(&ctx, &options), Some(vec![1]));
+
+        assert_eq!(word("λογος").run_on_context(&ctx, &WordSearchOptions::default()), None);
+    }
+
+    #[test]
+    fn test_find_token_hits()
+    {
+        let ctx = TextContext::new("For God so loved the world");
+        let options = WordSearchOptions::default();
+
+        assert_eq!(SearchRegex::new("so loved".into(), true).find_token_hits(&ctx, &options, ctx.scope()), [2, 3]);
+        assert_eq!(SearchRegex::new("od so".into(), true).find_token_hits(&ctx, &options, ctx.scope()), [1, 2]);
+        assert_eq!(SearchRegex::new("GOD|world".into(), true).find_token_hits(&ctx, &options, ctx.scope()), [1, 5]);
+        assert_eq!(SearchRegex::new("^for".into(), true).find_token_hits(&ctx, &options, ctx.scope()), [0]);
+        assert!(SearchRegex::new("heaven".into(), true).find_token_hits(&ctx, &options, ctx.scope()).is_empty());
+        assert!(SearchRegex::new("(".into(), true).find_token_hits(&ctx, &options, ctx.scope()).is_empty());
+    }
+
+    #[test]
+    fn test_find_token_hits_ignoring_diacritics()
+    {
+        let ctx = TextContext::new("ἐν ἀρχῇ ἦν ὁ λόγος");
+
+        let options = WordSearchOptions { ignore_diacritics: true, ..Default::default() };
+        assert_eq!(SearchRegex::new("ην ο λογος".into(), true).find_token_hits(&ctx, &options, ctx.scope()), [2, 3, 4]);
+        assert!(SearchRegex::new("ην ο λογος".into(), true).find_token_hits(&ctx, &WordSearchOptions::default(), ctx.scope()).is_empty());
+    }
+
+    #[test]
+    fn test_run_proximity()
+    {
+        let ctx = TextContext::new("for god so loved the world");
+        let options = WordSearchOptions::default();
+
+        assert_eq!(run_proximity(&ctx, &options, &word("god"), &word("loved"), 2, false, ctx.scope()), Some(vec![1, 2, 3]));
+        assert_eq!(run_proximity(&ctx, &options, &word("loved"), &word("god"), 2, false, ctx.scope()), Some(vec![1, 2, 3]));
+        assert_eq!(run_proximity(&ctx, &options, &word("god"), &word("world"), 2, false, ctx.scope()), None);
+    }
+
+    #[test]
+    fn test_run_proximity_ordered()
+    {
+        let ctx = TextContext::new("for god so loved the world");
+        let options = WordSearchOptions::default();
+
+        assert_eq!(run_proximity(&ctx, &options, &word("god"), &word("loved"), 2, true, ctx.scope()), Some(vec![1, 2, 3]));
+        assert_eq!(run_proximity(&ctx, &options, &word("loved"), &word("god"), 2, true, ctx.scope()), None);
+    }
+
+    #[test]
+    fn test_run_proximity_missing_side()
+    {
+        let ctx = TextContext::new("for god so loved the world");
+        let options = WordSearchOptions::default();
+
+        assert_eq!(run_proximity(&ctx, &options, &word("god"), &word("heaven"), 5, false, ctx.scope()), None);
+    }
+
+    #[test]
+    fn test_window_only_widens_proximity()
+    {
+        let ctx = TextContext::window("by faith | not by | works", 0);
+        let options = WordSearchOptions { verse_window: 1, ..Default::default() };
+
+        let and = WordSearchPart::And(vec![word("faith"), word("works")]);
+        assert_eq!(and.run_on_context(&ctx, &options), None);
+
+        let near = WordSearchPart::Near { left: Box::new(word("faith")), right: Box::new(word("works")), distance: 4 };
+        assert_eq!(near.run_on_context(&ctx, &options), Some(vec![1, 2, 3, 4]));
+
+        let not = WordSearchPart::Not(Box::new(word("works")));
+        assert_eq!(not.run_on_context(&ctx, &options), Some(vec![]));
+    }
+
+    #[test]
+    fn test_window_proximity_needs_a_word_in_the_verse()
+    {
+        let ctx = TextContext::window("by faith | not by | works", 1);
+        let near = WordSearchPart::Near { left: Box::new(word("faith")), right: Box::new(word("works")), distance: 4 };
+
+        assert_eq!(near.run_on_context(&ctx, &WordSearchOptions::default()), None);
+        assert_eq!(word("by").run_on_context(&ctx, &WordSearchOptions::default()), Some(vec![3]));
+    }
+}
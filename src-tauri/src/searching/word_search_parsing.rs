@@ -3,7 +3,7 @@ use std::iter::Peekable;
 
 use biblio_json::core::StrongsNumber;
 
-use crate::searching::word_search_engine::WordSearchPart;
+use crate::searching::word_search_engine::{SearchRegex, WordSearchPart};
 
 #[derive(Debug, Clone)]
 pub enum WordSearchToken
@@ -11,12 +11,25 @@ pub enum WordSearchToken
     Word(String),
     Strongs(String),
     Quote(String),
+    /// `/pattern/`, or `/pattern/v` to match against the whole verse
+    Regex
+    {
+        pattern: String,
+        whole_verse: bool,
+    },
     LParen,
     RParen,
     Or,
     Not,
+    /// `NEAR/n`, with `None` if the distance is missing
+    Near(Option<u32>),
+    /// `BEFORE/n`, with `None` if the distance is missing
+    Before(Option<u32>),
     EOF,
     Star,
+    Tilde,
+    /// Input that cannot be tokenized, with the reason
+    Invalid(String),
 }
 
 struct WordSearchLexer<'a>
@@ -49,6 +62,36 @@ impl<'a> WordSearchLexer<'a>
                 }
                 WordSearchToken::Quote(val)
             }
+            Some('/') =>
+            {
+                self.input.next();
+                let mut pattern = String::new();
+                let mut closed = false;
+                while let Some(c) = self.input.next()
+                {
+                    match c
+                    {
+                        '/' => {
+                            closed = true;
+                            break;
+                        },
+                        // An escaped slash is part of the pattern, other escapes are left to the regex
+                        '\\' if self.input.peek() == Some(&'/') => {
+                            pattern.push('/');
+                            self.input.next();
+                        },
+                        _ => pattern.push(c),
+                    }
+                }
+
+                if !closed
+                {
+                    return WordSearchToken::Invalid(format!("Expected a closing '/' after /{}", pattern));
+                }
+
+                let whole_verse = self.input.next_if_eq(&'v').is_some();
+                WordSearchToken::Regex { pattern, whole_verse }
+            }
             Some('(') =>
             {
                 self.input.next();
@@ -63,6 +106,10 @@ impl<'a> WordSearchLexer<'a>
                 self.input.next();
                 WordSearchToken::Star
             }
+            Some('~') => {
+                self.input.next();
+                WordSearchToken::Tilde
+            }
             Some(_) =>
             {
                 let word = self.consume_word();
@@ -71,6 +118,8 @@ impl<'a> WordSearchLexer<'a>
                 {
                     "OR" => WordSearchToken::Or,
                     "NOT" => WordSearchToken::Not,
+                    "NEAR" if self.input.peek() == Some(&'/') => WordSearchToken::Near(self.consume_distance()),
+                    "BEFORE" if self.input.peek() == Some(&'/') => WordSearchToken::Before(self.consume_distance()),
                     _ =>
                     {
                         if word.len() >= 2
@@ -98,12 +147,27 @@ impl<'a> WordSearchLexer<'a>
         }
     }
 
+    /// Consumes the `/n` after a proximity operator
+    fn consume_distance(&mut self) -> Option<u32>
+    {
+        self.input.next();
+        let mut digits = String::new();
+        while let Some(c) = self.input.next_if(char::is_ascii_digit)
+        {
+            digits.push(c);
+        }
+
+        digits.parse().ok()
+    }
+
+    /// A `/` only ends the word after a proximity operator, so that words such as "and/or" are kept whole
     fn consume_word(&mut self) -> String
     {
         let mut out = String::new();
         while let Some(&c) = self.input.peek()
         {
-            if c.is_whitespace() || c == '(' || c == ')' || c == '"' || c == '*' { break; }
+            if c.is_whitespace() || c == '(' || c == ')' || c == '"' || c == '*' || c == '~' { break; }
+            if c == '/' && (out == "NEAR" || out == "BEFORE") { break; }
             out.push(c);
             self.input.next();
         }
@@ -167,7 +231,7 @@ impl<'a> WordSearchParser<'a>
 
         while !matches!(self.lookahead, WordSearchToken::Or | WordSearchToken::RParen | WordSearchToken::EOF)
         {
-            parts.push(self.parse_not()?);
+            parts.push(self.parse_proximity()?);
         }
 
         Ok(if parts.len() == 1
@@ -180,6 +244,37 @@ impl<'a> WordSearchParser<'a>
         })
     }
 
+    // --- NEAR/n and BEFORE/n level ---
+    fn parse_proximity(&mut self) -> Result<WordSearchPart, String>
+    {
+        let mut left = self.parse_not()?;
+
+        loop
+        {
+            let (distance, ordered) = match self.lookahead
+            {
+                WordSearchToken::Near(distance) => (distance, false),
+                WordSearchToken::Before(distance) => (distance, true),
+                _ => break,
+            };
+
+            self.consume();
+            let distance = distance.ok_or_else(|| "Expected a word distance, eg: NEAR/5".to_string())?;
+            let right = Box::new(self.parse_not()?);
+
+            left = if ordered
+            {
+                WordSearchPart::Before { left: Box::new(left), right, distance }
+            }
+            else 
+            {
+                WordSearchPart::Near { left: Box::new(left), right, distance }
+            };
+        }
+
+        Ok(left)
+    }
+
     // --- NOT level ---
     fn parse_not(&mut self) -> Result<WordSearchPart, String>
     {
@@ -237,6 +332,13 @@ impl<'a> WordSearchParser<'a>
                 Ok(WordSearchPart::Sequence(seq))
             }
 
+            WordSearchToken::Regex { pattern, whole_verse } =>
+            {
+                SearchRegex::try_new(pattern, whole_verse)
+                    .map(WordSearchPart::Regex)
+                    .map_err(|e| format!("Regex error: {}", e))
+            }
+
             WordSearchToken::LParen =>
             {
                 let inside = self.parse_or()?;
@@ -255,6 +357,16 @@ impl<'a> WordSearchParser<'a>
                 }
             }
 
+            WordSearchToken::Tilde => {
+                match self.consume()
+                {
+                    WordSearchToken::Word(word) => Ok(WordSearchPart::Fuzzy(word)),
+                    other => Err(format!("Expected a word, found {:?}", other)),
+                }
+            }
+
+            WordSearchToken::Invalid(error) => Err(error),
+
             WordSearchToken::EOF =>
                 Err("Unexpected end of input".into()),
 
@@ -262,4 +374,86 @@ impl<'a> WordSearchParser<'a>
                 Err(format!("Unexpected token: {:?}", other)),
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests 
+{
+    use super::*;
+
+    fn parse(input: &str) -> Result<WordSearchPart, String>
+    {
+        WordSearchParser::new(input).parse()
+    }
+
+    #[test]
+    fn test_parse_regex()
+    {
+        assert_eq!(parse("/lov(e|ed)/").unwrap(), WordSearchPart::Regex(SearchRegex::new("lov(e|ed)".into(), false)));
+        assert_eq!(parse("/son of man/v").unwrap(), WordSearchPart::Regex(SearchRegex::new("son of man".into(), true)));
+        assert_eq!(parse(r"/a\/b/").unwrap(), WordSearchPart::Regex(SearchRegex::new("a/b".into(), false)));
+    }
+
+    #[test]
+    fn test_parse_regex_with_words()
+    {
+        assert_eq!(parse("god /lov(e|ed)/v").unwrap(), WordSearchPart::And(vec![
+            WordSearchPart::Word("god".into()),
+            WordSearchPart::Regex(SearchRegex::new("lov(e|ed)".into(), true)),
+        ]));
+    }
+
+    #[test]
+    fn test_parse_regex_errors()
+    {
+        assert!(parse("/love").is_err());
+        assert!(parse("god /love").is_err());
+        assert!(parse("/lov(e/").is_err());
+    }
+
+    #[test]
+    fn test_parse_proximity()
+    {
+        assert_eq!(parse("love NEAR/3 god").unwrap(), WordSearchPart::Near { 
+            left: Box::new(WordSearchPart::Word("love".into())), 
+            right: Box::new(WordSearchPart::Word("god".into())), 
+            distance: 3,
+        });
+        assert_eq!(parse("love BEFORE/12 god").unwrap(), WordSearchPart::Before { 
+            left: Box::new(WordSearchPart::Word("love".into())), 
+            right: Box::new(WordSearchPart::Word("god".into())), 
+            distance: 12,
+        });
+    }
+
+    #[test]
+    fn test_parse_proximity_errors()
+    {
+        assert!(parse("love NEAR/ god").is_err());
+        assert!(parse("love BEFORE/x god").is_err());
+        assert!(parse("love NEAR/3").is_err());
+    }
+
+    #[test]
+    fn test_parse_fuzzy()
+    {
+        assert_eq!(parse("~jerusalem").unwrap(), WordSearchPart::Fuzzy("jerusalem".into()));
+        assert_eq!(parse("god ~jerusalem").unwrap(), WordSearchPart::And(vec![
+            WordSearchPart::Word("god".into()),
+            WordSearchPart::Fuzzy("jerusalem".into()),
+        ]));
+        assert!(parse("~").is_err());
+        assert!(parse("~(god)").is_err());
+    }
+
+    #[test]
+    fn test_parse_slash_in_word()
+    {
+        assert_eq!(parse("and/or").unwrap(), WordSearchPart::Word("and/or".into()));
+        assert_eq!(parse("faith and/or works").unwrap(), WordSearchPart::And(vec![
+            WordSearchPart::Word("faith".into()),
+            WordSearchPart::Word("and/or".into()),
+            WordSearchPart::Word("works".into()),
+        ]));
+    }
+}
@@ -3,7 +3,7 @@ use std::{collections::VecDeque, ops::DerefMut, thread::{self, JoinHandle}};
 use biblio_json::modules::Module;
 use tauri::{AppHandle, Manager};
 
-use crate::{bible::BiblioJsonPackageHandle, core::utils::Shared, tts::{TtsAudioData, TtsAudioKey, TtsAudioLibrary, synth::SpeechSynth, voices::AppVoices}};
+use crate::{bible::{BiblioJsonPackageHandle, versification::map_verse_between}, core::utils::Shared, tts::{TtsAudioData, TtsAudioKey, TtsAudioLibrary, synth::SpeechSynth, voices::AppVoices}};
 
 pub struct TtsGenThread(Shared<TtsGenThreadInner>);
 
@@ -111,14 +111,21 @@ impl TtsGenThreadInner
                     TtsAudioKey::String { string, .. } => {
                         synth.synth_string(string.clone())
                     }
-                    TtsAudioKey::Verse { verse, bible, .. } => {
-                        let verse = package.visit(|p| {
-                            let bible = p.get_mod(&bible)
-                                .and_then(Module::as_bible)
-                                .unwrap();
-
-                            bible.source.verses.get(&verse.into()).unwrap().clone()
-                        });
+                    TtsAudioKey::Verse { verse, bible, numbered_by, .. } => {
+                        let Some(verse) = package.visit_indexed(|p, index| {
+                            let verse = match numbered_by
+                            {
+                                Some(numbered_by) => map_verse_between(index, verse.into(), numbered_by, bible),
+                                None => verse.into(),
+                            };
+
+                            // The bible may have been disabled or uninstalled since the verse was queued
+                            let bible = p.get_mod(&bible).and_then(Module::as_bible)?;
+                            bible.source.verses.get(&verse).cloned()
+                        }).flatten() else {
+                            log::error!("Failed to generate audio for {:?}, as its bible or verse is not loaded", key);
+                            continue; 
+                        };
 
                         synth.synth_verse(&verse)
                     }
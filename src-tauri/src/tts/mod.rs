@@ -41,6 +41,9 @@ pub enum TtsAudioKey
         verse: VerseIdJson,
         voice: String,
         bible: ModuleId,
+        /// The bible whose numbering `verse` uses, when it is not numbered by `bible` itself
+        #[serde(default)]
+        numbered_by: Option<ModuleId>,
     }
 }
 